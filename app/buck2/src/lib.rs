@@ -20,6 +20,7 @@ use buck2_client::commands::ctargets::ConfiguredTargetsCommand;
 use buck2_client::commands::debug::DebugCommand;
 use buck2_client::commands::expand_external_cell::ExpandExternalCellsCommand;
 use buck2_client::commands::explain::ExplainCommand;
+use buck2_client::commands::external_cells::ExternalCellsCommand;
 use buck2_client::commands::help_env::HelpEnvCommand;
 use buck2_client::commands::init::InitCommand;
 use buck2_client::commands::install::InstallCommand;
@@ -282,6 +283,8 @@ pub(crate) enum CommandKind {
     #[clap(hide = true)] // TODO iguridi: remove
     Explain(ExplainCommand),
     ExpandExternalCell(ExpandExternalCellsCommand),
+    #[clap(subcommand)]
+    ExternalCells(ExternalCellsCommand),
     Install(InstallCommand),
     Kill(KillCommand),
     Killall(KillallCommand),
@@ -443,7 +446,7 @@ impl CommandKind {
                 buck2_client_ctx::eprintln!(
                     "WARNING: \"buck2 query\" is an alias for \"buck2 uquery\". Consider using \"buck2 cquery\" or \"buck2 uquery\" explicitly."
                 )?;
-                cmd.exec(matches, command_ctx)
+                cmd.exec_or_query_snapshot(matches, command_ctx)
             }
             CommandKind::Server(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::Status(cmd) => cmd.exec(matches, command_ctx).into(),
@@ -453,7 +456,7 @@ impl CommandKind {
             CommandKind::Audit(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::Starlark(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::Run(cmd) => cmd.exec(matches, command_ctx),
-            CommandKind::Uquery(cmd) => cmd.exec(matches, command_ctx),
+            CommandKind::Uquery(cmd) => cmd.exec_or_query_snapshot(matches, command_ctx),
             CommandKind::Debug(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::Complete(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::Completion(cmd) => cmd.exec(Opt::command(), matches, command_ctx),
@@ -467,6 +470,7 @@ impl CommandKind {
             CommandKind::Lsp(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::Subscribe(cmd) => cmd.exec(matches, command_ctx),
             CommandKind::ExpandExternalCell(cmd) => cmd.exec(matches, command_ctx),
+            CommandKind::ExternalCells(cmd) => cmd.exec(matches, command_ctx),
         }
     }
 }
@@ -158,6 +158,7 @@ impl CasArtifactAction {
                 execution_kind: ActionExecutionKind::Deferred,
                 timing: ActionExecutionTimingData::default(),
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
@@ -321,6 +322,7 @@ impl Action for CasArtifactAction {
                 execution_kind: ActionExecutionKind::Deferred,
                 timing: ActionExecutionTimingData::default(),
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
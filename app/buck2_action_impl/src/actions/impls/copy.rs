@@ -197,6 +197,7 @@ impl Action for CopyAction {
                 execution_kind: ActionExecutionKind::Simple,
                 timing: ActionExecutionTimingData::default(),
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
@@ -218,6 +218,7 @@ impl DownloadFileAction {
                 execution_kind: ActionExecutionKind::Simple,
                 timing: ActionExecutionTimingData::default(),
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
@@ -338,6 +339,7 @@ impl Action for DownloadFileAction {
                 execution_kind,
                 timing: ActionExecutionTimingData::default(),
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
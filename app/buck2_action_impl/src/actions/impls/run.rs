@@ -34,6 +34,7 @@ use buck2_build_api::interpreter::rule_defs::cmd_args::SimpleCommandLineArtifact
 use buck2_build_api::interpreter::rule_defs::cmd_args::StarlarkCmdArgs;
 use buck2_build_api::interpreter::rule_defs::provider::builtin::worker_info::FrozenWorkerInfo;
 use buck2_build_api::interpreter::rule_defs::provider::builtin::worker_info::WorkerInfo;
+use buck2_common::file_ops::TrackedFileDigest;
 use buck2_core::category::CategoryRef;
 use buck2_core::execution_types::executor_config::MetaInternalExtraParams;
 use buck2_core::execution_types::executor_config::RemoteExecutorCustomImage;
@@ -170,6 +171,8 @@ pub(crate) struct UnregisteredRunAction {
     pub(crate) allow_dep_file_cache_upload: bool,
     pub(crate) force_full_hybrid_if_capable: bool,
     pub(crate) unique_input_inodes: bool,
+    /// Whether to run in a sandbox when executed locally. `None` defers to the executor config.
+    pub(crate) local_sandbox: Option<bool>,
     pub(crate) remote_execution_dependencies: Vec<RemoteExecutorDependency>,
     pub(crate) remote_execution_custom_image: Option<RemoteExecutorCustomImage>,
     pub(crate) meta_internal_extra_params: MetaInternalExtraParams,
@@ -293,6 +296,7 @@ enum ExecuteResult {
         executor_preference: ExecutorPreference,
         prepared_action: PreparedAction,
         input_files_bytes: u64,
+        input_digest: TrackedFileDigest,
     },
 }
 
@@ -620,6 +624,11 @@ impl RunAction {
         };
         let cmdline_digest = prepared_run_action.expanded.fingerprint();
         let input_files_bytes = prepared_run_action.paths.input_files_bytes();
+        let input_digest = prepared_run_action
+            .paths
+            .input_directory()
+            .fingerprint()
+            .dupe();
         // Run actions are assumed to be shared
         let host_sharing_requirements = HostSharingRequirements::Shared(self.inner.weight);
 
@@ -630,9 +639,11 @@ impl RunAction {
             .with_host_sharing_requirements(host_sharing_requirements.into())
             .with_low_pass_filter(self.inner.low_pass_filter)
             .with_outputs_cleanup(!self.inner.no_outputs_cleanup)
+            .with_allow_cache_upload(self.inner.allow_cache_upload || force_cache_upload()?)
             .with_local_environment_inheritance(EnvironmentInheritance::local_command_exclusions())
             .with_force_full_hybrid_if_capable(self.inner.force_full_hybrid_if_capable)
             .with_unique_input_inodes(self.inner.unique_input_inodes)
+            .with_local_sandbox(self.inner.local_sandbox)
             .with_remote_execution_dependencies(self.inner.remote_execution_dependencies.clone())
             .with_remote_execution_custom_image(self.inner.remote_execution_custom_image.clone())
             .with_meta_internal_extra_params(self.inner.meta_internal_extra_params.clone());
@@ -717,6 +728,7 @@ impl RunAction {
             executor_preference: req.executor_preference,
             prepared_action,
             input_files_bytes,
+            input_digest,
         })
     }
 }
@@ -812,18 +824,10 @@ impl Action for RunAction {
     }
 
     fn aquery_attributes(&self, fs: &ExecutorFs) -> indexmap::IndexMap<String, String> {
-        let mut cli_rendered = Vec::<String>::new();
-        let mut ctx = DefaultCommandLineContext::new(fs);
-        let values = Self::unpack(&self.starlark_values).unwrap();
-        values
-            .exe
-            .add_to_command_line(&mut cli_rendered, &mut ctx)
-            .unwrap();
-        values
-            .args
-            .add_to_command_line(&mut cli_rendered, &mut ctx)
-            .unwrap();
-        let cmd = format!("[{}]", cli_rendered.iter().join(", "));
+        let cmd = match self.aquery_argv(fs) {
+            Ok(argv) => format!("[{}]", argv.unwrap_or_default().iter().join(", ")),
+            Err(e) => format!("ERROR: constructing command line ({})", e),
+        };
         indexmap! {
             "cmd".to_owned() => cmd,
             "executor_preference".to_owned() => self.inner.executor_preference.to_string(),
@@ -837,9 +841,26 @@ impl Action for RunAction {
             "no_outputs_cleanup".to_owned() => self.inner.no_outputs_cleanup.to_string(),
             "allow_cache_upload".to_owned() => self.inner.allow_cache_upload.to_string(),
             "allow_dep_file_cache_upload".to_owned() => self.inner.allow_dep_file_cache_upload.to_string(),
+            "local_sandbox".to_owned() => match self.inner.local_sandbox {
+                None => "None".to_owned(),
+                Some(x) => x.to_string(),
+            },
         }
     }
 
+    fn aquery_argv(&self, fs: &ExecutorFs) -> buck2_error::Result<Option<Vec<String>>> {
+        let mut cli_rendered = Vec::<String>::new();
+        let mut ctx = DefaultCommandLineContext::new(fs);
+        let values = Self::unpack(&self.starlark_values)?;
+        values
+            .exe
+            .add_to_command_line(&mut cli_rendered, &mut ctx)?;
+        values
+            .args
+            .add_to_command_line(&mut cli_rendered, &mut ctx)?;
+        Ok(Some(cli_rendered))
+    }
+
     fn error_handler(&self) -> Option<OwnedFrozenValue> {
         self.error_handler.clone()
     }
@@ -854,6 +875,7 @@ impl Action for RunAction {
             executor_preference,
             prepared_action,
             input_files_bytes,
+            input_digest,
         ) = match self.execute_inner(ctx).await? {
             ExecuteResult::LocalDepFileHit(outputs, metadata) => {
                 return Ok((outputs, metadata));
@@ -864,12 +886,14 @@ impl Action for RunAction {
                 executor_preference,
                 prepared_action,
                 input_files_bytes,
+                input_digest,
             } => (
                 result,
                 dep_file_bundle,
                 executor_preference,
                 prepared_action,
                 input_files_bytes,
+                input_digest,
             ),
         };
 
@@ -906,6 +930,7 @@ impl Action for RunAction {
             self.inner.allow_cache_upload,
             self.inner.allow_dep_file_cache_upload,
             Some(input_files_bytes),
+            Some(input_digest),
         )?;
 
         if let Some(dep_file_bundle) = dep_file_bundle {
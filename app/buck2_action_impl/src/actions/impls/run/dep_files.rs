@@ -476,6 +476,7 @@ impl DepFileBundle {
                     execution_kind: ActionExecutionKind::LocalActionCache,
                     timing: Default::default(),
                     input_files_bytes: None,
+                    input_digest: None,
                 },
             )
         });
@@ -512,6 +513,7 @@ impl DepFileBundle {
                     execution_kind: ActionExecutionKind::LocalDepFile,
                     timing: Default::default(),
                     input_files_bytes: None,
+                    input_digest: None,
                 },
             )
         });
@@ -260,6 +260,7 @@ impl Action for SymlinkedDirAction {
                 execution_kind: ActionExecutionKind::Simple,
                 timing: ActionExecutionTimingData::default(),
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
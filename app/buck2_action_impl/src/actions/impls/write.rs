@@ -211,6 +211,7 @@ impl Action for WriteAction {
                 execution_kind: ActionExecutionKind::Simple,
                 timing: ActionExecutionTimingData { wall_time },
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
@@ -225,6 +225,7 @@ impl Action for WriteJsonAction {
                 execution_kind: ActionExecutionKind::Simple,
                 timing: ActionExecutionTimingData { wall_time },
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
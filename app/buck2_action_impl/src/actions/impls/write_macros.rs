@@ -199,6 +199,7 @@ impl Action for WriteMacrosToFileAction {
                 execution_kind: ActionExecutionKind::Simple,
                 timing: ActionExecutionTimingData { wall_time },
                 input_files_bytes: None,
+                input_digest: None,
             },
         ))
     }
@@ -121,6 +121,12 @@ pub(crate) fn analysis_actions_methods_run(methods: &mut MethodsBuilder) {
     ///     and `--local-only` CLI flags. The CLI flags take precedence.
     ///     * The `force_full_hybrid_if_capable` option overrides the `use_limited_hybrid` hybrid.
     ///     The options listed above take precedence if set.
+    /// * `local_sandbox`: whether to run the command in a sandbox when it runs locally. The sandbox
+    ///   only exposes the action's inputs, its outputs, its scratch path, a private `/tmp` and the
+    ///   paths listed in the `buck2.sandbox_toolchain_paths` buckconfig (which defaults to the
+    ///   usual system directories). Looking up any other path in the project fails the action.
+    ///   This defaults to the `use_local_sandbox` option of the executor, is only supported on
+    ///   Linux, and does not apply to actions that run in persistent workers.
     /// * `remote_execution_dependencies`: list of dependencies which is passed to Remote Execution.
     ///   Each dependency is dictionary with the following keys:
     ///     * `smc_tier`: name of the SMC tier to call by RE Scheduler.
@@ -192,6 +198,7 @@ pub(crate) fn analysis_actions_methods_run(methods: &mut MethodsBuilder) {
             Either<ValueOf<'v, &'v WorkerRunInfo<'v>>, ValueOf<'v, &'v RunInfo<'v>>>,
         >,
         #[starlark(require = named, default = false)] unique_input_inodes: bool,
+        #[starlark(require = named, default = NoneOr::None)] local_sandbox: NoneOr<bool>,
         #[starlark(require = named)] error_handler: Option<StarlarkCallable<'v>>,
         eval: &mut Evaluator<'v, '_, '_>,
         #[starlark(require = named, default=UnpackList::default())]
@@ -400,6 +407,7 @@ pub(crate) fn analysis_actions_methods_run(methods: &mut MethodsBuilder) {
             allow_dep_file_cache_upload,
             force_full_hybrid_if_capable,
             unique_input_inodes,
+            local_sandbox: local_sandbox.into_option(),
             remote_execution_dependencies: re_dependencies,
             remote_execution_custom_image: re_custom_image,
             meta_internal_extra_params: extra_params,
@@ -32,6 +32,7 @@ use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_artifact::actions::key::ActionKey;
 use buck2_artifact::artifact::build_artifact::BuildArtifact;
+use buck2_common::file_ops::TrackedFileDigest;
 use buck2_common::io::IoProvider;
 use buck2_core::category::Category;
 use buck2_core::category::CategoryRef;
@@ -160,6 +161,12 @@ pub trait Action: Allocative + Debug + Send + Sync + 'static {
         indexmap! {}
     }
 
+    /// The command line this action runs, for actions that run one. Used by `aquery` to match
+    /// actions by their arguments.
+    fn aquery_argv(&self, _fs: &ExecutorFs) -> buck2_error::Result<Option<Vec<String>>> {
+        Ok(None)
+    }
+
     /// error handler
     fn error_handler(&self) -> Option<OwnedFrozenValue> {
         None
@@ -223,6 +230,7 @@ pub trait ActionExecutionCtx: Send + Sync {
         allows_cache_upload: bool,
         allows_dep_file_cache_upload: bool,
         input_files_bytes: Option<u64>,
+        input_digest: Option<TrackedFileDigest>,
     ) -> Result<(ActionOutputs, ActionExecutionMetadata), ExecuteError>;
 
     /// Clean up all the output directories for this action. This requires a mutable reference
@@ -234,6 +234,7 @@ async fn build_action_inner(
     let mut buck2_build_time = None;
     let mut hostname = None;
     let mut input_files_bytes = None;
+    let mut input_digest = None;
     let error_diagnostics = match execute_result {
         Ok((outputs, meta)) => {
             output_size = outputs.calc_output_count_and_bytes().bytes;
@@ -242,6 +243,7 @@ async fn build_action_inner(
             wall_time = Some(meta.timing.wall_time);
             error = None;
             input_files_bytes = meta.input_files_bytes;
+            input_digest = meta.input_digest.map(|d| d.to_string());
 
             if let Some(command) = meta.execution_kind.command() {
                 prefers_local = Some(command.prefers_local);
@@ -379,6 +381,7 @@ async fn build_action_inner(
             error_diagnostics,
             input_files_bytes,
             invalidation_info,
+            input_digest,
         }),
     )
 }
@@ -16,6 +16,7 @@ use async_trait::async_trait;
 use buck2_artifact::artifact::build_artifact::BuildArtifact;
 use buck2_common::dice::data::HasIoProvider;
 use buck2_common::events::HasEvents;
+use buck2_common::file_ops::TrackedFileDigest;
 use buck2_common::http::HasHttpClient;
 use buck2_common::io::IoProvider;
 use buck2_common::liveliness_observer::NoopLivelinessObserver;
@@ -113,6 +114,7 @@ pub struct ActionExecutionMetadata {
     pub execution_kind: ActionExecutionKind,
     pub timing: ActionExecutionTimingData,
     pub input_files_bytes: Option<u64>,
+    pub input_digest: Option<TrackedFileDigest>,
 }
 
 /// The *way* that a particular action was executed.
@@ -429,6 +431,7 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
         allows_cache_upload: bool,
         allows_dep_file_cache_upload: bool,
         input_files_bytes: Option<u64>,
+        input_digest: Option<TrackedFileDigest>,
     ) -> Result<(ActionOutputs, ActionExecutionMetadata), ExecuteError> {
         let CommandExecutionResult {
             outputs,
@@ -467,6 +470,7 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
                         },
                         timing: report.timing.into(),
                         input_files_bytes,
+                        input_digest,
                     },
                 );
                 Ok(result)
@@ -870,6 +874,7 @@ mod tests {
                     false,
                     false,
                     None,
+                    None,
                 )?;
                 let outputs = self
                     .outputs
@@ -887,6 +892,7 @@ mod tests {
                         execution_kind: ActionExecutionKind::Simple,
                         timing: ActionExecutionTimingData::default(),
                         input_files_bytes: None,
+                        input_digest: None,
                     },
                 ))
             }
@@ -181,6 +181,14 @@ impl ActionQueryNode {
     pub fn key(&self) -> &ActionQueryNodeRef {
         &self.key
     }
+
+    /// The command line of the action, if this is an action that runs one.
+    pub fn argv(&self) -> buck2_error::Result<Option<Vec<String>>> {
+        match &self.data {
+            ActionQueryNodeData::Analysis(..) => Ok(None),
+            ActionQueryNodeData::Action(data) => data.argv(),
+        }
+    }
 }
 
 impl LabeledNode for ActionQueryNode {
@@ -234,6 +242,13 @@ impl ActionData {
         );
         attrs
     }
+
+    fn argv(&self) -> buck2_error::Result<Option<Vec<String>>> {
+        self.action.action().aquery_argv(&ExecutorFs::new(
+            &self.fs,
+            self.action.execution_config().options.path_separator,
+        ))
+    }
 }
 
 #[derive(
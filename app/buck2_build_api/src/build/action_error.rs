@@ -154,6 +154,7 @@ fn get_action_digest(command_details: Option<&CommandExecutionDetails>) -> Optio
                 Some(Command::WorkerCommand(c)) => Some(c.action_digest.clone()),
                 Some(Command::WorkerInitCommand(_)) => None,
                 Some(Command::RemoteCommand(c)) => Some(c.action_digest.clone()),
+                Some(Command::LocalDiskCacheHit(c)) => Some(c.action_digest.clone()),
                 None => None,
             }
         } else {
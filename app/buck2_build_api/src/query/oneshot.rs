@@ -15,6 +15,7 @@ use buck2_core::global_cfg_options::GlobalCfgOptions;
 use buck2_node::configured_universe::CqueryUniverse;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_util::late_binding::LateBinding;
 use dice::DiceComputations;
@@ -29,6 +30,7 @@ pub trait QueryFrontend: Send + Sync + 'static {
         working_dir: &ProjectRelativePath,
         query: &str,
         query_args: &[String],
+        limits: QueryLimits,
     ) -> buck2_error::Result<QueryEvaluationResult<TargetNode>>;
 
     async fn eval_cquery(
@@ -40,6 +42,7 @@ pub trait QueryFrontend: Send + Sync + 'static {
         global_cfg_options: GlobalCfgOptions,
         target_universe: Option<&[String]>,
         collect_universes: bool,
+        limits: QueryLimits,
     ) -> buck2_error::Result<(
         QueryEvaluationResult<ConfiguredTargetNode>,
         Option<Vec<Arc<CqueryUniverse>>>,
@@ -52,6 +55,7 @@ pub trait QueryFrontend: Send + Sync + 'static {
         query: &str,
         query_args: &[String],
         global_cfg_options: GlobalCfgOptions,
+        limits: QueryLimits,
     ) -> buck2_error::Result<QueryEvaluationResult<ActionQueryNode>>;
 }
 
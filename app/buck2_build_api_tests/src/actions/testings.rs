@@ -158,6 +158,7 @@ impl Action for SimpleAction {
             false,
             false,
             None,
+            None,
         )?;
 
         Ok((outputs, meta))
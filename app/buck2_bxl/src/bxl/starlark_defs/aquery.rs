@@ -16,6 +16,7 @@ use buck2_core::configuration::compatibility::IncompatiblePlatformReason;
 use buck2_core::global_cfg_options::GlobalCfgOptions;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::functions::helpers::CapturedExpr;
 use derivative::Derivative;
@@ -331,6 +332,7 @@ fn aquery_methods(builder: &mut MethodsBuilder) {
                                 query,
                                 &query_args,
                                 this.global_cfg_options_override.clone(),
+                                QueryLimits::default(),
                             )
                             .await?,
                         eval.heap(),
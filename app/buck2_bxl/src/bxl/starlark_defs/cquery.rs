@@ -13,6 +13,7 @@ use buck2_build_api::query::bxl::NEW_BXL_CQUERY_FUNCTIONS;
 use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_core::global_cfg_options::GlobalCfgOptions;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::functions::helpers::CapturedExpr;
 use derivative::Derivative;
@@ -547,6 +548,7 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
                                 this.global_cfg_options_override.clone(),
                                 target_universe.into_option().as_ref().map(|v| &v.items[..]),
                                 false,
+                                QueryLimits::default(),
                             )
                             .await?
                             .0,
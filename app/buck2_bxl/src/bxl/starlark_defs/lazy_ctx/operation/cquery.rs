@@ -14,11 +14,13 @@ use buck2_core::global_cfg_options::GlobalCfgOptions;
 use buck2_error::starlark_error::from_starlark_with_options;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
 use buck2_query::query::environment::QueryTarget;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
 use dice::DiceComputations;
 use indexmap::IndexMap;
 use starlark::values::dict::Dict;
+use starlark::values::list::AllocList;
 use starlark::values::Heap;
 use starlark::values::Value;
 
@@ -88,6 +90,10 @@ impl<T: NodeLike> SingleOrMappedQueryEvaluationValue<T> {
                     heap.alloc(StarlarkTargetSet::from(targets))
                 }
                 QueryEvaluationValue::FileSet(files) => heap.alloc(StarlarkFileSet::from(files)),
+                QueryEvaluationValue::Integer(value) => heap.alloc(value),
+                QueryEvaluationValue::Paths(paths) => {
+                    heap.alloc(AllocList(paths.into_iter().map(StarlarkTargetSet::from)))
+                }
             },
             SingleOrMappedQueryEvaluationValue::Map(map) => heap.alloc(Dict::new(
                 map.into_iter()
@@ -107,6 +113,10 @@ impl<T: NodeLike> SingleOrMappedQueryEvaluationValue<T> {
                                 QueryEvaluationValue::FileSet(files) => {
                                     heap.alloc(StarlarkFileSet::from(files))
                                 }
+                                QueryEvaluationValue::Integer(value) => heap.alloc(value),
+                                QueryEvaluationValue::Paths(paths) => heap.alloc(AllocList(
+                                    paths.into_iter().map(StarlarkTargetSet::from),
+                                )),
                             },
                         ))
                     })
@@ -143,6 +153,7 @@ impl LazyCqueryOperation {
                         self.global_cfg_options.clone(),
                         target_universe.as_ref().map(|items| &items[..]),
                         false,
+                        QueryLimits::default(),
                     )
                     .await?
                     .0;
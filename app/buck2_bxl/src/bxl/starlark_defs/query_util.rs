@@ -11,6 +11,7 @@ use buck2_error::starlark_error::from_starlark_with_options;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
 use starlark::values::dict::Dict;
+use starlark::values::list::AllocList;
 use starlark::values::Heap;
 use starlark::values::Value;
 
@@ -28,6 +29,10 @@ pub(crate) fn parse_query_evaluation_result<'v, T: NodeLike>(
                 heap.alloc(StarlarkTargetSet::from(targets))
             }
             QueryEvaluationValue::FileSet(files) => heap.alloc(StarlarkFileSet::from(files)),
+            QueryEvaluationValue::Integer(value) => heap.alloc(value),
+            QueryEvaluationValue::Paths(paths) => {
+                heap.alloc(AllocList(paths.into_iter().map(StarlarkTargetSet::from)))
+            }
         },
         QueryEvaluationResult::Multiple(multi) => heap.alloc(Dict::new(
             multi
@@ -49,6 +54,9 @@ pub(crate) fn parse_query_evaluation_result<'v, T: NodeLike>(
                             QueryEvaluationValue::FileSet(files) => {
                                 heap.alloc(StarlarkFileSet::from(files))
                             }
+                            QueryEvaluationValue::Integer(value) => heap.alloc(value),
+                            QueryEvaluationValue::Paths(paths) => heap
+                                .alloc(AllocList(paths.into_iter().map(StarlarkTargetSet::from))),
                         },
                     ))
                 })
@@ -14,6 +14,7 @@ use buck2_build_api::query::bxl::BxlUqueryFunctions;
 use buck2_build_api::query::bxl::NEW_BXL_UQUERY_FUNCTIONS;
 use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::functions::helpers::CapturedExpr;
 use derivative::Derivative;
@@ -528,7 +529,13 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
                     parse_query_evaluation_result(
                         QUERY_FRONTEND
                             .get()?
-                            .eval_uquery(dice, &this.ctx.working_dir()?, query, &query_args)
+                            .eval_uquery(
+                                dice,
+                                &this.ctx.working_dir()?,
+                                query,
+                                &query_args,
+                                QueryLimits::default(),
+                            )
                             .await?,
                         eval.heap(),
                     )
@@ -15,6 +15,7 @@ pub mod ctargets;
 pub mod debug;
 pub mod expand_external_cell;
 pub mod explain;
+pub mod external_cells;
 pub mod help_env;
 pub mod init;
 pub mod install;
@@ -22,6 +22,7 @@ use buck2_data::instant_event::Data;
 use buck2_data::InstantEvent;
 use buck2_data::PersistEventLogSubprocess;
 use buck2_error::BuckErrorContext;
+use buck2_event_log::retention::LiveLogLock;
 use buck2_event_log::ttl::manifold_event_log_ttl;
 use buck2_events::sink::remote::new_remote_event_sink_if_enabled;
 use buck2_events::sink::remote::RemoteEventSink;
@@ -99,8 +100,9 @@ impl PersistEventLogsCommand {
         stdin: impl io::AsyncBufRead + Unpin,
     ) -> (buck2_error::Result<()>, buck2_error::Result<()>) {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let file = match create_log_file(self.local_path).await {
-            Ok(f) => Mutex::new(f),
+        // Held until we're done writing, so that log retention leaves the log alone.
+        let (_lock, file) = match create_log_file(self.local_path).await {
+            Ok((lock, f)) => (lock, Mutex::new(f)),
             Err(e) => {
                 return (
                     Err(e),
@@ -142,8 +144,11 @@ async fn write_task(
     Ok(())
 }
 
-async fn create_log_file(local_path: String) -> Result<tokio::fs::File, buck2_error::Error> {
+async fn create_log_file(
+    local_path: String,
+) -> Result<(LiveLogLock, tokio::fs::File), buck2_error::Error> {
     let local_path = AbsPathBuf::new(local_path)?;
+    let lock = LiveLogLock::acquire(&local_path)?;
 
     let file = OpenOptions::new()
         .create(true)
@@ -158,7 +163,7 @@ async fn create_log_file(local_path: String) -> Result<tokio::fs::File, buck2_er
                 local_path.display()
             )
         })?;
-    Ok(file)
+    Ok((lock, file))
 }
 
 async fn upload_task(
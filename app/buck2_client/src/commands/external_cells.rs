@@ -0,0 +1,236 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::BuckArgMatches;
+use buck2_client_ctx::common::CommonBuildConfigurationOptions;
+use buck2_client_ctx::common::CommonEventLogOptions;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_common::argv::Argv;
+use buck2_common::argv::SanitizedArgv;
+use buck2_common::external_cells::lockfile::ExternalCellsLockfile;
+use buck2_common::external_cells::lockfile::LockedGitCell;
+use buck2_common::legacy_configs::cells::BuckConfigBasedCells;
+use buck2_common::legacy_configs::cells::GitRefCell;
+use buck2_core::fs::fs_util;
+use buck2_error::BuckErrorContext;
+use buck2_util::process::async_background_command;
+use dupe::Dupe;
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Input)]
+enum ExternalCellsUpdateError {
+    #[error("`{0}` is not an external cell configured with a `git_ref`")]
+    NotAGitRefCell(String),
+    #[error("Error running `git ls-remote {origin} {git_ref}`:\n{stderr}")]
+    LsRemoteFailed {
+        origin: String,
+        git_ref: String,
+        stderr: String,
+    },
+    #[error("Ref `{git_ref}` not found in `{origin}`")]
+    RefNotFound { origin: String, git_ref: String },
+}
+
+#[derive(Debug, clap::Subcommand)]
+#[clap(about = "Commands for managing external cells")]
+pub enum ExternalCellsCommand {
+    Update(UpdateCommand),
+}
+
+impl ExternalCellsCommand {
+    pub fn exec(self, matches: BuckArgMatches<'_>, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let matches = matches.unwrap_subcommand();
+        match self {
+            Self::Update(cmd) => cmd.exec(matches, ctx),
+        }
+    }
+
+    pub fn sanitize_argv(&self, argv: Argv) -> SanitizedArgv {
+        match self {
+            Self::Update(cmd) => cmd.sanitize_argv(argv),
+        }
+    }
+}
+
+/// Resolve the `git_ref` of git external cells to commits, and record them in the lockfile.
+///
+/// Only cells that are configured with a `git_ref` rather than a `commit_hash` are locked. Entries
+/// for cells that no longer use a `git_ref` are removed.
+#[derive(Debug, clap::Parser)]
+pub struct UpdateCommand {
+    /// Only update these cells. Other entries are kept as they are, as long as they still match
+    /// the configuration.
+    #[clap(value_name = "CELL")]
+    cells: Vec<String>,
+
+    #[clap(flatten)]
+    config_opts: CommonBuildConfigurationOptions,
+
+    #[clap(flatten)]
+    event_log_opts: CommonEventLogOptions,
+}
+
+impl UpdateCommand {
+    pub fn exec(self, matches: BuckArgMatches<'_>, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let config_args =
+            self.config_opts
+                .config_overrides(matches, ctx.immediate_config, &ctx.working_dir);
+        ctx.instant_command(
+            "external-cells-update",
+            &self.event_log_opts,
+            |ctx| async move {
+                let project_root = ctx.paths()?.project_root().dupe();
+
+                let configured = BuckConfigBasedCells::parse_git_ref_cells_with_config_args(
+                    &project_root,
+                    &config_args?,
+                )
+                .await?;
+                for cell in &self.cells {
+                    if !configured.iter().any(|c| c.name.as_str() == cell) {
+                        return Err(ExternalCellsUpdateError::NotAGitRefCell(cell.clone()).into());
+                    }
+                }
+
+                let lockfile_path = project_root.resolve(ExternalCellsLockfile::path());
+                let old = match fs_util::read_to_string_if_exists(&lockfile_path)? {
+                    Some(contents) => ExternalCellsLockfile::parse(&contents)?,
+                    None => ExternalCellsLockfile::default(),
+                };
+
+                let mut new = ExternalCellsLockfile::default();
+                for cell in configured {
+                    let name = cell.name.as_str().to_owned();
+                    let existing = old.git.get(&name).filter(|locked| {
+                        locked.git_origin == cell.git_origin && locked.git_ref == cell.git_ref
+                    });
+                    let locked = match existing {
+                        Some(locked) if !self.cells.is_empty() && !self.cells.contains(&name) => {
+                            locked.clone()
+                        }
+                        _ => {
+                            let locked = lock_cell(&cell).await?;
+                            buck2_client_ctx::eprintln!(
+                                "Locked `{}` at `{}` to {}",
+                                name,
+                                locked.git_ref,
+                                locked.commit_hash
+                            )?;
+                            locked
+                        }
+                    };
+                    new.git.insert(name, locked);
+                }
+
+                if new != old {
+                    fs_util::write(&lockfile_path, new.to_json_string()?)?;
+                }
+
+                Ok(())
+            },
+        )
+        .into()
+    }
+
+    pub fn sanitize_argv(&self, argv: Argv) -> SanitizedArgv {
+        argv.no_need_to_sanitize()
+    }
+}
+
+async fn lock_cell(cell: &GitRefCell) -> buck2_error::Result<LockedGitCell> {
+    let output = async_background_command("git")
+        .arg("ls-remote")
+        .arg(&cell.git_origin)
+        .arg(&cell.git_ref)
+        .output()
+        .await
+        .buck_error_context("Could not run git to resolve external cell ref")?;
+
+    if !output.status.success() {
+        return Err(ExternalCellsUpdateError::LsRemoteFailed {
+            origin: cell.git_origin.clone(),
+            git_ref: cell.git_ref.clone(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    let commit_hash = resolve_ref(&String::from_utf8_lossy(&output.stdout), &cell.git_ref)
+        .ok_or_else(|| ExternalCellsUpdateError::RefNotFound {
+            origin: cell.git_origin.clone(),
+            git_ref: cell.git_ref.clone(),
+        })?;
+
+    Ok(LockedGitCell {
+        git_origin: cell.git_origin.clone(),
+        git_ref: cell.git_ref.clone(),
+        commit_hash,
+    })
+}
+
+/// Pick the commit for `git_ref` out of the output of `git ls-remote`.
+///
+/// `ls-remote` matches the ref as a suffix, so there may be several candidates. Annotated tags are
+/// peeled to the commit they point at.
+fn resolve_ref(ls_remote: &str, git_ref: &str) -> Option<String> {
+    let refs: Vec<(&str, &str)> = ls_remote
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+    [
+        format!("refs/tags/{}^{{}}", git_ref),
+        format!("refs/tags/{}", git_ref),
+        format!("refs/heads/{}", git_ref),
+        git_ref.to_owned(),
+    ]
+    .iter()
+    .find_map(|candidate| {
+        refs.iter()
+            .find(|(_, name)| *name == candidate.as_str())
+            .map(|(commit, _)| (*commit).to_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_ref;
+
+    const LS_REMOTE: &str = "\
+1111111111111111111111111111111111111111\trefs/heads/main
+2222222222222222222222222222222222222222\trefs/heads/release/v1.0
+3333333333333333333333333333333333333333\trefs/tags/v1.0
+4444444444444444444444444444444444444444\trefs/tags/v1.0^{}
+";
+
+    #[test]
+    fn test_resolve_branch() {
+        assert_eq!(
+            resolve_ref(LS_REMOTE, "main").as_deref(),
+            Some("1111111111111111111111111111111111111111")
+        );
+        assert_eq!(
+            resolve_ref(LS_REMOTE, "refs/heads/release/v1.0").as_deref(),
+            Some("2222222222222222222222222222222222222222")
+        );
+    }
+
+    #[test]
+    fn test_resolve_annotated_tag() {
+        assert_eq!(
+            resolve_ref(LS_REMOTE, "v1.0").as_deref(),
+            Some("4444444444444444444444444444444444444444")
+        );
+    }
+
+    #[test]
+    fn test_resolve_missing() {
+        assert_eq!(resolve_ref(LS_REMOTE, "v2.0"), None);
+    }
+}
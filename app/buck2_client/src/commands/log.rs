@@ -11,7 +11,10 @@ mod critical_path;
 pub(crate) mod debug_replay;
 pub(crate) mod debug_what_ran;
 mod diff;
+mod evicted;
+mod export_sqlite;
 pub(crate) mod options;
+mod otlp;
 pub(crate) mod path_log;
 mod replay;
 mod show_log;
@@ -93,6 +96,9 @@ pub enum LogCommand {
     Replay(replay::ReplayCommand),
     ShowUser(show_user_log::ShowUserLogCommand),
     Summary(summary::SummaryCommand),
+    Otlp(otlp::OtlpCommand),
+    ExportSqlite(export_sqlite::ExportSqliteCommand),
+    Evicted(evicted::EvictedCommand),
     #[clap(subcommand)]
     Diff(diff::DiffCommand),
 }
@@ -112,6 +118,9 @@ impl LogCommand {
             Self::Replay(cmd) => cmd.exec(matches, ctx),
             Self::ShowUser(cmd) => cmd.exec(matches, ctx),
             Self::Summary(cmd) => cmd.exec(matches, ctx),
+            Self::Otlp(cmd) => cmd.exec(matches, ctx),
+            Self::ExportSqlite(cmd) => cmd.exec(matches, ctx),
+            Self::Evicted(cmd) => cmd.exec(matches, ctx),
             Self::Diff(cmd) => cmd.exec(matches, ctx),
         }
     }
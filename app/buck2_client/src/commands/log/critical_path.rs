@@ -89,8 +89,8 @@ impl CriticalPathCommand {
 }
 
 #[derive(Default)]
-struct OptionalDuration {
-    inner: Option<Duration>,
+pub(crate) struct OptionalDuration {
+    pub(crate) inner: Option<Duration>,
 }
 
 impl OptionalDuration {
@@ -127,116 +127,129 @@ impl Serialize for OptionalDuration {
 }
 
 #[derive(Default, Serialize)]
-struct CriticalPathEntry<'a> {
-    kind: &'a str,
+pub(crate) struct CriticalPathEntry<'a> {
+    pub(crate) kind: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<String>,
+    pub(crate) name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    category: Option<&'a str>,
+    pub(crate) category: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    identifier: Option<&'a str>,
+    pub(crate) identifier: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    execution_kind: Option<&'a str>,
-    total_duration: OptionalDuration,
-    user_duration: OptionalDuration,
-    potential_improvement_duration: OptionalDuration,
+    pub(crate) execution_kind: Option<&'a str>,
+    pub(crate) total_duration: OptionalDuration,
+    pub(crate) user_duration: OptionalDuration,
+    pub(crate) potential_improvement_duration: OptionalDuration,
 }
 
-fn log_critical_path(
-    critical_path: &buck2_data::BuildGraphExecutionInfo,
-    format: LogCommandOutputFormat,
-) -> buck2_error::Result<()> {
-    let target_display_options = TargetDisplayOptions::for_log();
+/// Flatten an entry of the critical path. Returns `None` for entries we don't know how to display.
+pub(crate) fn critical_path_entry(
+    entry: &buck2_data::CriticalPathEntry2,
+    target_display_options: TargetDisplayOptions,
+) -> buck2_error::Result<Option<CriticalPathEntry<'_>>> {
+    use buck2_data::critical_path_entry2::Entry;
 
-    buck2_client_ctx::stdio::print_with_writer::<buck2_error::Error, _>(|w| {
-        let mut log_writer = transform_format(format, w);
+    let mut critical_path = CriticalPathEntry::default();
 
-        for entry in &critical_path.critical_path2 {
-            use buck2_data::critical_path_entry2::Entry;
+    match &entry.entry {
+        Some(Entry::Analysis(analysis)) => {
+            use buck2_data::critical_path_entry2::analysis::Target;
 
-            let mut critical_path = CriticalPathEntry::default();
+            critical_path.kind = "analysis";
 
-            match &entry.entry {
-                Some(Entry::Analysis(analysis)) => {
-                    use buck2_data::critical_path_entry2::analysis::Target;
+            critical_path.name = match &analysis.target {
+                Some(Target::StandardTarget(t)) => Some(display::display_configured_target_label(
+                    t,
+                    target_display_options,
+                )?),
+                None => return Ok(None),
+            };
+        }
+        Some(Entry::ActionExecution(action_execution)) => {
+            use buck2_data::critical_path_entry2::action_execution::Owner;
 
-                    critical_path.kind = "analysis";
+            critical_path.kind = "action";
 
-                    critical_path.name = match &analysis.target {
-                        Some(Target::StandardTarget(t)) => Some(
-                            display::display_configured_target_label(t, target_display_options)?,
-                        ),
-                        None => continue,
-                    };
+            critical_path.name = Some(match &action_execution.owner {
+                Some(Owner::TargetLabel(t)) => {
+                    display::display_configured_target_label(t, target_display_options)?
                 }
-                Some(Entry::ActionExecution(action_execution)) => {
-                    use buck2_data::critical_path_entry2::action_execution::Owner;
+                Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
+                Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
+                None => return Ok(None),
+            });
 
-                    critical_path.kind = "action";
+            match &action_execution.name {
+                Some(name) => {
+                    critical_path.category = Some(&name.category);
+                    critical_path.identifier = Some(&name.identifier);
+                }
+                None => {}
+            }
 
-                    critical_path.name = Some(match &action_execution.owner {
-                        Some(Owner::TargetLabel(t)) => {
-                            display::display_configured_target_label(t, target_display_options)?
-                        }
-                        Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
-                        Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
-                        None => continue,
-                    });
-
-                    match &action_execution.name {
-                        Some(name) => {
-                            critical_path.category = Some(&name.category);
-                            critical_path.identifier = Some(&name.identifier);
-                        }
-                        None => {}
-                    }
+            critical_path.execution_kind = Some(
+                buck2_data::ActionExecutionKind::from_i32(action_execution.execution_kind)
+                    .unwrap_or(buck2_data::ActionExecutionKind::NotSet)
+                    .as_str_name(),
+            );
+        }
+        Some(Entry::FinalMaterialization(materialization)) => {
+            use buck2_data::critical_path_entry2::final_materialization::Owner;
 
-                    critical_path.execution_kind = Some(
-                        buck2_data::ActionExecutionKind::from_i32(action_execution.execution_kind)
-                            .unwrap_or(buck2_data::ActionExecutionKind::NotSet)
-                            .as_str_name(),
-                    );
+            critical_path.kind = "materialization";
+
+            critical_path.name = Some(match &materialization.owner {
+                Some(Owner::TargetLabel(t)) => {
+                    display::display_configured_target_label(t, target_display_options)?
                 }
-                Some(Entry::FinalMaterialization(materialization)) => {
-                    use buck2_data::critical_path_entry2::final_materialization::Owner;
+                Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
+                Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
+                None => return Ok(None),
+            });
+
+            critical_path.identifier = Some(&materialization.path);
+        }
+        Some(Entry::ComputeCriticalPath(..)) => {
+            critical_path.kind = "compute-critical-path";
+            critical_path.name = None;
+        }
+        Some(Entry::Load(load)) => {
+            critical_path.kind = "load";
+            critical_path.name = Some(load.package.clone());
+        }
+        Some(Entry::Listing(listing)) => {
+            critical_path.kind = "listing";
+            critical_path.name = Some(listing.package.clone());
+        }
+        Some(Entry::GenericEntry(generic_entry)) => {
+            critical_path.kind = &generic_entry.kind;
+            critical_path.name = None;
+        }
 
-                    critical_path.kind = "materialization";
+        None => return Ok(None),
+    }
 
-                    critical_path.name = Some(match &materialization.owner {
-                        Some(Owner::TargetLabel(t)) => {
-                            display::display_configured_target_label(t, target_display_options)?
-                        }
-                        Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
-                        Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
-                        None => continue,
-                    });
+    critical_path.total_duration = OptionalDuration::new(entry.total_duration.clone())?;
+    critical_path.user_duration = OptionalDuration::new(entry.user_duration.clone())?;
+    critical_path.potential_improvement_duration =
+        OptionalDuration::new(entry.potential_improvement_duration.clone())?;
 
-                    critical_path.identifier = Some(&materialization.path);
-                }
-                Some(Entry::ComputeCriticalPath(..)) => {
-                    critical_path.kind = "compute-critical-path";
-                    critical_path.name = None;
-                }
-                Some(Entry::Load(load)) => {
-                    critical_path.kind = "load";
-                    critical_path.name = Some(load.package.clone());
-                }
-                Some(Entry::Listing(listing)) => {
-                    critical_path.kind = "listing";
-                    critical_path.name = Some(listing.package.clone());
-                }
-                Some(Entry::GenericEntry(generic_entry)) => {
-                    critical_path.kind = &generic_entry.kind;
-                    critical_path.name = None;
-                }
+    Ok(Some(critical_path))
+}
 
-                None => continue,
-            }
+fn log_critical_path(
+    critical_path: &buck2_data::BuildGraphExecutionInfo,
+    format: LogCommandOutputFormat,
+) -> buck2_error::Result<()> {
+    let target_display_options = TargetDisplayOptions::for_log();
 
-            critical_path.total_duration = OptionalDuration::new(entry.total_duration.clone())?;
-            critical_path.user_duration = OptionalDuration::new(entry.user_duration.clone())?;
-            critical_path.potential_improvement_duration =
-                OptionalDuration::new(entry.potential_improvement_duration.clone())?;
+    buck2_client_ctx::stdio::print_with_writer::<buck2_error::Error, _>(|w| {
+        let mut log_writer = transform_format(format, w);
+
+        for entry in &critical_path.critical_path2 {
+            let Some(critical_path) = critical_path_entry(entry, target_display_options)? else {
+                continue;
+            };
 
             let res: Result<(), ClientIoError> = {
                 match &mut log_writer {
@@ -0,0 +1,450 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::BuckArgMatches;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_common::convert::ProstDurationExt;
+use buck2_data::ActionExecutionKind;
+use buck2_data::ActionKey;
+use buck2_data::ActionName;
+use buck2_event_log::stream_value::StreamValue;
+use buck2_event_observer::action_util::get_action_digest;
+use buck2_event_observer::display::display_action_identity;
+use buck2_event_observer::display::TargetDisplayOptions;
+use futures::Stream;
+use futures::TryStreamExt;
+use linked_hash_map::LinkedHashMap;
+use serde::Serialize;
+
+use crate::commands::log::diff::diff_options::DiffEventLogOptions;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[clap(rename_all = "snake_case")]
+enum ActionsDiffFormat {
+    Human,
+    Json,
+}
+
+/// Compares two commands action by action.
+///
+/// Reports actions that were executed in one command but served from a cache in the other, actions
+/// whose digest changed (and whether that was because of their command or their inputs), actions
+/// that only ran in one of the commands, and how wall time moved for each action category.
+#[derive(Debug, clap::Parser)]
+pub struct ActionsDiffCommand {
+    #[clap(flatten)]
+    diff_event_log: DiffEventLogOptions,
+
+    /// Which output format to use for this command.
+    #[clap(long, default_value = "human", value_enum)]
+    format: ActionsDiffFormat,
+}
+
+#[derive(Clone, Debug)]
+struct ActionRecord {
+    name: Option<ActionName>,
+    execution_kind: ActionExecutionKind,
+    action_digest: Option<String>,
+    /// The argv and environment of the command, for actions that ran a command locally.
+    command: Option<(Vec<String>, Vec<(String, String)>)>,
+    /// The digest of the command's input directory.
+    input_digest: Option<String>,
+    wall_time: Duration,
+}
+
+impl ActionRecord {
+    fn new(end: &buck2_data::ActionExecutionEnd) -> buck2_error::Result<Self> {
+        use buck2_data::command_execution_kind::Command;
+
+        let command = end
+            .commands
+            .last()
+            .and_then(|c| c.details.as_ref())
+            .and_then(|d| d.command_kind.as_ref())
+            .and_then(|k| k.command.as_ref())
+            .and_then(|command| {
+                let (argv, env) = match command {
+                    Command::LocalCommand(c) => (&c.argv, &c.env),
+                    Command::WorkerCommand(c) => (&c.argv, &c.env),
+                    _ => return None,
+                };
+                let env = env
+                    .iter()
+                    .map(|e| (e.key.clone(), e.value.clone()))
+                    .collect();
+                Some((argv.clone(), env))
+            });
+
+        Ok(Self {
+            name: end.name.clone(),
+            execution_kind: ActionExecutionKind::from_i32(end.execution_kind)
+                .unwrap_or(ActionExecutionKind::NotSet),
+            action_digest: get_action_digest(&end.commands).filter(|d| !d.is_empty()),
+            command,
+            input_digest: end.input_digest.clone(),
+            wall_time: end
+                .wall_time
+                .as_ref()
+                .map(|d| d.try_into_duration())
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    fn category(&self) -> &str {
+        self.name
+            .as_ref()
+            .map_or("unknown", |n| n.category.as_str())
+    }
+
+    /// `Some(true)` if the action was served from a cache, `Some(false)` if it ran, and `None` if
+    /// it is not the kind of action that could have been cached.
+    fn cache_hit(&self) -> Option<bool> {
+        match self.execution_kind {
+            ActionExecutionKind::ActionCache
+            | ActionExecutionKind::LocalDepFile
+            | ActionExecutionKind::RemoteDepFileCache
+            | ActionExecutionKind::LocalActionCache
+            | ActionExecutionKind::LocalDiskCache => Some(true),
+            ActionExecutionKind::Local
+            | ActionExecutionKind::Remote
+            | ActionExecutionKind::LocalWorker => Some(false),
+            ActionExecutionKind::NotSet
+            | ActionExecutionKind::Simple
+            | ActionExecutionKind::Deferred => None,
+        }
+    }
+}
+
+async fn get_actions(
+    mut events: impl Stream<Item = buck2_error::Result<StreamValue>> + Unpin + Send,
+) -> buck2_error::Result<LinkedHashMap<ActionKey, ActionRecord>> {
+    let mut out = LinkedHashMap::new();
+
+    while let Some(event) = events.try_next().await? {
+        let StreamValue::Event(event) = event else {
+            continue;
+        };
+        if let Some(buck2_data::buck_event::Data::SpanEnd(buck2_data::SpanEndEvent {
+            data: Some(buck2_data::span_end_event::Data::ActionExecution(end)),
+            ..
+        })) = &event.data
+        {
+            if let Some(key) = &end.key {
+                out.insert(key.clone(), ActionRecord::new(end)?);
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct CacheChange {
+    action: String,
+    first: &'static str,
+    second: &'static str,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct DigestChange {
+    action: String,
+    first: String,
+    second: String,
+    /// Whether the command changed, if we know the command in both invocations.
+    command_changed: Option<bool>,
+    /// Whether the inputs changed, if we know the input digest in both invocations.
+    inputs_changed: Option<bool>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct CategoryWallTime {
+    category: String,
+    first_us: u64,
+    second_us: u64,
+    delta_us: i64,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct ActionsDiff {
+    cache_changes: Vec<CacheChange>,
+    digest_changes: Vec<DigestChange>,
+    only_in_first: Vec<String>,
+    only_in_second: Vec<String>,
+    wall_time_by_category: Vec<CategoryWallTime>,
+}
+
+fn display_action(key: &ActionKey, record: &ActionRecord) -> buck2_error::Result<String> {
+    display_action_identity(
+        Some(key),
+        record.name.as_ref(),
+        TargetDisplayOptions::for_log(),
+    )
+}
+
+fn diff_actions(
+    first: &LinkedHashMap<ActionKey, ActionRecord>,
+    second: &LinkedHashMap<ActionKey, ActionRecord>,
+) -> buck2_error::Result<ActionsDiff> {
+    let mut diff = ActionsDiff::default();
+    let mut wall_times: BTreeMap<&str, (Duration, Duration)> = BTreeMap::new();
+
+    for (key, a) in first {
+        wall_times.entry(a.category()).or_default().0 += a.wall_time;
+
+        let Some(b) = second.get(key) else {
+            diff.only_in_first.push(display_action(key, a)?);
+            continue;
+        };
+
+        if let (Some(a_hit), Some(b_hit)) = (a.cache_hit(), b.cache_hit()) {
+            if a_hit != b_hit {
+                diff.cache_changes.push(CacheChange {
+                    action: display_action(key, b)?,
+                    first: a.execution_kind.as_str_name(),
+                    second: b.execution_kind.as_str_name(),
+                });
+            }
+        }
+
+        if let (Some(a_digest), Some(b_digest)) = (&a.action_digest, &b.action_digest) {
+            if a_digest != b_digest {
+                let command_changed = match (&a.command, &b.command) {
+                    (Some(a), Some(b)) => Some(a != b),
+                    _ => None,
+                };
+                let inputs_changed = match (&a.input_digest, &b.input_digest) {
+                    (Some(a), Some(b)) => Some(a != b),
+                    _ => None,
+                };
+                diff.digest_changes.push(DigestChange {
+                    action: display_action(key, b)?,
+                    first: a_digest.clone(),
+                    second: b_digest.clone(),
+                    command_changed,
+                    inputs_changed,
+                });
+            }
+        }
+    }
+
+    for (key, b) in second {
+        wall_times.entry(b.category()).or_default().1 += b.wall_time;
+        if !first.contains_key(key) {
+            diff.only_in_second.push(display_action(key, b)?);
+        }
+    }
+
+    diff.wall_time_by_category = wall_times
+        .into_iter()
+        .map(|(category, (first, second))| {
+            let first_us = first.as_micros() as u64;
+            let second_us = second.as_micros() as u64;
+            CategoryWallTime {
+                category: category.to_owned(),
+                first_us,
+                second_us,
+                delta_us: second_us as i64 - first_us as i64,
+            }
+        })
+        .collect();
+    // Biggest movers first.
+    diff.wall_time_by_category
+        .sort_by_key(|c| std::cmp::Reverse(c.delta_us.unsigned_abs()));
+
+    Ok(diff)
+}
+
+fn print_human(diff: &ActionsDiff) -> buck2_error::Result<()> {
+    let mut output = Vec::new();
+
+    output.push(format!("{:-^44}", "Cache Hit Changes"));
+    for c in &diff.cache_changes {
+        output.push(format!(
+            "{}\n  first: {} \t second: {}",
+            c.action, c.first, c.second
+        ));
+    }
+
+    output.push(format!("{:-^44}", "Action Digest Changes"));
+    for c in &diff.digest_changes {
+        let mut reasons = Vec::new();
+        if c.command_changed == Some(true) {
+            reasons.push("command");
+        }
+        if c.inputs_changed == Some(true) {
+            reasons.push("inputs");
+        }
+        if reasons.is_empty() {
+            reasons.push("unknown");
+        }
+        output.push(format!(
+            "{} (changed: {})\n  first: {} \t second: {}",
+            c.action,
+            reasons.join(", "),
+            c.first,
+            c.second
+        ));
+    }
+
+    output.push(format!("{:-^44}", "Only In First"));
+    output.extend(diff.only_in_first.iter().cloned());
+    output.push(format!("{:-^44}", "Only In Second"));
+    output.extend(diff.only_in_second.iter().cloned());
+
+    output.push(format!("{:-^44}", "Wall Time By Category (us)"));
+    for c in &diff.wall_time_by_category {
+        output.push(format!(
+            "{}\tfirst: {}\tsecond: {}\tdelta: {:+}",
+            c.category, c.first_us, c.second_us, c.delta_us
+        ));
+    }
+
+    buck2_client_ctx::println!("{}", output.join("\n"))?;
+    Ok(())
+}
+
+impl ActionsDiffCommand {
+    pub fn exec(self, _matches: BuckArgMatches<'_>, ctx: ClientCommandContext<'_>) -> ExitResult {
+        ctx.instant_command_no_log("log-diff-actions", |ctx| async move {
+            let (log_path1, log_path2) = self.diff_event_log.get(&ctx).await?;
+
+            let (_invocation1, events1) = log_path1.unpack_stream().await?;
+            let (_invocation2, events2) = log_path2.unpack_stream().await?;
+
+            let actions1 = get_actions(events1).await?;
+            let actions2 = get_actions(events2).await?;
+            let diff = diff_actions(&actions1, &actions2)?;
+
+            match self.format {
+                ActionsDiffFormat::Human => print_human(&diff)?,
+                ActionsDiffFormat::Json => {
+                    buck2_client_ctx::println!("{}", serde_json::to_string_pretty(&diff)?)?
+                }
+            }
+            buck2_error::Ok(())
+        })
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: &str) -> ActionKey {
+        ActionKey {
+            id: id.as_bytes().to_vec(),
+            owner: Some(buck2_data::action_key::Owner::TargetLabel(
+                buck2_data::ConfiguredTargetLabel {
+                    label: Some(buck2_data::TargetLabel {
+                        package: "root//foo".to_owned(),
+                        name: "bar".to_owned(),
+                    }),
+                    configuration: Some(buck2_data::Configuration {
+                        full_name: "cfg".to_owned(),
+                    }),
+                    execution_configuration: None,
+                },
+            )),
+            key: String::new(),
+        }
+    }
+
+    fn record(
+        kind: ActionExecutionKind,
+        digest: &str,
+        argv: &str,
+        input_digest: &str,
+        wall_time_ms: u64,
+    ) -> ActionRecord {
+        ActionRecord {
+            name: Some(ActionName {
+                category: "cxx_compile".to_owned(),
+                identifier: "bar.cpp".to_owned(),
+            }),
+            execution_kind: kind,
+            action_digest: Some(digest.to_owned()),
+            command: Some((vec![argv.to_owned()], Vec::new())),
+            input_digest: Some(input_digest.to_owned()),
+            wall_time: Duration::from_millis(wall_time_ms),
+        }
+    }
+
+    #[test]
+    fn test_diff_actions() -> buck2_error::Result<()> {
+        let mut first = LinkedHashMap::new();
+        first.insert(
+            key("a"),
+            record(ActionExecutionKind::Local, "d1", "cc", "i1", 10),
+        );
+        first.insert(
+            key("b"),
+            record(ActionExecutionKind::Local, "d2", "cc", "i2", 10),
+        );
+        first.insert(
+            key("c"),
+            record(ActionExecutionKind::Local, "d3", "cc", "i3", 10),
+        );
+        first.insert(
+            key("e"),
+            record(ActionExecutionKind::Local, "d5", "cc", "i5", 0),
+        );
+
+        let mut second = LinkedHashMap::new();
+        second.insert(
+            key("a"),
+            record(ActionExecutionKind::ActionCache, "d1", "cc", "i1", 1),
+        );
+        second.insert(
+            key("b"),
+            record(ActionExecutionKind::Local, "d2'", "cc", "i2'", 30),
+        );
+        second.insert(
+            key("d"),
+            record(ActionExecutionKind::Local, "d4", "cc", "i4", 10),
+        );
+        second.insert(
+            key("e"),
+            record(ActionExecutionKind::Local, "d5'", "c++", "i5", 0),
+        );
+
+        let diff = diff_actions(&first, &second)?;
+
+        assert_eq!(diff.cache_changes.len(), 1);
+        assert_eq!(diff.cache_changes[0].first, "ACTION_EXECUTION_KIND_LOCAL");
+        assert_eq!(
+            diff.cache_changes[0].second,
+            "ACTION_EXECUTION_KIND_ACTION_CACHE"
+        );
+
+        assert_eq!(diff.digest_changes.len(), 2);
+        assert_eq!(diff.digest_changes[0].command_changed, Some(false));
+        assert_eq!(diff.digest_changes[0].inputs_changed, Some(true));
+        assert_eq!(diff.digest_changes[1].command_changed, Some(true));
+        assert_eq!(diff.digest_changes[1].inputs_changed, Some(false));
+
+        assert_eq!(diff.only_in_first.len(), 1);
+        assert_eq!(diff.only_in_second.len(), 1);
+
+        assert_eq!(
+            diff.wall_time_by_category,
+            vec![CategoryWallTime {
+                category: "cxx_compile".to_owned(),
+                first_us: 30_000,
+                second_us: 41_000,
+                delta_us: 11_000,
+            }]
+        );
+        Ok(())
+    }
+}
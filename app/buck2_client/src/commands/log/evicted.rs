@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::io::Write;
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::BuckArgMatches;
+use buck2_client_ctx::exit_result::ClientIoError;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_event_log::retention::read_evicted_logs;
+use buck2_event_log::retention::EvictedLog;
+use buck2_event_log::retention::EvictionReason;
+
+use crate::commands::log::transform_format;
+use crate::commands::log::LogCommandOutputFormat;
+use crate::commands::log::LogCommandOutputFormatWithWriter;
+
+/// Lists event logs that were removed by the log retention policy.
+///
+/// Retention is configured with `buck2.event_log_retention_max_total_mb` and
+/// `buck2.event_log_retention_max_age_hours`. The output is a tab-separated list containing
+/// the eviction time, the reason, the size of the log and its file name.
+#[derive(Debug, clap::Parser)]
+pub struct EvictedCommand {
+    #[clap(
+        long = "format",
+        help = "Which output format to use for this command",
+        default_value = "tabulated",
+        ignore_case = true,
+        value_enum
+    )]
+    output: LogCommandOutputFormat,
+}
+
+fn write_output(
+    output: &mut LogCommandOutputFormatWithWriter,
+    record: &EvictedLog,
+) -> Result<(), ClientIoError> {
+    match output {
+        LogCommandOutputFormatWithWriter::Tabulated(w) => {
+            let reason = match record.reason {
+                EvictionReason::Age => "age",
+                EvictionReason::TotalSize => "total_size",
+            };
+            Ok(writeln!(
+                w,
+                "{}\t{}\t{}\t{}",
+                record.evicted_at, reason, record.size_bytes, record.file_name
+            )?)
+        }
+        LogCommandOutputFormatWithWriter::Csv(writer) => Ok(writer.serialize(record)?),
+        LogCommandOutputFormatWithWriter::Json(w) => {
+            serde_json::to_writer(w.by_ref(), &record)?;
+            w.write_all("\n".as_bytes())?;
+            Ok(())
+        }
+    }
+}
+
+impl EvictedCommand {
+    pub fn exec(self, _matches: BuckArgMatches<'_>, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let Self { output } = self;
+        buck2_client_ctx::stdio::print_with_writer::<buck2_error::Error, _>(|w| {
+            let mut output = transform_format(output, w);
+            ctx.instant_command_no_log("log-evicted", |ctx| async move {
+                let records = read_evicted_logs(&ctx.paths()?.log_evictions_path())?;
+                for record in &records {
+                    write_output(&mut output, record)?;
+                }
+                buck2_error::Ok(())
+            })
+        })?;
+        ExitResult::success()
+    }
+}
@@ -0,0 +1,657 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::BuckArgMatches;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::path_arg::PathArg;
+use buck2_common::convert::ProstDurationExt;
+use buck2_common::sqlite::KeyValueSqliteTable;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
+use buck2_error::BuckErrorContext;
+use buck2_event_log::stream_value::StreamValue;
+use buck2_event_log::utils::Invocation;
+use buck2_event_observer::action_util::get_action_digest;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_events::span::SpanId;
+use buck2_events::BuckEvent;
+use dupe::Dupe;
+use gazebo::variants::VariantName;
+use parking_lot::Mutex;
+use rusqlite::params;
+use rusqlite::Connection;
+use tokio_stream::StreamExt;
+
+use crate::commands::log::critical_path::critical_path_entry;
+use crate::commands::log::options::EventLogOptions;
+
+#[derive(Debug, buck2_error::Error)]
+#[buck2(tag = Input)]
+enum ExportSqliteError {
+    #[error("Output path `{}` is a directory, expected a file", _0.display())]
+    OutputIsDirectory(AbsPathBuf),
+}
+
+/// Export a selected event log to a SQLite database, for analysis with SQL.
+///
+/// The database has the following tables:
+///
+/// * `invocation`: the `command_line`, `working_dir` and `trace_id`, as key-value pairs.
+///
+/// * `spans`: every span, with its parent, kind and start and end times.
+///
+/// * `actions`: every action that was executed, keyed by the id of its span.
+///
+/// * `commands`: the commands run by each action, in the order they were attempted.
+///
+/// * `materializations`: materializations of action outputs.
+///
+/// * `test_results`: the result of each test.
+///
+/// * `dice_key_states`: snapshots of the state of DICE keys, by key type.
+///
+/// * `critical_path`: the entries on the critical path, in order.
+///
+/// Times are in microseconds; timestamps are relative to the UNIX epoch.
+#[derive(Debug, clap::Parser)]
+pub struct ExportSqliteCommand {
+    #[clap(flatten)]
+    event_log: EventLogOptions,
+
+    /// Where to write the database. Any existing file at this path is replaced.
+    #[clap(long, short = 'o', value_name = "PATH")]
+    output: PathArg,
+}
+
+impl ExportSqliteCommand {
+    pub fn exec(self, _matches: BuckArgMatches<'_>, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let Self { event_log, output } = self;
+
+        ctx.instant_command_no_log("log-export-sqlite", |ctx| async move {
+            let log_path = event_log.get(&ctx).await?;
+            let output = output.resolve(&ctx.working_dir);
+
+            let (invocation, mut events) = log_path.unpack_stream().await?;
+
+            if let Some(metadata) = fs_util::symlink_metadata_if_exists(&output)? {
+                if metadata.is_dir() {
+                    return Err(ExportSqliteError::OutputIsDirectory(output).into());
+                }
+                fs_util::remove_file(&output)?;
+            }
+            let connection = Arc::new(Mutex::new(Connection::open(&output)?));
+            // Everything is written in one transaction, committing every row is very slow.
+            connection.lock().execute_batch("BEGIN")?;
+            let mut exporter = SqliteExporter::new(connection.dupe(), &invocation)?;
+            while let Some(event) = events.try_next().await? {
+                if let StreamValue::Event(event) = event {
+                    exporter.handle_event(&BuckEvent::try_from(event)?)?;
+                }
+            }
+            exporter.finish()?;
+            connection
+                .lock()
+                .execute_batch("COMMIT")
+                .with_buck_error_context(|| format!("writing `{}`", output.display()))?;
+
+            buck2_error::Ok(())
+        })
+        .into()
+    }
+}
+
+const INVOCATION_TABLE_NAME: &str = "invocation";
+
+const SCHEMA: &str = "
+CREATE TABLE spans (
+    span_id INTEGER PRIMARY KEY,
+    parent_id INTEGER,
+    kind TEXT NOT NULL,
+    description TEXT,
+    start_us INTEGER NOT NULL,
+    end_us INTEGER,
+    duration_us INTEGER
+);
+CREATE TABLE actions (
+    span_id INTEGER NOT NULL,
+    owner TEXT,
+    category TEXT,
+    identifier TEXT,
+    kind TEXT NOT NULL,
+    execution_kind TEXT NOT NULL,
+    failed INTEGER NOT NULL,
+    action_digest TEXT,
+    wall_time_us INTEGER,
+    output_size INTEGER NOT NULL,
+    input_files_bytes INTEGER
+);
+CREATE TABLE commands (
+    action_span_id INTEGER NOT NULL,
+    attempt INTEGER NOT NULL,
+    executor TEXT,
+    status TEXT,
+    exit_code INTEGER,
+    wall_time_us INTEGER,
+    action_digest TEXT,
+    argv TEXT
+);
+CREATE TABLE materializations (
+    span_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    action_digest TEXT,
+    method TEXT,
+    file_count INTEGER NOT NULL,
+    total_bytes INTEGER NOT NULL,
+    success INTEGER NOT NULL,
+    error TEXT,
+    duration_us INTEGER
+);
+CREATE TABLE test_results (
+    name TEXT NOT NULL,
+    target TEXT,
+    status TEXT NOT NULL,
+    duration_us INTEGER,
+    max_memory_used_bytes INTEGER
+);
+CREATE TABLE dice_key_states (
+    timestamp_us INTEGER NOT NULL,
+    key_type TEXT NOT NULL,
+    started INTEGER NOT NULL,
+    finished INTEGER NOT NULL,
+    check_deps_started INTEGER NOT NULL,
+    check_deps_finished INTEGER NOT NULL,
+    compute_started INTEGER NOT NULL,
+    compute_finished INTEGER NOT NULL
+);
+CREATE TABLE critical_path (
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT,
+    category TEXT,
+    identifier TEXT,
+    execution_kind TEXT,
+    total_duration_us INTEGER,
+    user_duration_us INTEGER,
+    potential_improvement_duration_us INTEGER
+);
+";
+
+fn micros(d: Duration) -> i64 {
+    d.as_micros() as i64
+}
+
+fn timestamp_us(time: SystemTime) -> i64 {
+    micros(time.duration_since(UNIX_EPOCH).unwrap_or_default())
+}
+
+fn prost_micros(d: Option<&prost_types::Duration>) -> buck2_error::Result<Option<i64>> {
+    Ok(d.map(|d| d.try_into_duration()).transpose()?.map(micros))
+}
+
+fn command_executor(command: &buck2_data::command_execution_kind::Command) -> &'static str {
+    use buck2_data::command_execution_kind::Command;
+
+    match command {
+        Command::LocalCommand(..) | Command::OmittedLocalCommand(..) => "local",
+        Command::RemoteCommand(..) => "remote",
+        Command::WorkerInitCommand(..) => "worker_init",
+        Command::WorkerCommand(..) => "worker",
+        Command::LocalDiskCacheHit(..) => "local_disk_cache",
+    }
+}
+
+fn command_status(status: &buck2_data::command_execution::Status) -> &'static str {
+    use buck2_data::command_execution::Status;
+
+    match status {
+        Status::Success(..) => "success",
+        Status::Failure(..) => "failure",
+        Status::Timeout(..) => "timeout",
+        Status::Error(..) => "error",
+        Status::Cancelled(..) => "cancelled",
+        Status::WorkerFailure(..) => "worker_failure",
+    }
+}
+
+struct OpenSpan {
+    parent_id: Option<SpanId>,
+    kind: &'static str,
+    description: Option<String>,
+    start: SystemTime,
+}
+
+struct SqliteExporter {
+    connection: Arc<Mutex<Connection>>,
+    open_spans: HashMap<SpanId, OpenSpan>,
+}
+
+impl SqliteExporter {
+    fn new(
+        connection: Arc<Mutex<Connection>>,
+        invocation: &Invocation,
+    ) -> buck2_error::Result<Self> {
+        let invocation_table =
+            KeyValueSqliteTable::new(INVOCATION_TABLE_NAME.to_owned(), connection.dupe());
+        invocation_table.create_table()?;
+        invocation_table.insert_all(HashMap::from([
+            ("trace_id".to_owned(), invocation.trace_id.to_string()),
+            ("command_line".to_owned(), invocation.display_command_line()),
+            ("working_dir".to_owned(), invocation.working_dir.clone()),
+        ]))?;
+        connection
+            .lock()
+            .execute_batch(SCHEMA)
+            .buck_error_context("creating sqlite tables")?;
+        Ok(Self {
+            connection,
+            open_spans: HashMap::new(),
+        })
+    }
+
+    fn handle_event(&mut self, event: &BuckEvent) -> buck2_error::Result<()> {
+        match event.data() {
+            buck2_data::buck_event::Data::SpanStart(buck2_data::SpanStartEvent {
+                data: Some(start),
+            }) => {
+                if let Some(span_id) = event.span_id() {
+                    self.open_spans.insert(
+                        span_id,
+                        OpenSpan {
+                            parent_id: event.parent_id(),
+                            kind: start.variant_name(),
+                            description: display::display_event(
+                                event,
+                                TargetDisplayOptions::for_log(),
+                            )
+                            .ok(),
+                            start: event.timestamp(),
+                        },
+                    );
+                }
+            }
+            buck2_data::buck_event::Data::SpanEnd(end) => {
+                let Some(span_id) = event.span_id() else {
+                    return Ok(());
+                };
+                if let Some(open) = self.open_spans.remove(&span_id) {
+                    self.insert_span(span_id, open, Some(event.timestamp()))?;
+                }
+                match &end.data {
+                    Some(buck2_data::span_end_event::Data::ActionExecution(action)) => {
+                        self.insert_action(span_id, action)?;
+                    }
+                    Some(buck2_data::span_end_event::Data::Materialization(materialization)) => {
+                        self.insert_materialization(
+                            span_id,
+                            materialization,
+                            end.duration.as_ref(),
+                        )?;
+                    }
+                    _ => {}
+                }
+            }
+            buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                data: Some(instant),
+            }) => match instant {
+                buck2_data::instant_event::Data::TestResult(result) => {
+                    self.insert_test_result(result)?;
+                }
+                buck2_data::instant_event::Data::DiceStateSnapshot(snapshot) => {
+                    self.insert_dice_key_states(event.timestamp(), snapshot)?;
+                }
+                buck2_data::instant_event::Data::BuildGraphInfo(info) => {
+                    self.insert_critical_path(info)?;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Record the spans that never ended, for example because the command was interrupted.
+    fn finish(mut self) -> buck2_error::Result<()> {
+        for (span_id, open) in std::mem::take(&mut self.open_spans) {
+            self.insert_span(span_id, open, None)?;
+        }
+        Ok(())
+    }
+
+    fn insert_span(
+        &self,
+        span_id: SpanId,
+        open: OpenSpan,
+        end: Option<SystemTime>,
+    ) -> buck2_error::Result<()> {
+        let duration = end.map(|end| micros(end.duration_since(open.start).unwrap_or_default()));
+        self.connection
+            .lock()
+            .prepare_cached("INSERT INTO spans VALUES (?, ?, ?, ?, ?, ?, ?)")?
+            .execute(params![
+                u64::from(span_id) as i64,
+                open.parent_id.map(|p| u64::from(p) as i64),
+                open.kind,
+                open.description,
+                timestamp_us(open.start),
+                end.map(timestamp_us),
+                duration,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_action(
+        &self,
+        span_id: SpanId,
+        action: &buck2_data::ActionExecutionEnd,
+    ) -> buck2_error::Result<()> {
+        let span_id = u64::from(span_id) as i64;
+        let owner = action
+            .key
+            .as_ref()
+            .map(|key| display::display_action_key(key, TargetDisplayOptions::for_log()))
+            .transpose()?;
+        let connection = self.connection.lock();
+        connection
+            .prepare_cached("INSERT INTO actions VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?
+            .execute(params![
+                span_id,
+                owner,
+                action.name.as_ref().map(|n| &n.category),
+                action.name.as_ref().map(|n| &n.identifier),
+                buck2_data::ActionKind::from_i32(action.kind)
+                    .unwrap_or(buck2_data::ActionKind::NotSet)
+                    .as_str_name(),
+                buck2_data::ActionExecutionKind::from_i32(action.execution_kind)
+                    .unwrap_or(buck2_data::ActionExecutionKind::NotSet)
+                    .as_str_name(),
+                action.failed,
+                get_action_digest(&action.commands),
+                prost_micros(action.wall_time.as_ref())?,
+                action.output_size as i64,
+                action.input_files_bytes.map(|b| b as i64),
+            ])?;
+
+        for (attempt, command) in action.commands.iter().enumerate() {
+            let details = command.details.as_ref();
+            let kind = details
+                .and_then(|d| d.command_kind.as_ref())
+                .and_then(|k| k.command.as_ref());
+            let argv = match kind {
+                Some(buck2_data::command_execution_kind::Command::LocalCommand(c)) => Some(&c.argv),
+                Some(buck2_data::command_execution_kind::Command::WorkerInitCommand(c)) => {
+                    Some(&c.argv)
+                }
+                Some(buck2_data::command_execution_kind::Command::WorkerCommand(c)) => {
+                    Some(&c.argv)
+                }
+                _ => None,
+            };
+            connection
+                .prepare_cached("INSERT INTO commands VALUES (?, ?, ?, ?, ?, ?, ?, ?)")?
+                .execute(params![
+                    span_id,
+                    attempt as i64,
+                    kind.map(command_executor),
+                    command.status.as_ref().map(command_status),
+                    details.and_then(|d| d.signed_exit_code),
+                    prost_micros(
+                        details
+                            .and_then(|d| d.metadata.as_ref())
+                            .and_then(|m| m.wall_time.as_ref())
+                    )?,
+                    get_action_digest(std::slice::from_ref(command)),
+                    argv.map(serde_json::to_string).transpose()?,
+                ])?;
+        }
+        Ok(())
+    }
+
+    fn insert_materialization(
+        &self,
+        span_id: SpanId,
+        materialization: &buck2_data::MaterializationEnd,
+        duration: Option<&prost_types::Duration>,
+    ) -> buck2_error::Result<()> {
+        let method = materialization
+            .method
+            .and_then(buck2_data::MaterializationMethod::from_i32)
+            .map(|m| m.as_str_name());
+        self.connection
+            .lock()
+            .prepare_cached("INSERT INTO materializations VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?
+            .execute(params![
+                u64::from(span_id) as i64,
+                materialization.path,
+                materialization.action_digest,
+                method,
+                materialization.file_count as i64,
+                materialization.total_bytes as i64,
+                materialization.success,
+                materialization.error,
+                prost_micros(duration)?,
+            ])?;
+        Ok(())
+    }
+
+    fn insert_test_result(&self, result: &buck2_data::TestResult) -> buck2_error::Result<()> {
+        let target = result
+            .target_label
+            .as_ref()
+            .map(|t| display::display_configured_target_label(t, TargetDisplayOptions::for_log()))
+            .transpose()?;
+        self.connection
+            .lock()
+            .prepare_cached("INSERT INTO test_results VALUES (?, ?, ?, ?, ?)")?
+            .execute(params![
+                result.name,
+                target,
+                buck2_data::TestStatus::from_i32(result.status)
+                    .unwrap_or(buck2_data::TestStatus::NotSetTestStatus)
+                    .as_str_name(),
+                prost_micros(result.duration.as_ref())?,
+                result.max_memory_used_bytes.map(|b| b as i64),
+            ])?;
+        Ok(())
+    }
+
+    fn insert_dice_key_states(
+        &self,
+        timestamp: SystemTime,
+        snapshot: &buck2_data::DiceStateSnapshot,
+    ) -> buck2_error::Result<()> {
+        let connection = self.connection.lock();
+        let mut statement = connection
+            .prepare_cached("INSERT INTO dice_key_states VALUES (?, ?, ?, ?, ?, ?, ?, ?)")?;
+        for (key_type, state) in &snapshot.key_states {
+            statement.execute(params![
+                timestamp_us(timestamp),
+                key_type,
+                state.started,
+                state.finished,
+                state.check_deps_started,
+                state.check_deps_finished,
+                state.compute_started,
+                state.compute_finished,
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn insert_critical_path(
+        &self,
+        info: &buck2_data::BuildGraphExecutionInfo,
+    ) -> buck2_error::Result<()> {
+        let connection = self.connection.lock();
+        let mut statement = connection
+            .prepare_cached("INSERT INTO critical_path VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+        let entries = info
+            .critical_path2
+            .iter()
+            .filter_map(|e| critical_path_entry(e, TargetDisplayOptions::for_log()).transpose());
+        for (position, entry) in entries.enumerate() {
+            let entry = entry?;
+            statement.execute(params![
+                position as i64,
+                entry.kind,
+                entry.name,
+                entry.category,
+                entry.identifier,
+                entry.execution_kind,
+                entry.total_duration.inner.map(micros),
+                entry.user_duration.inner.map(micros),
+                entry.potential_improvement_duration.inner.map(micros),
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use super::*;
+
+    fn event(
+        secs: u64,
+        span_id: u64,
+        parent_id: u64,
+        data: impl Into<buck2_data::buck_event::Data>,
+    ) -> BuckEvent {
+        BuckEvent::new(
+            UNIX_EPOCH + Duration::from_secs(secs),
+            TraceId::null(),
+            SpanId::from_u64_opt(span_id),
+            SpanId::from_u64_opt(parent_id),
+            data.into(),
+        )
+    }
+
+    #[test]
+    fn test_export() -> buck2_error::Result<()> {
+        let connection = Arc::new(Mutex::new(Connection::open_in_memory()?));
+        let invocation = Invocation {
+            command_line_args: vec!["buck2".to_owned(), "build".to_owned()],
+            expanded_command_line_args: Vec::new(),
+            working_dir: "/repo".to_owned(),
+            trace_id: TraceId::null(),
+        };
+
+        let mut exporter = SqliteExporter::new(connection.dupe(), &invocation)?;
+        exporter.handle_event(&event(
+            1,
+            1,
+            0,
+            buck2_data::SpanStartEvent {
+                data: Some(buck2_data::CommandStart::default().into()),
+            },
+        ))?;
+        exporter.handle_event(&event(
+            2,
+            2,
+            1,
+            buck2_data::SpanStartEvent {
+                data: Some(buck2_data::ActionExecutionStart::default().into()),
+            },
+        ))?;
+        exporter.handle_event(&event(
+            4,
+            2,
+            1,
+            buck2_data::SpanEndEvent {
+                data: Some(
+                    buck2_data::ActionExecutionEnd {
+                        execution_kind: buck2_data::ActionExecutionKind::Local as i32,
+                        commands: vec![buck2_data::CommandExecution {
+                            details: Some(buck2_data::CommandExecutionDetails {
+                                command_kind: Some(buck2_data::CommandExecutionKind {
+                                    command: Some(
+                                        buck2_data::command_execution_kind::Command::LocalCommand(
+                                            buck2_data::LocalCommand {
+                                                argv: vec!["cc".to_owned()],
+                                                env: Vec::new(),
+                                                action_digest: "abc:1".to_owned(),
+                                            },
+                                        ),
+                                    ),
+                                }),
+                                ..Default::default()
+                            }),
+                            status: Some(buck2_data::command_execution::Status::Success(
+                                buck2_data::command_execution::Success {},
+                            )),
+                        }],
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            },
+        ))?;
+        exporter.finish()?;
+
+        let working_dir =
+            KeyValueSqliteTable::new(INVOCATION_TABLE_NAME.to_owned(), connection.dupe())
+                .get("working_dir")?;
+        assert_eq!(working_dir.as_deref(), Some("/repo"));
+
+        let connection = connection.lock();
+        let spans: Vec<(i64, Option<i64>, String, Option<i64>)> = connection
+            .prepare("SELECT span_id, parent_id, kind, duration_us FROM spans ORDER BY span_id")?
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            spans,
+            vec![
+                (1, None, "Command".to_owned(), None),
+                (2, Some(1), "ActionExecution".to_owned(), Some(2_000_000)),
+            ]
+        );
+
+        let command: (i64, String, String, String, String) = connection.query_row(
+            "SELECT action_span_id, executor, status, action_digest, argv FROM commands",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )?;
+        assert_eq!(
+            command,
+            (
+                2,
+                "local".to_owned(),
+                "success".to_owned(),
+                "abc:1".to_owned(),
+                "[\"cc\"]".to_owned()
+            )
+        );
+
+        let execution_kind: String =
+            connection.query_row("SELECT execution_kind FROM actions", [], |row| row.get(0))?;
+        assert_eq!(execution_kind, "ACTION_EXECUTION_KIND_LOCAL");
+
+        Ok(())
+    }
+}
@@ -20,6 +20,8 @@ use buck2_error::BuckErrorContext;
 use buck2_event_log::file_names::find_log_by_trace_id;
 use buck2_event_log::file_names::retrieve_nth_recent_log;
 use buck2_event_log::read::EventLogPathBuf;
+use buck2_event_log::retention::read_evicted_logs;
+use buck2_event_log::retention::EvictedLog;
 use buck2_event_log::utils::Encoding;
 use buck2_util::indent::indent;
 use buck2_util::process::async_background_command;
@@ -34,6 +36,13 @@ enum EventLogOptionsError {
     ManifoldFailed(String),
     #[error("Log not found locally by trace id `{0}`")]
     LogNotFoundLocally(TraceId),
+    #[error(
+        "Log for trace id `{trace_id}` was evicted by the log retention policy at {evicted_at} (see `buck2 log evicted`)"
+    )]
+    LogEvicted {
+        trace_id: TraceId,
+        evicted_at: String,
+    },
 }
 
 #[derive(Debug, Clone, clap::Parser)]
@@ -60,6 +69,16 @@ pub(crate) struct EventLogOptions {
     pub(crate) path: Option<PathArg>,
 }
 
+fn find_evicted_log(
+    ctx: &ClientCommandContext<'_>,
+    trace_id: &TraceId,
+) -> buck2_error::Result<Option<EvictedLog>> {
+    let trace_id = trace_id.to_string();
+    Ok(read_evicted_logs(&ctx.paths()?.log_evictions_path())?
+        .into_iter()
+        .rfind(|log| log.file_name.contains(&trace_id)))
+}
+
 impl EventLogOptions {
     pub(crate) async fn get(
         &self,
@@ -70,6 +89,19 @@ impl EventLogOptions {
         } else if let Some(id) = &self.trace_id {
             if let Some(log_path) = find_log_by_trace_id(&ctx.paths()?.log_dir(), id)? {
                 Ok(log_path)
+            } else if let Some(evicted) = find_evicted_log(ctx, id)? {
+                if self.no_remote {
+                    return Err(EventLogOptionsError::LogEvicted {
+                        trace_id: id.dupe(),
+                        evicted_at: evicted.evicted_at,
+                    }
+                    .into());
+                }
+                buck2_client_ctx::eprintln!(
+                    "Log was evicted locally at {}, trying to download it",
+                    evicted.evicted_at
+                )?;
+                EventLogPathBuf::infer(self.download_remote_id(id, ctx).await?)
             } else if !self.no_remote {
                 EventLogPathBuf::infer(self.download_remote_id(id, ctx).await?)
             } else {
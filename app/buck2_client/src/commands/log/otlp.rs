@@ -0,0 +1,430 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Export an event log as an OpenTelemetry trace, in the OTLP JSON encoding.
+//!
+//! See <https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding>. Notably, trace and span
+//! ids are hex strings rather than base64, and 64-bit integers are encoded as strings.
+
+use std::collections::HashMap;
+use std::io::BufWriter;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::BuckArgMatches;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::path_arg::PathArg;
+use buck2_core::fs::fs_util;
+use buck2_event_log::stream_value::StreamValue;
+use buck2_event_log::utils::Invocation;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_events::span::SpanId;
+use buck2_events::BuckEvent;
+use gazebo::variants::VariantName;
+use serde::Serialize;
+use tokio_stream::StreamExt;
+
+use crate::commands::log::options::EventLogOptions;
+
+/// Export the spans of a selected command as an OpenTelemetry trace.
+///
+/// This writes a file in the OTLP JSON encoding that can be imported by any OpenTelemetry
+/// collector. The command is a single trace, and every span in the event log becomes a span in
+/// that trace, with the same parent/child relationships.
+#[derive(Debug, clap::Parser)]
+pub struct OtlpCommand {
+    #[clap(flatten)]
+    event_log: EventLogOptions,
+
+    /// Where to write the trace.
+    #[clap(long, short = 'o', value_name = "PATH")]
+    output: PathArg,
+}
+
+impl OtlpCommand {
+    pub fn exec(self, _matches: BuckArgMatches<'_>, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let Self { event_log, output } = self;
+
+        ctx.instant_command_no_log("log-otlp", |ctx| async move {
+            let log_path = event_log.get(&ctx).await?;
+            let output = output.resolve(&ctx.working_dir);
+
+            let (invocation, mut events) = log_path.unpack_stream().await?;
+            let mut writer = OtlpWriter::new();
+            while let Some(event) = events.try_next().await? {
+                if let StreamValue::Event(event) = event {
+                    writer.handle_event(&BuckEvent::try_from(event)?)?;
+                }
+            }
+
+            let file = fs_util::create_file(&output)?;
+            serde_json::to_writer(BufWriter::new(file), &writer.finish(&invocation))?;
+
+            buck2_error::Ok(())
+        })
+        .into()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TracesData {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize)]
+struct ScopeSpans {
+    scope: InstrumentationScope,
+    spans: Vec<Span>,
+}
+
+#[derive(Serialize)]
+struct InstrumentationScope {
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<String>,
+    name: String,
+    /// Always `SPAN_KIND_INTERNAL`.
+    kind: u32,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    status: Status,
+}
+
+#[derive(Serialize, Default)]
+struct Status {
+    /// `STATUS_CODE_UNSET`, `STATUS_CODE_OK` or `STATUS_CODE_ERROR`.
+    code: u32,
+}
+
+impl Status {
+    const OK: u32 = 1;
+    const ERROR: u32 = 2;
+}
+
+#[derive(Serialize)]
+struct KeyValue {
+    key: &'static str,
+    value: AnyValue,
+}
+
+#[derive(Serialize)]
+enum AnyValue {
+    #[serde(rename = "stringValue")]
+    String(String),
+}
+
+impl KeyValue {
+    fn string(key: &'static str, value: impl Into<String>) -> Self {
+        Self {
+            key,
+            value: AnyValue::String(value.into()),
+        }
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn otlp_span_id(span_id: SpanId) -> String {
+    format!("{:016x}", u64::from(span_id))
+}
+
+/// The coarse kind of executor that ran the command that serviced an action.
+fn executor_kind(kind: &buck2_data::command_execution_kind::Command) -> &'static str {
+    use buck2_data::command_execution_kind::Command;
+
+    match kind {
+        Command::LocalCommand(..) | Command::OmittedLocalCommand(..) => "local",
+        Command::RemoteCommand(..) => "remote",
+        Command::WorkerInitCommand(..) | Command::WorkerCommand(..) => "worker",
+        Command::LocalDiskCacheHit(..) => "local_disk_cache",
+    }
+}
+
+struct OpenSpan {
+    parent_id: Option<SpanId>,
+    name: String,
+    start: SystemTime,
+    attributes: Vec<KeyValue>,
+}
+
+struct OtlpWriter {
+    open_spans: HashMap<SpanId, OpenSpan>,
+    closed_spans: Vec<(SpanId, OpenSpan, SystemTime, Status)>,
+    last_timestamp: SystemTime,
+}
+
+impl OtlpWriter {
+    fn new() -> Self {
+        Self {
+            open_spans: HashMap::new(),
+            closed_spans: Vec::new(),
+            last_timestamp: UNIX_EPOCH,
+        }
+    }
+
+    fn handle_event(&mut self, event: &BuckEvent) -> buck2_error::Result<()> {
+        self.last_timestamp = self.last_timestamp.max(event.timestamp());
+        let Some(span_id) = event.span_id() else {
+            return Ok(());
+        };
+
+        match event.data() {
+            buck2_data::buck_event::Data::SpanStart(buck2_data::SpanStartEvent {
+                data: Some(start),
+            }) => {
+                let mut attributes = vec![KeyValue::string("buck2.span", start.variant_name())];
+                let name = match start {
+                    buck2_data::span_start_event::Data::Command(..) => "command".to_owned(),
+                    _ => display::display_event(event, TargetDisplayOptions::for_log())
+                        .unwrap_or_else(|_| start.variant_name().to_owned()),
+                };
+
+                match start {
+                    buck2_data::span_start_event::Data::ActionExecution(action) => {
+                        if let Some(key) = &action.key {
+                            attributes.push(KeyValue::string(
+                                "buck2.action.key",
+                                display::display_action_key(key, TargetDisplayOptions::for_log())?,
+                            ));
+                        }
+                        if let Some(name) = &action.name {
+                            attributes
+                                .push(KeyValue::string("buck2.action.category", &name.category));
+                            attributes.push(KeyValue::string(
+                                "buck2.action.identifier",
+                                &name.identifier,
+                            ));
+                        }
+                    }
+                    buck2_data::span_start_event::Data::ExecutorStage(stage) => {
+                        if let Some(stage) = stage
+                            .stage
+                            .as_ref()
+                            .and_then(display::display_executor_stage)
+                        {
+                            attributes.push(KeyValue::string("buck2.executor_stage", stage));
+                        }
+                    }
+                    _ => {}
+                }
+
+                self.open_spans.insert(
+                    span_id,
+                    OpenSpan {
+                        parent_id: event.parent_id(),
+                        name,
+                        start: event.timestamp(),
+                        attributes,
+                    },
+                );
+            }
+            buck2_data::buck_event::Data::SpanEnd(end) => {
+                let Some(mut open) = self.open_spans.remove(&span_id) else {
+                    return Ok(());
+                };
+                let mut status = Status::default();
+                match &end.data {
+                    Some(buck2_data::span_end_event::Data::ActionExecution(action)) => {
+                        open.attributes.push(KeyValue::string(
+                            "buck2.action.execution_kind",
+                            buck2_data::ActionExecutionKind::from_i32(action.execution_kind)
+                                .unwrap_or(buck2_data::ActionExecutionKind::NotSet)
+                                .as_str_name(),
+                        ));
+                        // The last command is the one that produced the result.
+                        if let Some(kind) = action
+                            .commands
+                            .last()
+                            .and_then(|c| c.details.as_ref())
+                            .and_then(|d| d.command_kind.as_ref())
+                            .and_then(|k| k.command.as_ref())
+                        {
+                            open.attributes.push(KeyValue::string(
+                                "buck2.action.executor",
+                                executor_kind(kind),
+                            ));
+                        }
+                        status.code = if action.failed {
+                            Status::ERROR
+                        } else {
+                            Status::OK
+                        };
+                    }
+                    Some(buck2_data::span_end_event::Data::Command(command)) => {
+                        status.code = if command.is_success {
+                            Status::OK
+                        } else {
+                            Status::ERROR
+                        };
+                    }
+                    _ => {}
+                }
+                self.closed_spans
+                    .push((span_id, open, event.timestamp(), status));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Produce the trace. Spans that never ended (for example because the command was
+    /// interrupted) are ended at the last event in the log.
+    fn finish(mut self, invocation: &Invocation) -> TracesData {
+        let last_timestamp = self.last_timestamp;
+        self.closed_spans.extend(
+            self.open_spans
+                .drain()
+                .map(|(id, open)| (id, open, last_timestamp, Status::default())),
+        );
+
+        let trace_id = invocation.trace_id.to_string().replace('-', "");
+        let spans = self
+            .closed_spans
+            .into_iter()
+            .map(|(span_id, open, end, status)| Span {
+                trace_id: trace_id.clone(),
+                span_id: otlp_span_id(span_id),
+                parent_span_id: open.parent_id.map(otlp_span_id),
+                name: open.name,
+                kind: 1,
+                start_time_unix_nano: unix_nanos(open.start),
+                end_time_unix_nano: unix_nanos(end),
+                attributes: open.attributes,
+                status,
+            })
+            .collect();
+
+        TracesData {
+            resource_spans: vec![ResourceSpans {
+                resource: Resource {
+                    attributes: vec![
+                        KeyValue::string("service.name", "buck2"),
+                        KeyValue::string("buck2.trace_id", invocation.trace_id.to_string()),
+                        KeyValue::string("buck2.command_line", invocation.display_command_line()),
+                        KeyValue::string("buck2.working_dir", &invocation.working_dir),
+                    ],
+                },
+                scope_spans: vec![ScopeSpans {
+                    scope: InstrumentationScope { name: "buck2" },
+                    spans,
+                }],
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use super::*;
+
+    fn event(
+        secs: u64,
+        span_id: u64,
+        parent_id: u64,
+        data: impl Into<buck2_data::buck_event::Data>,
+    ) -> BuckEvent {
+        BuckEvent::new(
+            UNIX_EPOCH + Duration::from_secs(secs),
+            TraceId::null(),
+            SpanId::from_u64_opt(span_id),
+            SpanId::from_u64_opt(parent_id),
+            data.into(),
+        )
+    }
+
+    #[test]
+    fn test_spans() -> buck2_error::Result<()> {
+        let mut writer = OtlpWriter::new();
+        writer.handle_event(&event(
+            1,
+            1,
+            0,
+            buck2_data::SpanStartEvent {
+                data: Some(buck2_data::CommandStart::default().into()),
+            },
+        ))?;
+        writer.handle_event(&event(
+            2,
+            2,
+            1,
+            buck2_data::SpanStartEvent {
+                data: Some(buck2_data::FileWatcherStart::default().into()),
+            },
+        ))?;
+        writer.handle_event(&event(
+            3,
+            2,
+            1,
+            buck2_data::SpanEndEvent {
+                data: Some(buck2_data::FileWatcherEnd::default().into()),
+                ..Default::default()
+            },
+        ))?;
+
+        let invocation = Invocation {
+            command_line_args: vec!["buck2".to_owned(), "build".to_owned()],
+            expanded_command_line_args: Vec::new(),
+            working_dir: "/repo".to_owned(),
+            trace_id: TraceId::null(),
+        };
+        let trace = serde_json::to_value(writer.finish(&invocation))?;
+        let spans = &trace["resourceSpans"][0]["scopeSpans"][0]["spans"];
+
+        let watcher = &spans[0];
+        assert_eq!(watcher["spanId"], "0000000000000002");
+        assert_eq!(watcher["parentSpanId"], "0000000000000001");
+        assert_eq!(watcher["startTimeUnixNano"], "2000000000");
+        assert_eq!(watcher["endTimeUnixNano"], "3000000000");
+        assert_eq!(
+            watcher["attributes"][0]["value"]["stringValue"],
+            "FileWatcher"
+        );
+
+        // The command never ended, so it ends with the last event.
+        let command = &spans[1];
+        assert_eq!(command["name"], "command");
+        assert!(command.get("parentSpanId").is_none());
+        assert_eq!(command["endTimeUnixNano"], "3000000000");
+        assert_eq!(command["traceId"], "00000000000000000000000000000000");
+
+        Ok(())
+    }
+}
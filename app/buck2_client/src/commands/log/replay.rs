@@ -7,23 +7,34 @@
  * of this source tree.
  */
 
+use std::time::Duration;
+
 use buck2_client_ctx::client_ctx::ClientCommandContext;
 use buck2_client_ctx::common::ui::CommonConsoleOptions;
 use buck2_client_ctx::common::BuckArgMatches;
+use buck2_client_ctx::console_interaction_stream::ConsoleInteractionStream;
 use buck2_client_ctx::daemon::client::NoPartialResultHandler;
 use buck2_client_ctx::events_ctx::EventsCtx;
 use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::replayer::ReplayControl;
+use buck2_client_ctx::replayer::ReplayStopAt;
 use buck2_client_ctx::replayer::Replayer;
 use buck2_client_ctx::signal_handler::with_simple_sigint_handler;
 use buck2_client_ctx::subscribers::get::get_console_with_root;
 use buck2_client_ctx::subscribers::subscribers::EventSubscribers;
+use buck2_client_ctx::subscribers::superconsole::SuperConsoleConfig;
 use buck2_error::buck2_error;
+use dupe::Dupe;
 
 use crate::commands::log::options::EventLogOptions;
 
 /// Replay an event log.
 ///
 /// This command allows visualizing an existing event log in a Superconsole.
+///
+/// While replaying, press space to pause or resume, `.` to step to the next event, `n` to step to
+/// the next action start, `f` to step to the next action failure, `]` to skip ahead 10 seconds,
+/// and `>`/`<` to double or halve the playback speed.
 #[derive(Debug, clap::Parser)]
 #[clap(trailing_var_arg = true)]
 pub struct ReplayCommand {
@@ -37,6 +48,17 @@ pub struct ReplayCommand {
     )]
     pub speed: Option<f64>,
 
+    /// Skip ahead to this many seconds into the log before starting to play at normal speed.
+    #[clap(long, value_name = "SECONDS")]
+    seek: Option<f64>,
+
+    /// Pause when an action start or end event matches this expression. The expression is a
+    /// comma-separated list of conditions that must all hold: `failure`, `category=<CATEGORY>`
+    /// or `target=<TARGET>` (where the target may be a `//package/...` pattern). For example
+    /// `--stop-at category=cxx_compile,failure`.
+    #[clap(long, value_name = "EXPR")]
+    stop_at: Option<ReplayStopAt>,
+
     /// Preload the event log. This is typically only useful for benchmarking.
     #[clap(long)]
     preload: bool,
@@ -53,6 +75,8 @@ impl ReplayCommand {
         let Self {
             event_log,
             speed,
+            seek,
+            stop_at,
             preload,
             console_opts,
             override_args: _,
@@ -60,8 +84,25 @@ impl ReplayCommand {
 
         ctx.instant_command_no_log("log-replay", |mut ctx| async move {
             let work = async {
-                let (replayer, invocation) =
-                    Replayer::new(event_log.get(&ctx).await?, speed, preload).await?;
+                let seek = seek
+                    .map(|seek| {
+                        Duration::try_from_secs_f64(seek).map_err(|_| {
+                            buck2_error!(
+                                buck2_error::ErrorTag::Input,
+                                "`--seek` must be a finite, non-negative number of seconds"
+                            )
+                        })
+                    })
+                    .transpose()?;
+                let control = ReplayControl::new(speed);
+                let (replayer, invocation) = Replayer::new(
+                    event_log.get(&ctx).await?,
+                    control.dupe(),
+                    preload,
+                    seek,
+                    stop_at,
+                )
+                .await?;
                 let build_count_dir = match ctx.paths() {
                     Ok(paths) => Some(paths.build_count_dir()),
                     Err(_) => None,
@@ -73,7 +114,10 @@ impl ReplayCommand {
                     true,
                     speed,
                     "(replay)", // Could be better
-                    console_opts.superconsole_config(),
+                    SuperConsoleConfig {
+                        replay_control: Some(control),
+                        ..console_opts.superconsole_config()
+                    },
                     build_count_dir,
                 )?;
 
@@ -82,7 +126,8 @@ impl ReplayCommand {
                         &mut NoPartialResultHandler,
                         Box::pin(replayer),
                         None,
-                        ctx.console_interaction_stream(&console_opts),
+                        ctx.console_interaction_stream(&console_opts)
+                            .map(ConsoleInteractionStream::with_replay_controls),
                     )
                     .await;
 
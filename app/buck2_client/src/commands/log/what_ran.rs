@@ -59,6 +59,9 @@ use crate::commands::log::OutputFormatWithWriter;
 /// To reproduce an action that ran locally, make sure your working directory is the project root
 /// (if unsure, use `buck2 root --kind project` to find it), then run the command. The command is
 /// already shell-quoted.
+///
+/// If `buck2.detect_undeclared_inputs` was set, the files that local commands read without
+/// declaring them as inputs are listed after the command.
 #[derive(Debug, clap::Parser)]
 pub struct WhatRanCommand {
     #[clap(flatten)]
@@ -166,6 +169,7 @@ impl WhatRanCommand {
 struct WhatRanEntry {
     action: WhatRanRelevantAction,
     reproducers: Vec<CommandReproducer>,
+    undeclared_inputs: Vec<String>,
 }
 
 impl WhatRanEntry {
@@ -186,6 +190,7 @@ impl WhatRanEntry {
                 &options_regex,
                 std_err,
                 duration,
+                &self.undeclared_inputs,
             )?;
         }
         Ok(())
@@ -260,6 +265,7 @@ impl WhatRanCommandState {
                     WhatRanEntry {
                         action,
                         reproducers: Default::default(),
+                        undeclared_inputs: Default::default(),
                     },
                 );
                 return Ok(());
@@ -275,6 +281,17 @@ impl WhatRanCommandState {
             }
             // Emit WhatRanRelevantAction when we see the corresponding SpanEnd
             match &data {
+                buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                    data: Some(buck2_data::instant_event::Data::UndeclaredInputs(undeclared)),
+                }) => {
+                    if let Some(parent_id) = SpanId::from_u64_opt(event.parent_id) {
+                        if let Some(entry) = self.known_actions.get_mut(&parent_id) {
+                            entry
+                                .undeclared_inputs
+                                .extend(undeclared.paths.iter().cloned());
+                        }
+                    }
+                }
                 buck2_data::buck_event::Data::SpanEnd(span) => {
                     if let Some(entry) =
                         self.known_actions.remove(&SpanId::from_u64(event.span_id)?)
@@ -357,6 +374,9 @@ impl WhatRanOutputWriter for OutputFormatWithWriter<'_> {
         match &mut self.format {
             LogCommandOutputFormatWithWriter::Tabulated(w) => {
                 w.write_all(format!("{}\n", command.as_tabulated_reproducer()).as_bytes())?;
+                for path in command.undeclared_inputs {
+                    writeln!(w, "warning: read undeclared input `{}`", path)?;
+                }
                 if let Some(std_err) = std_err_formatted {
                     write!(
                         w,
@@ -383,6 +403,9 @@ impl WhatRanOutputWriter for OutputFormatWithWriter<'_> {
                                 action_key: cache_hit.action_key.as_deref(),
                             }
                         }
+                        buck2_data::CacheType::LocalDiskCache => JsonReproducer::LocalDiskCache {
+                            digest: &cache_hit.action_digest,
+                        },
                     },
                     CommandReproducer::ReExecute(re_execute) => JsonReproducer::Re {
                         digest: &re_execute.action_digest,
@@ -447,6 +470,7 @@ impl WhatRanOutputWriter for OutputFormatWithWriter<'_> {
                         .map(|duration| fmt_duration::fmt_duration(duration, 1.0)),
                     extra: command.extra.map(Into::into),
                     std_err,
+                    undeclared_inputs: command.undeclared_inputs,
                 };
                 serde_json::to_writer(w.by_ref(), &command)?;
                 w.write_all("\n".as_bytes())?;
@@ -497,6 +521,8 @@ struct JsonCommand<'a> {
     extra: Option<JsonExtra<'a>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     std_err: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    undeclared_inputs: &'a [String],
 }
 
 mod json_reproducer {
@@ -520,6 +546,9 @@ mod json_reproducer {
             #[serde(skip_serializing_if = "Option::is_none")]
             action_key: Option<&'a str>,
         },
+        LocalDiskCache {
+            digest: &'a str,
+        },
         Re {
             digest: &'a str,
             platform_properties: IndexMap<&'a str, &'a str>,
@@ -573,6 +602,7 @@ mod tests {
             duration: Some("1".to_owned()),
             extra: None,
             std_err: None,
+            undeclared_inputs: &[],
         }
     }
 
@@ -590,6 +620,7 @@ mod tests {
             duration: Some("1".to_owned()),
             extra: None,
             std_err: None,
+            undeclared_inputs: &[],
         }
     }
 
@@ -650,6 +681,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serialize_what_ran_command_with_undeclared_inputs() -> buck2_error::Result<()> {
+        let mut command = make_base_command();
+        let undeclared_inputs = &["foo/bar.h".to_owned()];
+        command.undeclared_inputs = undeclared_inputs;
+
+        let expected = r#"{
+  "reason": "test.run",
+  "identity": "some/target",
+  "reproducer": {
+    "executor": "Local",
+    "details": {
+      "command": [
+        "some",
+        "command"
+      ],
+      "env": {
+        "KEY": "val"
+      }
+    }
+  },
+  "duration": "1",
+  "undeclared_inputs": [
+    "foo/bar.h"
+  ]
+}"#;
+        assert_eq!(expected, serde_json::to_string_pretty(&command)?);
+        Ok(())
+    }
+
     #[test]
     fn serialize_what_ran_command_in_re() -> buck2_error::Result<()> {
         let command = make_base_command_in_re();
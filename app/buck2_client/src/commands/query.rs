@@ -9,6 +9,8 @@
 
 pub mod aquery;
 pub(crate) mod common;
+pub(crate) mod compare;
 pub mod cquery;
 pub(crate) mod profile;
+pub(crate) mod snapshot;
 pub mod uquery;
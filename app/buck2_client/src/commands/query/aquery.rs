@@ -25,6 +25,7 @@ use buck2_client_ctx::streaming::StreamingCommand;
 use buck2_core::if_else_opensource;
 
 use crate::commands::query::common::CommonQueryOptions;
+use crate::commands::query::common::QueryLimitOptions;
 
 fn help() -> &'static str {
     concat!(
@@ -73,6 +74,9 @@ pub struct AqueryCommand {
     #[clap(flatten)]
     query_common: CommonQueryOptions,
 
+    #[clap(flatten)]
+    limits: QueryLimitOptions,
+
     #[clap(flatten)]
     target_cfg: TargetCfgOptions,
 
@@ -90,7 +94,7 @@ impl StreamingCommand for AqueryCommand {
         matches: BuckArgMatches<'_>,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let (query, query_args) = self.query_common.get_query();
+        let (query, query_args) = self.query_common.get_query(&ctx.working_dir)?;
         let unstable_output_format = self.query_common.output_format() as i32;
         let output_attributes = self.query_common.attributes.get()?;
         let context = ctx.client_context(matches, &self)?;
@@ -105,6 +109,7 @@ impl StreamingCommand for AqueryCommand {
                     context: Some(context),
                     output_attributes,
                     unstable_output_format,
+                    limits: Some(self.limits.to_proto()?),
                 },
                 ctx.console_interaction_stream(&self.common_opts.console_opts),
                 &mut StdoutPartialResultHandler,
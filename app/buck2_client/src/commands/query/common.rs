@@ -8,7 +8,12 @@
  */
 
 use buck2_cli_proto::QueryOutputFormat;
+use buck2_client_ctx::path_arg::PathArg;
 use buck2_client_ctx::query_args::CommonAttributeArgs;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::working_dir::AbsWorkingDir;
+use buck2_error::BuckErrorContext;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query_parser::placeholder::QUERY_PERCENT_SS_PLACEHOLDER;
 use dupe::Dupe;
 
@@ -27,6 +32,9 @@ enum QueryOutputFormatArg {
     DotCompact,
     Starlark,
     Html,
+    Graphml,
+    Mermaid,
+    Cypher,
 }
 
 /// Args common to all the query commands
@@ -57,9 +65,12 @@ pub(crate) struct CommonQueryOptions {
            dot_compact - compact alternative to dot format. \n
            json - JSON format. \n
            starlark - targets are printed like starlark code that would produce them.
-           html - html file containing interactive target graph.
+           html - html file containing interactive target graph. \n
+           graphml - GraphML graph, with the selected attributes and dep kinds. \n
+           mermaid - Mermaid flowchart, with the selected attributes and dep kinds. \n
+           cypher - Cypher CREATE statement, with the selected attributes and dep kinds.
          ",
-        value_name = "dot|dot_compact|json|starlark|html",
+        value_name = "dot|dot_compact|json|starlark|html|graphml|mermaid|cypher",
         value_enum
     )]
     output_format: Option<QueryOutputFormatArg>,
@@ -69,6 +80,77 @@ pub(crate) struct CommonQueryOptions {
         help = "list of literals for a multi-query (one containing `%s` or `%Ss`)"
     )]
     query_args: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "File of query macros, written as `define NAME(ARG, ...) = EXPR;`, to make available \
+            to the query. Lines starting with `#` are comments."
+    )]
+    macros: Option<PathArg>,
+}
+
+/// Guards against queries that are much more expensive than intended. A query that exceeds one of
+/// the limits fails with an error that points at the sub-expression that exceeded it.
+#[derive(Debug, clap::Parser)]
+pub(crate) struct QueryLimitOptions {
+    /// Fail if the query evaluates to more than this many targets or files. Sub-expressions may
+    /// evaluate to more.
+    #[clap(long, value_name = "N")]
+    max_results: Option<u64>,
+
+    /// Fail if the query loads more than this many targets and files in total. Traversals like
+    /// `deps` count every target they reach as they go, and set literals count their members.
+    #[clap(long, value_name = "N")]
+    max_traversal_nodes: Option<u64>,
+
+    /// Fail if evaluating the query takes longer than this, for example `30s` or `2m`.
+    #[clap(long, value_name = "DURATION")]
+    timeout: Option<humantime::Duration>,
+}
+
+impl QueryLimitOptions {
+    pub(crate) fn to_proto(&self) -> buck2_error::Result<buck2_cli_proto::QueryLimits> {
+        Ok(buck2_cli_proto::QueryLimits {
+            max_results: self.max_results,
+            max_traversal_nodes: self.max_traversal_nodes,
+            timeout: self
+                .timeout
+                .map(|t| {
+                    let t: std::time::Duration = t.into();
+                    t.try_into()
+                })
+                .transpose()
+                .buck_error_context("Invalid `timeout`")?,
+        })
+    }
+
+    /// The limits for evaluating the query in the client with `--snapshot`, or in a subscription.
+    pub(crate) fn to_limits(&self) -> QueryLimits {
+        QueryLimits {
+            max_results: self.max_results,
+            max_traversal_nodes: self.max_traversal_nodes,
+            timeout: self.timeout.map(|t| t.into()),
+        }
+    }
+
+    /// The same limits as command line arguments, to evaluate the base of `--compare-to` with.
+    pub(crate) fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(max_results) = self.max_results {
+            args.push("--max-results".to_owned());
+            args.push(max_results.to_string());
+        }
+        if let Some(max_traversal_nodes) = self.max_traversal_nodes {
+            args.push("--max-traversal-nodes".to_owned());
+            args.push(max_traversal_nodes.to_string());
+        }
+        if let Some(timeout) = &self.timeout {
+            args.push("--timeout".to_owned());
+            args.push(timeout.to_string());
+        }
+        args
+    }
 }
 
 impl CommonQueryOptions {
@@ -93,6 +175,9 @@ impl CommonQueryOptions {
             Some(QueryOutputFormatArg::DotCompact) => QueryOutputFormat::DotCompact,
             Some(QueryOutputFormatArg::Starlark) => QueryOutputFormat::Starlark,
             Some(QueryOutputFormatArg::Html) => QueryOutputFormat::Html,
+            Some(QueryOutputFormatArg::Graphml) => QueryOutputFormat::Graphml,
+            Some(QueryOutputFormatArg::Mermaid) => QueryOutputFormat::Mermaid,
+            Some(QueryOutputFormatArg::Cypher) => QueryOutputFormat::Cypher,
             None => {
                 if self.json {
                     QueryOutputFormat::Json
@@ -107,8 +192,8 @@ impl CommonQueryOptions {
         }
     }
 
-    pub fn get_query(&self) -> (String, Vec<String>) {
-        if self.query.contains(QUERY_PERCENT_SS_PLACEHOLDER) {
+    pub fn get_query(&self, cwd: &AbsWorkingDir) -> buck2_error::Result<(String, Vec<String>)> {
+        let (query, query_args) = if self.query.contains(QUERY_PERCENT_SS_PLACEHOLDER) {
             let replacement = Self::args_as_set(&self.query_args);
             (
                 self.query
@@ -117,6 +202,28 @@ impl CommonQueryOptions {
             )
         } else {
             (self.query.clone(), self.query_args.clone())
+        };
+
+        match &self.macros {
+            Some(macros) => {
+                let macros = fs_util::read_to_string(macros.resolve(cwd))?;
+                Ok((Self::prepend_macros(&macros, &query), query_args))
+            }
+            None => Ok((query, query_args)),
+        }
+    }
+
+    /// Macro definitions are part of the query language, so they are evaluated by prefixing them
+    /// to the query. Comments are blanked rather than removed to keep line numbers in errors.
+    fn prepend_macros(macros: &str, query: &str) -> String {
+        let mut s = String::new();
+        for line in macros.lines() {
+            if !line.trim_start().starts_with('#') {
+                s += line;
+            }
+            s += "\n";
         }
+        s += query;
+        s
     }
 }
@@ -0,0 +1,463 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! `--compare-to`: evaluate a query against both the working copy and the mergebase with another
+//! revision, and report how the results differ.
+//!
+//! The daemon only knows about the files on disk, so the base state is checked out into a
+//! detached git worktree under buck-out and queried with a separate invocation of buck2 there.
+//! That invocation starts a daemon of its own, which is killed before the worktree is removed.
+//! Worktrees and daemons left behind by interrupted comparisons are cleaned up by the next one.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use buck2_client_ctx::common::CommonBuildConfigurationOptions;
+use buck2_client_ctx::events_ctx::PartialResultCtx;
+use buck2_client_ctx::events_ctx::PartialResultHandler;
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_path::AbsPath;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::working_dir::AbsWorkingDir;
+use buck2_error::BuckErrorContext;
+use buck2_util::process::async_background_command;
+use fs4::FileExt;
+use serde_json::Value;
+
+/// Attributes compared with `--compare-attributes` when none are requested explicitly. These are
+/// the attributes of `buck2 targets` without `--output-all-attributes`.
+const BASIC_ATTRIBUTES: &str = r"^(buck\.package|buck\.type|[^\.]*)$";
+
+const TARGET_HASH_ATTRIBUTE: &str = "buck.target_hash";
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Input)]
+enum CompareToError {
+    #[error("`--compare-to` is not supported with multi-queries")]
+    MultiQuery,
+    #[error("`--compare-to` is only supported in git repositories (`{0}` is not in one)")]
+    UnsupportedVcs(String),
+    #[error("`git {args}` failed:\n{stderr}")]
+    GitFailed { args: String, stderr: String },
+    #[error("Evaluating the query at `{rev}` failed:\n{stderr}")]
+    BaseQueryFailed { rev: String, stderr: String },
+    #[error("Unexpected query output, expected a JSON list or object of targets")]
+    UnexpectedOutput,
+}
+
+#[derive(Debug, clap::Parser)]
+pub(crate) struct CompareToOptions {
+    /// Also evaluate the query at the mergebase of the working copy and this revision, and print
+    /// the targets that were added (`+`) or removed (`-`) in the working copy instead of the
+    /// query result.
+    ///
+    /// The mergebase is checked out into a git worktree under buck-out and evaluated by a separate
+    /// buck2 daemon, which is killed and the worktree removed once the query has been evaluated.
+    #[clap(long, value_name = "REV")]
+    pub(crate) compare_to: Option<String>,
+
+    /// With `--compare-to`, also print the targets that changed (`~`), followed by the attributes
+    /// that differ. Compares the attributes selected with `--output-attribute`, or the basic
+    /// attributes if none are.
+    #[clap(long, requires = "compare_to")]
+    pub(crate) compare_attributes: bool,
+}
+
+/// Receive StdoutBytes, just capture them.
+#[derive(Default)]
+pub(crate) struct CaptureStdout {
+    pub(crate) buf: Vec<u8>,
+}
+
+#[async_trait]
+impl PartialResultHandler for CaptureStdout {
+    type PartialResult = buck2_cli_proto::StdoutBytes;
+
+    async fn handle_partial_result(
+        &mut self,
+        _ctx: PartialResultCtx<'_, '_>,
+        partial_res: Self::PartialResult,
+    ) -> buck2_error::Result<()> {
+        self.buf.extend(partial_res.data);
+        Ok(())
+    }
+}
+
+async fn git(cwd: &Path, args: &[&str]) -> buck2_error::Result<String> {
+    let output = async_background_command("git")
+        .current_dir(cwd)
+        .args(args)
+        .output()
+        .await
+        .buck_error_context("Could not run git")?;
+    if !output.status.success() {
+        return Err(CompareToError::GitFailed {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// A detached worktree with the mergebase of `HEAD` and the revision to compare to.
+///
+/// The worktree is locked while it's in use, so that a worktree whose lock is free was left
+/// behind by a comparison that was interrupted, along with the daemon that ran in it.
+struct BaseCheckout {
+    toplevel: PathBuf,
+    worktree: PathBuf,
+    /// The project root in the worktree, where its daemon runs.
+    project_root: PathBuf,
+    /// The directory in the worktree that corresponds to the working directory.
+    dir: PathBuf,
+    lock: File,
+}
+
+impl BaseCheckout {
+    async fn add(paths: &InvocationPaths, cwd: &Path, rev: &str) -> buck2_error::Result<Self> {
+        let toplevel = git(cwd, &["rev-parse", "--show-toplevel"])
+            .await
+            .map_err(|_| CompareToError::UnsupportedVcs(cwd.display().to_string()))?;
+        let toplevel = PathBuf::from(toplevel);
+        let prefix = git(cwd, &["rev-parse", "--show-prefix"]).await?;
+        let project_prefix = git(
+            paths.project_root().root().as_path(),
+            &["rev-parse", "--show-prefix"],
+        )
+        .await?;
+        let merge_base = git(cwd, &["merge-base", "HEAD", rev]).await?;
+
+        let compare_to_dir = paths
+            .buck_out_path()
+            .join(FileName::unchecked_new("compare-to"));
+        fs_util::create_dir_all(&compare_to_dir)?;
+
+        // Another comparison against the same mergebase has to finish with the worktree first.
+        let lock = File::create(
+            compare_to_dir
+                .as_path()
+                .join(format!("{}.lock", merge_base)),
+        )?;
+        let lock = tokio::task::spawn_blocking(move || {
+            lock.lock_exclusive()?;
+            buck2_error::Ok(lock)
+        })
+        .await??;
+
+        Self::remove_stale(&toplevel, &compare_to_dir, &project_prefix).await;
+
+        let worktree = compare_to_dir.as_path().join(&merge_base);
+        // Left over by a comparison that was interrupted, as we hold its lock.
+        if worktree.exists() {
+            Self::remove_worktree(&toplevel, &worktree, &worktree.join(&project_prefix)).await;
+        }
+
+        let worktree_str = worktree
+            .to_str()
+            .buck_error_context("Non-UTF-8 buck-out path")?;
+        git(
+            &toplevel,
+            &["worktree", "add", "--detach", worktree_str, &merge_base],
+        )
+        .await?;
+
+        // The worktree is inside of this project, so stop buck2 from looking for the project root
+        // any further up than the worktree's.
+        let project_root = worktree.join(&project_prefix);
+        let buckroot = project_root.join(".buckroot");
+        if !buckroot.exists() {
+            fs_util::write(AbsPath::new(&buckroot)?, "")?;
+        }
+
+        Ok(Self {
+            dir: worktree.join(prefix),
+            toplevel,
+            worktree,
+            project_root,
+            lock,
+        })
+    }
+
+    /// Remove the worktrees of comparisons that were interrupted, and kill their daemons.
+    async fn remove_stale(toplevel: &Path, compare_to_dir: &AbsNormPath, project_prefix: &str) {
+        let entries = match fs_util::read_dir(compare_to_dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Failed to list `{}`: {:#}", compare_to_dir, e);
+                return;
+            }
+        };
+        for entry in entries {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let worktree = entry.path();
+            if !worktree.is_dir() {
+                continue;
+            }
+            let lock_path = compare_to_dir
+                .as_path()
+                .join(format!("{}.lock", entry.file_name().to_string_lossy()));
+            // Locked by a comparison that's still running.
+            let Ok(lock) = File::create(lock_path) else {
+                continue;
+            };
+            if lock.try_lock_exclusive().is_err() {
+                continue;
+            }
+            Self::remove_worktree(toplevel, &worktree, &worktree.join(project_prefix)).await;
+        }
+    }
+
+    /// Kill the daemon that evaluated the query in a worktree and remove the worktree. Failures
+    /// are only reported: the next comparison will try again.
+    async fn remove_worktree(toplevel: &Path, worktree: &Path, project_root: &Path) {
+        if project_root.exists() {
+            let kill: std::io::Result<_> = async {
+                async_background_command(std::env::current_exe()?)
+                    .current_dir(project_root)
+                    .arg("kill")
+                    .output()
+                    .await
+            }
+            .await;
+            match kill {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => tracing::warn!(
+                    "Failed to kill the buck2 daemon in `{}`:\n{}",
+                    worktree.display(),
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+                Err(e) => tracing::warn!(
+                    "Failed to kill the buck2 daemon in `{}`: {:#}",
+                    worktree.display(),
+                    e
+                ),
+            }
+        }
+
+        let res: buck2_error::Result<()> = async {
+            let worktree_str = worktree
+                .to_str()
+                .buck_error_context("Non-UTF-8 buck-out path")?;
+            if git(toplevel, &["worktree", "remove", "--force", worktree_str])
+                .await
+                .is_err()
+            {
+                // Not a worktree git knows about (anymore), e.g. because its checkout was
+                // interrupted.
+                fs_util::remove_all(AbsPath::new(worktree)?)?;
+                git(toplevel, &["worktree", "prune"]).await?;
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = res {
+            tracing::warn!(
+                "Failed to remove the worktree `{}`: {:#}",
+                worktree.display(),
+                e
+            );
+        }
+    }
+
+    async fn remove(self) {
+        Self::remove_worktree(&self.toplevel, &self.worktree, &self.project_root).await;
+        drop(self.lock);
+    }
+}
+
+impl CompareToOptions {
+    /// The attributes to request from both evaluations.
+    pub(crate) fn output_attributes(&self, requested: Vec<String>) -> Vec<String> {
+        if self.compare_attributes && requested.is_empty() {
+            vec![BASIC_ATTRIBUTES.to_owned()]
+        } else {
+            requested
+        }
+    }
+
+    pub(crate) fn check_query_args(query_args: &[String]) -> buck2_error::Result<()> {
+        if query_args.is_empty() {
+            Ok(())
+        } else {
+            Err(CompareToError::MultiQuery.into())
+        }
+    }
+
+    /// Evaluate `query` at the mergebase with `--compare-to`, with the output of
+    /// `buck2 <command> --json`.
+    pub(crate) async fn query_base(
+        &self,
+        paths: &InvocationPaths,
+        cwd: &AbsWorkingDir,
+        command: &str,
+        query: &str,
+        output_attributes: &[String],
+        config_opts: &CommonBuildConfigurationOptions,
+        extra_args: Vec<String>,
+    ) -> buck2_error::Result<Vec<u8>> {
+        let rev = self
+            .compare_to
+            .as_deref()
+            .internal_error("Comparing without `--compare-to`")?;
+        let checkout = BaseCheckout::add(paths, cwd.path().as_path(), rev).await?;
+
+        let mut cmd = async_background_command(std::env::current_exe()?);
+        cmd.current_dir(&checkout.dir).arg(command).arg("--json");
+        if self.compare_attributes {
+            cmd.arg("--target-hash");
+        }
+        for attr in output_attributes {
+            cmd.arg("--output-attribute").arg(attr);
+        }
+        for value in &config_opts.config_values {
+            cmd.arg("--config").arg(value);
+        }
+        for file in &config_opts.config_files {
+            cmd.arg("--config-file").arg(file);
+        }
+        cmd.args(extra_args).arg(query);
+
+        let res = match cmd.output().await {
+            Ok(output) if output.status.success() => Ok(output.stdout),
+            Ok(output) => Err(CompareToError::BaseQueryFailed {
+                rev: rev.to_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }
+            .into()),
+            Err(e) => Err(e.into()),
+        };
+        checkout.remove().await;
+        res
+    }
+}
+
+/// How the result of a query differs between the base and the working copy.
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub(crate) struct QueryDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    /// Targets whose hash differs, with the attributes that differ.
+    changed: BTreeMap<String, Vec<String>>,
+}
+
+/// Targets of `--json` query output, with their attributes if there are any.
+fn parse_targets(output: &[u8]) -> buck2_error::Result<BTreeMap<String, Option<Value>>> {
+    match serde_json::from_slice(output)? {
+        Value::Array(targets) => targets
+            .into_iter()
+            .map(|t| match t {
+                Value::String(t) => Ok((t, None)),
+                _ => Err(CompareToError::UnexpectedOutput.into()),
+            })
+            .collect(),
+        Value::Object(targets) => Ok(targets.into_iter().map(|(t, a)| (t, Some(a))).collect()),
+        _ => Err(CompareToError::UnexpectedOutput.into()),
+    }
+}
+
+impl QueryDiff {
+    pub(crate) fn new(base: &[u8], current: &[u8]) -> buck2_error::Result<Self> {
+        let base = parse_targets(base)?;
+        let current = parse_targets(current)?;
+
+        let mut diff = QueryDiff::default();
+        for (target, attrs) in &current {
+            match base.get(target) {
+                None => diff.added.push(target.clone()),
+                Some(base_attrs) => {
+                    if let (Some(Value::Object(base_attrs)), Some(Value::Object(attrs))) =
+                        (base_attrs, attrs)
+                    {
+                        if base_attrs.get(TARGET_HASH_ATTRIBUTE) != attrs.get(TARGET_HASH_ATTRIBUTE)
+                        {
+                            let mut names: Vec<String> = base_attrs
+                                .keys()
+                                .chain(attrs.keys())
+                                .filter(|k| {
+                                    *k != TARGET_HASH_ATTRIBUTE
+                                        && base_attrs.get(*k) != attrs.get(*k)
+                                })
+                                .cloned()
+                                .collect();
+                            names.sort();
+                            names.dedup();
+                            diff.changed.insert(target.clone(), names);
+                        }
+                    }
+                }
+            }
+        }
+        diff.removed = base
+            .keys()
+            .filter(|t| !current.contains_key(*t))
+            .cloned()
+            .collect();
+        Ok(diff)
+    }
+
+    pub(crate) fn print(&self, json: bool) -> buck2_error::Result<()> {
+        if json {
+            buck2_client_ctx::println!("{}", serde_json::to_string_pretty(self)?)?;
+            return Ok(());
+        }
+        for target in &self.added {
+            buck2_client_ctx::println!("+ {}", target)?;
+        }
+        for target in &self.removed {
+            buck2_client_ctx::println!("- {}", target)?;
+        }
+        for (target, attrs) in &self.changed {
+            buck2_client_ctx::println!("~ {}", target)?;
+            for attr in attrs {
+                buck2_client_ctx::println!("    {}", attr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lists() {
+        let diff = QueryDiff::new(br#"["//:a", "//:b"]"#, br#"["//:b", "//:c"]"#).unwrap();
+        assert_eq!(diff.added, vec!["//:c"]);
+        assert_eq!(diff.removed, vec!["//:a"]);
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_attributes() {
+        let base = br#"{
+            "//:a": {"buck.target_hash": "1", "srcs": ["a.c"], "name": "a"},
+            "//:b": {"buck.target_hash": "2", "name": "b"}
+        }"#;
+        let current = br#"{
+            "//:a": {"buck.target_hash": "3", "srcs": ["a.c", "b.c"], "name": "a"},
+            "//:b": {"buck.target_hash": "2", "name": "b"}
+        }"#;
+        let diff = QueryDiff::new(base, current).unwrap();
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.changed,
+            BTreeMap::from([("//:a".to_owned(), vec!["srcs".to_owned()])])
+        );
+    }
+}
@@ -10,6 +10,7 @@
 use async_trait::async_trait;
 use buck2_cli_proto::CqueryRequest;
 use buck2_cli_proto::CqueryResponse;
+use buck2_cli_proto::QueryOutputFormat;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
 use buck2_client_ctx::common::target_cfg::TargetCfgWithUniverseOptions;
 use buck2_client_ctx::common::ui::CommonConsoleOptions;
@@ -25,6 +26,10 @@ use buck2_client_ctx::streaming::StreamingCommand;
 use buck2_core::if_else_opensource;
 
 use crate::commands::query::common::CommonQueryOptions;
+use crate::commands::query::common::QueryLimitOptions;
+use crate::commands::query::compare::CaptureStdout;
+use crate::commands::query::compare::CompareToOptions;
+use crate::commands::query::compare::QueryDiff;
 use crate::commands::query::profile::QueryProfileOptions;
 
 fn help() -> &'static str {
@@ -79,12 +84,35 @@ pub struct CqueryCommand {
     #[clap(flatten)]
     query_common: CommonQueryOptions,
 
+    #[clap(flatten)]
+    limits: QueryLimitOptions,
+
     #[clap(
         long,
         help = "Show the providers of the query result instead of the attributes and labels"
     )]
     show_providers: bool,
 
+    /// Include the hash of each target, as computed by `buck2 targets --show-target-hash`, in JSON
+    /// output as `buck.target_hash`.
+    #[clap(long)]
+    target_hash: bool,
+
+    /// Label each edge in dot output with the kind of dep it is (target, exec, toolchain or
+    /// configuration), and include the edges between the targets of the result, with their kind,
+    /// in JSON output as `buck.edges`.
+    #[clap(long)]
+    edge_kinds: bool,
+
+    /// Include, for each attribute containing a `select()`, the keys that matched the target's
+    /// configuration, the constraints that made them match and the key that was picked, in JSON
+    /// output as `buck.select_resolutions`.
+    #[clap(long)]
+    select_resolutions: bool,
+
+    #[clap(flatten)]
+    compare: CompareToOptions,
+
     #[clap(flatten)]
     target_cfg: TargetCfgWithUniverseOptions,
 
@@ -105,11 +133,71 @@ impl StreamingCommand for CqueryCommand {
         matches: BuckArgMatches<'_>,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let (query, query_args) = self.query_common.get_query();
+        let (query, query_args) = self.query_common.get_query(&ctx.working_dir)?;
         let unstable_output_format = self.query_common.output_format() as i32;
-        let output_attributes = self.query_common.attributes.get()?;
+        let output_attributes = self
+            .compare
+            .output_attributes(self.query_common.attributes.get()?);
         let context = ctx.client_context(matches, &self)?;
 
+        if self.compare.compare_to.is_some() {
+            CompareToOptions::check_query_args(&query_args)?;
+
+            let mut current = CaptureStdout::default();
+            let CqueryResponse {} = buckd
+                .with_flushing()
+                .cquery(
+                    CqueryRequest {
+                        query: query.clone(),
+                        query_args,
+                        context: Some(context),
+                        output_attributes: output_attributes.clone(),
+                        target_universe: self.target_cfg.target_universe.clone(),
+                        target_cfg: Some(self.target_cfg.target_cfg.target_cfg()),
+                        show_providers: false,
+                        target_hash: self.compare.compare_attributes,
+                        edge_kinds: false,
+                        select_resolutions: false,
+                        limits: Some(self.limits.to_proto()?),
+                        unstable_output_format: QueryOutputFormat::Json as i32,
+                        profile_mode: None,
+                        profile_output: None,
+                    },
+                    ctx.console_interaction_stream(&self.common_opts.console_opts),
+                    &mut current,
+                )
+                .await??;
+
+            let mut extra_args = self.limits.to_args();
+            if !self.target_cfg.target_universe.is_empty() {
+                extra_args.push("--target-universe".to_owned());
+                extra_args.push(self.target_cfg.target_universe.join(","));
+            }
+            if let Some(target_platforms) = &self.target_cfg.target_cfg.target_platforms {
+                extra_args.push("--target-platforms".to_owned());
+                extra_args.push(target_platforms.clone());
+            }
+            for modifier in &self.target_cfg.target_cfg.cli_modifier {
+                extra_args.push("--modifier".to_owned());
+                extra_args.push(modifier.clone());
+            }
+            let base = self
+                .compare
+                .query_base(
+                    ctx.paths()?,
+                    &ctx.working_dir,
+                    "cquery",
+                    &query,
+                    &output_attributes,
+                    &self.common_opts.config_opts,
+                    extra_args,
+                )
+                .await?;
+            QueryDiff::new(&base, &current.buf)?
+                .print(self.query_common.output_format() == QueryOutputFormat::Json)?;
+            return ExitResult::success();
+        }
+
         let CqueryResponse {} = buckd
             .with_flushing()
             .cquery(
@@ -121,6 +209,10 @@ impl StreamingCommand for CqueryCommand {
                     target_universe: self.target_cfg.target_universe,
                     target_cfg: Some(self.target_cfg.target_cfg.target_cfg()),
                     show_providers: self.show_providers,
+                    target_hash: self.target_hash,
+                    edge_kinds: self.edge_kinds,
+                    select_resolutions: self.select_resolutions,
+                    limits: Some(self.limits.to_proto()?),
                     unstable_output_format,
                     profile_mode: self.profile_options.profile_mode_proto().map(|m| m as i32),
                     profile_output: self
@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! `--snapshot`: evaluate a uquery against a snapshot of the target graph written by
+//! `buck2 targets --streaming --snapshot`.
+//!
+//! The snapshot has everything the query needs, so it is evaluated in the client, without a
+//! daemon or a checkout of the repository the snapshot was taken in.
+
+use std::io::BufReader;
+
+use buck2_cli_proto::QueryOutputFormat;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_path::AbsPath;
+use buck2_error::BuckErrorContext;
+use buck2_query::query::environment::QueryTargets;
+use buck2_query::query::graph::node::LabeledNode;
+use buck2_query::query::snapshot::QuerySnapshot;
+use buck2_query::query::snapshot::SnapshotNode;
+use buck2_query::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimitsTracker;
+use buck2_query::query::syntax::simple::eval::set::TargetSet;
+use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
+use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
+use regex::RegexSet;
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Input)]
+enum SnapshotQueryError {
+    #[error("`--snapshot` is not supported with multi-queries")]
+    MultiQuery,
+    #[error("`--snapshot` only supports the default and JSON output formats")]
+    UnsupportedOutputFormat,
+    #[error("Query result is a set of files, which have no attributes")]
+    FileSetHasNoAttributes,
+    #[error("Query result is a number, which has no attributes")]
+    IntegerHasNoAttributes,
+}
+
+pub(crate) async fn query_snapshot(
+    snapshot: &AbsPath,
+    query: &str,
+    query_args: &[String],
+    output_format: QueryOutputFormat,
+    output_attributes: &[String],
+    limits: QueryLimits,
+) -> buck2_error::Result<()> {
+    if !query_args.is_empty() {
+        return Err(SnapshotQueryError::MultiQuery.into());
+    }
+    let json = match output_format {
+        QueryOutputFormat::Default => false,
+        QueryOutputFormat::Json => true,
+        _ => return Err(SnapshotQueryError::UnsupportedOutputFormat.into()),
+    };
+    let attributes = if output_attributes.is_empty() {
+        None
+    } else {
+        Some(RegexSet::new(output_attributes)?)
+    };
+
+    let snapshot = QuerySnapshot::read(BufReader::new(fs_util::open_file(snapshot)?))
+        .with_buck_error_context(|| format!("Error reading snapshot `{}`", snapshot.display()))?;
+    let functions = DefaultQueryFunctionsModule::new();
+    let result = QueryEvaluator::new(&snapshot, &functions)
+        .with_limits(QueryLimitsTracker::new(limits))
+        .eval_query(query)
+        .await?;
+
+    match result {
+        QueryEvaluationValue::TargetSet(targets) => {
+            if json || attributes.is_some() {
+                let targets = targets_json(&targets, attributes.as_ref())?;
+                buck2_client_ctx::println!("{}", serde_json::to_string_pretty(&targets)?)?;
+            } else {
+                for target in targets.iter() {
+                    buck2_client_ctx::println!("{}", target.node_key())?;
+                }
+            }
+        }
+        QueryEvaluationValue::Paths(paths) => {
+            if json || attributes.is_some() {
+                let paths = paths
+                    .iter()
+                    .map(|path| targets_json(path, attributes.as_ref()))
+                    .collect::<buck2_error::Result<Vec<_>>>()?;
+                buck2_client_ctx::println!("{}", serde_json::to_string_pretty(&paths)?)?;
+            } else {
+                for (i, path) in paths.iter().enumerate() {
+                    if i > 0 {
+                        buck2_client_ctx::println!()?;
+                    }
+                    for target in path.iter() {
+                        buck2_client_ctx::println!("{}", target.node_key())?;
+                    }
+                }
+            }
+        }
+        QueryEvaluationValue::FileSet(files) => {
+            if attributes.is_some() {
+                return Err(SnapshotQueryError::FileSetHasNoAttributes.into());
+            }
+            if json {
+                let files: Vec<String> = files.iter().map(|f| f.to_string()).collect();
+                buck2_client_ctx::println!("{}", serde_json::to_string_pretty(&files)?)?;
+            } else {
+                for file in files.iter() {
+                    buck2_client_ctx::println!("{}", file)?;
+                }
+            }
+        }
+        QueryEvaluationValue::Integer(value) => {
+            if attributes.is_some() {
+                return Err(SnapshotQueryError::IntegerHasNoAttributes.into());
+            }
+            buck2_client_ctx::println!("{}", value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Labels of the targets, or a map from label to the matching attributes when attributes
+/// were requested.
+fn targets_json(
+    targets: &TargetSet<SnapshotNode>,
+    attributes: Option<&RegexSet>,
+) -> buck2_error::Result<serde_json::Value> {
+    let Some(attributes) = attributes else {
+        let labels: Vec<String> = targets.iter_names().map(|t| t.to_string()).collect();
+        return Ok(labels.into());
+    };
+    let mut map = serde_json::Map::new();
+    for target in targets.iter() {
+        let mut attrs = serde_json::Map::new();
+        QueryTargets::for_all_attrs(target, |name, value| {
+            if attributes.is_match(name) {
+                attrs.insert(name.to_owned(), value.clone());
+            }
+            buck2_error::Ok(())
+        })?;
+        map.insert(target.node_key().to_string(), attrs.into());
+    }
+    Ok(map.into())
+}
@@ -8,6 +8,7 @@
  */
 
 use async_trait::async_trait;
+use buck2_cli_proto::QueryOutputFormat;
 use buck2_cli_proto::UqueryRequest;
 use buck2_cli_proto::UqueryResponse;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
@@ -21,10 +22,17 @@ use buck2_client_ctx::common::CommonStarlarkOptions;
 use buck2_client_ctx::daemon::client::BuckdClientConnector;
 use buck2_client_ctx::daemon::client::StdoutPartialResultHandler;
 use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::path_arg::PathArg;
+use buck2_client_ctx::streaming::BuckSubcommand;
 use buck2_client_ctx::streaming::StreamingCommand;
 use buck2_core::if_else_opensource;
 
 use crate::commands::query::common::CommonQueryOptions;
+use crate::commands::query::common::QueryLimitOptions;
+use crate::commands::query::compare::CaptureStdout;
+use crate::commands::query::compare::CompareToOptions;
+use crate::commands::query::compare::QueryDiff;
+use crate::commands::query::snapshot::query_snapshot;
 
 fn help() -> &'static str {
     concat!(
@@ -81,6 +89,27 @@ pub struct UqueryCommand {
     #[clap(flatten)]
     query_common: CommonQueryOptions,
 
+    #[clap(flatten)]
+    limits: QueryLimitOptions,
+
+    /// Include the hash of each target, as computed by `buck2 targets --show-target-hash`, in JSON
+    /// output as `buck.target_hash`.
+    #[clap(long)]
+    target_hash: bool,
+
+    #[clap(flatten)]
+    compare: CompareToOptions,
+
+    /// Evaluate the query against a snapshot of the target graph written by
+    /// `buck2 targets --streaming --snapshot`, instead of the build files on disk. This needs
+    /// neither a daemon nor a checkout of the repository.
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = &["compare_to", "target_hash"]
+    )]
+    snapshot: Option<PathArg>,
+
     /// Uquery doesn't need these flags, but they are used in mode files, so we need to keep them.
     #[clap(flatten)]
     _target_cfg: TargetCfgUnusedOptions,
@@ -89,6 +118,32 @@ pub struct UqueryCommand {
     common_opts: CommonCommandOptions,
 }
 
+impl UqueryCommand {
+    /// Runs the command, evaluating the query in the client when `--snapshot` is passed.
+    pub fn exec_or_query_snapshot(
+        mut self,
+        matches: BuckArgMatches<'_>,
+        ctx: ClientCommandContext<'_>,
+    ) -> ExitResult {
+        let Some(snapshot) = self.snapshot.take() else {
+            return self.exec(matches, ctx);
+        };
+        ctx.instant_command_no_log("uquery", |ctx| async move {
+            let (query, query_args) = self.query_common.get_query(&ctx.working_dir)?;
+            query_snapshot(
+                &snapshot.resolve(&ctx.working_dir),
+                &query,
+                &query_args,
+                self.query_common.output_format(),
+                &self.query_common.attributes.get()?,
+                self.limits.to_limits(),
+            )
+            .await
+        })
+        .into()
+    }
+}
+
 #[async_trait]
 impl StreamingCommand for UqueryCommand {
     const COMMAND_NAME: &'static str = "uquery";
@@ -99,11 +154,51 @@ impl StreamingCommand for UqueryCommand {
         matches: BuckArgMatches<'_>,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let (query, query_args) = self.query_common.get_query();
+        let (query, query_args) = self.query_common.get_query(&ctx.working_dir)?;
         let unstable_output_format = self.query_common.output_format() as i32;
-        let output_attributes = self.query_common.attributes.get()?;
+        let output_attributes = self
+            .compare
+            .output_attributes(self.query_common.attributes.get()?);
         let context = ctx.client_context(matches, &self)?;
 
+        if self.compare.compare_to.is_some() {
+            CompareToOptions::check_query_args(&query_args)?;
+
+            let mut current = CaptureStdout::default();
+            let UqueryResponse {} = buckd
+                .with_flushing()
+                .uquery(
+                    UqueryRequest {
+                        query: query.clone(),
+                        query_args,
+                        context: Some(context),
+                        output_attributes: output_attributes.clone(),
+                        unstable_output_format: QueryOutputFormat::Json as i32,
+                        target_hash: self.compare.compare_attributes,
+                        limits: Some(self.limits.to_proto()?),
+                    },
+                    ctx.console_interaction_stream(&self.common_opts.console_opts),
+                    &mut current,
+                )
+                .await??;
+
+            let base = self
+                .compare
+                .query_base(
+                    ctx.paths()?,
+                    &ctx.working_dir,
+                    "uquery",
+                    &query,
+                    &output_attributes,
+                    &self.common_opts.config_opts,
+                    self.limits.to_args(),
+                )
+                .await?;
+            QueryDiff::new(&base, &current.buf)?
+                .print(self.query_common.output_format() == QueryOutputFormat::Json)?;
+            return ExitResult::success();
+        }
+
         let UqueryResponse {} = buckd
             .with_flushing()
             .uquery(
@@ -113,6 +208,8 @@ impl StreamingCommand for UqueryCommand {
                     context: Some(context),
                     output_attributes,
                     unstable_output_format,
+                    target_hash: self.target_hash,
+                    limits: Some(self.limits.to_proto()?),
                 },
                 ctx.console_interaction_stream(&self.common_opts.console_opts),
                 &mut StdoutPartialResultHandler,
@@ -10,6 +10,7 @@
 use async_trait::async_trait;
 use buck2_cli_proto::protobuf_util::ProtobufSplitter;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::target_cfg::TargetCfgWithUniverseOptions;
 use buck2_client_ctx::common::ui::CommonConsoleOptions;
 use buck2_client_ctx::common::ui::ConsoleType;
 use buck2_client_ctx::common::BuckArgMatches;
@@ -31,6 +32,8 @@ use once_cell::sync::Lazy;
 use prost::Message;
 use tokio_util::codec::FramedRead;
 
+use crate::commands::query::common::QueryLimitOptions;
+
 /// Open a subscription channel to the Buck2 daemon. This allows you to interact with the Buck2
 /// daemon via the `stdin` and `stdout` of this command: you send requests to the daemon by writing
 /// to `stdin`, and you get responses via `stdout`.
@@ -51,11 +54,25 @@ pub struct SubscribeCommand {
     #[clap(long)]
     active_commands: bool,
 
+    /// Subscribe to the result of this uquery. The query itself is the id of its results.
+    #[clap(long, value_name = "QUERY")]
+    uquery: Vec<String>,
+
+    /// Subscribe to the result of this cquery. The query itself is the id of its results.
+    #[clap(long, value_name = "QUERY")]
+    cquery: Vec<String>,
+
+    #[clap(flatten)]
+    limits: QueryLimitOptions,
+
     /// Whether to get output as JSON. The JSON format is deemed unstable so this should only be
     /// used for debugging.
     #[clap(long)]
     unstable_json: bool,
 
+    #[clap(flatten)]
+    target_cfg: TargetCfgWithUniverseOptions,
+
     #[clap(flatten)]
     config_opts: CommonBuildConfigurationOptions,
 
@@ -116,6 +133,36 @@ impl StreamingCommand for SubscribeCommand {
             stream.right_stream()
         };
 
+        let target_cfg = self.target_cfg.target_cfg.target_cfg();
+        let limits = self.limits.to_limits();
+        let queries = self
+            .uquery
+            .into_iter()
+            .map(|query| (buck2_subscription_proto::QueryKind::Uquery, query))
+            .chain(
+                self.cquery
+                    .into_iter()
+                    .map(|query| (buck2_subscription_proto::QueryKind::Cquery, query)),
+            )
+            .map(|(kind, query)| SubscriptionRequest {
+                request: Some(
+                    buck2_subscription_proto::SubscribeToQuery {
+                        id: query.clone(),
+                        kind: kind as i32,
+                        query,
+                        target_universe: self.target_cfg.target_universe.clone(),
+                        target_platform: target_cfg.target_platform.clone(),
+                        cli_modifiers: target_cfg.cli_modifiers.clone(),
+                        max_results: limits.max_results,
+                        max_traversal_nodes: limits.max_traversal_nodes,
+                        timeout_ms: limits.timeout.map(|t| t.as_millis() as u64),
+                    }
+                    .into(),
+                ),
+            })
+            .collect::<Vec<_>>();
+        let stream = futures::stream::iter(queries).chain(stream);
+
         let stream = stream.map(|request| buck2_cli_proto::SubscriptionRequestWrapper {
             request: Some(request),
         });
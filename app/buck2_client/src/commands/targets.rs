@@ -122,6 +122,15 @@ pub struct TargetsCommand {
     #[clap(long)]
     stats: bool,
 
+    /// Print a snapshot of the target graph, which `buck2 uquery --snapshot` can query
+    /// later without a daemon or a checkout of the repository.
+    #[clap(
+        long,
+        requires = "streaming",
+        conflicts_with_all = &["json", "json_lines", "stats", "resolve_alias"]
+    )]
+    snapshot: bool,
+
     /// Print the fully-qualified build target for the specified aliases
     #[clap(long, alias = "resolvealias")]
     resolve_alias: bool,
@@ -241,7 +250,12 @@ pub struct TargetsCommand {
 impl TargetsCommand {
     #[allow(clippy::if_same_then_else)]
     fn output_format(&self) -> buck2_error::Result<OutputFormat> {
-        if self.json {
+        if self.snapshot {
+            if self.json || self.json_lines || self.stats || !self.attributes.get()?.is_empty() {
+                return Err(TargetsError::IncompatibleArguments.into());
+            }
+            Ok(OutputFormat::Snapshot)
+        } else if self.json {
             if self.json_lines || self.stats {
                 return Err(TargetsError::IncompatibleArguments.into());
             }
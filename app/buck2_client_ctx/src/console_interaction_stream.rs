@@ -15,6 +15,8 @@ use crate::stdin::Stdin;
 pub struct ConsoleInteractionStream<'a> {
     stdin: &'a mut Stdin,
     term: InteractiveTerminal,
+    /// Whether the keys controlling an event log replay are bound.
+    replay_controls: bool,
 }
 
 impl<'a> ConsoleInteractionStream<'a> {
@@ -31,7 +33,20 @@ impl<'a> ConsoleInteractionStream<'a> {
             }
         };
 
-        Some(Self { stdin, term })
+        Some(Self {
+            stdin,
+            term,
+            replay_controls: false,
+        })
+    }
+
+    /// Bind the keys that pause, step and change the speed of an event log replay. These are
+    /// only bound while replaying, so that they don't take up keys in other commands.
+    pub fn with_replay_controls(self) -> Self {
+        Self {
+            replay_controls: true,
+            ..self
+        }
     }
 }
 
@@ -139,6 +154,13 @@ pub enum SuperConsoleToggle {
     IncrLines,
     DecrLines,
     Help,
+    ReplayPause,
+    ReplayStepEvent,
+    ReplayStepActionStart,
+    ReplayStepFailure,
+    ReplaySeekForward,
+    ReplayFaster,
+    ReplaySlower,
 }
 
 impl SuperConsoleToggle {
@@ -155,6 +177,13 @@ impl SuperConsoleToggle {
             SuperConsoleToggle::IncrLines => "more lines",
             SuperConsoleToggle::DecrLines => "less lines",
             SuperConsoleToggle::Help => "help",
+            SuperConsoleToggle::ReplayPause => "pause replay",
+            SuperConsoleToggle::ReplayStepEvent => "step to next event",
+            SuperConsoleToggle::ReplayStepActionStart => "step to next action start",
+            SuperConsoleToggle::ReplayStepFailure => "step to next action failure",
+            SuperConsoleToggle::ReplaySeekForward => "skip ahead 10 seconds",
+            SuperConsoleToggle::ReplayFaster => "replay faster",
+            SuperConsoleToggle::ReplaySlower => "replay slower",
         }
     }
 
@@ -171,8 +200,29 @@ impl SuperConsoleToggle {
             SuperConsoleToggle::IncrLines => '+',
             SuperConsoleToggle::DecrLines => '-',
             SuperConsoleToggle::Help => '?',
+            SuperConsoleToggle::ReplayPause => ' ',
+            SuperConsoleToggle::ReplayStepEvent => '.',
+            SuperConsoleToggle::ReplayStepActionStart => 'n',
+            SuperConsoleToggle::ReplayStepFailure => 'f',
+            SuperConsoleToggle::ReplaySeekForward => ']',
+            SuperConsoleToggle::ReplayFaster => '>',
+            SuperConsoleToggle::ReplaySlower => '<',
         }
     }
+
+    /// Controls that only apply when replaying an event log.
+    pub fn is_replay_control(&self) -> bool {
+        matches!(
+            self,
+            SuperConsoleToggle::ReplayPause
+                | SuperConsoleToggle::ReplayStepEvent
+                | SuperConsoleToggle::ReplayStepActionStart
+                | SuperConsoleToggle::ReplayStepFailure
+                | SuperConsoleToggle::ReplaySeekForward
+                | SuperConsoleToggle::ReplayFaster
+                | SuperConsoleToggle::ReplaySlower
+        )
+    }
 }
 
 #[async_trait::async_trait]
@@ -198,6 +248,13 @@ impl<'a> SuperConsoleInteraction for ConsoleInteractionStream<'a> {
                     '+' => Some(SuperConsoleToggle::IncrLines),
                     '-' => Some(SuperConsoleToggle::DecrLines),
                     '?' | 'h' => Some(SuperConsoleToggle::Help),
+                    ' ' if self.replay_controls => Some(SuperConsoleToggle::ReplayPause),
+                    '.' if self.replay_controls => Some(SuperConsoleToggle::ReplayStepEvent),
+                    'n' if self.replay_controls => Some(SuperConsoleToggle::ReplayStepActionStart),
+                    'f' if self.replay_controls => Some(SuperConsoleToggle::ReplayStepFailure),
+                    ']' if self.replay_controls => Some(SuperConsoleToggle::ReplaySeekForward),
+                    '>' if self.replay_controls => Some(SuperConsoleToggle::ReplayFaster),
+                    '<' if self.replay_controls => Some(SuperConsoleToggle::ReplaySlower),
                     _ => None,
                 };
                 Ok(console_toggle)
@@ -8,148 +8,502 @@
  */
 
 use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
 use std::time::SystemTime;
 
+use buck2_error::BuckErrorContext;
 use buck2_event_log::read::EventLogPathBuf;
 use buck2_event_log::stream_value::StreamValue;
 use buck2_event_log::utils::Invocation;
+use buck2_event_observer::display::display_action_key;
+use buck2_event_observer::display::TargetDisplayOptions;
+use dupe::Dupe;
 use futures::stream::BoxStream;
 use futures::task::Poll;
-use futures::Future;
 use futures::Stream;
 use futures::StreamExt;
 use futures::TryStreamExt;
-use pin_project::pin_project;
+use tokio::sync::watch;
 use tokio::time::Instant;
-use tokio::time::Sleep;
 
-#[pin_project]
-struct Pending {
-    #[pin]
-    delay: Sleep,
-    event: Option<Box<buck2_data::BuckEvent>>,
+#[derive(Debug, buck2_error::Error)]
+#[buck2(tag = Input)]
+pub enum ReplayStopAtError {
+    #[error("Empty condition in `--stop-at` expression `{0}`")]
+    EmptyCondition(String),
+    #[error(
+        "Unknown condition `{0}` in `--stop-at` expression, expected `failure`, `category=<CATEGORY>` or `target=<TARGET>`"
+    )]
+    UnknownCondition(String),
 }
 
-#[pin_project]
-pub struct Replayer<T = BoxStream<'static, buck2_error::Result<StreamValue>>> {
-    #[pin]
-    events: T,
-    was_complete: bool,
-    syncher: Syncher,
-    #[pin]
-    pending: Option<Pending>,
+/// What to play (without delay) before pausing again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Event,
+    ActionStart,
+    Failure,
+}
+
+impl Step {
+    fn matches(self, event: &buck2_data::BuckEvent) -> bool {
+        match self {
+            Step::Event => true,
+            Step::ActionStart => {
+                matches!(action_event(event), Some(ActionEvent { failed: None, .. }))
+            }
+            Step::Failure => matches!(
+                action_event(event),
+                Some(ActionEvent {
+                    failed: Some(true),
+                    ..
+                })
+            ),
+        }
+    }
+}
+
+/// Bounds for changing the speed from the console, so that repeatedly halving or doubling it never
+/// reaches zero or infinity.
+const MIN_SPEED: f64 = 1.0 / 1024.0;
+const MAX_SPEED: f64 = 1024.0;
+
+/// How far ahead in the log seeking from the console skips.
+const SEEK_STEP: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+struct ControlState {
+    paused: bool,
+    speed: f64,
+    step: Option<Step>,
+    /// Log time to skip ahead by, requested from the console. The playback turns it into a
+    /// `seek_to` when it sees it, since only the playback knows where in the log it is.
+    skip: Duration,
+    /// Play everything before this far into the log without delay.
+    seek_to: Option<Duration>,
+}
+
+/// Shared handle to control a running replay, typically from console key presses.
+#[derive(Clone, Dupe)]
+pub struct ReplayControl {
+    state: Arc<watch::Sender<ControlState>>,
+    /// Whether events were played since the console last rendered them.
+    played: Arc<AtomicBool>,
+}
+
+impl ReplayControl {
+    pub fn new(speed: Option<f64>) -> Self {
+        let (state, _) = watch::channel(ControlState {
+            paused: false,
+            speed: speed.unwrap_or(1.0),
+            step: None,
+            skip: Duration::ZERO,
+            seek_to: None,
+        });
+        Self {
+            state: Arc::new(state),
+            played: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the replay is paused, and not currently stepping or seeking to an event.
+    pub fn is_paused(&self) -> bool {
+        let state = self.state.borrow();
+        state.paused && state.step.is_none() && state.skip.is_zero() && state.seek_to.is_none()
+    }
+
+    /// Whether events were played since the last call. While paused, the console only needs to
+    /// render what stepping or seeking played.
+    pub fn take_played(&self) -> bool {
+        self.played.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.state.borrow().speed
+    }
+
+    pub fn toggle_pause(&self) {
+        self.state.send_modify(|s| {
+            s.paused = !s.paused;
+            s.step = None;
+        });
+    }
+
+    fn pause(&self) {
+        self.state.send_modify(|s| {
+            s.paused = true;
+            s.step = None;
+        });
+    }
+
+    fn step(&self, step: Step) {
+        self.state.send_modify(|s| {
+            s.paused = true;
+            s.step = Some(step);
+        });
+    }
+
+    /// Play the next event and pause.
+    pub fn step_to_next_event(&self) {
+        self.step(Step::Event)
+    }
+
+    /// Play until the next action starts and pause.
+    pub fn step_to_next_action_start(&self) {
+        self.step(Step::ActionStart)
+    }
+
+    /// Play until the next action fails and pause.
+    pub fn step_to_next_failure(&self) {
+        self.step(Step::Failure)
+    }
+
+    /// Skip ahead in the log, playing everything in between without delay. A paused replay stays
+    /// paused once it gets there.
+    pub fn seek_forward(&self) {
+        self.state.send_modify(|s| {
+            s.skip += SEEK_STEP;
+            s.step = None;
+        });
+    }
+
+    fn seek_to(&self, position: Duration) {
+        self.state.send_modify(|s| s.seek_to = Some(position));
+    }
+
+    pub fn faster(&self) {
+        self.state.send_modify(|s| {
+            if s.speed * 2.0 <= MAX_SPEED {
+                s.speed *= 2.0;
+            }
+        });
+    }
+
+    pub fn slower(&self) {
+        self.state.send_modify(|s| {
+            if s.speed / 2.0 >= MIN_SPEED {
+                s.speed /= 2.0;
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+enum StopAtCondition {
+    Failure,
+    Category(String),
+    Target(String),
+}
+
+/// A `--stop-at` expression: comma-separated conditions that must all hold for an action start or
+/// end event for the replay to pause on it, e.g. `category=cxx_compile,failure`.
+#[derive(Debug, Clone)]
+pub struct ReplayStopAt {
+    conditions: Vec<StopAtCondition>,
+}
+
+impl FromStr for ReplayStopAt {
+    type Err = ReplayStopAtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let conditions = s
+            .split(',')
+            .map(|condition| {
+                let condition = condition.trim();
+                if condition.is_empty() {
+                    return Err(ReplayStopAtError::EmptyCondition(s.to_owned()));
+                }
+                match condition.split_once('=') {
+                    None if condition == "failure" => Ok(StopAtCondition::Failure),
+                    Some(("category", category)) => {
+                        Ok(StopAtCondition::Category(category.to_owned()))
+                    }
+                    Some(("target", target)) => Ok(StopAtCondition::Target(target.to_owned())),
+                    _ => Err(ReplayStopAtError::UnknownCondition(condition.to_owned())),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { conditions })
+    }
+}
+
+impl ReplayStopAt {
+    fn matches(&self, event: &buck2_data::BuckEvent) -> bool {
+        let Some(action) = action_event(event) else {
+            return false;
+        };
+        self.conditions.iter().all(|condition| match condition {
+            StopAtCondition::Failure => action.failed == Some(true),
+            StopAtCondition::Category(category) => {
+                action.name.is_some_and(|name| &name.category == category)
+            }
+            StopAtCondition::Target(target) => action.key.is_some_and(|key| {
+                display_action_key(key, TargetDisplayOptions::for_console(false))
+                    .is_ok_and(|label| target_matches(&label, target))
+            }),
+        })
+    }
+}
+
+/// Match a label like `cell//foo:bar` against `//foo:bar`, `cell//foo:bar` or a `//foo/...`
+/// pattern.
+fn target_matches(label: &str, pattern: &str) -> bool {
+    let without_cell = label.find("//").map_or(label, |i| &label[i..]);
+    match pattern.strip_suffix("...") {
+        Some(package) => {
+            let package = package.trim_end_matches('/');
+            [label, without_cell].iter().any(|l| {
+                l.strip_prefix(package)
+                    .is_some_and(|rest| rest.starts_with('/') || rest.starts_with(':'))
+            })
+        }
+        None => label == pattern || without_cell == pattern,
+    }
+}
+
+struct ActionEvent<'a> {
+    key: Option<&'a buck2_data::ActionKey>,
+    name: Option<&'a buck2_data::ActionName>,
+    /// `None` for the start of an action.
+    failed: Option<bool>,
+}
+
+fn action_event(event: &buck2_data::BuckEvent) -> Option<ActionEvent<'_>> {
+    match event.data.as_ref()? {
+        buck2_data::buck_event::Data::SpanStart(start) => match start.data.as_ref()? {
+            buck2_data::span_start_event::Data::ActionExecution(action) => Some(ActionEvent {
+                key: action.key.as_ref(),
+                name: action.name.as_ref(),
+                failed: None,
+            }),
+            _ => None,
+        },
+        buck2_data::buck_event::Data::SpanEnd(end) => match end.data.as_ref()? {
+            buck2_data::span_end_event::Data::ActionExecution(action) => Some(ActionEvent {
+                key: action.key.as_ref(),
+                name: action.name.as_ref(),
+                failed: Some(action.failed),
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub struct Replayer {
+    events: BoxStream<'static, buck2_error::Result<StreamValue>>,
 }
 
 impl Replayer {
     pub async fn new(
         log_path: EventLogPathBuf,
-        speed: Option<f64>,
+        control: ReplayControl,
         preload: bool,
+        seek: Option<Duration>,
+        stop_at: Option<ReplayStopAt>,
     ) -> buck2_error::Result<(Self, Invocation)> {
         let (invocation, events) = log_path.unpack_stream().await?;
 
         let events = if preload {
             let events = events.try_collect::<Vec<_>>().await?;
-            futures::stream::iter(events).map(Ok).left_stream()
+            futures::stream::iter(events).map(Ok).boxed()
         } else {
-            events.right_stream()
+            events.boxed()
         };
 
-        let syncher = Syncher::new(speed);
-
-        let myself = Self {
-            events: Box::pin(events),
+        if let Some(seek) = seek {
+            control.seek_to(seek);
+        }
+        let playback = Playback {
+            events,
+            updates: control.state.subscribe(),
+            control,
+            stop_at,
+            log_start: None,
+            last_event_time: None,
+            anchor: None,
             was_complete: false,
-            pending: None,
-            syncher,
         };
 
-        Ok((myself, invocation))
-    }
+        let events = futures::stream::unfold(playback, |mut playback| async move {
+            let next = playback.next().await?;
+            Some((next, playback))
+        })
+        .boxed();
 
-    pub fn speed(&self) -> f64 {
-        self.syncher.speed
+        Ok((Self { events }, invocation))
     }
 }
 
-/// Handle time drifting when replaying events - add pauses in between events to simulate the real deal.
-struct Syncher {
-    start: Option<(Instant, SystemTime)>,
-    speed: f64,
+/// Handle time drifting when replaying events - add pauses in between events to simulate the real
+/// deal, while following the commands sent through the `ReplayControl`.
+struct Playback {
+    events: BoxStream<'static, buck2_error::Result<StreamValue>>,
+    control: ReplayControl,
+    updates: watch::Receiver<ControlState>,
+    stop_at: Option<ReplayStopAt>,
+    log_start: Option<SystemTime>,
+    last_event_time: Option<SystemTime>,
+    /// A point in real time and the log time it corresponds to. Reset whenever the playback is
+    /// paused or changes speed.
+    anchor: Option<(Instant, SystemTime)>,
+    was_complete: bool,
 }
-impl Syncher {
-    fn new(playback_speed: Option<f64>) -> Self {
-        Self {
-            start: None,
-            speed: playback_speed.unwrap_or(1.0),
+
+impl Playback {
+    async fn next(&mut self) -> Option<buck2_error::Result<StreamValue>> {
+        if self.was_complete {
+            return None;
+        }
+
+        let event = match self.events.next().await {
+            Some(Ok(StreamValue::Event(event))) => event,
+            // If the stream has errored out, finished, or contains a result, flag completion.
+            event => {
+                self.was_complete = true;
+                return event;
+            }
+        };
+
+        match self.wait_for(&event).await {
+            Ok(seeking) => {
+                self.control.played.store(true, Ordering::Relaxed);
+                // Don't hold on to the borrow here: pausing needs to write the state.
+                let step = self.updates.borrow().step;
+                if let Some(step) = step {
+                    if step.matches(&event) {
+                        self.control.pause();
+                    }
+                }
+                if !seeking && self.stop_at.as_ref().is_some_and(|s| s.matches(&event)) {
+                    self.control.pause();
+                }
+                Some(Ok(StreamValue::Event(event)))
+            }
+            Err(e) => {
+                self.was_complete = true;
+                Some(Err(e))
+            }
         }
     }
 
-    /// Returns an appropriate delay for this event.
-    ///
-    /// The first event will be sent immediately. Each subsequent event will be sent with a delay
-    /// based on its time since that first event.
-    fn synch_playback_time(&mut self, event: &buck2_data::BuckEvent) -> buck2_error::Result<Sleep> {
-        let event_time = SystemTime::try_from(event.timestamp.as_ref().unwrap().clone())?;
-        let (sync_start, log_start) = self.start.get_or_insert((Instant::now(), event_time));
-        let log_offset_time = event_time.duration_since(*log_start)?;
-        let sync_offset_time = log_offset_time.div_f64(self.speed);
-        let sync_event_time = *sync_start + sync_offset_time;
-        Ok(tokio::time::sleep_until(sync_event_time))
+    /// Wait until it's time to play this event. Returns whether we're still seeking.
+    async fn wait_for(&mut self, event: &buck2_data::BuckEvent) -> buck2_error::Result<bool> {
+        let event_time = SystemTime::try_from(
+            event
+                .timestamp
+                .clone()
+                .buck_error_context("Event is missing a timestamp")?,
+        )?;
+        let log_start = *self.log_start.get_or_insert(event_time);
+        let last_event_time = self.last_event_time.replace(event_time);
+        let position = event_time.duration_since(log_start).unwrap_or_default();
+
+        loop {
+            let state = self.updates.borrow_and_update().clone();
+            if !state.skip.is_zero() {
+                // Skipping again while seeking skips further ahead of where we're seeking to.
+                self.control.state.send_modify(|s| {
+                    s.seek_to = Some(s.seek_to.map_or(position, |t| t.max(position)) + s.skip);
+                    s.skip = Duration::ZERO;
+                });
+                continue;
+            }
+            if let Some(seek_to) = state.seek_to {
+                if position < seek_to {
+                    self.anchor = None;
+                    return Ok(true);
+                }
+                self.control.state.send_modify(|s| s.seek_to = None);
+                continue;
+            }
+            if state.step.is_some() {
+                self.anchor = None;
+                return Ok(false);
+            }
+            if state.paused {
+                self.anchor = None;
+                // We hold a `ReplayControl` ourselves, so the sender can't be dropped.
+                let _ignored = self.updates.changed().await;
+                continue;
+            }
+
+            let (sync_start, anchor_time) = *self
+                .anchor
+                .get_or_insert_with(|| (Instant::now(), last_event_time.unwrap_or(event_time)));
+            let offset = event_time.duration_since(anchor_time).unwrap_or_default();
+            let deadline = sync_start + offset.div_f64(state.speed);
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => return Ok(false),
+                _ = self.updates.changed() => self.anchor = None,
+            }
+        }
     }
 }
 
-impl<T> Stream for Replayer<T>
-where
-    T: Stream<Item = buck2_error::Result<StreamValue>>,
-{
+impl Stream for Replayer {
     type Item = buck2_error::Result<StreamValue>;
 
     fn poll_next(
-        self: Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        // The basic idea is to hold on to the next event and a `sleep` until it's elapsed.
-        // At this point, return the event.  Then grab a new one and a sleep and repeat the process.
-        // Knowing whether the stream is done is dependent on whether the previous iteration either
-        // erred or returned a result, so we keep track of that as well.
-
-        let mut this = self.project();
-
-        if *this.was_complete {
-            return Poll::Ready(None);
-        }
-
-        if this.pending.is_none() {
-            let event = futures::ready!(this.events.poll_next(cx));
+        self.events.poll_next_unpin(cx)
+    }
+}
 
-            match event {
-                Some(Ok(StreamValue::Event(event))) => {
-                    let delay = this.syncher.synch_playback_time(&event)?;
-                    this.pending.set(Some(Pending {
-                        delay,
-                        event: Some(event),
-                    }));
-                }
-                // If the stream has errored out, finished, or contains a result, flag completion.
-                _ => {
-                    *this.was_complete = true;
-                    return Poll::Ready(event);
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Unwrap is safe: we just checked this was Some.
-        let pending = this.pending.as_mut().as_pin_mut().unwrap().project();
+    #[test]
+    fn test_parse_stop_at() {
+        let stop_at: ReplayStopAt = "category=cxx_compile, failure".parse().unwrap();
+        assert!(matches!(
+            stop_at.conditions.as_slice(),
+            [StopAtCondition::Category(c), StopAtCondition::Failure] if c == "cxx_compile"
+        ));
+        assert!("failure,".parse::<ReplayStopAt>().is_err());
+        assert!("kind=run".parse::<ReplayStopAt>().is_err());
+    }
 
-        futures::ready!(pending.delay.poll(cx));
+    #[test]
+    fn test_target_matches() {
+        assert!(target_matches("root//foo:bar", "//foo:bar"));
+        assert!(target_matches("root//foo:bar", "root//foo:bar"));
+        assert!(target_matches("root//foo/baz:bar", "//foo/..."));
+        assert!(target_matches("root//foo:bar", "//foo/..."));
+        assert!(!target_matches("root//foobar:bar", "//foo/..."));
+        assert!(!target_matches("root//foo:bar", "//foo:baz"));
+        assert!(!target_matches("root//bar:foo", "//foo/..."));
+    }
 
-        // Take the event and remove the Pending entry. This unwrap() is safe since if we have a
-        // reference to this Pending the event cannot have been taken yet, since we immediately
-        // delete it on the next line.
-        let event = pending.event.take().unwrap();
-        this.pending.set(None);
+    #[test]
+    fn test_seek_forward_while_paused() {
+        let control = ReplayControl::new(None);
+        control.toggle_pause();
+        assert!(control.is_paused());
+        control.seek_forward();
+        assert!(!control.is_paused());
+        control.seek_forward();
+        assert_eq!(control.state.borrow().skip, SEEK_STEP * 2);
+    }
 
-        Poll::Ready(Some(Ok(StreamValue::Event(event))))
+    #[test]
+    fn test_speed_is_bounded() {
+        let control = ReplayControl::new(None);
+        for _ in 0..2000 {
+            control.slower();
+        }
+        assert_eq!(control.speed(), MIN_SPEED);
+        for _ in 0..2000 {
+            control.faster();
+        }
+        assert_eq!(control.speed(), MAX_SPEED);
     }
 }
@@ -49,6 +49,7 @@ use superconsole::Span;
 pub(crate) use superconsole::SuperConsole;
 
 use crate::console_interaction_stream::SuperConsoleToggle;
+use crate::replayer::ReplayControl;
 use crate::subscribers::emit_event::emit_event_if_relevant;
 use crate::subscribers::simpleconsole::SimpleConsole;
 use crate::subscribers::subscriber::EventSubscriber;
@@ -153,6 +154,8 @@ pub struct SuperConsoleConfig {
     /// Two lines for root events with single child event.
     pub two_lines: bool,
     pub max_lines: usize,
+    /// Set when replaying an event log, to pause and step through the replay.
+    pub replay_control: Option<ReplayControl>,
 }
 
 impl Default for SuperConsoleConfig {
@@ -167,6 +170,7 @@ impl Default for SuperConsoleConfig {
             display_platform: false,
             two_lines: false,
             max_lines: 10,
+            replay_control: None,
         }
     }
 }
@@ -748,8 +752,33 @@ impl StatefulSuperConsoleImpl {
                 SuperConsoleToggle::DecrLines { .. } => {
                     self.state.config.max_lines = self.state.config.max_lines.saturating_sub(1)
                 }
+                SuperConsoleToggle::ReplayPause
+                | SuperConsoleToggle::ReplayStepEvent
+                | SuperConsoleToggle::ReplayStepActionStart
+                | SuperConsoleToggle::ReplayStepFailure
+                | SuperConsoleToggle::ReplaySeekForward
+                | SuperConsoleToggle::ReplayFaster
+                | SuperConsoleToggle::ReplaySlower => {
+                    if let Some(control) = &self.state.config.replay_control {
+                        match c {
+                            SuperConsoleToggle::ReplayPause => control.toggle_pause(),
+                            SuperConsoleToggle::ReplayStepEvent => control.step_to_next_event(),
+                            SuperConsoleToggle::ReplayStepActionStart => {
+                                control.step_to_next_action_start()
+                            }
+                            SuperConsoleToggle::ReplayStepFailure => control.step_to_next_failure(),
+                            SuperConsoleToggle::ReplaySeekForward => control.seek_forward(),
+                            SuperConsoleToggle::ReplayFaster => control.faster(),
+                            SuperConsoleToggle::ReplaySlower => control.slower(),
+                            _ => {}
+                        }
+                        self.state.time_speed = TimeSpeed::new(Some(control.speed()))?;
+                    }
+                }
                 SuperConsoleToggle::Help { .. } => {
+                    let replay = self.state.config.replay_control.is_some();
                     let help_message = SuperConsoleToggle::iter()
+                        .filter(|t| replay || !t.is_replay_control())
                         .map(|t| format!("`{}` = toggle {}", t.key(), t.description()))
                         .collect::<Vec<_>>()
                         .join("\n");
@@ -767,6 +796,13 @@ impl StatefulSuperConsoleImpl {
 
     async fn tick(&mut self, tick: &Tick) -> buck2_error::Result<()> {
         self.state.current_tick = tick.dupe();
+        // Freeze the console on the moment the replay was paused, or on wherever stepping or
+        // seeking took it since.
+        if let Some(control) = &self.state.config.replay_control {
+            if !control.take_played() && control.is_paused() {
+                return Ok(());
+            }
+        }
         self.super_console
             .render(&BuckRootComponent {
                 header: &self.header,
@@ -902,7 +938,9 @@ fn lines_for_command_details(
                     help_message.with(Color::DarkRed),
                 )]));
             }
-            Some(Command::OmittedLocalCommand(..)) | None => {
+            Some(Command::OmittedLocalCommand(..))
+            | Some(Command::LocalDiskCacheHit(..))
+            | None => {
                 // Nothing to show in this case.
             }
             Some(Command::WorkerInitCommand(worker_init_command)) => {
@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+pub mod lockfile;
+
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -0,0 +1,200 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The external cells lockfile.
+//!
+//! Git external cells may name a branch or tag via `git_ref` instead of an exact `commit_hash`.
+//! The commit that ref resolves to is recorded in a lockfile that is checked into the root of the
+//! project, and is updated by `buck2 external-cells update`. Builds only ever read the lockfile,
+//! they never resolve refs themselves.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use buck2_core::cells::name::CellName;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_error::BuckErrorContext;
+
+use crate::legacy_configs::file_ops::ConfigParserFileOps;
+use crate::legacy_configs::file_ops::ConfigPath;
+
+/// Name of the lockfile, relative to the project root.
+pub const EXTERNAL_CELLS_LOCKFILE: &str = "external_cells.lock";
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Input)]
+enum LockfileError {
+    #[error(
+        "External cell `{0}` uses `git_ref`, but `external_cells.lock` has no entry for it. Run `buck2 external-cells update` to add one"
+    )]
+    MissingEntry(CellName),
+    #[error(
+        "External cell `{cell}` is configured with `{property} = {configured}`, but `external_cells.lock` was generated for `{locked}`. Run `buck2 external-cells update` to update the lockfile"
+    )]
+    Mismatch {
+        cell: CellName,
+        property: &'static str,
+        configured: String,
+        locked: String,
+    },
+}
+
+/// A git cell's ref, together with the commit it was resolved to.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LockedGitCell {
+    pub git_origin: String,
+    pub git_ref: String,
+    pub commit_hash: String,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize
+)]
+pub struct ExternalCellsLockfile {
+    /// Keyed by cell name.
+    #[serde(default)]
+    pub git: BTreeMap<String, LockedGitCell>,
+}
+
+impl ExternalCellsLockfile {
+    pub fn path() -> &'static ProjectRelativePath {
+        ProjectRelativePath::unchecked_new(EXTERNAL_CELLS_LOCKFILE)
+    }
+
+    pub fn parse(contents: &str) -> buck2_error::Result<Self> {
+        serde_json::from_str(contents)
+            .with_buck_error_context(|| format!("Error parsing `{}`", EXTERNAL_CELLS_LOCKFILE))
+    }
+
+    /// Serialize the lockfile. The output is stable so that it diffs nicely under version control.
+    pub fn to_json_string(&self) -> buck2_error::Result<String> {
+        let mut out = serde_json::to_string_pretty(self)?;
+        out.push('\n');
+        Ok(out)
+    }
+
+    /// Read the lockfile from the root of the project, if there is one.
+    pub async fn read(file_ops: &mut dyn ConfigParserFileOps) -> buck2_error::Result<Option<Self>> {
+        let Some(lines) = file_ops
+            .read_file_lines_if_exists(&ConfigPath::Project(Self::path().to_buf()))
+            .await?
+        else {
+            return Ok(None);
+        };
+        let contents = lines
+            .collect::<Result<Vec<_>, _>>()
+            .with_buck_error_context(|| format!("Error reading `{}`", EXTERNAL_CELLS_LOCKFILE))?
+            .join("\n");
+        Ok(Some(Self::parse(&contents)?))
+    }
+
+    /// Find the commit that a git cell's ref is locked to, checking that the lockfile entry was
+    /// generated from the same configuration.
+    pub fn locked_git_commit(
+        lockfile: Option<&Self>,
+        cell: CellName,
+        git_origin: &str,
+        git_ref: &str,
+    ) -> buck2_error::Result<Arc<str>> {
+        let entry = lockfile
+            .and_then(|l| l.git.get(cell.as_str()))
+            .ok_or(LockfileError::MissingEntry(cell))?;
+        for (property, configured, locked) in [
+            ("git_origin", git_origin, &entry.git_origin),
+            ("git_ref", git_ref, &entry.git_ref),
+        ] {
+            if configured != locked.as_str() {
+                return Err(LockfileError::Mismatch {
+                    cell,
+                    property,
+                    configured: configured.to_owned(),
+                    locked: locked.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(entry.commit_hash.as_str().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lockfile() -> ExternalCellsLockfile {
+        ExternalCellsLockfile::parse(indoc!(
+            r#"
+                {
+                  "git": {
+                    "libfoo": {
+                      "git_origin": "https://github.com/jeff/libfoo.git",
+                      "git_ref": "main",
+                      "commit_hash": "aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee"
+                    }
+                  }
+                }
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let lockfile = lockfile();
+        let serialized = lockfile.to_json_string().unwrap();
+        assert_eq!(ExternalCellsLockfile::parse(&serialized).unwrap(), lockfile);
+    }
+
+    #[test]
+    fn test_locked_git_commit() {
+        let lockfile = lockfile();
+        assert_eq!(
+            &*ExternalCellsLockfile::locked_git_commit(
+                Some(&lockfile),
+                CellName::testing_new("libfoo"),
+                "https://github.com/jeff/libfoo.git",
+                "main",
+            )
+            .unwrap(),
+            "aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee"
+        );
+    }
+
+    #[test]
+    fn test_locked_git_commit_mismatch() {
+        let lockfile = lockfile();
+        let e = ExternalCellsLockfile::locked_git_commit(
+            Some(&lockfile),
+            CellName::testing_new("libfoo"),
+            "https://github.com/jeff/libfoo.git",
+            "v1.0",
+        )
+        .unwrap_err();
+        let e = format!("{:?}", e);
+        assert!(e.contains("`git_ref = v1.0`"), "error: {}", e);
+
+        let e = ExternalCellsLockfile::locked_git_commit(
+            None,
+            CellName::testing_new("libfoo"),
+            "https://github.com/jeff/libfoo.git",
+            "main",
+        )
+        .unwrap_err();
+        let e = format!("{:?}", e);
+        assert!(e.contains("has no entry"), "error: {}", e);
+    }
+}
@@ -91,6 +91,13 @@ impl InvocationPaths {
             .join(ForwardRelativePath::unchecked_new("log"))
     }
 
+    /// Record of logs removed by the log retention policy. Lives outside of `log_dir` so that it
+    /// isn't itself cleaned up as a log.
+    pub fn log_evictions_path(&self) -> AbsNormPathBuf {
+        self.buck_out_path()
+            .join(ForwardRelativePath::unchecked_new("log_evictions.jsonl"))
+    }
+
     pub fn tmp_dir(&self) -> AbsNormPathBuf {
         self.buck_out_path()
             .join(ForwardRelativePath::unchecked_new("tmp"))
@@ -157,8 +164,33 @@ impl InvocationPaths {
         FileName::unchecked_new("materializer_state")
     }
 
+    /// Subdirectory of `cache_dir` where file watchers persist what they know about the repo
+    /// across daemon restarts
+    pub fn file_watcher_state_path(&self) -> AbsNormPathBuf {
+        self.cache_dir_path()
+            .join(self.file_watcher_state_dir_name())
+    }
+
+    pub fn file_watcher_state_dir_name(&self) -> &FileName {
+        FileName::unchecked_new("file_watcher_state")
+    }
+
+    /// Subdirectory of `cache_dir` where hybrid execution keeps how long actions took on each
+    /// executor
+    pub fn hybrid_history_path(&self) -> AbsNormPathBuf {
+        self.cache_dir_path().join(self.hybrid_history_dir_name())
+    }
+
+    pub fn hybrid_history_dir_name(&self) -> &FileName {
+        FileName::unchecked_new("hybrid_history")
+    }
+
     pub fn valid_cache_dirs(&self) -> Vec<&FileName> {
-        vec![self.materializer_state_dir_name()]
+        vec![
+            self.materializer_state_dir_name(),
+            self.file_watcher_state_dir_name(),
+            self.hybrid_history_dir_name(),
+        ]
     }
 }
 
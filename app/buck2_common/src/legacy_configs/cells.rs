@@ -15,8 +15,10 @@ use buck2_core::buck2_env;
 use buck2_core::cells::alias::NonEmptyCellAlias;
 use buck2_core::cells::cell_root_path::CellRootPath;
 use buck2_core::cells::cell_root_path::CellRootPathBuf;
+use buck2_core::cells::external::ArchiveCellSetup;
 use buck2_core::cells::external::ExternalCellOrigin;
 use buck2_core::cells::external::GitCellSetup;
+use buck2_core::cells::external::OverlayCellSetup;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellAliasResolver;
 use buck2_core::cells::CellResolver;
@@ -25,6 +27,7 @@ use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::paths::RelativePath;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_error::BuckErrorContext;
 use dice::DiceComputations;
 use dupe::Dupe;
@@ -32,6 +35,7 @@ use dupe::Dupe;
 use crate::cas_digest::RawDigest;
 use crate::dice::cells::HasCellResolver;
 use crate::dice::data::HasIoProvider;
+use crate::external_cells::lockfile::ExternalCellsLockfile;
 use crate::external_cells::EXTERNAL_CELLS_IMPL;
 use crate::legacy_configs::aggregator::CellsAggregator;
 use crate::legacy_configs::args::resolve_config_args;
@@ -52,6 +56,14 @@ use crate::legacy_configs::path::ProjectConfigSource;
 use crate::legacy_configs::path::DEFAULT_EXTERNAL_CONFIG_SOURCES;
 use crate::legacy_configs::path::DEFAULT_PROJECT_CONFIG_SOURCES;
 
+/// A git external cell that names a branch or tag rather than a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRefCell {
+    pub name: CellName,
+    pub git_origin: String,
+    pub git_ref: String,
+}
+
 /// Buckconfigs can partially be loaded from within dice. However, some parts of what makes up the
 /// buckconfig comes from outside the buildgraph, and this type represents those parts.
 #[derive(PartialEq, Eq, Allocative)]
@@ -215,6 +227,52 @@ impl BuckConfigBasedCells {
         .await
     }
 
+    /// Find the git external cells which are configured with a `git_ref`, without checking them
+    /// against the lockfile.
+    ///
+    /// This is what updates the lockfile, so it must work even when the lockfile is out of date.
+    pub async fn parse_git_ref_cells_with_config_args(
+        project_fs: &ProjectRoot,
+        config_args: &[buck2_cli_proto::ConfigOverride],
+    ) -> buck2_error::Result<Vec<GitRefCell>> {
+        let mut file_ops = DefaultConfigParserFileOps {
+            project_fs: project_fs.dupe(),
+        };
+        let processed_config_args = resolve_config_args(config_args, &mut file_ops).await?;
+        let (root_config, _) = Self::parse_root_config(
+            &mut file_ops,
+            &processed_config_args,
+            true, /* follow includes */
+        )
+        .await?;
+        let aggregator = Self::cells_aggregator(&root_config)?;
+
+        let mut cells = Vec::new();
+        if let Some(external_cells) = root_config.get_section("external_cells") {
+            for (alias, origin) in external_cells.iter() {
+                let name =
+                    aggregator.resolve_root_alias(NonEmptyCellAlias::new(alias.to_owned())?)?;
+                let section = &format!("external_cell_{}", name.as_str());
+                let get = |property| root_config.get(BuckconfigKeyRef { section, property });
+                let origin = match origin.as_str() {
+                    "overlay" => get("base"),
+                    origin => Some(origin),
+                };
+                if origin != Some("git") {
+                    continue;
+                }
+                if let (Some(git_origin), Some(git_ref)) = (get("git_origin"), get("git_ref")) {
+                    cells.push(GitRefCell {
+                        name,
+                        git_origin: git_origin.to_owned(),
+                        git_ref: git_ref.to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(cells)
+    }
+
     pub async fn testing_parse_with_file_ops(
         file_ops: &mut dyn ConfigParserFileOps,
         config_args: &[buck2_cli_proto::ConfigOverride],
@@ -281,28 +339,89 @@ impl BuckConfigBasedCells {
         // NOTE: This will _not_ perform IO unless it needs to.
         let processed_config_args = resolve_config_args(&config_args, &mut file_ops).await?;
 
-        let external_paths = get_external_buckconfig_paths(&mut file_ops).await?;
+        let (root_config, started_parse) =
+            Self::parse_root_config(&mut file_ops, &processed_config_args, follow_includes).await?;
+
+        let mut aggregator = Self::cells_aggregator(&root_config)?;
+
+        if let Some(external_cells) = root_config.get_section("external_cells") {
+            let lockfile = ExternalCellsLockfile::read(&mut file_ops).await?;
+            for (alias, origin) in external_cells.iter() {
+                if origin.as_str() == "disabled" {
+                    // Ignore this entry, treat it as a normal cell
+                    continue;
+                }
+                let alias = NonEmptyCellAlias::new(alias.to_owned())?;
+                let name = aggregator.resolve_root_alias(alias)?;
+                let origin = Self::parse_external_cell_origin(
+                    name,
+                    origin.as_str(),
+                    &root_config,
+                    lockfile.as_ref(),
+                )?;
+                let bundled = match &origin {
+                    ExternalCellOrigin::Overlay(setup) => &*setup.base,
+                    origin => origin,
+                };
+                if let ExternalCellOrigin::Bundled(name) = *bundled {
+                    // This code is executed both in the client and in the daemon. When in the
+                    // client and using a client-only build, this late binding might not be bound,
+                    // and so we can't check this. That doesn't matter though, as we'll get an error
+                    // when this fails in the daemon anyway
+                    if let Ok(imp) = EXTERNAL_CELLS_IMPL.get() {
+                        imp.check_bundled_cell_exists(name)?;
+                    }
+                }
+                aggregator.mark_external_cell(name, origin)?;
+            }
+        }
+
+        let cell_resolver = aggregator.make_cell_resolver()?;
+
+        Ok(Self {
+            cell_resolver,
+            root_config,
+            config_paths: file_ops.trace,
+            external_data: Arc::new(ExternalBuckconfigData {
+                external_path_configs: started_parse,
+                args: processed_config_args,
+            }),
+        })
+    }
+
+    async fn parse_root_config(
+        file_ops: &mut dyn ConfigParserFileOps,
+        processed_config_args: &[ResolvedLegacyConfigArg],
+        follow_includes: bool,
+    ) -> buck2_error::Result<(LegacyBuckConfig, Vec<ExternalPathBuckconfigData>)> {
+        let external_paths = get_external_buckconfig_paths(file_ops).await?;
         let started_parse = LegacyBuckConfig::start_parse_for_external_files(
             &external_paths,
-            &mut file_ops,
+            file_ops,
             follow_includes,
         )
         .await?;
 
         let root_path = CellRootPathBuf::new(ProjectRelativePath::empty().to_owned());
 
-        let buckconfig_paths = get_project_buckconfig_paths(&root_path, &mut file_ops).await?;
+        let buckconfig_paths = get_project_buckconfig_paths(&root_path, file_ops).await?;
 
         let root_config = LegacyBuckConfig::finish_parse(
             started_parse.clone(),
             buckconfig_paths.as_slice(),
             &root_path,
-            &mut file_ops,
-            &processed_config_args,
+            file_ops,
+            processed_config_args,
             follow_includes,
         )
         .await?;
 
+        Ok((root_config, started_parse))
+    }
+
+    fn cells_aggregator(root_config: &LegacyBuckConfig) -> buck2_error::Result<CellsAggregator> {
+        let root_path = CellRootPathBuf::new(ProjectRelativePath::empty().to_owned());
+
         let mut cell_definitions = Vec::new();
 
         // `cells` is preferred over `repositories` since it's more clear, however it's unlikely
@@ -330,43 +449,9 @@ impl BuckConfigBasedCells {
             }
         }
 
-        let root_aliases = Self::get_cell_aliases_from_config(&root_config)?.collect();
-
-        let mut aggregator = CellsAggregator::new(cell_definitions, root_aliases)?;
-
-        if let Some(external_cells) = root_config.get_section("external_cells") {
-            for (alias, origin) in external_cells.iter() {
-                if origin.as_str() == "disabled" {
-                    // Ignore this entry, treat it as a normal cell
-                    continue;
-                }
-                let alias = NonEmptyCellAlias::new(alias.to_owned())?;
-                let name = aggregator.resolve_root_alias(alias)?;
-                let origin = Self::parse_external_cell_origin(name, origin.as_str(), &root_config)?;
-                if let ExternalCellOrigin::Bundled(name) = origin {
-                    // This code is executed both in the client and in the daemon. When in the
-                    // client and using a client-only build, this late binding might not be bound,
-                    // and so we can't check this. That doesn't matter though, as we'll get an error
-                    // when this fails in the daemon anyway
-                    if let Ok(imp) = EXTERNAL_CELLS_IMPL.get() {
-                        imp.check_bundled_cell_exists(name)?;
-                    }
-                }
-                aggregator.mark_external_cell(name, origin)?;
-            }
-        }
-
-        let cell_resolver = aggregator.make_cell_resolver()?;
+        let root_aliases = Self::get_cell_aliases_from_config(root_config)?.collect();
 
-        Ok(Self {
-            cell_resolver,
-            root_config,
-            config_paths: file_ops.trace,
-            external_data: Arc::new(ExternalBuckconfigData {
-                external_path_configs: started_parse,
-                args: processed_config_args,
-            }),
-        })
+        CellsAggregator::new(cell_definitions, root_aliases)
     }
 
     pub(crate) fn get_cell_aliases_from_config(
@@ -448,6 +533,7 @@ impl BuckConfigBasedCells {
         cell: CellName,
         value: &str,
         config: &LegacyBuckConfig,
+        lockfile: Option<&ExternalCellsLockfile>,
     ) -> buck2_error::Result<ExternalCellOrigin> {
         #[derive(buck2_error::Error, Debug)]
         #[buck2(tag = Input)]
@@ -456,6 +542,18 @@ impl BuckConfigBasedCells {
             Unknown(String),
             #[error("Missing buckconfig `{0}.{1}` for external cell configuration")]
             MissingConfiguration(String, String),
+            #[error(
+                "Unsupported archive url `{0}` for external cell, expected `http://`, `https://` or `file://`"
+            )]
+            UnsupportedArchiveUrl(String),
+            #[error(
+                "External cell overlay base `{0}` must be one of `bundled`, `git` or `archive`"
+            )]
+            InvalidOverlayBase(String),
+            #[error(
+                "External cell `{0}` sets both `commit_hash` and `git_ref`, only one may be used"
+            )]
+            CommitHashAndGitRef(CellName),
         }
 
         let get_config = |section: &str, property: &str| {
@@ -473,14 +571,81 @@ impl BuckConfigBasedCells {
             Ok(ExternalCellOrigin::Bundled(cell))
         } else if value == "git" {
             let section = &format!("external_cell_{}", cell.as_str());
-            let commit: Arc<str> = get_config(section, "commit_hash")?.into();
+            let git_origin = get_config(section, "git_origin")?;
+            let git_ref = config.get(crate::legacy_configs::key::BuckconfigKeyRef {
+                section,
+                property: "git_ref",
+            });
+            let commit: Arc<str> = match git_ref {
+                None => get_config(section, "commit_hash")?.into(),
+                Some(git_ref) => {
+                    if config
+                        .get(crate::legacy_configs::key::BuckconfigKeyRef {
+                            section,
+                            property: "commit_hash",
+                        })
+                        .is_some()
+                    {
+                        return Err(ExternalCellOriginParseError::CommitHashAndGitRef(cell).into());
+                    }
+                    ExternalCellsLockfile::locked_git_commit(lockfile, cell, git_origin, git_ref)?
+                }
+            };
             // No use in storing the commit hash as a byte array, but let's reuse existing code to
             // check for validity
             let _ = RawDigest::parse_sha1(commit.as_bytes())?;
             Ok(ExternalCellOrigin::Git(GitCellSetup {
-                git_origin: get_config(section, "git_origin")?.into(),
+                git_origin: git_origin.into(),
                 commit,
             }))
+        } else if value == "archive" {
+            let section = &format!("external_cell_{}", cell.as_str());
+            let url = get_config(section, "url")?;
+            if !["http://", "https://", "file://"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+            {
+                return Err(
+                    ExternalCellOriginParseError::UnsupportedArchiveUrl(url.to_owned()).into(),
+                );
+            }
+            let sha256 = get_config(section, "sha256")?.to_ascii_lowercase();
+            let _ = RawDigest::parse_sha256(sha256.as_bytes())?;
+            let strip_prefix = config
+                .get(crate::legacy_configs::key::BuckconfigKeyRef {
+                    section,
+                    property: "strip_prefix",
+                })
+                .map(|p| p.trim_matches('/'))
+                .filter(|p| !p.is_empty())
+                .map(Arc::from);
+            Ok(ExternalCellOrigin::Archive(ArchiveCellSetup {
+                url: url.into(),
+                sha256: sha256.into(),
+                strip_prefix,
+            }))
+        } else if value == "overlay" {
+            let section = &format!("external_cell_{}", cell.as_str());
+            let base = get_config(section, "base")?;
+            if base == "overlay" || base == "disabled" {
+                return Err(
+                    ExternalCellOriginParseError::InvalidOverlayBase(base.to_owned()).into(),
+                );
+            }
+            let base = Self::parse_external_cell_origin(cell, base, config, lockfile)?;
+            let patches = config
+                .parse_list::<String>(crate::legacy_configs::key::BuckconfigKeyRef {
+                    section,
+                    property: "patches",
+                })?
+                .unwrap_or_default()
+                .iter()
+                .map(|p| Ok(ProjectRelativePath::new(p)?.to_buf()))
+                .collect::<buck2_error::Result<Vec<ProjectRelativePathBuf>>>()?;
+            Ok(ExternalCellOrigin::Overlay(OverlayCellSetup {
+                base: Arc::new(base),
+                patches: patches.into(),
+            }))
         } else {
             Err(ExternalCellOriginParseError::Unknown(value.to_owned()).into())
         }
@@ -588,9 +753,12 @@ mod tests {
     use buck2_cli_proto::ConfigOverride;
     use buck2_core::cells::cell_root_path::CellRootPath;
     use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::external::ArchiveCellSetup;
     use buck2_core::cells::external::ExternalCellOrigin;
     use buck2_core::cells::external::GitCellSetup;
+    use buck2_core::cells::external::OverlayCellSetup;
     use buck2_core::cells::name::CellName;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
     use dice::DiceComputations;
     use indoc::indoc;
 
@@ -1292,4 +1460,286 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_archive_external_cell() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[(
+            ".buckconfig",
+            indoc!(
+                r#"
+                    [cells]
+                        root = .
+                        libfoo = foo/
+                    [external_cells]
+                        libfoo = archive
+                    [external_cell_libfoo]
+                        url = https://example.com/libfoo-1.0.tar.gz
+                        sha256 = AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA
+                        strip_prefix = libfoo-1.0/
+                "#
+            ),
+        )])?;
+
+        let resolver = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await?
+            .cell_resolver;
+
+        let instance = resolver.get(CellName::testing_new("libfoo")).unwrap();
+
+        assert_eq!(
+            instance.external(),
+            Some(&ExternalCellOrigin::Archive(ArchiveCellSetup {
+                url: "https://example.com/libfoo-1.0.tar.gz".into(),
+                sha256: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into(),
+                strip_prefix: Some("libfoo-1.0".into()),
+            })),
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive_external_cell_bad_url() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[(
+            ".buckconfig",
+            indoc!(
+                r#"
+                    [cells]
+                        root = .
+                        libfoo = foo/
+                    [external_cells]
+                        libfoo = archive
+                    [external_cell_libfoo]
+                        url = ftp://example.com/libfoo-1.0.tar.gz
+                        sha256 = aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa
+                "#
+            ),
+        )])?;
+
+        let e = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await
+            .err()
+            .unwrap();
+
+        let e = format!("{:?}", e);
+        assert!(e.contains("Unsupported archive url"), "error: {}", e);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overlay_external_cell() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[(
+            ".buckconfig",
+            indoc!(
+                r#"
+                    [cells]
+                        root = .
+                        libfoo = foo/
+                    [external_cells]
+                        libfoo = overlay
+                    [external_cell_libfoo]
+                        base = git
+                        git_origin = https://github.com/jeff/libfoo.git
+                        commit_hash = aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee
+                        patches = patches/0001-fix.patch, patches/0002-feature.patch
+                "#
+            ),
+        )])?;
+
+        let resolver = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await?
+            .cell_resolver;
+
+        let instance = resolver.get(CellName::testing_new("libfoo")).unwrap();
+
+        assert_eq!(
+            instance.external(),
+            Some(&ExternalCellOrigin::Overlay(OverlayCellSetup {
+                base: Arc::new(ExternalCellOrigin::Git(GitCellSetup {
+                    git_origin: "https://github.com/jeff/libfoo.git".into(),
+                    commit: "aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee".into(),
+                })),
+                patches: vec![
+                    ProjectRelativePathBuf::testing_new("patches/0001-fix.patch"),
+                    ProjectRelativePathBuf::testing_new("patches/0002-feature.patch"),
+                ]
+                .into(),
+            })),
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overlay_external_cell_nested() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[(
+            ".buckconfig",
+            indoc!(
+                r#"
+                    [cells]
+                        root = .
+                        libfoo = foo/
+                    [external_cells]
+                        libfoo = overlay
+                    [external_cell_libfoo]
+                        base = overlay
+                "#
+            ),
+        )])?;
+
+        let e = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await
+            .err()
+            .unwrap();
+
+        let e = format!("{:?}", e);
+        assert!(e.contains("must be one of"), "error: {}", e);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_git_external_cell_git_ref() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[
+            (
+                ".buckconfig",
+                indoc!(
+                    r#"
+                        [cells]
+                            root = .
+                            libfoo = foo/
+                        [external_cells]
+                            libfoo = git
+                        [external_cell_libfoo]
+                            git_origin = https://github.com/jeff/libfoo.git
+                            git_ref = main
+                    "#
+                ),
+            ),
+            (
+                "external_cells.lock",
+                indoc!(
+                    r#"
+                        {
+                          "git": {
+                            "libfoo": {
+                              "git_origin": "https://github.com/jeff/libfoo.git",
+                              "git_ref": "main",
+                              "commit_hash": "aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee"
+                            }
+                          }
+                        }
+                    "#
+                ),
+            ),
+        ])?;
+
+        let resolver = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await?
+            .cell_resolver;
+
+        let instance = resolver.get(CellName::testing_new("libfoo")).unwrap();
+
+        assert_eq!(
+            instance.external(),
+            Some(&ExternalCellOrigin::Git(GitCellSetup {
+                git_origin: "https://github.com/jeff/libfoo.git".into(),
+                commit: "aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee".into(),
+            })),
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_git_external_cell_git_ref_outdated_lockfile() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[
+            (
+                ".buckconfig",
+                indoc!(
+                    r#"
+                        [cells]
+                            root = .
+                            libfoo = foo/
+                        [external_cells]
+                            libfoo = git
+                        [external_cell_libfoo]
+                            git_origin = https://github.com/jeff/libfoo.git
+                            git_ref = v2.0
+                    "#
+                ),
+            ),
+            (
+                "external_cells.lock",
+                indoc!(
+                    r#"
+                        {
+                          "git": {
+                            "libfoo": {
+                              "git_origin": "https://github.com/jeff/libfoo.git",
+                              "git_ref": "v1.0",
+                              "commit_hash": "aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee"
+                            }
+                          }
+                        }
+                    "#
+                ),
+            ),
+        ])?;
+
+        let e = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await
+            .err()
+            .unwrap();
+
+        let e = format!("{:?}", e);
+        assert!(e.contains("buck2 external-cells update"), "error: {}", e);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_git_external_cell_git_ref_and_commit_hash() -> buck2_error::Result<()> {
+        initialize_external_cells_impl();
+
+        let mut file_ops = TestConfigParserFileOps::new(&[(
+            ".buckconfig",
+            indoc!(
+                r#"
+                    [cells]
+                        root = .
+                        libfoo = foo/
+                    [external_cells]
+                        libfoo = git
+                    [external_cell_libfoo]
+                        git_origin = https://github.com/jeff/libfoo.git
+                        git_ref = main
+                        commit_hash = aaaaaaaabbbbbbbbccccccccddddddddeeeeeeee
+                "#
+            ),
+        )])?;
+
+        let e = BuckConfigBasedCells::testing_parse_with_file_ops(&mut file_ops, &[])
+            .await
+            .err()
+            .unwrap();
+
+        let e = format!("{:?}", e);
+        assert!(e.contains("only one may be used"), "error: {}", e);
+
+        Ok(())
+    }
 }
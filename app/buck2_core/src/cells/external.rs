@@ -13,11 +13,14 @@ use std::sync::Arc;
 use dupe::Dupe;
 
 use crate::cells::name::CellName;
+use crate::fs::project_rel_path::ProjectRelativePathBuf;
 
-#[derive(Debug, Clone, Dupe, allocative::Allocative, PartialEq, Eq)]
+#[derive(Debug, Clone, Dupe, allocative::Allocative, PartialEq, Eq, Hash)]
 pub enum ExternalCellOrigin {
     Bundled(CellName),
     Git(GitCellSetup),
+    Archive(ArchiveCellSetup),
+    Overlay(OverlayCellSetup),
 }
 
 #[derive(
@@ -37,11 +40,88 @@ pub struct GitCellSetup {
     pub commit: Arc<str>,
 }
 
+/// An external cell whose contents come from a (possibly compressed) tarball.
+#[derive(Debug, Clone, Dupe, allocative::Allocative, PartialEq, Eq, Hash)]
+pub struct ArchiveCellSetup {
+    /// Either an `http(s)://` or `file://` URL.
+    pub url: Arc<str>,
+    // Guaranteed to be a valid lowercase sha256 hash
+    pub sha256: Arc<str>,
+    /// Leading directory to remove from all paths in the archive, if any.
+    pub strip_prefix: Option<Arc<str>>,
+}
+
+impl ArchiveCellSetup {
+    /// Identifies the contents of the unpacked archive. Unpacking the same archive with another
+    /// `strip_prefix` gives other contents, so that is part of it too.
+    pub fn contents_key(&self) -> String {
+        match &self.strip_prefix {
+            None => self.sha256.to_string(),
+            Some(strip_prefix) => format!(
+                "{}-{}",
+                self.sha256,
+                &blake3::hash(strip_prefix.as_bytes()).to_hex()[..16]
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveCellSetup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "archive({}, {}", self.url, self.sha256)?;
+        if let Some(strip_prefix) = &self.strip_prefix {
+            write!(f, ", strip_prefix={}", strip_prefix)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// An external cell made by applying patches from the main repo on top of another origin.
+#[derive(Debug, Clone, Dupe, allocative::Allocative, PartialEq, Eq, Hash)]
+pub struct OverlayCellSetup {
+    // Guaranteed not to itself be an overlay
+    pub base: Arc<ExternalCellOrigin>,
+    /// Unified diffs, applied in order. They are only read when the cell is materialized.
+    pub patches: Arc<[ProjectRelativePathBuf]>,
+}
+
+impl OverlayCellSetup {
+    /// Identifies the directory the overlay goes in: the base, and the paths of the patches
+    /// applied on top of it. The contents of the base and patches can change without this
+    /// changing, so the materialized overlay records which ones it was made from.
+    pub fn directory_key(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        match &*self.base {
+            ExternalCellOrigin::Bundled(cell) => hasher.update(cell.as_str().as_bytes()),
+            ExternalCellOrigin::Git(setup) => hasher.update(setup.commit.as_bytes()),
+            ExternalCellOrigin::Archive(setup) => hasher.update(setup.contents_key().as_bytes()),
+            ExternalCellOrigin::Overlay(setup) => hasher.update(setup.directory_key().as_bytes()),
+        };
+        for patch in self.patches.iter() {
+            hasher.update(b"\0");
+            hasher.update(patch.as_str().as_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+impl fmt::Display for OverlayCellSetup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "overlay({}", self.base)?;
+        for patch in self.patches.iter() {
+            write!(f, ", {}", patch)?;
+        }
+        write!(f, ")")
+    }
+}
+
 impl fmt::Display for ExternalCellOrigin {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Bundled(cell) => write!(f, "bundled({})", cell),
             Self::Git(git) => write!(f, "{}", git),
+            Self::Archive(archive) => write!(f, "{}", archive),
+            Self::Overlay(overlay) => write!(f, "{}", overlay),
         }
     }
 }
@@ -28,12 +28,16 @@ use static_interner::Interner;
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Dupe, Allocative)]
 pub struct LocalExecutorOptions {
     pub use_persistent_workers: bool,
+    /// Whether local actions run in a sandbox that only exposes their inputs, unless the action
+    /// says otherwise.
+    pub use_sandbox: bool,
 }
 
 impl Default for LocalExecutorOptions {
     fn default() -> Self {
         Self {
             use_persistent_workers: true,
+            use_sandbox: false,
         }
     }
 }
@@ -188,8 +192,8 @@ impl Display for Executor {
             Self::Local(options) => {
                 write!(
                     f,
-                    "Local + use persistent workers {}",
-                    options.use_persistent_workers
+                    "Local + use persistent workers {} + sandbox {}",
+                    options.use_persistent_workers, options.use_sandbox
                 )
             }
             Self::RemoteEnabled(options) => {
@@ -219,12 +219,16 @@ impl BuckOutPathResolver {
         path: &CellRelativePath,
         origin: ExternalCellOrigin,
     ) -> ProjectRelativePathBuf {
+        let archive_dir;
+        let overlay_dir;
         ProjectRelativePathBuf::from(ForwardRelativePathBuf::concat([
             self.buck_out_v2.as_forward_relative_path(),
             ForwardRelativePath::new("external_cells").unwrap(),
             match origin {
                 ExternalCellOrigin::Bundled(_) => ForwardRelativePath::new("bundled").unwrap(),
                 ExternalCellOrigin::Git(_) => ForwardRelativePath::new("git").unwrap(),
+                ExternalCellOrigin::Archive(_) => ForwardRelativePath::new("archive").unwrap(),
+                ExternalCellOrigin::Overlay(_) => ForwardRelativePath::new("overlay").unwrap(),
             },
             match &origin {
                 ExternalCellOrigin::Bundled(cell) => {
@@ -233,6 +237,14 @@ impl BuckOutPathResolver {
                 ExternalCellOrigin::Git(setup) => {
                     ForwardRelativePath::new(setup.commit.as_ref()).unwrap()
                 }
+                ExternalCellOrigin::Archive(setup) => {
+                    archive_dir = setup.contents_key();
+                    ForwardRelativePath::new(&archive_dir).unwrap()
+                }
+                ExternalCellOrigin::Overlay(setup) => {
+                    overlay_dir = setup.directory_key();
+                    ForwardRelativePath::new(&overlay_dir).unwrap()
+                }
             },
             path.as_ref(),
         ]))
@@ -323,6 +335,9 @@ mod tests {
 
     use crate::category::CategoryRef;
     use crate::cells::cell_root_path::CellRootPathBuf;
+    use crate::cells::external::ArchiveCellSetup;
+    use crate::cells::external::ExternalCellOrigin;
+    use crate::cells::external::OverlayCellSetup;
     use crate::cells::name::CellName;
     use crate::cells::paths::CellRelativePath;
     use crate::cells::CellResolver;
@@ -623,4 +638,45 @@ mod tests {
         assert!(expected_result.is_match(result.as_str()));
         Ok(())
     }
+
+    #[test]
+    fn test_resolve_archive_external_cell_source_depends_on_strip_prefix() {
+        let path_resolver =
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new("buck-out".into()));
+        let resolve = |strip_prefix: Option<&str>| {
+            path_resolver.resolve_external_cell_source(
+                CellRelativePath::unchecked_new("BUCK"),
+                ExternalCellOrigin::Archive(ArchiveCellSetup {
+                    url: Arc::from("https://example.com/a.tar.gz"),
+                    sha256: Arc::from("0".repeat(64)),
+                    strip_prefix: strip_prefix.map(Arc::from),
+                }),
+            )
+        };
+
+        assert_eq!(
+            resolve(None).as_str(),
+            format!("buck-out/external_cells/archive/{}/BUCK", "0".repeat(64))
+        );
+        assert_ne!(resolve(Some("a")), resolve(None));
+        assert_ne!(resolve(Some("a")), resolve(Some("b")));
+    }
+
+    #[test]
+    fn test_resolve_overlay_external_cell_source_depends_on_patches() {
+        let path_resolver =
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new("buck-out".into()));
+        let resolve = |patch: &str| {
+            path_resolver.resolve_external_cell_source(
+                CellRelativePath::unchecked_new("BUCK"),
+                ExternalCellOrigin::Overlay(OverlayCellSetup {
+                    base: Arc::new(ExternalCellOrigin::Bundled(CellName::testing_new("foo"))),
+                    patches: vec![ProjectRelativePathBuf::unchecked_new(patch.into())].into(),
+                }),
+            )
+        };
+
+        assert_eq!(resolve("a.patch"), resolve("a.patch"));
+        assert_ne!(resolve("a.patch"), resolve("b.patch"));
+    }
 }
@@ -58,7 +58,9 @@ pub(crate) async fn remove_old_logs(logdir: &AbsNormPath) {
 }
 
 /// List files in logdir, ordered from oldest to newest.
-fn get_files_in_log_dir(logdir: &AbsNormPath) -> buck2_error::Result<Vec<AbsNormPathBuf>> {
+pub(crate) fn get_files_in_log_dir(
+    logdir: &AbsNormPath,
+) -> buck2_error::Result<Vec<AbsNormPathBuf>> {
     Ok(fs_util::read_dir_if_exists(logdir)?
         .map(sort_logs)
         .unwrap_or_default())
@@ -73,7 +75,11 @@ pub fn get_local_logs(logdir: &AbsNormPath) -> buck2_error::Result<Vec<EventLogP
 }
 
 fn sort_logs(dir: fs_util::ReadDir) -> Vec<AbsNormPathBuf> {
-    let mut logfiles = dir.filter_map(Result::ok).collect::<Vec<_>>();
+    // Skip directories, such as the locks of logs being written.
+    let mut logfiles = dir
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().map_or(true, |t| !t.is_dir()))
+        .collect::<Vec<_>>();
     logfiles.sort_by_cached_key(|file| {
         // Return Unix epoch if unable to get creation time.
         if let Ok(metadata) = file.metadata() {
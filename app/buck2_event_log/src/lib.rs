@@ -22,6 +22,7 @@ use tokio::task::JoinHandle;
 
 pub mod file_names;
 pub mod read;
+pub mod retention;
 pub mod stream_value;
 pub mod ttl;
 pub mod user_event_types;
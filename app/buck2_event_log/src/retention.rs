@@ -0,0 +1,435 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Retention of local event logs by total size and by age.
+//!
+//! Clients only ever keep the last few logs by count (see `remove_old_logs`), which isn't enough
+//! when a handful of logs from large builds take up gigabytes. The daemon periodically applies
+//! the policies configured here on top of that, and records what it removed so that `buck2 log`
+//! can tell users where their logs went.
+//!
+//! Logs still being written may belong to any daemon or client, so writers hold a lock on a file
+//! named after the log in the `live` subdirectory of the log directory for as long as they write,
+//! and retention leaves logs alone whose lock is taken.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use buck2_common::legacy_configs::configs::LegacyBuckConfig;
+use buck2_common::legacy_configs::key::BuckconfigKeyRef;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::abs_path::AbsPath;
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
+use buck2_error::BuckErrorContext;
+use chrono::Utc;
+use fs4::FileExt;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::file_names::get_files_in_log_dir;
+
+/// How many eviction records we keep around.
+const EVICTION_RECORDS_RETAINED: usize = 100;
+
+/// Subdirectory of the log directory holding the locks of logs that are being written.
+const LIVE_LOCKS_DIR: &str = "live";
+
+#[derive(Debug, buck2_error::Error)]
+#[buck2(tag = Input)]
+enum LogRetentionConfigError {
+    #[error("`buck2.{0}` must be a finite, non-negative number, got `{1}`")]
+    InvalidDuration(&'static str, f64),
+    #[error("`buck2.event_log_retention_period_minutes` must be greater than zero")]
+    ZeroPeriod,
+}
+
+fn parse_duration(
+    property: &'static str,
+    value: f64,
+    secs_per_unit: f64,
+) -> buck2_error::Result<Duration> {
+    Duration::try_from_secs_f64(value * secs_per_unit)
+        .map_err(|_| LogRetentionConfigError::InvalidDuration(property, value).into())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRetentionConfig {
+    /// Evict the oldest logs once all logs together are larger than this.
+    pub max_total_bytes: Option<u64>,
+    /// Evict logs that were last written longer ago than this.
+    pub max_age: Option<Duration>,
+    /// How often the daemon applies the policy.
+    pub period: Duration,
+}
+
+impl LogRetentionConfig {
+    /// Returns `None` if no retention policy is configured.
+    pub fn from_buck_config(root_config: &LegacyBuckConfig) -> buck2_error::Result<Option<Self>> {
+        let max_total_mb: Option<u64> = root_config.parse(BuckconfigKeyRef {
+            section: "buck2",
+            property: "event_log_retention_max_total_mb",
+        })?;
+        let max_age_hours: Option<f64> = root_config.parse(BuckconfigKeyRef {
+            section: "buck2",
+            property: "event_log_retention_max_age_hours",
+        })?;
+        let period_minutes = root_config
+            .parse(BuckconfigKeyRef {
+                section: "buck2",
+                property: "event_log_retention_period_minutes",
+            })?
+            .unwrap_or(10.0);
+
+        if max_total_mb.is_none() && max_age_hours.is_none() {
+            return Ok(None);
+        }
+
+        let max_age = max_age_hours
+            .map(|h| parse_duration("event_log_retention_max_age_hours", h, 60.0 * 60.0))
+            .transpose()?;
+        let period = parse_duration("event_log_retention_period_minutes", period_minutes, 60.0)?;
+        if period.is_zero() {
+            return Err(LogRetentionConfigError::ZeroPeriod.into());
+        }
+
+        Ok(Some(Self {
+            max_total_bytes: max_total_mb.map(|mb| mb * 1024 * 1024),
+            max_age,
+            period,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+    Age,
+    TotalSize,
+}
+
+/// A log that was removed by the retention policy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvictedLog {
+    pub file_name: String,
+    pub size_bytes: u64,
+    pub reason: EvictionReason,
+    /// RFC 3339 timestamp.
+    pub evicted_at: String,
+}
+
+struct LogFile {
+    file_name: String,
+    size_bytes: u64,
+    modified: SystemTime,
+}
+
+fn live_lock_path(log_path: &AbsPath) -> buck2_error::Result<AbsPathBuf> {
+    let (Some(logdir), Some(file_name)) = (log_path.parent(), log_path.file_name()) else {
+        return Err(buck2_error::buck2_error!(
+            buck2_error::ErrorTag::Tier0,
+            "Event log path `{}` has no parent directory",
+            log_path.display()
+        ));
+    };
+    Ok(logdir.join(LIVE_LOCKS_DIR).join(file_name))
+}
+
+/// Marks an event log as being written until dropped, so that retention won't evict it.
+pub struct LiveLogLock {
+    path: AbsPathBuf,
+    file: File,
+}
+
+impl LiveLogLock {
+    /// Take the lock for the log at `log_path`. Must be called before the log is created.
+    pub fn acquire(log_path: &AbsPath) -> buck2_error::Result<Self> {
+        let path = live_lock_path(log_path)?;
+        if let Some(dir) = path.parent() {
+            fs_util::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_buck_error_context(|| format!("Error creating lock file `{}`", path.display()))?;
+        file.lock_exclusive()
+            .with_buck_error_context(|| format!("Error locking `{}`", path.display()))?;
+        Ok(Self { path, file })
+    }
+}
+
+impl Drop for LiveLogLock {
+    fn drop(&mut self) {
+        // Remove the lock file while still holding the lock. Anyone who opened it before then
+        // only gets to lock it once we're done writing.
+        fs_util::remove_file(&self.path).ok();
+        self.file.unlock().ok();
+    }
+}
+
+/// Whether a writer holds the lock of the log at `log_path`.
+fn is_live_log(log_path: &AbsPath) -> bool {
+    let Ok(path) = live_lock_path(log_path) else {
+        return false;
+    };
+    // Writers create the lock before the log, so a missing lock means nobody is writing.
+    match OpenOptions::new().write(true).open(&path) {
+        Ok(file) => file.try_lock_exclusive().is_err(),
+        Err(e) => e.kind() != std::io::ErrorKind::NotFound,
+    }
+}
+
+/// Pick the logs to evict. `logs` is ordered from oldest to newest. The newest log and the logs
+/// that are still being written are never evicted.
+fn select_evictions(
+    logs: &[LogFile],
+    config: &LogRetentionConfig,
+    now: SystemTime,
+    is_live: impl Fn(usize) -> bool,
+) -> Vec<(usize, EvictionReason)> {
+    let evictable = |i: usize| i + 1 < logs.len() && !is_live(i);
+    let mut evicted = Vec::new();
+
+    if let Some(max_age) = config.max_age {
+        for (i, log) in logs.iter().enumerate() {
+            let age = now.duration_since(log.modified).unwrap_or_default();
+            if age > max_age && evictable(i) {
+                evicted.push((i, EvictionReason::Age));
+            }
+        }
+    }
+
+    if let Some(max_total_bytes) = config.max_total_bytes {
+        let mut total: u64 = logs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !evicted.iter().any(|(j, _)| i == j))
+            .map(|(_, log)| log.size_bytes)
+            .sum();
+        for (i, log) in logs.iter().enumerate() {
+            if total <= max_total_bytes {
+                break;
+            }
+            if evicted.iter().any(|(j, _)| i == *j) || !evictable(i) {
+                continue;
+            }
+            total -= log.size_bytes;
+            evicted.push((i, EvictionReason::TotalSize));
+        }
+    }
+
+    evicted
+}
+
+fn list_logs(logdir: &AbsNormPath) -> buck2_error::Result<Vec<(AbsNormPathBuf, LogFile)>> {
+    let mut logs = Vec::new();
+    for path in get_files_in_log_dir(logdir)? {
+        // Logs may disappear under us when a client cleans them up.
+        let Ok(metadata) = fs_util::symlink_metadata(&path) else {
+            continue;
+        };
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        let log = LogFile {
+            file_name: file_name.to_string_lossy().into_owned(),
+            size_bytes: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        };
+        logs.push((path, log));
+    }
+    Ok(logs)
+}
+
+/// Apply the retention policy to the logs in `logdir`, and append what was evicted to the record
+/// at `record_path`.
+pub fn apply_log_retention(
+    logdir: &AbsNormPath,
+    record_path: &AbsNormPath,
+    config: &LogRetentionConfig,
+) -> buck2_error::Result<Vec<EvictedLog>> {
+    let (paths, logs): (Vec<_>, Vec<_>) = list_logs(logdir)?.into_iter().unzip();
+    let evictions = select_evictions(&logs, config, SystemTime::now(), |i| is_live_log(&paths[i]));
+
+    let mut evicted = Vec::new();
+    for (i, reason) in evictions {
+        let log = &logs[i];
+        // Another process may have gotten to it first.
+        if fs_util::remove_file(&paths[i]).is_ok() {
+            // Writers that didn't get to clean up after themselves leave their lock behind.
+            if let Ok(lock_path) = live_lock_path(&paths[i]) {
+                fs_util::remove_file(lock_path).ok();
+            }
+            evicted.push(EvictedLog {
+                file_name: log.file_name.clone(),
+                size_bytes: log.size_bytes,
+                reason,
+                evicted_at: Utc::now().to_rfc3339(),
+            });
+        }
+    }
+
+    if !evicted.is_empty() {
+        let mut records = read_evicted_logs(record_path)?;
+        records.extend(evicted.iter().cloned());
+        let skip = records.len().saturating_sub(EVICTION_RECORDS_RETAINED);
+        let mut out = Vec::new();
+        for record in &records[skip..] {
+            serde_json::to_writer(&mut out, record)?;
+            out.write_all(b"\n")?;
+        }
+        fs_util::write(record_path, out)?;
+    }
+
+    Ok(evicted)
+}
+
+/// Logs evicted by the retention policy, from oldest to newest eviction.
+pub fn read_evicted_logs(record_path: &AbsNormPath) -> buck2_error::Result<Vec<EvictedLog>> {
+    let Some(contents) = fs_util::read_to_string_if_exists(record_path)? else {
+        return Ok(Vec::new());
+    };
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .with_buck_error_context(|| format!("Error parsing `{}`", record_path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_common::legacy_configs::configs::testing::parse;
+
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn log(name: &str, size_mb: u64, age_hours: u64, now: SystemTime) -> LogFile {
+        LogFile {
+            file_name: name.to_owned(),
+            size_bytes: size_mb * MB,
+            modified: now - Duration::from_secs(age_hours * 60 * 60),
+        }
+    }
+
+    #[test]
+    fn test_select_by_size() {
+        let now = SystemTime::now();
+        let logs = vec![
+            log("a", 500, 3, now),
+            log("b", 500, 2, now),
+            log("c", 500, 1, now),
+        ];
+        let config = LogRetentionConfig {
+            max_total_bytes: Some(1000 * MB),
+            max_age: None,
+            period: Duration::from_secs(60),
+        };
+        assert_eq!(
+            select_evictions(&logs, &config, now, |_| false),
+            vec![(0, EvictionReason::TotalSize)]
+        );
+        // Logs that are still being written are skipped over.
+        assert_eq!(
+            select_evictions(&logs, &config, now, |i| i == 0),
+            vec![(1, EvictionReason::TotalSize)]
+        );
+    }
+
+    #[test]
+    fn test_select_by_age() {
+        let now = SystemTime::now();
+        let logs = vec![
+            log("a", 1, 48, now),
+            log("b", 1, 30, now),
+            log("c", 1, 1, now),
+        ];
+        let config = LogRetentionConfig {
+            max_total_bytes: None,
+            max_age: Some(Duration::from_secs(24 * 60 * 60)),
+            period: Duration::from_secs(60),
+        };
+        assert_eq!(
+            select_evictions(&logs, &config, now, |_| false),
+            vec![(0, EvictionReason::Age), (1, EvictionReason::Age)]
+        );
+    }
+
+    #[test]
+    fn test_never_evict_newest() {
+        let now = SystemTime::now();
+        let logs = vec![log("a", 2000, 48, now)];
+        let config = LogRetentionConfig {
+            max_total_bytes: Some(MB),
+            max_age: Some(Duration::from_secs(60)),
+            period: Duration::from_secs(60),
+        };
+        assert_eq!(select_evictions(&logs, &config, now, |_| false), vec![]);
+    }
+
+    #[test]
+    fn test_live_log_lock() -> buck2_error::Result<()> {
+        let tmp_dir = tempfile::TempDir::new()?;
+        let log_path = AbsPathBuf::try_from(tmp_dir.path().join("log.pb.zst")).unwrap();
+        assert!(!is_live_log(&log_path));
+        let lock = LiveLogLock::acquire(&log_path)?;
+        assert!(is_live_log(&log_path));
+        drop(lock);
+        assert!(!is_live_log(&log_path));
+        assert!(!live_lock_path(&log_path)?.exists());
+        Ok(())
+    }
+
+    fn config(buck2_section: &str) -> buck2_error::Result<Option<LogRetentionConfig>> {
+        LogRetentionConfig::from_buck_config(&parse(
+            &[("config", &format!("[buck2]\n{}", buck2_section))],
+            "config",
+        )?)
+    }
+
+    #[test]
+    fn test_from_buck_config() -> buck2_error::Result<()> {
+        assert_eq!(config("")?, None);
+        assert_eq!(
+            config(
+                "event_log_retention_max_age_hours = 1.5\nevent_log_retention_period_minutes = 1"
+            )?,
+            Some(LogRetentionConfig {
+                max_total_bytes: None,
+                max_age: Some(Duration::from_secs(90 * 60)),
+                period: Duration::from_secs(60),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_buck_config_rejects_invalid_durations() {
+        assert!(config("event_log_retention_max_age_hours = -1").is_err());
+        assert!(config("event_log_retention_max_age_hours = NaN").is_err());
+        assert!(config("event_log_retention_max_age_hours = inf").is_err());
+        let with_period = |period| {
+            config(&format!(
+                "event_log_retention_max_total_mb = 1\nevent_log_retention_period_minutes = {}",
+                period
+            ))
+        };
+        assert!(with_period("0").is_err());
+        assert!(with_period("-5").is_err());
+    }
+}
@@ -483,9 +483,15 @@ pub fn display_executor_stage(
             match buck2_data::CacheType::from_i32(cache_query.cache_type).unwrap() {
                 buck2_data::CacheType::ActionCache => "re_action_cache",
                 buck2_data::CacheType::RemoteDepFileCache => "re_dep_file_cache",
+                buck2_data::CacheType::LocalDiskCache => "local_disk_cache",
+            }
+        }
+        Stage::CacheHit(cache_hit) => {
+            match buck2_data::CacheType::from_i32(cache_hit.cache_type).unwrap() {
+                buck2_data::CacheType::LocalDiskCache => "local_disk_cache_restore",
+                _ => "re_download",
             }
         }
-        Stage::CacheHit(..) => "re_download",
         Stage::Re(re) => {
             use buck2_data::re_stage::Stage;
 
@@ -698,7 +704,9 @@ impl<'a> ActionErrorDisplay<'a> {
                         );
                     }
                 }
-                Some(Command::OmittedLocalCommand(..)) | None => {
+                Some(Command::OmittedLocalCommand(..))
+                | Some(Command::LocalDiskCacheHit(..))
+                | None => {
                     // Nothing to show in this case.
                 }
             };
@@ -824,6 +832,7 @@ fn failure_reason_for_command_execution(
             Some(Command::LocalCommand(..)) | Some(Command::OmittedLocalCommand(..)) => "Local ",
             Some(Command::WorkerInitCommand(..)) => "Local Worker Initialization ",
             Some(Command::WorkerCommand(..)) => "Local Worker ",
+            Some(Command::LocalDiskCacheHit(..)) => "Local Disk Cache ",
             None => "",
         }
     } else {
@@ -99,6 +99,8 @@ pub struct WhatRanOutputCommand<'a> {
     pub extra: Option<WhatRanOutputCommandExtra<'a>>,
     pub std_err: Option<&'a str>,
     pub duration: Option<std::time::Duration>,
+    /// Files the command read without declaring them, if those were being detected.
+    pub undeclared_inputs: &'a [String],
 }
 
 impl<'a> WhatRanOutputCommand<'a> {
@@ -156,6 +158,7 @@ pub fn emit_what_ran_entry(
     options: &WhatRanOptionsRegex,
     std_err: Option<&str>,
     duration: Option<std::time::Duration>,
+    undeclared_inputs: &[String],
 ) -> buck2_error::Result<()> {
     let should_emit = options
         .filter_category_regex
@@ -210,6 +213,7 @@ pub fn emit_what_ran_entry(
         extra,
         std_err,
         duration,
+        undeclared_inputs,
     })?;
 
     Ok(())
@@ -230,15 +234,11 @@ impl CommandReproducer {
     pub fn executor(&self) -> String {
         match self {
             Self::CacheQuery(..) => "cache_query".to_owned(),
-            Self::CacheHit(cache) => {
-                let cache_type = cache.cache_type;
-                match buck2_data::CacheHitType::from_i32(cache_type) {
-                    Some(buck2_data::CacheHitType::RemoteDepFileCache) => {
-                        "re_dep_file_cache".to_owned()
-                    }
-                    _ => "cache".to_owned(),
-                }
-            }
+            Self::CacheHit(cache) => match cache.cache_type() {
+                buck2_data::CacheType::ActionCache => "cache".to_owned(),
+                buck2_data::CacheType::RemoteDepFileCache => "re_dep_file_cache".to_owned(),
+                buck2_data::CacheType::LocalDiskCache => "local_disk_cache".to_owned(),
+            },
             Self::ReExecute(execute) => executor_with_platform(execute),
             Self::LocalExecute(..) => "local".to_owned(),
             Self::WorkerExecute(..) => "worker".to_owned(),
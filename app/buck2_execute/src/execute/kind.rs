@@ -69,6 +69,9 @@ pub enum CommandExecutionKind {
         env: SortedVectorMap<String, String>,
         fallback_exe: Vec<String>,
     },
+    /// This action was served by the local disk action cache and not executed.
+    #[display("local_disk_cache")]
+    LocalDiskCache { digest: ActionDigest },
 }
 
 impl CommandExecutionKind {
@@ -81,6 +84,7 @@ impl CommandExecutionKind {
             Self::Remote { .. } => buck2_data::ActionExecutionKind::Remote,
             Self::ActionCache { .. } => buck2_data::ActionExecutionKind::ActionCache,
             Self::RemoteDepFileCache { .. } => buck2_data::ActionExecutionKind::RemoteDepFileCache,
+            Self::LocalDiskCache { .. } => buck2_data::ActionExecutionKind::LocalDiskCache,
         }
     }
 
@@ -183,6 +187,12 @@ impl CommandExecutionKind {
                     .collect(),
                 fallback_exe: fallback_exe.to_owned(),
             }),
+
+            Self::LocalDiskCache { digest } => {
+                Command::LocalDiskCacheHit(buck2_data::LocalDiskCacheHit {
+                    action_digest: digest.to_string(),
+                })
+            }
         });
 
         buck2_data::CommandExecutionKind { command }
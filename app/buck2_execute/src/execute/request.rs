@@ -309,6 +309,9 @@ pub struct CommandExecutionRequest {
     prefetch_lossy_stderr: bool,
     /// Whether to cleanup outputs
     pub outputs_cleanup: bool,
+    /// Whether the action allows its result to be shared through a cache, like `allow_cache_upload`
+    /// on `run()`. Only then is a local result added to the local disk cache.
+    allow_cache_upload: bool,
     /// What environment variables to inherit from the Buck2 daemon.
     local_environment_inheritance: Option<EnvironmentInheritance>,
     /// Whether this command should override the fallback-only behavior on an hybrid executor and
@@ -316,6 +319,8 @@ pub struct CommandExecutionRequest {
     force_full_hybrid_if_capable: bool,
     /// Whether to disable capturing performance counters for this execution.
     disable_miniperf: bool,
+    /// Whether to run this in a sandbox when executed locally. `None` defers to the executor.
+    local_sandbox: Option<bool>,
     required_local_resources: SortedSet<LocalResourceState>,
     /// Persistent worker to use for execution
     worker: Option<WorkerSpec>,
@@ -352,9 +357,11 @@ impl CommandExecutionRequest {
             working_directory: ProjectRelativePathBuf::default(),
             prefetch_lossy_stderr: false,
             outputs_cleanup: true,
+            allow_cache_upload: false,
             local_environment_inheritance: None,
             force_full_hybrid_if_capable: false,
             disable_miniperf: false,
+            local_sandbox: None,
             required_local_resources: SortedSet::new(),
             worker: None,
             unique_input_inodes: false,
@@ -485,6 +492,15 @@ impl CommandExecutionRequest {
         &self.working_directory
     }
 
+    pub fn with_allow_cache_upload(mut self, allow_cache_upload: bool) -> Self {
+        self.allow_cache_upload = allow_cache_upload;
+        self
+    }
+
+    pub fn allow_cache_upload(&self) -> bool {
+        self.allow_cache_upload
+    }
+
     pub fn with_local_environment_inheritance(
         mut self,
         local_environment_inheritance: EnvironmentInheritance,
@@ -515,6 +531,15 @@ impl CommandExecutionRequest {
         self.disable_miniperf
     }
 
+    pub fn with_local_sandbox(mut self, local_sandbox: Option<bool>) -> Self {
+        self.local_sandbox = local_sandbox;
+        self
+    }
+
+    pub fn local_sandbox(&self) -> Option<bool> {
+        self.local_sandbox
+    }
+
     pub fn with_required_local_resources(
         mut self,
         required_local_resources: Vec<LocalResourceState>,
@@ -7,6 +7,9 @@
  * of this source tree.
  */
 
+use std::sync::Arc;
+
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
 use dupe::Dupe;
 
 /// Command-level config that can tweak how the executors work.
@@ -17,4 +20,13 @@ pub struct ExecutorGlobalKnobs {
     /// Whether to emit action keys to execution logs (thos are pretty verbose and omitted by
     /// default).
     pub log_action_keys: bool,
+
+    /// Paths outside the project that sandboxed local actions may read, e.g. compilers and system
+    /// libraries. `None` means the usual system directories.
+    pub local_sandbox_toolchain_paths: Option<Arc<Vec<AbsPathBuf>>>,
+
+    /// Whether to report files that local actions read without declaring them as inputs. Those
+    /// are only reported, the actions still get to read them. Hosts that can't detect them warn
+    /// and run actions as usual.
+    pub detect_undeclared_inputs: bool,
 }
@@ -135,6 +135,9 @@ impl RemoteActionResult for ActionCacheResult {
             buck2_data::CacheType::RemoteDepFileCache => {
                 CommandExecutionKind::RemoteDepFileCache { details }
             }
+            buck2_data::CacheType::LocalDiskCache => CommandExecutionKind::LocalDiskCache {
+                digest: details.action_digest,
+            },
         }
     }
 
@@ -12,8 +12,10 @@ pub mod action_cache_upload_permission_checker;
 pub mod caching;
 pub(crate) mod empty_action_result;
 pub mod hybrid;
+pub mod hybrid_history;
 pub mod local;
 pub mod local_actions_throttle;
+pub mod local_disk_cache;
 pub mod re;
 pub mod stacked;
 pub mod to_re_platform;
@@ -39,8 +39,12 @@ use futures::future::Either;
 use futures::future::Future;
 use futures::FutureExt;
 use host_sharing::HostSharingRequirements;
+use parking_lot::Mutex;
 use tokio::sync::MutexGuard;
 
+use crate::executors::hybrid_history::HybridDecision;
+use crate::executors::hybrid_history::HybridHistory;
+use crate::executors::hybrid_history::HybridHistoryKey;
 use crate::executors::local::LocalExecutor;
 use crate::executors::local_actions_throttle::LocalActionsThrottle;
 use crate::low_pass_filter::LowPassFilter;
@@ -51,6 +55,10 @@ use crate::low_pass_filter::LowPassFilter;
 ///
 /// If the remote executor claims the request but does not produce a successful response, we will
 /// enqueue the request again to the local executor.
+///
+/// With a [HybridHistory], full hybrid execution learns which executor is faster for each kind of
+/// action, and stops racing the other one for actions that have no preference. Actions that would
+/// run locally still race while every local slot is taken.
 pub struct HybridExecutor<R> {
     pub local: LocalExecutor,
     pub remote: R,
@@ -60,6 +68,7 @@ pub struct HybridExecutor<R> {
     pub re_max_input_files_bytes: u64,
     pub fallback_tracker: Arc<FallbackTracker>,
     pub local_actions_throttle: Option<Arc<LocalActionsThrottle>>,
+    pub history: Option<Arc<HybridHistory>>,
 }
 
 impl<R> HybridExecutor<R>
@@ -111,6 +120,19 @@ where
         paths.input_files_bytes() > self.re_max_input_files_bytes
     }
 
+    /// Whether running `command` locally would have to wait for other local actions to finish.
+    fn is_local_saturated(&self, command: &PreparedCommand<'_, '_>) -> bool {
+        let broker = &self.local.host_sharing_broker;
+        let permits = match command.request.host_sharing_requirements() {
+            HostSharingRequirements::ExclusiveAccess => broker.num_machine_permits(),
+            HostSharingRequirements::OnePerToken(.., class)
+            | HostSharingRequirements::Shared(class) => {
+                broker.requested_permits(class).into_count()
+            }
+        };
+        broker.available_permits() < permits
+    }
+
     async fn ensure_low_memory_pressure(&self) {
         if let Some(ref t) = self.local_actions_throttle {
             t.ensure_low_memory_pressure().await
@@ -206,6 +228,15 @@ where
             cancellations,
             fallback_on_failure,
         );
+        // The history compares what each side costs end to end, and for remote that includes
+        // uploading inputs and downloading outputs, which the timing of its result leaves out.
+        let remote_elapsed = Mutex::new(None);
+        let remote_result = async {
+            let start = tokio::time::Instant::now();
+            let res = remote_result.await;
+            *remote_elapsed.lock() = Some(start.elapsed());
+            res
+        };
 
         if executor_preference.requires_local()
             || self.is_action_too_large_for_remote(command.request.paths())
@@ -221,6 +252,27 @@ where
             return remote_result.await;
         }
 
+        // Only learn from, and steer, actions that would otherwise race.
+        let history = match &self.history {
+            Some(history)
+                if matches!(self.level, HybridExecutionLevel::Full { .. })
+                    && matches!(executor_preference, ExecutorPreference::Default) =>
+            {
+                Some((
+                    history,
+                    HybridHistoryKey::new(&command.target.as_proto_action_name()),
+                ))
+            }
+            _ => None,
+        };
+        let executor_preference = match history.as_ref().map(|(h, key)| h.decide(key)) {
+            // Waiting for a local slot could take longer than running remotely, so race instead.
+            Some(HybridDecision::Local) if self.is_local_saturated(command) => executor_preference,
+            Some(HybridDecision::Local) => ExecutorPreference::LocalPreferred,
+            Some(HybridDecision::Remote) => ExecutorPreference::RemotePreferred,
+            Some(HybridDecision::Race) | None => executor_preference,
+        };
+
         let jobs = HybridExecutorJobs {
             local: local_result.map(|r| (r, JobPriority(1))),
             remote: remote_result.map(|r| (r, JobPriority(0))),
@@ -381,6 +433,10 @@ where
             first_res
         };
 
+        if let Some((history, key)) = history {
+            history.record(key, &res, *remote_elapsed.lock());
+        }
+
         res.eligible_for_full_hybrid = !fallback_only;
         res
     }
@@ -615,3 +671,205 @@ impl FallbackTracker {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use buck2_common::liveliness_observer::NoopLivelinessObserver;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::execution_types::executor_config::RemoteExecutorUseCase;
+    use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+    use buck2_core::fs::buck_out_path::BuckOutPathResolver;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+    use buck2_execute::digest_config::DigestConfig;
+    use buck2_execute::execute::action_digest_and_blobs::ActionDigestAndBlobsBuilder;
+    use buck2_execute::execute::blocking::testing::DummyBlockingExecutor;
+    use buck2_execute::execute::kind::CommandExecutionKind;
+    use buck2_execute::execute::kind::RemoteCommandExecutionDetails;
+    use buck2_execute::execute::prepared::PreparedAction;
+    use buck2_execute::execute::request::CommandExecutionRequest;
+    use buck2_execute::execute::result::CommandExecutionMetadata;
+    use buck2_execute::execute::target::CommandExecutionTarget;
+    use buck2_execute::knobs::ExecutorGlobalKnobs;
+    use buck2_execute::materialize::nodisk::NoDiskMaterializer;
+    use host_sharing::HostSharingBroker;
+    use host_sharing::HostSharingStrategy;
+    use indexmap::IndexMap;
+    use indexmap::IndexSet;
+    use remote_execution as RE;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestTarget;
+
+    impl CommandExecutionTarget for TestTarget {
+        fn re_action_key(&self) -> String {
+            "test".to_owned()
+        }
+
+        fn re_affinity_key(&self) -> String {
+            "test".to_owned()
+        }
+
+        fn as_proto_action_key(&self) -> buck2_data::ActionKey {
+            buck2_data::ActionKey::default()
+        }
+
+        fn as_proto_action_name(&self) -> buck2_data::ActionName {
+            buck2_data::ActionName {
+                category: "test".to_owned(),
+                identifier: "a/b".to_owned(),
+            }
+        }
+    }
+
+    /// Succeeds right away, reporting that the command took a second to run after queueing for a
+    /// minute.
+    struct TestRemote;
+
+    #[async_trait]
+    impl PreparedCommandExecutor for TestRemote {
+        async fn exec_cmd(
+            &self,
+            command: &PreparedCommand<'_, '_>,
+            manager: CommandExecutionManager,
+            _cancellations: &CancellationContext,
+        ) -> CommandExecutionResult {
+            let details = RemoteCommandExecutionDetails::new(
+                command.prepared_action.digest(),
+                None,
+                None,
+                RemoteExecutorUseCase::buck2_default(),
+                &command.prepared_action.platform,
+            );
+            // Uploading, queueing and downloading take 2s on top of the 1s the action runs for.
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            manager.claim().await.success(
+                CommandExecutionKind::Remote {
+                    details,
+                    queue_time: Duration::from_secs(1),
+                    materialized_inputs_for_failed: None,
+                },
+                IndexMap::new(),
+                Default::default(),
+                CommandExecutionMetadata {
+                    wall_time: Duration::from_secs(1),
+                    execution_time: Duration::from_secs(1),
+                    queue_duration: Some(Duration::from_secs(1)),
+                    ..Default::default()
+                },
+            )
+        }
+
+        fn is_local_execution_possible(&self, _executor_preference: ExecutorPreference) -> bool {
+            false
+        }
+    }
+
+    fn artifact_fs(temp: &ProjectRootTemp) -> ArtifactFs {
+        ArtifactFs::new(
+            CellResolver::testing_with_name_and_path(
+                CellName::testing_new("cell"),
+                CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new("cell_path".into())),
+            ),
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new("buck_out/v2".into())),
+            temp.path().dupe(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_history_races_when_local_is_saturated() -> buck2_error::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let digest_config = DigestConfig::testing_default();
+        let key = HybridHistoryKey::new(&TestTarget.as_proto_action_name());
+        let history = Arc::new(HybridHistory::testing_local_wins(key.clone()));
+
+        let executor = HybridExecutor {
+            local: LocalExecutor::new(
+                artifact_fs(&temp),
+                Arc::new(NoDiskMaterializer),
+                Arc::new(DummyBlockingExecutor {
+                    fs: temp.path().dupe(),
+                }),
+                Arc::new(HostSharingBroker::new(
+                    HostSharingStrategy::SmallerTasksFirst,
+                    1,
+                )),
+                temp.path().root().to_buf(),
+                None,
+                ExecutorGlobalKnobs::default(),
+                None,
+                false,
+            ),
+            remote: TestRemote,
+            level: HybridExecutionLevel::Full {
+                fallback_on_failure: false,
+                low_pass_filter: false,
+            },
+            executor_preference: ExecutorPreference::Default,
+            low_pass_filter: Arc::new(LowPassFilter::new(1)),
+            re_max_input_files_bytes: u64::MAX,
+            fallback_tracker: Arc::new(FallbackTracker::new()),
+            local_actions_throttle: None,
+            history: Some(history.dupe()),
+        };
+
+        let request = CommandExecutionRequest::new(
+            vec!["true".to_owned()],
+            Vec::new(),
+            CommandExecutionPaths::new(
+                Vec::new(),
+                IndexSet::new(),
+                &artifact_fs(&temp),
+                digest_config,
+            )?,
+            Default::default(),
+        );
+        let prepared_action = PreparedAction {
+            action_and_blobs: ActionDigestAndBlobsBuilder::new(digest_config)
+                .build(&RE::Action::default()),
+            platform: RE::Platform::default(),
+            remote_execution_dependencies: Vec::new(),
+        };
+        let command = PreparedCommand {
+            request: &request,
+            target: &TestTarget,
+            prepared_action: &prepared_action,
+            digest_config,
+        };
+
+        // Take the only local slot. Were the action to prefer local, it would wait for it forever.
+        let _permit = executor
+            .local
+            .host_sharing_broker
+            .acquire(&HostSharingRequirements::default())
+            .await;
+
+        let manager = CommandExecutionManager::new(
+            Box::new(MutexClaimManager::new()),
+            EventDispatcher::null(),
+            NoopLivelinessObserver::create(),
+        );
+        let res = tokio::time::timeout(
+            Duration::from_secs(60),
+            executor.exec_cmd(&command, manager, CancellationContext::testing()),
+        )
+        .await
+        .expect("The action should have raced remotely");
+
+        assert!(matches!(
+            res.report.status,
+            CommandExecutionStatus::Success {
+                execution_kind: CommandExecutionKind::Remote { .. }
+            }
+        ));
+        // Uploads and downloads count towards what running remotely costs, queueing doesn't.
+        assert_eq!(history.testing_remote(&key), (1, Duration::from_secs(2)));
+        Ok(())
+    }
+}
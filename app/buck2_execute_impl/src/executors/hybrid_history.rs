@@ -0,0 +1,666 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! How long hybrid actions took locally and remotely in the past, used to decide whether the next
+//! one should run locally, remotely, or race both executors.
+//!
+//! Actions are grouped by category and identifier prefix (the identifier up to its last `/`),
+//! since those tend to have a similar cost. For each group, we keep a moving average of how long
+//! successful executions took on each side. Only the winner of a race reports a duration, so an
+//! executor that never wins has no average at all.
+//!
+//! The history is kept in memory and written back to a sqlite database in the background, so that
+//! it survives daemon restarts. Groups that weren't used for a while are dropped.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use buck2_common::sqlite::KeyValueSqliteTable;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_error::BuckErrorContext;
+use buck2_execute::execute::kind::CommandExecutionKind;
+use buck2_execute::execute::result::CommandExecutionResult;
+use buck2_execute::execute::result::CommandExecutionStatus;
+use dupe::Dupe;
+use parking_lot::Mutex;
+use rusqlite::Connection;
+
+/// Bump this when changing the schema of the history table. Databases with another version are
+/// deleted on startup.
+const DB_SCHEMA_VERSION: u64 = 0;
+
+const HISTORY_TABLE_NAME: &str = "hybrid_history";
+
+/// The latest duration makes up `1 / SMOOTHING` of the moving average.
+const SMOOTHING: u32 = 4;
+
+/// How many durations an executor needs to have reported before we trust its average.
+const MIN_SAMPLES: u64 = 3;
+
+/// Once in this many decisions for a group, we race anyway, so that averages that went stale
+/// (e.g. because RE got faster) get a chance to be corrected.
+const EXPLORE_INTERVAL: u64 = 20;
+
+/// Groups that weren't used for this long are dropped, since the actions in them are probably gone.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Past this many groups in memory, the least recently used ones are dropped.
+const MAX_GROUPS: usize = 100_000;
+
+/// How long the writer collects updates before it retries after failing to write them.
+const WRITE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HybridHistoryKey {
+    category: String,
+    identifier_prefix: String,
+}
+
+impl HybridHistoryKey {
+    pub fn new(name: &buck2_data::ActionName) -> Self {
+        let identifier_prefix = match name.identifier.rfind('/') {
+            Some(i) => &name.identifier[..i],
+            None => &name.identifier,
+        };
+        Self {
+            category: name.category.clone(),
+            identifier_prefix: identifier_prefix.to_owned(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Dupe, Debug, PartialEq, Eq)]
+pub enum HybridDecision {
+    /// Only run locally, falling back to remote as if the action preferred local.
+    Local,
+    /// Only run remotely, falling back to local as if the action preferred remote.
+    Remote,
+    /// Race both executors, like we would without any history.
+    Race,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct DurationAverage {
+    samples: u64,
+    average: Duration,
+}
+
+impl DurationAverage {
+    fn add(&mut self, duration: Duration) {
+        self.average = if self.samples == 0 {
+            duration
+        } else {
+            (self.average * (SMOOTHING - 1) + duration) / SMOOTHING
+        };
+        self.samples += 1;
+    }
+
+    fn get(&self) -> Option<Duration> {
+        (self.samples >= MIN_SAMPLES).then_some(self.average)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct HybridHistoryEntry {
+    local: DurationAverage,
+    remote: DurationAverage,
+    /// When a duration was last recorded for the group, in seconds since the epoch.
+    last_used_secs: u64,
+}
+
+impl HybridHistoryEntry {
+    fn decide(&self) -> HybridDecision {
+        match (self.local.get(), self.remote.get()) {
+            (Some(local), Some(remote)) if local <= remote => HybridDecision::Local,
+            // Local is much slower, so running it is mostly wasted work.
+            (Some(local), Some(remote)) if remote * 2 <= local => HybridDecision::Remote,
+            // One side keeps winning races, and the other has never won a single one.
+            (Some(_), None) if self.remote.samples == 0 => HybridDecision::Local,
+            (None, Some(_)) if self.local.samples == 0 => HybridDecision::Remote,
+            _ => HybridDecision::Race,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Dupe, Debug)]
+enum Side {
+    Local,
+    Remote,
+}
+
+struct HybridHistoryState {
+    entries: HashMap<HybridHistoryKey, HybridHistoryEntry>,
+    /// How many decisions were made per group since the daemon started.
+    decisions: HashMap<HybridHistoryKey, u64>,
+    /// Sends updated entries to the thread writing them to the database, if any.
+    writer: Option<mpsc::Sender<(HybridHistoryKey, HybridHistoryEntry)>>,
+}
+
+pub struct HybridHistory {
+    state: Mutex<HybridHistoryState>,
+}
+
+impl HybridHistory {
+    fn with_entries(
+        entries: HashMap<HybridHistoryKey, HybridHistoryEntry>,
+        writer: Option<mpsc::Sender<(HybridHistoryKey, HybridHistoryEntry)>>,
+    ) -> Self {
+        Self {
+            state: Mutex::new(HybridHistoryState {
+                entries,
+                decisions: HashMap::new(),
+                writer,
+            }),
+        }
+    }
+
+    /// Load the history persisted in `dir`, and persist updates there. If the database there
+    /// can't be used, it is replaced by an empty one.
+    pub fn open(dir: &AbsNormPath) -> buck2_error::Result<Self> {
+        let db_path = dir.join(FileName::unchecked_new("db.sqlite"));
+
+        let loaded = match HybridHistoryDb::load(&db_path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                tracing::warn!("Discarding hybrid execution history in `{}`: {:#}", dir, e);
+                None
+            }
+        };
+        let (db, entries) = match loaded {
+            Some(loaded) => loaded,
+            None => {
+                fs_util::remove_all(dir)?;
+                fs_util::create_dir_all(dir)?;
+                (HybridHistoryDb::create(&db_path)?, HashMap::new())
+            }
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("hybrid-history".to_owned())
+            .spawn(move || db.write_loop(receiver))
+            .buck_error_context("Error spawning the hybrid history writer")?;
+
+        Ok(Self::with_entries(entries, Some(sender)))
+    }
+
+    pub fn decide(&self, key: &HybridHistoryKey) -> HybridDecision {
+        let mut state = self.state.lock();
+
+        let decisions = state.decisions.entry(key.clone()).or_default();
+        *decisions += 1;
+        if *decisions % EXPLORE_INTERVAL == 0 {
+            return HybridDecision::Race;
+        }
+
+        state
+            .entries
+            .get(key)
+            .map_or(HybridDecision::Race, |e| e.decide())
+    }
+
+    /// Record what `result` cost, if it is a successful local or remote execution. For remote
+    /// executions, that is `remote_elapsed`, the time from starting the execution to having its
+    /// outputs, which includes uploading inputs and downloading outputs. Time spent waiting for an
+    /// executor is left out: it says how busy the executor was, not how fast it runs this kind of
+    /// action.
+    pub fn record(
+        &self,
+        key: HybridHistoryKey,
+        result: &CommandExecutionResult,
+        remote_elapsed: Option<Duration>,
+    ) {
+        let (side, duration) = match &result.report.status {
+            CommandExecutionStatus::Success {
+                execution_kind:
+                    CommandExecutionKind::Local { .. } | CommandExecutionKind::LocalWorker { .. },
+            } => (Side::Local, result.report.timing.execution_time),
+            CommandExecutionStatus::Success {
+                execution_kind: CommandExecutionKind::Remote { queue_time, .. },
+            } => (
+                Side::Remote,
+                remote_elapsed
+                    .unwrap_or(result.report.timing.wall_time)
+                    .saturating_sub(*queue_time),
+            ),
+            _ => return,
+        };
+        self.record_duration(key, side, duration);
+    }
+
+    fn record_duration(&self, key: HybridHistoryKey, side: Side, duration: Duration) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+
+        let entry = state.entries.entry(key.clone()).or_default();
+        match side {
+            Side::Local => entry.local.add(duration),
+            Side::Remote => entry.remote.add(duration),
+        }
+        entry.last_used_secs = now_secs();
+
+        if let Some(writer) = &state.writer {
+            // If the writer is gone, it already logged why.
+            let _ignored = writer.send((key, *entry));
+        }
+
+        if state.entries.len() > MAX_GROUPS || state.decisions.len() > MAX_GROUPS {
+            state.prune();
+        }
+    }
+}
+
+impl HybridHistoryState {
+    /// Drop the least recently used groups until a tenth of `MAX_GROUPS` is free again. They stay
+    /// in the database until they are older than `MAX_AGE`.
+    fn prune(&mut self) {
+        let keep = MAX_GROUPS - MAX_GROUPS / 10;
+        if self.entries.len() > keep {
+            let mut last_used = self
+                .entries
+                .values()
+                .map(|e| e.last_used_secs)
+                .collect::<Vec<_>>();
+            let excess = self.entries.len() - keep;
+            let (_, cutoff, _) = last_used.select_nth_unstable(excess);
+            let cutoff = *cutoff;
+            self.entries.retain(|_, e| e.last_used_secs >= cutoff);
+        }
+        // Decisions only pace exploration, so losing them is harmless.
+        let entries = &self.entries;
+        self.decisions.retain(|key, _| entries.contains_key(key));
+        if self.decisions.len() > keep {
+            self.decisions.clear();
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+impl HybridHistory {
+    /// A history that isn't persisted, where local won enough races for `key` to be preferred.
+    pub(super) fn testing_local_wins(key: HybridHistoryKey) -> Self {
+        let history = Self::with_entries(HashMap::new(), None);
+        for _ in 0..MIN_SAMPLES {
+            history.record_duration(key.clone(), Side::Local, Duration::from_millis(10));
+        }
+        history
+    }
+
+    /// How many remote durations were recorded for `key`, and their average.
+    pub(super) fn testing_remote(&self, key: &HybridHistoryKey) -> (u64, Duration) {
+        let state = self.state.lock();
+        let remote = state.entries.get(key).map(|e| e.remote).unwrap_or_default();
+        (remote.samples, remote.average)
+    }
+}
+
+struct HybridHistoryDb {
+    path: AbsNormPathBuf,
+    connection: Arc<Mutex<Connection>>,
+    versions_table: KeyValueSqliteTable,
+}
+
+impl HybridHistoryDb {
+    fn open(path: &AbsNormPath) -> buck2_error::Result<Self> {
+        let connection = Connection::open(path)?;
+        // Losing the last few updates on power loss is fine, this is only a heuristic.
+        connection.pragma_update(None, "synchronous", "OFF")?;
+        let connection = Arc::new(Mutex::new(connection));
+        let versions_table = KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
+        Ok(Self {
+            path: path.to_buf(),
+            connection,
+            versions_table,
+        })
+    }
+
+    fn create(path: &AbsNormPath) -> buck2_error::Result<Self> {
+        let db = Self::open(path)?;
+        db.versions_table.create_table()?;
+        db.versions_table.insert_all(HashMap::from([(
+            "schema_version".to_owned(),
+            DB_SCHEMA_VERSION.to_string(),
+        )]))?;
+        db.connection
+            .lock()
+            .execute(
+                &format!(
+                    "CREATE TABLE {} (
+                        category            TEXT NOT NULL,
+                        identifier_prefix   TEXT NOT NULL,
+                        local_samples       INTEGER NOT NULL,
+                        local_average_us    INTEGER NOT NULL,
+                        remote_samples      INTEGER NOT NULL,
+                        remote_average_us   INTEGER NOT NULL,
+                        last_used_s         INTEGER NOT NULL,
+                        PRIMARY KEY (category, identifier_prefix)
+                    )",
+                    HISTORY_TABLE_NAME
+                ),
+                [],
+            )
+            .with_buck_error_context(|| format!("creating sqlite table {}", HISTORY_TABLE_NAME))?;
+        Ok(db)
+    }
+
+    /// Returns `None` if there is no database at `path`, or if it has another schema version.
+    /// Groups older than `MAX_AGE` are deleted from it.
+    fn load(
+        path: &AbsNormPath,
+    ) -> buck2_error::Result<Option<(Self, HashMap<HybridHistoryKey, HybridHistoryEntry>)>> {
+        if !fs_util::try_exists(path)? {
+            return Ok(None);
+        }
+
+        let db = Self::open(path)?;
+        if db.versions_table.get("schema_version")? != Some(DB_SCHEMA_VERSION.to_string()) {
+            return Ok(None);
+        }
+
+        let entries = {
+            let connection = db.connection.lock();
+            connection
+                .execute(
+                    &format!("DELETE FROM {} WHERE last_used_s < ?1", HISTORY_TABLE_NAME),
+                    [now_secs().saturating_sub(MAX_AGE.as_secs()) as i64],
+                )
+                .with_buck_error_context(|| {
+                    format!("deleting from sqlite table {}", HISTORY_TABLE_NAME)
+                })?;
+            let mut stmt = connection.prepare(&format!(
+                "SELECT category, identifier_prefix, local_samples, local_average_us, remote_samples, remote_average_us, last_used_s FROM {}",
+                HISTORY_TABLE_NAME
+            ))?;
+            stmt.query_map([], |row| {
+                Ok((
+                    HybridHistoryKey {
+                        category: row.get(0)?,
+                        identifier_prefix: row.get(1)?,
+                    },
+                    HybridHistoryEntry {
+                        local: DurationAverage {
+                            samples: row.get::<_, i64>(2)? as u64,
+                            average: Duration::from_micros(row.get::<_, i64>(3)? as u64),
+                        },
+                        remote: DurationAverage {
+                            samples: row.get::<_, i64>(4)? as u64,
+                            average: Duration::from_micros(row.get::<_, i64>(5)? as u64),
+                        },
+                        last_used_secs: row.get::<_, i64>(6)? as u64,
+                    },
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()
+            .with_buck_error_context(|| {
+                format!("reading from sqlite table {}", HISTORY_TABLE_NAME)
+            })?
+        };
+        Ok(Some((db, entries)))
+    }
+
+    fn write(
+        &self,
+        entries: &HashMap<HybridHistoryKey, HybridHistoryEntry>,
+    ) -> buck2_error::Result<()> {
+        let mut connection = self.connection.lock();
+        let tx = connection.transaction()?;
+        {
+            let mut stmt = tx.prepare(&format!(
+                "INSERT OR REPLACE INTO {} (category, identifier_prefix, local_samples, local_average_us, remote_samples, remote_average_us, last_used_s) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                HISTORY_TABLE_NAME
+            ))?;
+            for (key, entry) in entries {
+                stmt.execute(rusqlite::params![
+                    key.category,
+                    key.identifier_prefix,
+                    entry.local.samples as i64,
+                    entry.local.average.as_micros() as i64,
+                    entry.remote.samples as i64,
+                    entry.remote.average.as_micros() as i64,
+                    entry.last_used_secs as i64,
+                ])?;
+            }
+        }
+        tx.commit().with_buck_error_context(|| {
+            format!("writing to sqlite table {}", HISTORY_TABLE_NAME)
+        })?;
+        Ok(())
+    }
+
+    /// Open the database at `path` again, e.g. after it was deleted or a write failed.
+    fn reopen(&self) -> buck2_error::Result<Self> {
+        if fs_util::try_exists(&self.path)? {
+            Self::open(&self.path)
+        } else {
+            Self::create(&self.path)
+        }
+    }
+
+    /// Write updated entries until the history is dropped. Updates that queue up while we write
+    /// are batched into a single transaction. If writing fails, updates are kept and written
+    /// together with later ones, after re-opening the database.
+    fn write_loop(mut self, receiver: mpsc::Receiver<(HybridHistoryKey, HybridHistoryEntry)>) {
+        let mut pending = HashMap::new();
+        let mut failing = false;
+        loop {
+            if failing {
+                // Give whatever went wrong some time to clear up, but keep collecting updates.
+                let deadline = Instant::now() + WRITE_RETRY_INTERVAL;
+                loop {
+                    match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    {
+                        Ok((key, entry)) => {
+                            pending.insert(key, entry);
+                        }
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                match self.reopen() {
+                    Ok(db) => self = db,
+                    Err(e) => {
+                        tracing::debug!("Error re-opening hybrid execution history: {:#}", e);
+                        continue;
+                    }
+                }
+            } else {
+                match receiver.recv() {
+                    Ok((key, entry)) => {
+                        pending.insert(key, entry);
+                    }
+                    Err(mpsc::RecvError) => return,
+                }
+            }
+            pending.extend(receiver.try_iter());
+
+            match self.write(&pending) {
+                Ok(()) => {
+                    pending.clear();
+                    failing = false;
+                }
+                Err(e) => {
+                    // Only warn once until writing works again.
+                    if !failing {
+                        tracing::warn!("Error writing hybrid execution history: {:#}", e);
+                    }
+                    failing = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+
+    use super::*;
+
+    fn key(identifier: &str) -> HybridHistoryKey {
+        HybridHistoryKey::new(&buck2_data::ActionName {
+            category: "cxx_compile".to_owned(),
+            identifier: identifier.to_owned(),
+        })
+    }
+
+    fn record_all(history: &HybridHistory, side: Side, millis: &[u64]) {
+        for millis in millis {
+            history.record_duration(key("a/b.cpp"), side, Duration::from_millis(*millis));
+        }
+    }
+
+    #[test]
+    fn test_key_uses_identifier_prefix() {
+        assert_eq!(key("a/b.cpp"), key("a/c.cpp"));
+        assert_ne!(key("a/b.cpp"), key("b/b.cpp"));
+        assert_eq!(key("b.cpp").identifier_prefix, "b.cpp");
+    }
+
+    #[test]
+    fn test_duration_average() {
+        let mut average = DurationAverage::default();
+        average.add(Duration::from_millis(100));
+        assert_eq!(average.average, Duration::from_millis(100));
+        average.add(Duration::from_millis(500));
+        assert_eq!(average.average, Duration::from_millis(200));
+        assert_eq!(average.get(), None);
+        average.add(Duration::from_millis(200));
+        assert_eq!(average.get(), Some(Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_decide_without_history() {
+        let history = HybridHistory::with_entries(HashMap::new(), None);
+        assert_eq!(history.decide(&key("a/b.cpp")), HybridDecision::Race);
+        record_all(&history, Side::Local, &[10, 10]);
+        assert_eq!(history.decide(&key("a/b.cpp")), HybridDecision::Race);
+    }
+
+    #[test]
+    fn test_decide_local_when_local_always_wins() {
+        let history = HybridHistory::with_entries(HashMap::new(), None);
+        record_all(&history, Side::Local, &[10, 10, 10]);
+        assert_eq!(history.decide(&key("a/c.cpp")), HybridDecision::Local);
+        assert_eq!(history.decide(&key("b/c.cpp")), HybridDecision::Race);
+
+        // Once remote has won a race, we need enough samples to compare.
+        record_all(&history, Side::Remote, &[5]);
+        assert_eq!(history.decide(&key("a/c.cpp")), HybridDecision::Race);
+        record_all(&history, Side::Remote, &[20, 20]);
+        assert_eq!(history.decide(&key("a/c.cpp")), HybridDecision::Local);
+    }
+
+    #[test]
+    fn test_decide_remote_when_local_is_much_slower() {
+        let history = HybridHistory::with_entries(HashMap::new(), None);
+        record_all(&history, Side::Local, &[100, 100, 100]);
+        record_all(&history, Side::Remote, &[80, 80, 80]);
+        assert_eq!(history.decide(&key("a/b.cpp")), HybridDecision::Race);
+
+        record_all(&history, Side::Remote, &[10, 10, 10, 10, 10, 10]);
+        assert_eq!(history.decide(&key("a/b.cpp")), HybridDecision::Remote);
+    }
+
+    #[test]
+    fn test_decide_races_periodically() {
+        let history = HybridHistory::with_entries(HashMap::new(), None);
+        record_all(&history, Side::Local, &[10, 10, 10]);
+        let decisions = (0..EXPLORE_INTERVAL)
+            .map(|_| history.decide(&key("a/b.cpp")))
+            .collect::<Vec<_>>();
+        assert!(decisions[..decisions.len() - 1]
+            .iter()
+            .all(|d| *d == HybridDecision::Local));
+        assert_eq!(decisions.last(), Some(&HybridDecision::Race));
+    }
+
+    #[test]
+    fn test_db_roundtrip() -> buck2_error::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let path = fs
+            .path()
+            .resolve(ProjectRelativePath::unchecked_new("db.sqlite"));
+        assert!(HybridHistoryDb::load(&path)?.is_none());
+
+        let mut entry = HybridHistoryEntry::default();
+        entry.local.add(Duration::from_millis(12));
+        entry.remote.add(Duration::from_micros(3456));
+        entry.last_used_secs = now_secs();
+        let stale = HybridHistoryEntry {
+            last_used_secs: now_secs() - MAX_AGE.as_secs() - 1,
+            ..entry
+        };
+        HybridHistoryDb::create(&path)?.write(&HashMap::from([
+            (key("a/b.cpp"), entry),
+            (key("c/d.cpp"), stale),
+        ]))?;
+
+        let (_db, entries) = HybridHistoryDb::load(&path)?.unwrap();
+        assert_eq!(entries, HashMap::from([(key("a/b.cpp"), entry)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_keeps_recently_used_groups() {
+        let entries = (0..MAX_GROUPS as u64 + 1)
+            .map(|i| {
+                let entry = HybridHistoryEntry {
+                    last_used_secs: i,
+                    ..HybridHistoryEntry::default()
+                };
+                (key(&format!("{}/a.cpp", i)), entry)
+            })
+            .collect();
+        let history = HybridHistory::with_entries(entries, None);
+        history.decide(&key("0/a.cpp"));
+        history.decide(&key("5/a.cpp"));
+        record_all(&history, Side::Local, &[10]);
+
+        let state = history.state.lock();
+        assert_eq!(state.entries.len(), MAX_GROUPS - MAX_GROUPS / 10);
+        assert!(state.entries.contains_key(&key("a/b.cpp")));
+        assert!(state
+            .entries
+            .contains_key(&key(&format!("{}/a.cpp", MAX_GROUPS))));
+        assert!(!state.entries.contains_key(&key("5/a.cpp")));
+        assert!(state.decisions.is_empty());
+    }
+
+    #[test]
+    fn test_db_with_other_schema_version_is_ignored() -> buck2_error::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let path = fs
+            .path()
+            .resolve(ProjectRelativePath::unchecked_new("db.sqlite"));
+        let db = HybridHistoryDb::create(&path)?;
+        db.versions_table.insert_all(HashMap::from([(
+            "schema_version".to_owned(),
+            (DB_SCHEMA_VERSION + 1).to_string(),
+        )]))?;
+        drop(db);
+
+        assert!(HybridHistoryDb::load(&path)?.is_none());
+        Ok(())
+    }
+}
@@ -10,7 +10,10 @@
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::ops::ControlFlow;
+use std::path::PathBuf;
 use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
@@ -81,6 +84,7 @@ use host_sharing::host_sharing::HostSharingGuard;
 use host_sharing::HostSharingBroker;
 use host_sharing::HostSharingRequirements;
 use indexmap::IndexMap;
+use itertools::Itertools;
 use tracing::info;
 
 use crate::executors::worker::WorkerHandle;
@@ -94,6 +98,30 @@ enum LocalExecutionError {
 
     #[error("Trying to execute a remote-only action on a local executor")]
     RemoteOnlyAction,
+
+    #[error("Sandboxed local execution requires the forkserver, which is only available on Unix")]
+    SandboxRequiresForkserver,
+
+    #[error(
+        "Action accessed paths that it did not declare as inputs:\n{}",
+        .0.iter().map(|p| format!("  {}", p)).join("\n")
+    )]
+    UndeclaredPathAccess(Vec<String>),
+}
+
+/// What sandboxed actions get to read outside of the project, unless
+/// `buck2.sandbox_toolchain_paths` says otherwise.
+const DEFAULT_SANDBOX_TOOLCHAIN_PATHS: &[&str] = &[
+    "/bin", "/etc", "/lib", "/lib32", "/lib64", "/nix", "/opt", "/sbin", "/usr",
+];
+
+/// What a sandboxed local command gets to see, as absolute paths.
+struct LocalSandbox {
+    project_root: PathBuf,
+    read_only: Vec<PathBuf>,
+    writable: Vec<PathBuf>,
+    /// Let the command see everything, and only report what it looked up that it shouldn't have.
+    trace_only: bool,
 }
 
 #[derive(Clone)]
@@ -109,6 +137,8 @@ pub struct LocalExecutor {
     knobs: ExecutorGlobalKnobs,
     #[allow(unused)]
     worker_pool: Option<Arc<WorkerPool>>,
+    /// Whether to sandbox actions that don't say whether they want a sandbox.
+    use_sandbox: bool,
 }
 
 impl LocalExecutor {
@@ -121,6 +151,7 @@ impl LocalExecutor {
         forkserver: Option<ForkserverClient>,
         knobs: ExecutorGlobalKnobs,
         worker_pool: Option<Arc<WorkerPool>>,
+        use_sandbox: bool,
     ) -> Self {
         Self {
             artifact_fs,
@@ -131,6 +162,7 @@ impl LocalExecutor {
             forkserver,
             knobs,
             worker_pool,
+            use_sandbox,
         }
     }
 
@@ -147,6 +179,7 @@ impl LocalExecutor {
         liveliness_observer: impl LivelinessObserver + 'static,
         disable_miniperf: bool,
         action_digest: &'a str,
+        sandbox: Option<&'a LocalSandbox>,
     ) -> impl futures::future::Future<
         Output = buck2_error::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>,
     > + Send
@@ -169,13 +202,14 @@ impl LocalExecutor {
                             liveliness_observer,
                             self.knobs.enable_miniperf && !disable_miniperf,
                             action_digest,
+                            sandbox,
                         )
                         .await
                     }
 
                     #[cfg(not(unix))]
                     {
-                        let _unused = (forkserver, disable_miniperf, action_digest);
+                        let _unused = (forkserver, disable_miniperf, action_digest, sandbox);
                         Err(buck2_error!(
                             buck2_error::ErrorTag::Input,
                             "Forkserver is not supported off-UNIX"
@@ -184,6 +218,10 @@ impl LocalExecutor {
                 }
 
                 None => {
+                    if sandbox.is_some() {
+                        return Err(LocalExecutionError::SandboxRequiresForkserver.into());
+                    }
+
                     let exe = maybe_absolutize_exe(exe, &working_directory)?;
                     let mut cmd = background_command(exe.as_ref());
                     cmd.current_dir(working_directory.as_path());
@@ -257,18 +295,18 @@ impl LocalExecutor {
                 )
                 .await;
 
-                let scratch_path = r1?.scratch;
+                let inputs = r1?;
                 r2?;
 
-                buck2_error::Ok((scratch_path, start.elapsed()))
+                buck2_error::Ok((inputs, start.elapsed()))
             },
         )
         .boxed()
         .await;
 
-        let (scratch_path, input_materialization_duration) = match executor_stage_result {
-            Ok((scratch_path, input_materialization_duration)) => {
-                (scratch_path, input_materialization_duration)
+        let (inputs, input_materialization_duration) = match executor_stage_result {
+            Ok((inputs, input_materialization_duration)) => {
+                (inputs, input_materialization_duration)
             }
             Err(e) => return manager.error("materialize_inputs_failed", e),
         };
@@ -276,7 +314,7 @@ impl LocalExecutor {
         // TODO: Release here.
         let manager = manager.claim().boxed().await;
 
-        let scratch_path = &scratch_path.0;
+        let scratch_path = &inputs.scratch.0;
 
         if let Err(e) = executor_stage_async(
             buck2_data::LocalStage {
@@ -383,10 +421,25 @@ impl LocalExecutor {
         let liveliness_observer = manager.inner.liveliness_observer.dupe().and(cancellation);
 
         let (worker, manager) = self
-            .initialize_worker(request, manager, dispatcher)
+            .initialize_worker(request, manager, dispatcher.dupe())
             .boxed()
             .await?;
 
+        // Persistent workers outlive the actions they run, so those can't be sandboxed.
+        let sandbox = if worker.is_some() {
+            None
+        } else if request.local_sandbox().unwrap_or(self.use_sandbox) {
+            Some(self.sandbox_for(request, &inputs.declared, scratch_path.as_deref(), false))
+        } else if self.knobs.detect_undeclared_inputs
+            && self.forkserver.is_some()
+            && undeclared_input_detection_supported(&dispatcher)
+        {
+            Some(self.sandbox_for(request, &inputs.declared, scratch_path.as_deref(), true))
+        } else {
+            None
+        };
+        let trace_only = sandbox.as_ref().is_some_and(|s| s.trace_only);
+
         let execution_kind = match worker {
             None => CommandExecutionKind::Local {
                 digest: action_digest.dupe(),
@@ -454,6 +507,7 @@ impl LocalExecutor {
                         liveliness_observer,
                         request.disable_miniperf(),
                         &action_digest.to_string(),
+                        sandbox.as_ref(),
                     )
                     .await
                 };
@@ -486,15 +540,38 @@ impl LocalExecutor {
 
         let std_streams = CommandStdStreams::Local { stdout, stderr };
 
+        // When only tracing, the command ran as it would have without a sandbox.
+        let status = match status {
+            GatherOutputStatus::SandboxViolation {
+                exit_code,
+                execution_stats,
+                undeclared_paths,
+            } if trace_only => {
+                dispatcher.instant_event(buck2_data::UndeclaredInputs {
+                    paths: self.display_paths(&undeclared_paths),
+                });
+                GatherOutputStatus::Finished {
+                    exit_code,
+                    execution_stats,
+                }
+            }
+            status => status,
+        };
+
         match status {
             GatherOutputStatus::Finished {
                 exit_code,
                 execution_stats,
             } => {
-                let (outputs, hashing_time) = match self
-                    .calculate_and_declare_output_values(request, digest_config)
-                    .boxed()
-                    .await
+                let (outputs, hashing_time) = match calculate_and_declare_output_values(
+                    &self.artifact_fs,
+                    self.materializer.as_ref(),
+                    self.blocking_executor.as_ref(),
+                    request,
+                    digest_config,
+                )
+                .boxed()
+                .await
                 {
                     Ok((output_values, hashing_time)) => (output_values, hashing_time),
                     Err(e) => {
@@ -570,71 +647,65 @@ impl LocalExecutor {
             GatherOutputStatus::TimedOut(duration) => {
                 manager.timeout(execution_kind, duration, std_streams, *timing, None)
             }
+            GatherOutputStatus::SandboxViolation {
+                undeclared_paths, ..
+            } => manager.error(
+                "sandbox_violation",
+                LocalExecutionError::UndeclaredPathAccess(self.display_paths(&undeclared_paths)),
+            ),
             GatherOutputStatus::Cancelled => manager.cancel_claim(),
         }
     }
 
-    async fn calculate_and_declare_output_values(
+    /// Paths under the project root are shown relative to it.
+    fn display_paths(&self, paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| {
+                let p = p.strip_prefix(self.root.as_path()).unwrap_or(p);
+                p.display().to_string()
+            })
+            .collect()
+    }
+
+    /// What a sandboxed `request` gets to see: its inputs and the toolchain can be read, and only
+    /// its outputs and scratch directory can be written to. The directories outputs go in are not
+    /// exposed, only the outputs themselves.
+    fn sandbox_for(
         &self,
         request: &CommandExecutionRequest,
-        digest_config: DigestConfig,
-    ) -> buck2_error::Result<(IndexMap<CommandExecutionOutput, ArtifactValue>, HashingInfo)> {
-        let mut builder = inputs_directory(request.inputs(), &self.artifact_fs)?;
-
-        // Read outputs from disk and add them to the builder
-        let mut entries = Vec::new();
-        let mut total_hashing_time = Duration::ZERO;
-        let mut total_hashed_outputs = 0;
-        for output in request.outputs() {
-            let path = output.resolve(&self.artifact_fs).into_path();
-            let abspath = self.root.join(&path);
-            let (entry, hashing_info) = build_entry_from_disk(
-                abspath,
-                FileDigestConfig::build(digest_config.cas_digest_config()),
-                self.blocking_executor.as_ref(),
-                self.artifact_fs.fs().root(),
-            )
-            .await
-            .with_buck_error_context(|| format!("collecting output {:?}", path))?;
-            total_hashing_time += hashing_info.hashing_duration;
-            total_hashed_outputs += hashing_info.hashed_artifacts_count;
-            if let Some(entry) = entry {
-                insert_entry(&mut builder, &path, entry)?;
-                entries.push((output.cloned(), path));
-            }
-        }
+        declared_inputs: &[ProjectRelativePathBuf],
+        scratch_path: Option<&ProjectRelativePath>,
+        trace_only: bool,
+    ) -> LocalSandbox {
+        let toolchain: Vec<PathBuf> = match &self.knobs.local_sandbox_toolchain_paths {
+            Some(paths) => paths.iter().map(|p| p.as_path().to_owned()).collect(),
+            None => DEFAULT_SANDBOX_TOOLCHAIN_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+        };
 
-        let mut to_declare = vec![];
-        let mut mapped_outputs = IndexMap::with_capacity(entries.len());
+        let read_only = declared_inputs
+            .iter()
+            .map(|p| self.root.join(p).into_path_buf())
+            .chain(toolchain)
+            .collect();
 
-        for (output, path) in entries {
-            let value = extract_artifact_value(&builder, &path, digest_config)?;
-            if let Some(value) = value {
-                match output {
-                    CommandExecutionOutput::BuildArtifact { .. } => {
-                        to_declare.push((path, value.dupe()));
-                    }
-                    CommandExecutionOutput::TestPath { .. } => {
-                        // Don't declare those as we don't currently have any form of GC so this
-                        // would take up space for nothing, and most importantly, we will never
-                        // need them to be in materializer state for e.g. matching as nothing
-                        // should depend on them.
-                    }
-                }
+        let outputs = request
+            .outputs()
+            .map(|o| o.resolve(&self.artifact_fs).into_path());
+        let writable = outputs
+            .chain(scratch_path.map(|p| p.to_buf()))
+            .map(|p| self.root.join(p).into_path_buf())
+            .collect();
 
-                mapped_outputs.insert(output, value);
-            }
+        LocalSandbox {
+            project_root: self.root.as_path().to_owned(),
+            read_only,
+            writable,
+            trace_only,
         }
-
-        self.materializer.declare_existing(to_declare).await?;
-
-        Ok((
-            mapped_outputs,
-            HashingInfo {
-                hashing_duration: total_hashing_time,
-                hashed_artifacts_count: total_hashed_outputs,
-            },
-        ))
     }
 
     async fn acquire_worker_permit(
@@ -846,6 +917,8 @@ impl<'a> StrOrOsStr<'a> {
 pub struct MaterializedInputPaths {
     pub scratch: ScratchPath,
     pub paths: Vec<ProjectRelativePathBuf>,
+    /// Every input the command declared, including those that didn't need materializing.
+    pub declared: Vec<ProjectRelativePathBuf>,
 }
 
 /// Materialize all inputs artifact for CommandExecutionRequest so the command can be executed locally.
@@ -858,15 +931,18 @@ pub async fn materialize_inputs(
     request: &CommandExecutionRequest,
 ) -> buck2_error::Result<MaterializedInputPaths> {
     let mut paths = vec![];
+    let mut declared = vec![];
     let mut scratch = ScratchPath(None);
 
     for input in request.inputs() {
         match input {
             CommandExecutionInput::Artifact(group) => {
                 for (artifact, _) in group.iter() {
+                    let path = artifact.resolve_path(artifact_fs)?;
                     if artifact.requires_materialization(artifact_fs) {
-                        paths.push(artifact.resolve_path(artifact_fs)?);
+                        paths.push(path.clone());
                     }
+                    declared.push(path);
                 }
             }
             CommandExecutionInput::ActionMetadata(metadata) => {
@@ -877,6 +953,7 @@ pub async fn materialize_inputs(
                 artifact_fs
                     .fs()
                     .write_file(&path, &metadata.data.0.0, false)?;
+                declared.push(path);
             }
             CommandExecutionInput::ScratchPath(path) => {
                 let path = artifact_fs.buck_out_path_resolver().resolve_scratch(path);
@@ -913,7 +990,11 @@ pub async fn materialize_inputs(
         }
     }
 
-    Ok(MaterializedInputPaths { scratch, paths })
+    Ok(MaterializedInputPaths {
+        scratch,
+        paths,
+        declared,
+    })
 }
 
 /// A scratch path discovered during `materialize_inputs`.
@@ -993,6 +1074,73 @@ pub async fn materialize_build_outputs_from_previous_run(
     materializer.ensure_materialized(paths).await
 }
 
+/// Hash the outputs `request` left on disk, and declare the build artifacts among them to the
+/// materializer.
+pub(crate) async fn calculate_and_declare_output_values(
+    artifact_fs: &ArtifactFs,
+    materializer: &dyn Materializer,
+    blocking_executor: &dyn BlockingExecutor,
+    request: &CommandExecutionRequest,
+    digest_config: DigestConfig,
+) -> buck2_error::Result<(IndexMap<CommandExecutionOutput, ArtifactValue>, HashingInfo)> {
+    let mut builder = inputs_directory(request.inputs(), artifact_fs)?;
+
+    // Read outputs from disk and add them to the builder
+    let mut entries = Vec::new();
+    let mut total_hashing_time = Duration::ZERO;
+    let mut total_hashed_outputs = 0;
+    for output in request.outputs() {
+        let path = output.resolve(artifact_fs).into_path();
+        let abspath = artifact_fs.fs().resolve(&path);
+        let (entry, hashing_info) = build_entry_from_disk(
+            abspath,
+            FileDigestConfig::build(digest_config.cas_digest_config()),
+            blocking_executor,
+            artifact_fs.fs().root(),
+        )
+        .await
+        .with_buck_error_context(|| format!("collecting output {:?}", path))?;
+        total_hashing_time += hashing_info.hashing_duration;
+        total_hashed_outputs += hashing_info.hashed_artifacts_count;
+        if let Some(entry) = entry {
+            insert_entry(&mut builder, &path, entry)?;
+            entries.push((output.cloned(), path));
+        }
+    }
+
+    let mut to_declare = vec![];
+    let mut mapped_outputs = IndexMap::with_capacity(entries.len());
+
+    for (output, path) in entries {
+        let value = extract_artifact_value(&builder, &path, digest_config)?;
+        if let Some(value) = value {
+            match output {
+                CommandExecutionOutput::BuildArtifact { .. } => {
+                    to_declare.push((path, value.dupe()));
+                }
+                CommandExecutionOutput::TestPath { .. } => {
+                    // Don't declare those as we don't currently have any form of GC so this
+                    // would take up space for nothing, and most importantly, we will never
+                    // need them to be in materializer state for e.g. matching as nothing
+                    // should depend on them.
+                }
+            }
+
+            mapped_outputs.insert(output, value);
+        }
+    }
+
+    materializer.declare_existing(to_declare).await?;
+
+    Ok((
+        mapped_outputs,
+        HashingInfo {
+            hashing_duration: total_hashing_time,
+            hashed_artifacts_count: total_hashed_outputs,
+        },
+    ))
+}
+
 /// Create any output dirs requested by the command. Note that this makes no effort to delete
 /// the output paths first. Eventually it should, but right now this happens earlier. This
 /// would be a separate refactor.
@@ -1098,9 +1246,30 @@ impl EnvironmentBuilder for Command {
     }
 }
 
+/// Whether trace-only sandboxes work on this host. If they don't, warn once and run actions as if
+/// `buck2.detect_undeclared_inputs` was unset, rather than failing all of them.
+fn undeclared_input_detection_supported(dispatcher: &EventDispatcher) -> bool {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    #[cfg(unix)]
+    let supported = buck2_forkserver::unix::undeclared_input_detection_supported();
+    #[cfg(not(unix))]
+    let supported = false;
+
+    if !supported && !WARNED.swap(true, Ordering::Relaxed) {
+        dispatcher.console_warning(
+            "`buck2.detect_undeclared_inputs` is set, but this host can't detect undeclared \
+            inputs (that needs Linux 5.5 or later), so local actions run without it"
+                .to_owned(),
+        );
+    }
+    supported
+}
+
 #[cfg(unix)]
 mod unix {
     use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
 
     use super::*;
 
@@ -1115,6 +1284,7 @@ mod unix {
         liveliness_observer: impl LivelinessObserver + 'static,
         enable_miniperf: bool,
         action_digest: &str,
+        sandbox: Option<&LocalSandbox>,
     ) -> buck2_error::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)> {
         let exe = exe.as_ref();
 
@@ -1133,6 +1303,12 @@ mod unix {
             std_redirects: None,
             graceful_shutdown_timeout_s: None,
             action_digest: Some(action_digest.to_owned()),
+            sandbox: sandbox.map(|sandbox| buck2_forkserver_proto::SandboxConfig {
+                project_root: path_bytes(&sandbox.project_root),
+                read_only_paths: sandbox.read_only.iter().map(|p| path_bytes(p)).collect(),
+                writable_paths: sandbox.writable.iter().map(|p| path_bytes(p)).collect(),
+                trace_only: sandbox.trace_only,
+            }),
         };
         apply_local_execution_environment(&mut req, working_directory, env, env_inheritance);
         forkserver
@@ -1140,6 +1316,10 @@ mod unix {
             .await
     }
 
+    fn path_bytes(path: &Path) -> Vec<u8> {
+        path.as_os_str().as_bytes().to_vec()
+    }
+
     trait CommandRequestExt {
         fn push_env_directive<D>(&mut self, directive: D)
         where
@@ -1232,6 +1412,7 @@ mod tests {
             None,
             ExecutorGlobalKnobs::default(),
             None,
+            false,
         );
 
         Ok((executor, temp.path().root().to_buf(), temp))
@@ -1253,6 +1434,7 @@ mod tests {
                 NoopLivelinessObserver::create(),
                 false,
                 "",
+                None,
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
@@ -1290,6 +1472,7 @@ mod tests {
                 NoopLivelinessObserver::create(),
                 false,
                 "",
+                None,
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
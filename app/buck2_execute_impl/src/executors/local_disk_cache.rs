@@ -0,0 +1,1042 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An action cache on local disk, for when there is no remote cache to ask.
+//!
+//! Entries are keyed by action digest, and by the environment variables the command inherits from
+//! the daemon, which the action digest doesn't cover. They live under `ac/`. The files they
+//! reference are stored once per content digest under `cas/`. A file's mtime records when it was last used: hits touch
+//! the entry and its blobs, and once the cache outgrows its size limit the least recently used
+//! files are deleted. An entry that lost any of its blobs that way is a miss.
+//!
+//! Several daemons can share a cache, so blobs can disappear at any time. A hit is only reported
+//! once its blobs have been staged, see [`StagedEntry`]. Files are written and staged under `tmp/`
+//! first, and whatever a crashed daemon left there is deleted once it is old enough.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::ops::ControlFlow;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_action_metadata_proto::LocalDiskCacheEntry;
+use buck2_action_metadata_proto::LocalDiskCacheFile;
+use buck2_action_metadata_proto::LocalDiskCacheOutput;
+use buck2_action_metadata_proto::LocalDiskCacheSymlink;
+use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::TrackedFileDigest;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_directory::directory::directory::Directory;
+use buck2_directory::directory::directory_iterator::DirectoryIterator;
+use buck2_directory::directory::entry::DirectoryEntry;
+use buck2_error::BuckErrorContext;
+use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::digest_config::DigestConfig;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::entry::HashingInfo;
+use buck2_execute::execute::action_digest::ActionDigest;
+use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
+use buck2_execute::execute::environment_inheritance::EnvironmentInheritance;
+use buck2_execute::execute::executor_stage_async;
+use buck2_execute::execute::kind::CommandExecutionKind;
+use buck2_execute::execute::manager::CommandExecutionManager;
+use buck2_execute::execute::manager::CommandExecutionManagerExt;
+use buck2_execute::execute::output::CommandStdStreams;
+use buck2_execute::execute::prepared::PreparedCommand;
+use buck2_execute::execute::prepared::PreparedCommandExecutor;
+use buck2_execute::execute::prepared::PreparedCommandOptionalExecutor;
+use buck2_execute::execute::request::CommandExecutionOutput;
+use buck2_execute::execute::request::CommandExecutionOutputRef;
+use buck2_execute::execute::request::CommandExecutionRequest;
+use buck2_execute::execute::request::ExecutorPreference;
+use buck2_execute::execute::result::CommandExecutionMetadata;
+use buck2_execute::execute::result::CommandExecutionResult;
+use buck2_execute::materialize::materializer::Materializer;
+use buck2_futures::cancellation::CancellationContext;
+use dupe::Dupe;
+use indexmap::IndexMap;
+use parking_lot::Mutex;
+use prost::Message;
+
+use crate::executors::local::calculate_and_declare_output_values;
+
+/// When the cache is full, it's trimmed to this percentage of its size limit, so that we don't
+/// have to scan it again on the next store.
+const LOW_WATERMARK_PERCENT: u64 = 90;
+
+/// Used to name temporary files, which get renamed into place once fully written.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Temporary files and staged entries live for as long as one store or restore, so anything older
+/// than this was left behind by a daemon that crashed.
+const STALE_TEMP_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Dupe, Allocative)]
+pub struct LocalDiskActionCache {
+    inner: Arc<LocalDiskActionCacheInner>,
+}
+
+#[derive(Allocative)]
+struct LocalDiskActionCacheInner {
+    root: AbsNormPathBuf,
+    max_bytes: u64,
+    #[allocative(skip)]
+    size: Mutex<CacheSize>,
+}
+
+#[derive(Default)]
+struct CacheSize {
+    /// How many bytes the cache holds, as far as this daemon knows. `None` until the first store
+    /// scans the cache directory.
+    total_bytes: Option<u64>,
+    /// While a store scans the cache directory and evicts files, how many bytes other stores
+    /// added in the meantime. Only one store scans at a time, and the others don't wait for it.
+    scanning: Option<u64>,
+}
+
+/// A file in the cache directory, as seen when deciding what to evict.
+struct CachedFile {
+    path: AbsNormPathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl LocalDiskActionCache {
+    pub fn new(root: AbsNormPathBuf, max_bytes: u64) -> Self {
+        Self {
+            inner: Arc::new(LocalDiskActionCacheInner {
+                root,
+                max_bytes,
+                size: Mutex::new(CacheSize::default()),
+            }),
+        }
+    }
+
+    pub fn root(&self) -> &AbsNormPath {
+        &self.inner.root
+    }
+
+    fn entry_path(&self, key: &LocalDiskCacheKey) -> AbsNormPathBuf {
+        self.inner
+            .root
+            .join(ForwardRelativePath::unchecked_new("ac"))
+            .join(FileName::unchecked_new(&key.0))
+    }
+
+    fn blob_path(&self, blob: &str) -> buck2_error::Result<AbsNormPathBuf> {
+        // Entries are read back from disk, so don't let them point outside of the cache.
+        let blob = FileName::new(blob)?;
+        let shard = blob.as_str().get(..2).unwrap_or_default();
+        Ok(self
+            .inner
+            .root
+            .join(ForwardRelativePath::unchecked_new("cas"))
+            .join(ForwardRelativePath::new(shard)?)
+            .join(blob))
+    }
+
+    /// Read the entry for an action, and mark it and its blobs as recently used. Unreadable
+    /// entries, and entries that lost blobs to eviction, are deleted and reported as misses.
+    pub fn lookup(
+        &self,
+        key: &LocalDiskCacheKey,
+    ) -> buck2_error::Result<Option<LocalDiskCacheEntry>> {
+        let entry_path = self.entry_path(key);
+        let Some(data) = fs_util::read_if_exists(&entry_path)? else {
+            return Ok(None);
+        };
+
+        let entry = match LocalDiskCacheEntry::decode(data.as_slice()) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!(
+                    "Discarding corrupt local disk cache entry `{}`: {}",
+                    entry_path,
+                    e
+                );
+                self.remove(&entry_path)?;
+                return Ok(None);
+            }
+        };
+
+        for file in entry.outputs.iter().flat_map(|output| output.files.iter()) {
+            if !touch(&self.blob_path(&file.blob)?)? {
+                self.remove(&entry_path)?;
+                return Ok(None);
+            }
+        }
+        touch(&entry_path)?;
+
+        Ok(Some(entry))
+    }
+
+    /// Add an entry for an action. `blobs` maps the name of every blob the entry references to a
+    /// file holding its contents.
+    pub fn store(
+        &self,
+        key: &LocalDiskCacheKey,
+        entry: &LocalDiskCacheEntry,
+        blobs: &[(String, AbsNormPathBuf)],
+    ) -> buck2_error::Result<()> {
+        let mut added = 0;
+
+        for (blob, source) in blobs {
+            let dest = self.blob_path(blob)?;
+            if touch(&dest)? {
+                continue;
+            }
+            added += self.write_atomically(&dest, |temp| {
+                fs_util::copy(source, temp)?;
+                // Blobs are shared by files that may or may not be executable, so the entry
+                // records that instead.
+                clear_executable(temp)
+            })?;
+        }
+
+        let data = entry.encode_to_vec();
+        added += self.write_atomically(&self.entry_path(key), |temp| {
+            fs_util::write(temp, &data)?;
+            Ok(())
+        })?;
+
+        {
+            let mut size = self.inner.size.lock();
+            if let Some(added_meanwhile) = size.scanning.as_mut() {
+                // The scan may or may not see what we just wrote, so this may count it twice. That
+                // only makes the next eviction come a little early.
+                *added_meanwhile += added;
+                return Ok(());
+            }
+            if let Some(total) = size.total_bytes.as_mut() {
+                if *total + added <= self.inner.max_bytes {
+                    *total += added;
+                    return Ok(());
+                }
+            }
+            size.scanning = Some(0);
+        }
+
+        // The scan and deletes do I/O over the whole cache, so they happen without the lock held.
+        let remaining = self.scan().map(|files| self.evict(files));
+
+        let mut size = self.inner.size.lock();
+        let added_meanwhile = size.scanning.take().unwrap_or_default();
+        size.total_bytes = remaining.as_ref().ok().map(|r| r + added_meanwhile);
+        remaining?;
+
+        Ok(())
+    }
+
+    /// Make the blobs of an entry found by [`lookup`](Self::lookup) safe from eviction until it
+    /// has been restored. Returns `None` if some of them were evicted since the lookup.
+    pub fn stage(&self, entry: LocalDiskCacheEntry) -> buck2_error::Result<Option<StagedEntry>> {
+        let staged = StagedEntry {
+            dir: self.temp_path()?,
+            entry,
+        };
+        fs_util::create_dir_all(&staged.dir)?;
+
+        let mut blobs = HashSet::new();
+        for file in staged
+            .entry
+            .outputs
+            .iter()
+            .flat_map(|output| output.files.iter())
+        {
+            if !blobs.insert(file.blob.as_str()) {
+                continue;
+            }
+            let source = self.blob_path(&file.blob)?;
+            let dest = staged.blob_path(&file.blob)?;
+            // Hard links keep the blob's contents around if it gets evicted. Not every filesystem
+            // has them, so fall back to copying.
+            let res = std::fs::hard_link(source.as_path(), dest.as_path())
+                .or_else(|_| std::fs::copy(source.as_path(), dest.as_path()).map(|_| ()));
+            match res {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(e)
+                        .with_buck_error_context(|| format!("Error staging `{}`", source));
+                }
+            }
+        }
+
+        Ok(Some(staged))
+    }
+
+    /// A fresh path in the cache's temporary directory.
+    fn temp_path(&self) -> buck2_error::Result<AbsNormPathBuf> {
+        let temp_dir = self
+            .inner
+            .root
+            .join(ForwardRelativePath::unchecked_new("tmp"));
+        fs_util::create_dir_all(&temp_dir)?;
+        Ok(temp_dir.join(ForwardRelativePath::unchecked_new(&format!(
+            "{}-{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))))
+    }
+
+    /// Write `dest` by way of a temporary file, so that concurrent readers never see it partially
+    /// written. Returns the size of the file.
+    fn write_atomically(
+        &self,
+        dest: &AbsNormPath,
+        write: impl FnOnce(&AbsNormPath) -> buck2_error::Result<()>,
+    ) -> buck2_error::Result<u64> {
+        let temp = self.temp_path()?;
+
+        let res = (|| {
+            write(&temp)?;
+            let size = fs_util::metadata(&temp)?.len();
+            if let Some(parent) = dest.parent() {
+                fs_util::create_dir_all(parent)?;
+            }
+            fs_util::rename(&temp, dest)?;
+            Ok(size)
+        })();
+
+        if res.is_err() {
+            fs_util::remove_all(&temp)?;
+        }
+        res
+    }
+
+    fn remove(&self, path: &AbsNormPath) -> buck2_error::Result<()> {
+        let Some(metadata) = fs_util::symlink_metadata_if_exists(path)? else {
+            return Ok(());
+        };
+        fs_util::remove_file(path)?;
+        if let Some(total) = self.inner.size.lock().total_bytes.as_mut() {
+            *total = total.saturating_sub(metadata.len());
+        }
+        Ok(())
+    }
+
+    /// List every entry and blob in the cache. This also deletes the stale files in `tmp/`.
+    fn scan(&self) -> buck2_error::Result<Vec<CachedFile>> {
+        self.remove_stale_temp_files()?;
+
+        let mut files = Vec::new();
+        let mut dirs = vec![self
+            .inner
+            .root
+            .join(ForwardRelativePath::unchecked_new("ac"))];
+        if let Some(shards) = fs_util::read_dir_if_exists(
+            self.inner
+                .root
+                .join(ForwardRelativePath::unchecked_new("cas")),
+        )? {
+            for shard in shards {
+                dirs.push(shard?.path());
+            }
+        }
+
+        for dir in dirs {
+            let Some(entries) = fs_util::read_dir_if_exists(&dir)? else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                let metadata = match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    // Someone else evicted this while we were looking.
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                files.push(CachedFile {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    last_used: metadata.modified()?,
+                });
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn remove_stale_temp_files(&self) -> buck2_error::Result<()> {
+        let Some(entries) = fs_util::read_dir_if_exists(
+            self.inner
+                .root
+                .join(ForwardRelativePath::unchecked_new("tmp")),
+        )?
+        else {
+            return Ok(());
+        };
+        let now = SystemTime::now();
+        for entry in entries {
+            let entry = entry?;
+            let modified = match entry.metadata().and_then(|m| m.modified()) {
+                Ok(modified) => modified,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if now.duration_since(modified).unwrap_or_default() < STALE_TEMP_AGE {
+                continue;
+            }
+            if let Err(e) = fs_util::remove_all(entry.path()) {
+                tracing::warn!(
+                    "Error deleting a stale local disk cache temporary file: {:#}",
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the least recently used of the `files` in the cache until it is below its low
+    /// watermark, if it is over its size limit. Returns how many bytes are left.
+    fn evict(&self, mut files: Vec<CachedFile>) -> u64 {
+        let mut total: u64 = files.iter().map(|f| f.size).sum();
+        if total <= self.inner.max_bytes {
+            return total;
+        }
+
+        files.sort_by_key(|f| f.last_used);
+        let target = self.inner.max_bytes / 100 * LOW_WATERMARK_PERCENT;
+        for file in files {
+            if total <= target {
+                break;
+            }
+            // Someone else may have evicted this already, and a file we failed to delete only
+            // means the cache stays bigger for a while.
+            if let Err(e) = fs_util::remove_all(&file.path) {
+                tracing::warn!("Error evicting from the local disk cache: {:#}", e);
+                continue;
+            }
+            total -= file.size;
+        }
+
+        total
+    }
+}
+
+/// A cache entry whose blobs have been linked to a directory of their own, so that evicting them
+/// can't make restoring the entry fail after the command claimed the hit. The directory is deleted
+/// on drop.
+pub struct StagedEntry {
+    dir: AbsNormPathBuf,
+    entry: LocalDiskCacheEntry,
+}
+
+impl StagedEntry {
+    pub fn entry(&self) -> &LocalDiskCacheEntry {
+        &self.entry
+    }
+
+    fn blob_path(&self, blob: &str) -> buck2_error::Result<AbsNormPathBuf> {
+        Ok(self.dir.join(FileName::new(blob)?))
+    }
+
+    /// Write the files of the entry's outputs below `project_root`. Output paths must not exist.
+    pub fn restore(&self, project_root: &ProjectRoot) -> buck2_error::Result<()> {
+        for output in &self.entry.outputs {
+            let output_path = project_root.resolve(ProjectRelativePath::new(&output.path)?);
+            let resolve = |path: &str| -> buck2_error::Result<AbsNormPathBuf> {
+                Ok(output_path.join(ForwardRelativePath::new(path)?))
+            };
+
+            if let Some(parent) = output_path.parent() {
+                fs_util::create_dir_all(parent)?;
+            }
+            for dir in &output.directories {
+                fs_util::create_dir_all(resolve(dir)?)?;
+            }
+            for file in &output.files {
+                let path = resolve(&file.path)?;
+                fs_util::copy(self.blob_path(&file.blob)?, &path)?;
+                if file.is_executable {
+                    fs_util::set_executable(&path)?;
+                }
+            }
+            for symlink in &output.symlinks {
+                fs_util::symlink(&symlink.target, resolve(&symlink.path)?)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for StagedEntry {
+    fn drop(&mut self) {
+        if let Err(e) = fs_util::remove_all(&self.dir) {
+            tracing::warn!("Error cleaning up `{}`: {:#}", self.dir, e);
+        }
+    }
+}
+
+/// Mark a file as recently used. Returns whether it exists.
+fn touch(path: &AbsNormPath) -> buck2_error::Result<bool> {
+    let file = match File::options().write(true).open(path.as_path()) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            return Err(e).with_buck_error_context(|| format!("Error opening `{}`", path));
+        }
+    };
+    file.set_modified(SystemTime::now())
+        .with_buck_error_context(|| format!("Error touching `{}`", path))?;
+    Ok(true)
+}
+
+fn clear_executable(path: &AbsNormPath) -> buck2_error::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut perms = fs_util::metadata(path)?.permissions();
+        perms.set_mode(0o644);
+        fs_util::set_permissions(path, perms)?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ignore = path;
+    }
+
+    Ok(())
+}
+
+fn blob_name(digest: &TrackedFileDigest) -> String {
+    format!("{}_{}", digest.raw_digest(), digest.size())
+}
+
+/// What an entry is stored under: the action digest, and a digest of the environment variables the
+/// command inherits from the daemon.
+pub struct LocalDiskCacheKey(String);
+
+impl LocalDiskCacheKey {
+    /// The key for a command, `None` if its result shouldn't be cached. Only commands that allow
+    /// cache uploads qualify, like for the remote cache, and not the ones that keep state in their
+    /// outputs, use workers or local resources, and tests.
+    fn for_command(
+        request: &CommandExecutionRequest,
+        digest: &ActionDigest,
+        digest_config: DigestConfig,
+    ) -> Option<Self> {
+        let cacheable = request.allow_cache_upload()
+            && request.outputs_cleanup
+            && request.worker().is_none()
+            && request.required_local_resources().is_empty()
+            && request
+                .outputs()
+                .all(|output| matches!(output, CommandExecutionOutputRef::BuildArtifact { .. }));
+        if !cacheable {
+            return None;
+        }
+
+        // The cache is shared by daemons with different environments, and survives changes to it,
+        // so the results of a command are only reused in the same environment.
+        let mut environment = Vec::new();
+        for (key, value) in inherited_environment(request.local_environment_inheritance()) {
+            for s in [key, value] {
+                environment.extend_from_slice(s.as_encoded_bytes());
+                environment.push(0);
+            }
+        }
+        let environment = FileDigest::from_content(&environment, digest_config.cas_digest_config());
+
+        Some(Self(format!(
+            "{}_{}_{}",
+            digest.raw_digest(),
+            digest.size(),
+            environment.raw_digest()
+        )))
+    }
+}
+
+/// The variables a command inherits from the daemon's environment, before its own are set.
+fn inherited_environment(
+    inheritance: Option<&EnvironmentInheritance>,
+) -> BTreeMap<OsString, OsString> {
+    let mut environment = BTreeMap::new();
+    if !inheritance.is_some_and(|inheritance| inheritance.clear()) {
+        environment.extend(std::env::vars_os());
+    }
+    if let Some(inheritance) = inheritance {
+        for key in inheritance.exclusions() {
+            environment.remove(OsStr::new(key));
+        }
+        for (key, value) in inheritance.values() {
+            environment.insert(key.into(), value.clone());
+        }
+    }
+    environment
+}
+
+/// Serves actions from the local disk cache.
+pub struct LocalDiskCacheChecker {
+    pub cache: LocalDiskActionCache,
+    pub artifact_fs: ArtifactFs,
+    pub materializer: Arc<dyn Materializer>,
+    pub blocking_executor: Arc<dyn BlockingExecutor>,
+}
+
+impl LocalDiskCacheChecker {
+    async fn restore_outputs(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        staged: &StagedEntry,
+        cancellations: &CancellationContext,
+    ) -> buck2_error::Result<(IndexMap<CommandExecutionOutput, ArtifactValue>, HashingInfo)> {
+        let output_paths: Vec<_> = command
+            .request
+            .outputs()
+            .map(|output| output.resolve(&self.artifact_fs).into_path())
+            .collect();
+
+        self.materializer
+            .invalidate_many(output_paths.clone())
+            .await?;
+        self.blocking_executor
+            .execute_io(
+                Box::new(CleanOutputPaths {
+                    paths: output_paths,
+                }),
+                cancellations,
+            )
+            .await
+            .buck_error_context("Failed to cleanup output directory")?;
+
+        self.blocking_executor
+            .execute_io_inline(|| staged.restore(self.artifact_fs.fs()))
+            .await?;
+
+        calculate_and_declare_output_values(
+            &self.artifact_fs,
+            self.materializer.as_ref(),
+            self.blocking_executor.as_ref(),
+            command.request,
+            command.digest_config,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl PreparedCommandOptionalExecutor for LocalDiskCacheChecker {
+    async fn maybe_execute(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> ControlFlow<CommandExecutionResult, CommandExecutionManager> {
+        let action_digest = command.prepared_action.digest();
+        let Some(key) =
+            LocalDiskCacheKey::for_command(command.request, &action_digest, command.digest_config)
+        else {
+            return ControlFlow::Continue(manager);
+        };
+
+        let start = Instant::now();
+        let start_time = SystemTime::now();
+
+        // Anything that goes wrong before we claim the hit makes it a miss, so the blobs get staged
+        // here already.
+        let staged = executor_stage_async(
+            buck2_data::CacheQuery {
+                action_digest: action_digest.to_string(),
+                cache_type: buck2_data::CacheType::LocalDiskCache.into(),
+            },
+            self.blocking_executor
+                .execute_io_inline(|| match self.cache.lookup(&key)? {
+                    Some(entry) => self.cache.stage(entry),
+                    None => Ok(None),
+                }),
+        )
+        .await;
+
+        let staged = match staged {
+            Ok(Some(staged)) => staged,
+            Ok(None) => return ControlFlow::Continue(manager),
+            Err(e) => {
+                // The cache is only an optimization, so a broken one shouldn't fail the build.
+                tracing::warn!(
+                    "Error reading or staging the local disk cache entry for `{}`: {:#}",
+                    action_digest,
+                    e
+                );
+                return ControlFlow::Continue(manager);
+            }
+        };
+
+        let execution_kind = CommandExecutionKind::LocalDiskCache {
+            digest: action_digest.dupe(),
+        };
+        let manager = manager
+            .with_execution_kind(execution_kind.clone())
+            .claim()
+            .await;
+
+        let restored = executor_stage_async(
+            buck2_data::CacheHit {
+                action_digest: action_digest.to_string(),
+                action_key: None,
+                cache_type: buck2_data::CacheType::LocalDiskCache.into(),
+            },
+            self.restore_outputs(command, &staged, cancellations),
+        )
+        .await;
+
+        let (outputs, hashing_info) = match restored {
+            Ok(restored) => restored,
+            Err(e) => return ControlFlow::Break(manager.error("local_disk_cache_restore", e)),
+        };
+
+        tracing::info!(
+            "Action result is in the local disk cache, skipping execution of:\n```\n$ {}\n```\n for action `{}`",
+            command.request.all_args_str(),
+            action_digest,
+        );
+
+        let timing = CommandExecutionMetadata {
+            wall_time: start.elapsed(),
+            execution_time: Duration::from_micros(staged.entry().execution_time_us),
+            start_time,
+            hashing_duration: hashing_info.hashing_duration,
+            hashed_artifacts_count: hashing_info.hashed_artifacts_count,
+            ..Default::default()
+        };
+
+        ControlFlow::Break(manager.success(
+            execution_kind,
+            outputs,
+            CommandStdStreams::Local {
+                stdout: staged.entry().stdout.clone(),
+                stderr: staged.entry().stderr.clone(),
+            },
+            timing,
+        ))
+    }
+}
+
+/// Runs commands with `inner`, and adds the ones that succeeded locally to the local disk cache.
+pub struct LocalDiskCacheWriter<E> {
+    pub cache: LocalDiskActionCache,
+    pub artifact_fs: ArtifactFs,
+    pub blocking_executor: Arc<dyn BlockingExecutor>,
+    pub inner: E,
+}
+
+impl<E> LocalDiskCacheWriter<E> {
+    async fn store(
+        &self,
+        key: &LocalDiskCacheKey,
+        result: &CommandExecutionResult,
+    ) -> buck2_error::Result<()> {
+        let CommandStdStreams::Local { stdout, stderr } = &result.report.std_streams else {
+            return Ok(());
+        };
+
+        let mut blobs = Vec::new();
+        let mut outputs = Vec::new();
+        for (output, value) in result.resolve_outputs(&self.artifact_fs) {
+            let output_path = self.artifact_fs.fs().resolve(output.path());
+            let mut cached = LocalDiskCacheOutput {
+                path: output.path().to_string(),
+                ..Default::default()
+            };
+
+            match value.entry().as_ref() {
+                DirectoryEntry::Leaf(leaf) => add_leaf(
+                    &mut cached,
+                    &mut blobs,
+                    &output_path,
+                    ForwardRelativePath::empty(),
+                    leaf,
+                )?,
+                DirectoryEntry::Dir(dir) => {
+                    cached.directories.push(String::new());
+                    for (path, entry) in dir.unordered_walk().with_paths() {
+                        match entry {
+                            DirectoryEntry::Dir(_) => cached.directories.push(path.to_string()),
+                            DirectoryEntry::Leaf(leaf) => {
+                                add_leaf(&mut cached, &mut blobs, &output_path, &path, leaf)?
+                            }
+                        }
+                    }
+                }
+            }
+
+            outputs.push(cached);
+        }
+
+        let entry = LocalDiskCacheEntry {
+            outputs,
+            stdout: stdout.clone(),
+            stderr: stderr.clone(),
+            execution_time_us: result
+                .report
+                .timing
+                .execution_time
+                .as_micros()
+                .try_into()
+                .unwrap_or(u64::MAX),
+        };
+
+        self.blocking_executor
+            .execute_io_inline(|| self.cache.store(key, &entry, &blobs))
+            .await
+    }
+}
+
+fn add_leaf(
+    output: &mut LocalDiskCacheOutput,
+    blobs: &mut Vec<(String, AbsNormPathBuf)>,
+    output_path: &AbsNormPath,
+    path: &ForwardRelativePath,
+    leaf: &ActionDirectoryMember,
+) -> buck2_error::Result<()> {
+    let abspath = output_path.join(path);
+    match leaf {
+        ActionDirectoryMember::File(f) => {
+            let blob = blob_name(&f.digest);
+            output.files.push(LocalDiskCacheFile {
+                path: path.to_string(),
+                blob: blob.clone(),
+                is_executable: f.is_executable,
+            });
+            blobs.push((blob, abspath));
+        }
+        ActionDirectoryMember::Symlink(_) | ActionDirectoryMember::ExternalSymlink(_) => {
+            let target = fs_util::read_link(&abspath)?;
+            let target = target.to_str().with_buck_error_context(|| {
+                format!("Symlink target of `{}` is not UTF-8", abspath)
+            })?;
+            output.symlinks.push(LocalDiskCacheSymlink {
+                path: path.to_string(),
+                target: target.to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl<E> PreparedCommandExecutor for LocalDiskCacheWriter<E>
+where
+    E: PreparedCommandExecutor,
+{
+    async fn exec_cmd(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> CommandExecutionResult {
+        let result = self.inner.exec_cmd(command, manager, cancellations).await;
+
+        if result.was_locally_executed() {
+            let action_digest = command.prepared_action.digest();
+            if let Some(key) = LocalDiskCacheKey::for_command(
+                command.request,
+                &action_digest,
+                command.digest_config,
+            ) {
+                if let Err(e) = self.store(&key, &result).await {
+                    tracing::warn!(
+                        "Error adding `{}` to the local disk cache: {:#}",
+                        action_digest,
+                        e
+                    );
+                }
+            }
+        }
+
+        result
+    }
+
+    fn is_local_execution_possible(&self, executor_preference: ExecutorPreference) -> bool {
+        self.inner.is_local_execution_possible(executor_preference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::paths::abs_path::AbsPath;
+
+    use super::*;
+
+    fn key(n: u8) -> LocalDiskCacheKey {
+        LocalDiskCacheKey(format!("{:0>40}_10_{:0>40}", n, 0))
+    }
+
+    fn cache(max_bytes: u64) -> (tempfile::TempDir, LocalDiskActionCache) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = AbsNormPathBuf::new(dir.path().to_path_buf()).unwrap();
+        (dir, LocalDiskActionCache::new(root, max_bytes))
+    }
+
+    /// Store an action with a single output file holding `contents`.
+    fn store_file(cache: &LocalDiskActionCache, key: &LocalDiskCacheKey, contents: &str) {
+        let source = cache
+            .root()
+            .join(ForwardRelativePath::unchecked_new("source"));
+        fs_util::write(&source, contents).unwrap();
+
+        let blob = format!("{:0>40}_{}", contents, contents.len());
+        let entry = LocalDiskCacheEntry {
+            outputs: vec![LocalDiskCacheOutput {
+                path: "out".to_owned(),
+                files: vec![LocalDiskCacheFile {
+                    path: String::new(),
+                    blob: blob.clone(),
+                    is_executable: false,
+                }],
+                ..Default::default()
+            }],
+            stdout: b"stdout".to_vec(),
+            ..Default::default()
+        };
+        cache.store(key, &entry, &[(blob, source)]).unwrap();
+    }
+
+    fn set_last_used(path: &AbsNormPath, secs_ago: u64) {
+        File::options()
+            .write(true)
+            .open(path.as_path())
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(secs_ago))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_store_and_lookup() {
+        let (_dir, cache) = cache(1 << 20);
+        assert!(cache.lookup(&key(1)).unwrap().is_none());
+
+        store_file(&cache, &key(1), "abc");
+        let entry = cache.lookup(&key(1)).unwrap().unwrap();
+        assert_eq!(entry.stdout, b"stdout");
+        assert!(cache.lookup(&key(2)).unwrap().is_none());
+
+        let project = tempfile::tempdir().unwrap();
+        let project_root =
+            ProjectRoot::new_unchecked(AbsNormPathBuf::new(project.path().to_path_buf()).unwrap());
+        let staged = cache.stage(entry).unwrap().unwrap();
+        staged.restore(&project_root).unwrap();
+        assert_eq!(
+            fs_util::read_to_string(AbsPath::new(&project.path().join("out")).unwrap()).unwrap(),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn test_entry_missing_blob_is_a_miss() {
+        let (_dir, cache) = cache(1 << 20);
+        store_file(&cache, &key(1), "abc");
+
+        let entry = cache.lookup(&key(1)).unwrap().unwrap();
+        fs_util::remove_file(cache.blob_path(&entry.outputs[0].files[0].blob).unwrap()).unwrap();
+
+        assert!(cache.lookup(&key(1)).unwrap().is_none());
+        assert!(!fs_util::try_exists(cache.entry_path(&key(1))).unwrap());
+    }
+
+    #[test]
+    fn test_staged_entry_survives_eviction() {
+        let (_dir, cache) = cache(1 << 20);
+        store_file(&cache, &key(1), "abc");
+
+        let entry = cache.lookup(&key(1)).unwrap().unwrap();
+        let blob = cache.blob_path(&entry.outputs[0].files[0].blob).unwrap();
+        let staged = cache.stage(entry.clone()).unwrap().unwrap();
+        fs_util::remove_file(&blob).unwrap();
+
+        let project = tempfile::tempdir().unwrap();
+        let project_root =
+            ProjectRoot::new_unchecked(AbsNormPathBuf::new(project.path().to_path_buf()).unwrap());
+        staged.restore(&project_root).unwrap();
+        assert_eq!(
+            fs_util::read_to_string(AbsPath::new(&project.path().join("out")).unwrap()).unwrap(),
+            "abc"
+        );
+
+        let staged_dir = staged.dir.clone();
+        drop(staged);
+        assert!(!fs_util::try_exists(&staged_dir).unwrap());
+
+        // Once the blob is gone, the entry can't be staged anymore.
+        assert!(cache.stage(entry).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        // Each action takes up 62 bytes, so this holds two but not three.
+        let (_dir, cache) = cache(150);
+        store_file(&cache, &key(1), "a");
+        store_file(&cache, &key(2), "b");
+
+        // Using the first action makes the second one the oldest.
+        for file in cache.scan().unwrap() {
+            set_last_used(&file.path, 60);
+        }
+        cache.lookup(&key(1)).unwrap().unwrap();
+
+        store_file(&cache, &key(3), "c");
+
+        assert!(cache.lookup(&key(1)).unwrap().is_some());
+        assert!(cache.lookup(&key(2)).unwrap().is_none());
+        assert!(cache.lookup(&key(3)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_removes_stale_temp_files() {
+        let (_dir, cache) = cache(1 << 20);
+        let stale = cache.temp_path().unwrap();
+        let fresh = cache.temp_path().unwrap();
+        fs_util::write(&stale, "stale").unwrap();
+        fs_util::write(&fresh, "fresh").unwrap();
+        set_last_used(&stale, STALE_TEMP_AGE.as_secs() + 60);
+
+        cache.scan().unwrap();
+
+        assert!(!fs_util::try_exists(&stale).unwrap());
+        assert!(fs_util::try_exists(&fresh).unwrap());
+    }
+
+    #[test]
+    fn test_inherited_environment() {
+        assert!(inherited_environment(Some(&EnvironmentInheritance::empty())).is_empty());
+        assert_eq!(
+            inherited_environment(None).len(),
+            std::env::vars_os().count()
+        );
+        assert!(
+            !inherited_environment(Some(&EnvironmentInheritance::local_command_exclusions()))
+                .contains_key(OsStr::new("LD_PRELOAD"))
+        );
+    }
+
+    #[test]
+    fn test_rejects_blobs_outside_of_cache() {
+        let (_dir, cache) = cache(1 << 20);
+        assert!(cache.blob_path("../escape").is_err());
+    }
+}
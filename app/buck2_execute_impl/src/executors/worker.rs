@@ -166,6 +166,7 @@ fn spawn_via_forkserver(
             }),
             graceful_shutdown_timeout_s,
             action_digest: None,
+            sandbox: None,
         };
         apply_local_execution_environment(&mut req, &working_directory, env, None);
         let res = forkserver
@@ -289,7 +290,10 @@ async fn spawn_worker(
             }
             futures::future::Either::Right((command_result, _)) => Err(match command_result {
                 Ok(GatherOutputStatus::SpawnFailed(e)) => WorkerInitError::SpawnFailed(e),
-                Ok(GatherOutputStatus::Finished { exit_code, .. }) => {
+                Ok(
+                    GatherOutputStatus::Finished { exit_code, .. }
+                    | GatherOutputStatus::SandboxViolation { exit_code, .. },
+                ) => {
                     let stdout = fs_util::read_to_string(stdout_path)
                         .map_err(|e| WorkerInitError::InternalError(e.into()))?;
                     let stderr = fs_util::read_to_string(stderr_path)
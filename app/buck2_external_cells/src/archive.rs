@@ -0,0 +1,551 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Component;
+use std::path::Path;
+use std::sync::Arc;
+
+use buck2_common::dice::data::HasIoProvider;
+use buck2_common::http::HasHttpClient;
+use buck2_core::cells::external::ArchiveCellSetup;
+use buck2_core::cells::external::ExternalCellOrigin;
+use buck2_core::cells::name::CellName;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_error::BuckErrorContext;
+use buck2_execute::digest_config::HasDigestConfig;
+use buck2_execute::execute::blocking::HasBlockingExecutor;
+use buck2_execute::execute::blocking::IoRequest;
+use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
+use buck2_execute::materialize::http::http_download;
+use buck2_execute::materialize::http::Checksum;
+use buck2_execute::materialize::materializer::HasMaterializer;
+use buck2_execute::materialize::materializer::Materializer;
+use dice::CancellationContext;
+use dice::DiceComputations;
+use dice::Key;
+use dupe::Dupe;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::materialized::declare_existing_directory;
+use crate::materialized::populate_once;
+use crate::materialized::MaterializedFileOpsDelegate;
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Input)]
+enum ArchiveError {
+    #[error(
+        "Archive `{url}` has sha256 `{obtained}`, but external cell config expects `{expected}`"
+    )]
+    InvalidChecksum {
+        url: Arc<str>,
+        expected: Arc<str>,
+        obtained: String,
+    },
+    #[error("Archive `{0}` contains no files under `strip_prefix` `{1}`")]
+    EmptyAfterStripPrefix(Arc<str>, Arc<str>),
+    #[error("Symlink `{0}` points to `{1}`, which is outside of the cell")]
+    SymlinkOutsideCell(ForwardRelativePathBuf, String),
+    #[error("Entry `{0}` would be written through the symlink `{1}`")]
+    WriteThroughSymlink(ForwardRelativePathBuf, ForwardRelativePathBuf),
+}
+
+/// Where the archive for a cell can be read from once it is on local disk.
+enum ArchiveLocation {
+    /// Downloaded into buck-out.
+    Downloaded(ProjectRelativePathBuf),
+    /// A `file://` url, read in place.
+    Local(AbsPathBuf),
+}
+
+impl ArchiveLocation {
+    fn resolve(&self, project_fs: &ProjectRoot) -> AbsPathBuf {
+        match self {
+            ArchiveLocation::Downloaded(path) => project_fs.resolve(path).into_abs_path_buf(),
+            ArchiveLocation::Local(path) => path.clone(),
+        }
+    }
+}
+
+struct ArchiveExtractIoRequest {
+    setup: ArchiveCellSetup,
+    archive: ArchiveLocation,
+    path: ProjectRelativePathBuf,
+}
+
+impl ArchiveExtractIoRequest {
+    /// Downloads over http are checksummed while streaming. Local archives are not, so check them
+    /// here.
+    fn verify_checksum(&self, archive: &AbsPathBuf) -> buck2_error::Result<()> {
+        if let ArchiveLocation::Downloaded(_) = self.archive {
+            return Ok(());
+        }
+
+        let mut file = fs_util::open_file(archive)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .with_buck_error_context(|| format!("Error hashing archive `{}`", archive))?;
+        let obtained = hex::encode(hasher.finalize());
+        if obtained != *self.setup.sha256 {
+            return Err(ArchiveError::InvalidChecksum {
+                url: self.setup.url.dupe(),
+                expected: self.setup.sha256.dupe(),
+                obtained,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Wrap the archive in the decompressor matching its magic bytes, if any.
+fn decompress(archive: &AbsPathBuf) -> buck2_error::Result<Box<dyn Read>> {
+    const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+    const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+    let mut reader = BufReader::new(fs_util::open_file(archive)?);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Symlinks are followed when the cell is read, so they must point inside of it.
+fn check_symlink_target(rel_path: &ForwardRelativePath, target: &Path) -> buck2_error::Result<()> {
+    // How deep below the cell root the target currently is, starting from the symlink's directory.
+    let mut depth = rel_path.iter().count() - 1;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => {
+                return Err(ArchiveError::SymlinkOutsideCell(
+                    rel_path.to_buf(),
+                    target.display().to_string(),
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writing below a symlink unpacked earlier would write wherever that symlink points, so refuse
+/// to.
+fn check_no_symlink_ancestors(
+    dest: &AbsNormPath,
+    rel_path: &ForwardRelativePath,
+) -> buck2_error::Result<()> {
+    let mut ancestor = rel_path.parent();
+    while let Some(dir) = ancestor {
+        if dir.is_empty() {
+            break;
+        }
+        if let Some(metadata) = fs_util::symlink_metadata_if_exists(dest.join(dir))? {
+            if metadata.file_type().is_symlink() {
+                return Err(
+                    ArchiveError::WriteThroughSymlink(rel_path.to_buf(), dir.to_buf()).into(),
+                );
+            }
+        }
+        ancestor = dir.parent();
+    }
+    Ok(())
+}
+
+/// Unpack a tar archive into `dest`, dropping `strip_prefix` from every entry and skipping entries
+/// outside of it. Returns whether anything was unpacked.
+fn unpack(
+    archive: &AbsPathBuf,
+    strip_prefix: Option<&str>,
+    dest: &AbsNormPath,
+) -> buck2_error::Result<bool> {
+    // Map a path in the archive to a path in the cell, or `None` if the entry should be skipped.
+    let relativize = |path: &Path| -> buck2_error::Result<Option<ForwardRelativePathBuf>> {
+        let path = path.strip_prefix(".").unwrap_or(path);
+        let path = match strip_prefix {
+            Some(prefix) => match path.strip_prefix(prefix) {
+                Ok(path) => path,
+                Err(_) => return Ok(None),
+            },
+            None => path,
+        };
+        if path.as_os_str().is_empty() {
+            return Ok(None);
+        }
+        // This rejects `..` and absolute paths, so entries can't escape the cell.
+        Ok(Some(
+            ForwardRelativePath::new_trim_trailing_slashes(path)?.to_buf(),
+        ))
+    };
+
+    let mut tar = tar::Archive::new(decompress(archive)?);
+    let mut found_any = false;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let Some(rel_path) = relativize(&entry.path()?)? else {
+            continue;
+        };
+        let target = dest.join(&rel_path);
+        check_no_symlink_ancestors(dest, &rel_path)?;
+        // Replace, rather than write through, a symlink from an earlier entry at the same path.
+        if let Some(metadata) = fs_util::symlink_metadata_if_exists(&target)? {
+            if metadata.file_type().is_symlink() {
+                fs_util::remove_file(&target)?;
+            }
+        }
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => fs_util::create_dir_all(&target)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::Symlink => {
+                if entry.header().entry_type() == tar::EntryType::Symlink {
+                    let Some(link_name) = entry.link_name()? else {
+                        continue;
+                    };
+                    check_symlink_target(&rel_path, &link_name)?;
+                }
+                if let Some(parent) = target.parent() {
+                    fs_util::create_dir_all(parent)?;
+                }
+                entry.unpack(&target)?;
+            }
+            tar::EntryType::Link => {
+                // Hard links name another entry in the archive, which `tar` would otherwise
+                // resolve relative to the daemon's working directory. Copy that entry instead.
+                let Some(link_name) = entry.link_name()? else {
+                    continue;
+                };
+                let Some(source) = relativize(&link_name)? else {
+                    continue;
+                };
+                check_no_symlink_ancestors(dest, &source)?;
+                if let Some(parent) = target.parent() {
+                    fs_util::create_dir_all(parent)?;
+                }
+                fs_util::copy(dest.join(&source), &target)?;
+            }
+            // Pax headers and the like don't correspond to anything on disk.
+            _ => continue,
+        }
+        found_any = true;
+    }
+
+    Ok(found_any)
+}
+
+impl IoRequest for ArchiveExtractIoRequest {
+    fn execute(self: Box<Self>, project_fs: &ProjectRoot) -> buck2_error::Result<()> {
+        let archive = self.archive.resolve(project_fs);
+        self.verify_checksum(&archive)?;
+
+        let dest = project_fs.resolve(&self.path);
+        fs_util::create_dir_all(&dest)?;
+
+        let found_any = unpack(&archive, self.setup.strip_prefix.as_deref(), &dest)
+            .with_buck_error_context(|| format!("Error unpacking archive `{}`", self.setup.url))?;
+        if !found_any {
+            if let Some(strip_prefix) = &self.setup.strip_prefix {
+                return Err(ArchiveError::EmptyAfterStripPrefix(
+                    self.setup.url.dupe(),
+                    strip_prefix.dupe(),
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn download_impl(
+    ctx: &mut DiceComputations<'_>,
+    setup: &ArchiveCellSetup,
+    path: &ProjectRelativePath,
+    materializer: &dyn Materializer,
+    cancellations: &CancellationContext,
+) -> buck2_error::Result<()> {
+    let io = ctx.get_blocking_executor();
+    let download_path = ProjectRelativePathBuf::unchecked_new(format!("{}.download", path));
+    io.execute_io(
+        Box::new(CleanOutputPaths {
+            paths: vec![path.to_owned(), download_path.clone()],
+        }),
+        cancellations,
+    )
+    .await?;
+
+    let archive = match setup.url.strip_prefix("file://") {
+        Some(local) => ArchiveLocation::Local(AbsPathBuf::new(local)?),
+        None => {
+            let checksum = Checksum::new(None, Some(&setup.sha256))?;
+            http_download(
+                &ctx.per_transaction_data().get_http_client(),
+                ctx.global_data().get_io_provider().project_root(),
+                ctx.global_data().get_digest_config(),
+                &download_path,
+                &setup.url,
+                &checksum,
+                false,
+            )
+            .await?;
+            ArchiveLocation::Downloaded(download_path.clone())
+        }
+    };
+
+    io.execute_io(
+        Box::new(ArchiveExtractIoRequest {
+            setup: setup.dupe(),
+            archive,
+            path: path.to_owned(),
+        }),
+        cancellations,
+    )
+    .await?;
+
+    // The archive itself is no longer needed once it's been unpacked.
+    io.execute_io(
+        Box::new(CleanOutputPaths {
+            paths: vec![download_path],
+        }),
+        cancellations,
+    )
+    .await?;
+
+    declare_existing_directory(ctx, path, materializer).await
+}
+
+async fn download_and_materialize(
+    ctx: &mut DiceComputations<'_>,
+    path: &ProjectRelativePath,
+    setup: &ArchiveCellSetup,
+    cancellations: &CancellationContext,
+) -> buck2_error::Result<()> {
+    let materializer = ctx.per_transaction_data().get_materializer();
+
+    if materializer.has_artifact_at(path.to_owned()).await? {
+        return Ok(());
+    }
+
+    // As with git cells, don't allow the download to be cancelled, since another key may be
+    // waiting on it.
+    populate_once(
+        &Arc::from(setup.contents_key()),
+        cancellations
+            .critical_section(|| download_impl(ctx, setup, path, &*materializer, cancellations)),
+    )
+    .await
+}
+
+pub(crate) async fn get_file_ops_delegate(
+    ctx: &mut DiceComputations<'_>,
+    cell: CellName,
+    setup: ArchiveCellSetup,
+) -> buck2_error::Result<Arc<MaterializedFileOpsDelegate>> {
+    #[derive(
+        dupe::Dupe,
+        Clone,
+        Debug,
+        derive_more::Display,
+        PartialEq,
+        Eq,
+        Hash,
+        allocative::Allocative
+    )]
+    #[display("({}, {})", _0, _1)]
+    struct ArchiveFileOpsDelegateKey(CellName, ArchiveCellSetup);
+
+    #[async_trait::async_trait]
+    impl Key for ArchiveFileOpsDelegateKey {
+        type Value = buck2_error::Result<Arc<MaterializedFileOpsDelegate>>;
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            cancellations: &CancellationContext,
+        ) -> Self::Value {
+            let ops = MaterializedFileOpsDelegate::new(
+                ctx,
+                self.0,
+                ExternalCellOrigin::Archive(self.1.dupe()),
+            )
+            .await?;
+            download_and_materialize(ctx, &ops.get_base_path(), &self.1, cancellations).await?;
+            Ok(Arc::new(ops))
+        }
+
+        fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+            false
+        }
+    }
+
+    ctx.compute(&ArchiveFileOpsDelegateKey(cell, setup)).await?
+}
+
+pub(crate) async fn materialize_all(
+    ctx: &mut DiceComputations<'_>,
+    cell: CellName,
+    setup: ArchiveCellSetup,
+) -> buck2_error::Result<ProjectRelativePathBuf> {
+    let ops = get_file_ops_delegate(ctx, cell, setup).await?;
+    Ok(ops.get_base_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+
+    use super::*;
+
+    fn write_tar_gz(path: &AbsPathBuf, entries: &[(&str, &str)]) {
+        let file = fs_util::create_file(path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap()
+            .flush()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unpack_strip_prefix() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = AbsNormPathBuf::new(tempdir.path().to_owned()).unwrap();
+        let archive = root.join(ForwardRelativePath::new("cell.tar.gz").unwrap());
+        let archive = archive.into_abs_path_buf();
+        write_tar_gz(
+            &archive,
+            &[
+                ("libfoo-1.0/BUCK", "# buck"),
+                ("libfoo-1.0/src/foo.c", "int foo;"),
+                ("README", "outside prefix"),
+            ],
+        );
+
+        let dest = root.join(ForwardRelativePath::new("out").unwrap());
+        assert!(unpack(&archive, Some("libfoo-1.0"), &dest).unwrap());
+
+        assert_eq!(
+            fs_util::read_to_string(dest.join(ForwardRelativePath::new("src/foo.c").unwrap()))
+                .unwrap(),
+            "int foo;"
+        );
+        assert!(
+            !fs_util::try_exists(dest.join(ForwardRelativePath::new("README").unwrap())).unwrap()
+        );
+    }
+
+    fn write_tar_gz_with_symlink(path: &AbsPathBuf, link: &str, target: &str, file: &str) {
+        let file_handle = fs_util::create_file(path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file_handle, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder.append_link(&mut header, link, target).unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, file, &b"evil"[..])
+            .unwrap();
+
+        builder
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap()
+            .flush()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_check_symlink_target() {
+        let link = ForwardRelativePath::new("a/b/link").unwrap();
+        assert!(check_symlink_target(link, Path::new("c")).is_ok());
+        assert!(check_symlink_target(link, Path::new("../../c")).is_ok());
+        assert!(check_symlink_target(link, Path::new("../../../c")).is_err());
+        assert!(check_symlink_target(link, Path::new("/etc")).is_err());
+    }
+
+    #[test]
+    fn test_unpack_rejects_symlink_outside_cell() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = AbsNormPathBuf::new(tempdir.path().to_owned()).unwrap();
+        let archive = root
+            .join(ForwardRelativePath::new("cell.tar.gz").unwrap())
+            .into_abs_path_buf();
+        let outside = root.join(ForwardRelativePath::new("outside").unwrap());
+        fs_util::create_dir_all(&outside).unwrap();
+        write_tar_gz_with_symlink(&archive, "a", outside.as_path().to_str().unwrap(), "a/x");
+
+        let dest = root.join(ForwardRelativePath::new("out").unwrap());
+        assert!(unpack(&archive, None, &dest).is_err());
+        assert!(
+            !fs_util::try_exists(outside.join(ForwardRelativePath::new("x").unwrap())).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unpack_does_not_write_through_symlinks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = AbsNormPathBuf::new(tempdir.path().to_owned()).unwrap();
+        let archive = root
+            .join(ForwardRelativePath::new("cell.tar.gz").unwrap())
+            .into_abs_path_buf();
+        write_tar_gz_with_symlink(&archive, "a", "b", "a/x");
+
+        let dest = root.join(ForwardRelativePath::new("out").unwrap());
+        assert!(unpack(&archive, None, &dest).is_err());
+    }
+
+    #[test]
+    fn test_unpack_nothing_under_prefix() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = AbsNormPathBuf::new(tempdir.path().to_owned()).unwrap();
+        let archive = root
+            .join(ForwardRelativePath::new("cell.tar.gz").unwrap())
+            .into_abs_path_buf();
+        write_tar_gz(&archive, &[("other/BUCK", "")]);
+
+        let dest = root.join(ForwardRelativePath::new("out").unwrap());
+        assert!(!unpack(&archive, Some("libfoo-1.0"), &dest).unwrap());
+    }
+}
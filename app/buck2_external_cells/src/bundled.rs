@@ -55,6 +55,8 @@ use cmp_any::PartialEqAny;
 use dice::CancellationContext;
 use dice::DiceComputations;
 use dice::Key;
+use sha2::Digest;
+use sha2::Sha256;
 
 fn load_nano_prelude() -> buck2_error::Result<BundledCell> {
     let path = env::var("NANO_PRELUDE")
@@ -144,6 +146,20 @@ pub(crate) fn find_bundled_data(cell_name: CellName) -> buck2_error::Result<Bund
         })
 }
 
+/// Identifies the contents of a bundled cell, which change along with buck2 rather than with the
+/// cell's config.
+pub(crate) fn contents_digest(cell_name: CellName) -> buck2_error::Result<String> {
+    let data = find_bundled_data(cell_name)?;
+    let mut hasher = Sha256::new();
+    for file in data.files {
+        hasher.update(file.path.as_bytes());
+        hasher.update([u8::from(file.is_executable)]);
+        hasher.update((file.contents.len() as u64).to_le_bytes());
+        hasher.update(file.contents);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, allocative::Allocative)]
 struct ContentsAndMetadata {
     contents: &'static [u8],
@@ -23,8 +23,11 @@ use buck2_core::cells::name::CellName;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use dice::DiceComputations;
 
+mod archive;
 mod bundled;
 mod git;
+mod materialized;
+mod overlay;
 
 struct ConcreteExternalCellsImpl;
 
@@ -50,6 +53,12 @@ impl buck2_common::external_cells::ExternalCellsImpl for ConcreteExternalCellsIm
             ExternalCellOrigin::Git(setup) => {
                 Ok(git::get_file_ops_delegate(ctx, cell_name, setup).await? as _)
             }
+            ExternalCellOrigin::Archive(setup) => {
+                Ok(archive::get_file_ops_delegate(ctx, cell_name, setup).await? as _)
+            }
+            ExternalCellOrigin::Overlay(setup) => {
+                Ok(overlay::get_file_ops_delegate(ctx, cell_name, setup).await? as _)
+            }
         }
     }
 
@@ -90,6 +99,12 @@ impl buck2_common::external_cells::ExternalCellsImpl for ConcreteExternalCellsIm
         let materialized_path = match origin {
             ExternalCellOrigin::Bundled(cell) => bundled::materialize_all(ctx, cell).await?,
             ExternalCellOrigin::Git(setup) => git::materialize_all(ctx, cell, setup).await?,
+            ExternalCellOrigin::Archive(setup) => {
+                archive::materialize_all(ctx, cell, setup).await?
+            }
+            ExternalCellOrigin::Overlay(setup) => {
+                overlay::materialize_all(ctx, cell, setup).await?
+            }
         };
 
         Ok(io.project_root().copy(&materialized_path, &dest_path)?)
@@ -0,0 +1,248 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Shared support for external cells whose contents are fetched into buck-out and then read from
+//! disk, as opposed to bundled cells which are served from memory.
+
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use buck2_build_api::actions::artifact::get_artifact_fs::GetArtifactFs;
+use buck2_common::dice::data::HasIoProvider;
+use buck2_common::dice::file_ops::delegate::FileOpsDelegate;
+use buck2_common::file_ops::FileDigestConfig;
+use buck2_common::file_ops::RawDirEntry;
+use buck2_common::file_ops::RawPathMetadata;
+use buck2_common::io::fs::FsIoProvider;
+use buck2_common::io::IoProvider;
+use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::external::ExternalCellOrigin;
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::paths::CellRelativePath;
+use buck2_core::fs::buck_out_path::BuckOutPathResolver;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_directory::directory::directory::Directory;
+use buck2_error::internal_error;
+use buck2_error::BuckErrorContext;
+use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::digest_config::HasDigestConfig;
+use buck2_execute::directory::INTERNER;
+use buck2_execute::entry::build_entry_from_disk;
+use buck2_execute::execute::blocking::HasBlockingExecutor;
+use buck2_execute::materialize::materializer::Materializer;
+use cmp_any::PartialEqAny;
+use dice::DiceComputations;
+use dupe::Dupe;
+use tokio::sync::Semaphore;
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Tier0)]
+enum MaterializedCellError {
+    #[error("Expected a directory at the external cell location `{0}`")]
+    NoDirectory(ProjectRelativePathBuf),
+}
+
+/// Hash the contents of a freshly populated external cell directory and tell the materializer
+/// about it.
+///
+/// We have to do this because the materializer requires an artifact value. This work is kind of
+/// duplicated with the reading in the fileops, but only the first time the contents are
+/// populated. On subsequent invocations of the daemon, we won't rerun this however, so that case
+/// will still avoid doing unnecessary work.
+pub(crate) async fn declare_existing_directory(
+    ctx: &mut DiceComputations<'_>,
+    path: &ProjectRelativePath,
+    materializer: &dyn Materializer,
+) -> buck2_error::Result<()> {
+    let io = ctx.get_blocking_executor();
+    let io_prov = ctx.global_data().get_io_provider();
+    let proj_root = io_prov.project_root().root();
+    let abs_path = proj_root.join(path);
+    let digest_config = ctx.global_data().get_digest_config();
+    let file_digest_config = FileDigestConfig::build(digest_config.cas_digest_config());
+    let entry = build_entry_from_disk(abs_path, file_digest_config, &*io, proj_root)
+        .await?
+        .0
+        .ok_or_else(|| MaterializedCellError::NoDirectory(path.to_owned()))?;
+    let entry = entry.map_dir(|d| {
+        d.to_builder()
+            .fingerprint(digest_config.as_directory_serializer())
+            .shared(&*INTERNER)
+    });
+
+    materializer
+        .declare_existing(vec![(path.to_owned(), ArtifactValue::new(entry, None))])
+        .await?;
+
+    Ok(())
+}
+
+/// Run `populate` unless another key is already populating the directory identified by `key`, in
+/// which case wait for that to finish instead.
+///
+/// `key` must uniquely identify the contents of the directory, e.g. a commit hash or a content
+/// digest.
+pub(crate) async fn populate_once(
+    key: &Arc<str>,
+    populate: impl Future<Output = buck2_error::Result<()>>,
+) -> buck2_error::Result<()> {
+    // A map of keys to semaphores that are actually condvars which protect access to the
+    // directory associated with that key
+    static DIRECTORY_LICENSES: OnceLock<Mutex<HashMap<Arc<str>, Arc<Semaphore>>>> = OnceLock::new();
+
+    // We have to write this in a slightly funny way to convince the compiler that there's no
+    // `map_guard` being held across an await point
+    let semaphore;
+    let semaphore_guard;
+    'populate: {
+        'wait: {
+            let mut map_guard = DIRECTORY_LICENSES
+                .get_or_init(Default::default)
+                .lock()
+                .unwrap();
+            let entry = map_guard.entry(key.dupe());
+
+            match entry {
+                hash_map::Entry::Occupied(entry) => {
+                    // There's another key simultaneously populating this directory. Just wait for
+                    // it to finish and then return. We don't need to check the contents of the
+                    // directory, since we assume that the key uniquely identifies those.
+                    semaphore = entry.get().dupe();
+                    break 'wait;
+                }
+                hash_map::Entry::Vacant(entry) => {
+                    // It's on us to populate this directory. Make a condvar so that we block other accesses
+                    semaphore = Arc::new(Semaphore::new(1));
+                    semaphore_guard = semaphore.try_acquire().unwrap(); // we know there's a permit available
+                    entry.insert(semaphore.dupe());
+                    break 'populate;
+                }
+            }
+        }
+
+        drop(semaphore.acquire().await.unwrap());
+        return Ok(());
+    }
+
+    let res = populate.await;
+
+    // Give up our lock
+    drop(semaphore_guard);
+    DIRECTORY_LICENSES
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .remove(key)
+        .unwrap();
+
+    res
+}
+
+/// A `FileOpsDelegate` for an external cell that has been materialized into buck-out.
+#[derive(allocative::Allocative)]
+pub(crate) struct MaterializedFileOpsDelegate {
+    buck_out_resolver: BuckOutPathResolver,
+    cell: CellName,
+    origin: ExternalCellOrigin,
+    // The fs accesses in this code are sort of a mix between source file accesses and buck-out
+    // accesses. Unconditionally using an `FsIoProvider` turns out to give all the right behavior
+    io: FsIoProvider,
+}
+
+impl MaterializedFileOpsDelegate {
+    pub(crate) async fn new(
+        ctx: &mut DiceComputations<'_>,
+        cell: CellName,
+        origin: ExternalCellOrigin,
+    ) -> buck2_error::Result<Self> {
+        let artifact_fs = ctx.get_artifact_fs().await?;
+        Ok(Self {
+            buck_out_resolver: artifact_fs.buck_out_path_resolver().clone(),
+            cell,
+            origin,
+            io: FsIoProvider::new(
+                artifact_fs.fs().dupe(),
+                ctx.global_data().get_digest_config().cas_digest_config(),
+            ),
+        })
+    }
+
+    fn resolve(&self, path: &CellRelativePath) -> ProjectRelativePathBuf {
+        self.buck_out_resolver
+            .resolve_external_cell_source(path, self.origin.dupe())
+    }
+
+    pub(crate) fn get_base_path(&self) -> ProjectRelativePathBuf {
+        self.resolve(CellRelativePath::empty())
+    }
+}
+
+#[async_trait::async_trait]
+impl FileOpsDelegate for MaterializedFileOpsDelegate {
+    async fn read_file_if_exists(
+        &self,
+        path: &'async_trait CellRelativePath,
+    ) -> buck2_error::Result<Option<String>> {
+        let project_path = self.resolve(path);
+        (&self.io as &dyn IoProvider)
+            .read_file_if_exists(project_path)
+            .await
+    }
+
+    async fn read_dir(
+        &self,
+        path: &'async_trait CellRelativePath,
+    ) -> buck2_error::Result<Vec<RawDirEntry>> {
+        let project_path = self.resolve(path);
+        let mut entries = (&self.io as &dyn IoProvider)
+            .read_dir(project_path)
+            .await
+            .with_buck_error_context(|| format!("Error listing dir `{}`", path))?;
+
+        // Make sure entries are deterministic, since read_dir isn't.
+        entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        Ok(entries)
+    }
+
+    async fn read_path_metadata_if_exists(
+        &self,
+        path: &'async_trait CellRelativePath,
+    ) -> buck2_error::Result<Option<RawPathMetadata>> {
+        let project_path = self.resolve(path);
+
+        let Some(metadata) = (&self.io as &dyn IoProvider)
+            .read_path_metadata_if_exists(project_path)
+            .await
+            .with_buck_error_context(|| format!("Error accessing metadata for path `{}`", path))?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(metadata.try_map(
+            |path| match path.strip_prefix_opt(&self.get_base_path()) {
+                Some(path) => Ok(Arc::new(CellPath::new(self.cell, path.to_owned().into()))),
+                None => Err(internal_error!(
+                    "Non-cell internal symlink at `{}` in cell `{}`",
+                    path,
+                    self.cell
+                )),
+            },
+        )?))
+    }
+
+    fn eq_token(&self) -> PartialEqAny {
+        PartialEqAny::always_false()
+    }
+}
@@ -0,0 +1,326 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::io::Write;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use buck2_common::dice::cells::HasCellResolver;
+use buck2_common::dice::data::HasIoProvider;
+use buck2_common::dice::file_ops::DiceFileComputations;
+use buck2_core::cells::external::ExternalCellOrigin;
+use buck2_core::cells::external::OverlayCellSetup;
+use buck2_core::cells::name::CellName;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_error::internal_error;
+use buck2_error::BuckErrorContext;
+use buck2_execute::execute::blocking::HasBlockingExecutor;
+use buck2_execute::execute::blocking::IoRequest;
+use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
+use buck2_execute::materialize::materializer::HasMaterializer;
+use buck2_util::process::background_command;
+use dice::CancellationContext;
+use dice::DiceComputations;
+use dice::Key;
+use dupe::Dupe;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::archive;
+use crate::bundled;
+use crate::git;
+use crate::materialized::declare_existing_directory;
+use crate::materialized::populate_once;
+use crate::materialized::MaterializedFileOpsDelegate;
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(tag = Input)]
+enum OverlayError {
+    #[error(
+        "Error applying patch `{patch}` to external cell, exit code: {exit_code:?}, stderr:\n{stderr}"
+    )]
+    PatchFailed {
+        patch: ProjectRelativePathBuf,
+        exit_code: ExitStatus,
+        stderr: String,
+    },
+}
+
+/// A patch file along with its contents, as read from the main repo.
+#[derive(Clone)]
+struct Patch {
+    path: ProjectRelativePathBuf,
+    contents: Arc<str>,
+}
+
+/// Identifies the contents of the overlay: those of the base, and of every patch applied on top
+/// of it.
+///
+/// Patches are digested line by line, so CRLF line endings and a missing newline at the end of
+/// the file don't make for different contents.
+fn contents_key(base: &ExternalCellOrigin, patches: &[Patch]) -> buck2_error::Result<String> {
+    let mut hasher = Sha256::new();
+    match base {
+        ExternalCellOrigin::Bundled(cell) => hasher.update(bundled::contents_digest(*cell)?),
+        ExternalCellOrigin::Git(setup) => hasher.update(setup.commit.as_bytes()),
+        ExternalCellOrigin::Archive(setup) => hasher.update(setup.contents_key()),
+        ExternalCellOrigin::Overlay(_) => {
+            return Err(internal_error!("Nested external cell overlay"));
+        }
+    }
+    for patch in patches {
+        hasher.update(b"\0");
+        for line in patch.contents.lines() {
+            hasher.update(line.as_bytes());
+            hasher.update(b"\n");
+        }
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The directory the overlay is assembled in before being moved into place, so that an
+/// interrupted or failed population never leaves a partially patched cell behind.
+fn staging_path(path: &ProjectRelativePath) -> ProjectRelativePathBuf {
+    ProjectRelativePathBuf::unchecked_new(format!("{}.tmp", path))
+}
+
+/// Records the `contents_key` of the overlay, which is written once it is in place. The
+/// directory is named after the base and the patch paths only, so this tells whether it is up
+/// to date.
+fn stamp_path(path: &ProjectRelativePath) -> ProjectRelativePathBuf {
+    ProjectRelativePathBuf::unchecked_new(format!("{}.contents", path))
+}
+
+struct OverlayIoRequest {
+    base: ProjectRelativePathBuf,
+    path: ProjectRelativePathBuf,
+    patches: Vec<Patch>,
+    contents_key: String,
+}
+
+impl OverlayIoRequest {
+    fn apply_patch(
+        &self,
+        project_fs: &ProjectRoot,
+        staging: &ProjectRelativePath,
+        patch: &Patch,
+    ) -> buck2_error::Result<()> {
+        let cwd = project_fs.resolve(staging);
+        let ceiling = cwd
+            .parent()
+            .ok_or_else(|| internal_error!("External cell at `{}` has no parent", cwd))?;
+
+        // `git apply` works outside of a repository like `patch` does, but since buck-out usually
+        // lives inside one we need to stop it from looking any further up.
+        let mut child = background_command("git")
+            .arg("apply")
+            .arg("-p1")
+            .arg("--whitespace=nowarn")
+            .arg("-")
+            .current_dir(&cwd)
+            .env("GIT_CEILING_DIRECTORIES", ceiling.as_os_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .buck_error_context("Could not run git to apply external cell patch")?;
+
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| internal_error!("Missing stdin for git apply"))?
+            .write_all(patch.contents.as_bytes())?;
+        let output = child.wait_with_output()?;
+
+        if !output.status.success() {
+            return Err(OverlayError::PatchFailed {
+                patch: patch.path.clone(),
+                exit_code: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl IoRequest for OverlayIoRequest {
+    fn execute(self: Box<Self>, project_fs: &ProjectRoot) -> buck2_error::Result<()> {
+        let staging = staging_path(&self.path);
+        project_fs.copy(&self.base, &staging)?;
+
+        for patch in &self.patches {
+            self.apply_patch(project_fs, &staging, patch)?;
+        }
+
+        fs_util::rename(project_fs.resolve(&staging), project_fs.resolve(&self.path))?;
+        fs_util::write(
+            project_fs.resolve(&stamp_path(&self.path)),
+            self.contents_key.as_bytes(),
+        )?;
+
+        Ok(())
+    }
+}
+
+async fn materialize_base(
+    ctx: &mut DiceComputations<'_>,
+    cell: CellName,
+    base: &ExternalCellOrigin,
+) -> buck2_error::Result<ProjectRelativePathBuf> {
+    match base {
+        ExternalCellOrigin::Bundled(cell) => bundled::materialize_all(ctx, *cell).await,
+        ExternalCellOrigin::Git(setup) => git::materialize_all(ctx, cell, setup.dupe()).await,
+        ExternalCellOrigin::Archive(setup) => {
+            archive::materialize_all(ctx, cell, setup.dupe()).await
+        }
+        ExternalCellOrigin::Overlay(_) => Err(internal_error!("Nested external cell overlay")),
+    }
+}
+
+async fn overlay_impl(
+    ctx: &mut DiceComputations<'_>,
+    base: &ProjectRelativePath,
+    path: &ProjectRelativePath,
+    patches: &[Patch],
+    contents_key: &str,
+    cancellations: &CancellationContext,
+) -> buck2_error::Result<()> {
+    let materializer = ctx.per_transaction_data().get_materializer();
+    let io = ctx.get_blocking_executor();
+
+    // Clean up after any previous attempt that didn't finish.
+    io.execute_io(
+        Box::new(CleanOutputPaths {
+            paths: vec![path.to_owned(), staging_path(path), stamp_path(path)],
+        }),
+        cancellations,
+    )
+    .await?;
+
+    io.execute_io(
+        Box::new(OverlayIoRequest {
+            base: base.to_owned(),
+            path: path.to_owned(),
+            patches: patches.to_vec(),
+            contents_key: contents_key.to_owned(),
+        }),
+        cancellations,
+    )
+    .await?;
+
+    declare_existing_directory(ctx, path, &*materializer).await
+}
+
+async fn read_patches(
+    ctx: &mut DiceComputations<'_>,
+    setup: &OverlayCellSetup,
+) -> buck2_error::Result<Vec<Patch>> {
+    let cell_resolver = ctx.get_cell_resolver().await?;
+    let mut patches = Vec::with_capacity(setup.patches.len());
+    for path in setup.patches.iter() {
+        let cell_path = cell_resolver.get_cell_path(path)?;
+        // Reading the patch through DICE means that editing it invalidates the cell.
+        let contents = DiceFileComputations::read_file(ctx, cell_path.as_ref())
+            .await
+            .with_buck_error_context(|| format!("Error reading external cell patch `{}`", path))?;
+        patches.push(Patch {
+            path: path.clone(),
+            contents: contents.into(),
+        });
+    }
+    Ok(patches)
+}
+
+pub(crate) async fn get_file_ops_delegate(
+    ctx: &mut DiceComputations<'_>,
+    cell: CellName,
+    setup: OverlayCellSetup,
+) -> buck2_error::Result<Arc<MaterializedFileOpsDelegate>> {
+    #[derive(
+        dupe::Dupe,
+        Clone,
+        Debug,
+        derive_more::Display,
+        PartialEq,
+        Eq,
+        Hash,
+        allocative::Allocative
+    )]
+    #[display("({}, {})", _0, _1)]
+    struct OverlayFileOpsDelegateKey(CellName, OverlayCellSetup);
+
+    #[async_trait::async_trait]
+    impl Key for OverlayFileOpsDelegateKey {
+        type Value = buck2_error::Result<Arc<MaterializedFileOpsDelegate>>;
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            cancellations: &CancellationContext,
+        ) -> Self::Value {
+            let ops = MaterializedFileOpsDelegate::new(
+                ctx,
+                self.0,
+                ExternalCellOrigin::Overlay(self.1.dupe()),
+            )
+            .await?;
+            let path = ops.get_base_path();
+
+            let patches = read_patches(ctx, &self.1).await?;
+            let contents_key = contents_key(&self.1.base, &patches)?;
+            let stamp = fs_util::read_to_string_if_exists(
+                ctx.global_data()
+                    .get_io_provider()
+                    .project_root()
+                    .resolve(&stamp_path(&path)),
+            )?;
+
+            let materializer = ctx.per_transaction_data().get_materializer();
+            if stamp.as_deref() != Some(contents_key.as_str())
+                || !materializer.has_artifact_at(path.clone()).await?
+            {
+                let base = materialize_base(ctx, self.0, &self.1.base).await?;
+                materializer.ensure_materialized(vec![base.clone()]).await?;
+
+                // As with git cells, don't allow this to be cancelled, since another key may be
+                // waiting on it.
+                populate_once(
+                    &Arc::from(self.1.directory_key()),
+                    cancellations.critical_section(|| {
+                        overlay_impl(ctx, &base, &path, &patches, &contents_key, cancellations)
+                    }),
+                )
+                .await?;
+            }
+
+            Ok(Arc::new(ops))
+        }
+
+        fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+            false
+        }
+    }
+
+    ctx.compute(&OverlayFileOpsDelegateKey(cell, setup)).await?
+}
+
+pub(crate) async fn materialize_all(
+    ctx: &mut DiceComputations<'_>,
+    cell: CellName,
+    setup: OverlayCellSetup,
+) -> buck2_error::Result<ProjectRelativePathBuf> {
+    let ops = get_file_ops_delegate(ctx, cell, setup).await?;
+    Ok(ops.get_base_path())
+}
@@ -17,6 +17,8 @@ use buck2_common::legacy_configs::configs::LegacyBuckConfig;
 use buck2_common::legacy_configs::key::BuckconfigKeyRef;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellResolver;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::is_open_source;
 use buck2_error::buck2_error;
@@ -36,14 +38,23 @@ pub trait FileWatcher: Allocative + Send + Sync + 'static {
         &self,
         dice: DiceTransactionUpdater,
     ) -> buck2_error::Result<(DiceTransactionUpdater, Mergebase)>;
+
+    /// Notifications of changes that the next `sync` will apply, as soon as the watcher sees them.
+    /// `None` if the watcher only finds out about changes when it syncs.
+    fn subscribe_to_changes(&self) -> Option<tokio::sync::watch::Receiver<()>> {
+        None
+    }
 }
 
 impl dyn FileWatcher {
     /// Create a new FileWatcher. Note that this is not async, since it's called during daemon
     /// startup and shouldn't be doing any work that could warrant suspending.
+    ///
+    /// `state_dir` is where watchers can persist state across daemon restarts.
     pub fn new(
         fb: fbinit::FacebookInit,
         project_root: &ProjectRoot,
+        state_dir: &AbsNormPath,
         root_config: &LegacyBuckConfig,
         cells: CellResolver,
         ignore_specs: HashMap<CellName, IgnoreSet>,
@@ -68,12 +79,34 @@ impl dyn FileWatcher {
                 WatchmanFileWatcher::new(project_root.root(), root_config, cells, ignore_specs)
                     .buck_error_context("Creating watchman file watcher")?,
             )),
-            "notify" => Ok(Arc::new(
-                NotifyFileWatcher::new(project_root, cells, ignore_specs)
+            "notify" => {
+                let snapshot = root_config
+                    .parse::<bool>(BuckconfigKeyRef {
+                        section: "buck2",
+                        property: "file_watcher_snapshot",
+                    })?
+                    .unwrap_or(false);
+                let snapshot_path =
+                    snapshot.then(|| state_dir.join(ForwardRelativePath::unchecked_new("notify")));
+                let merge_base_with = root_config
+                    .get(BuckconfigKeyRef {
+                        section: "project",
+                        property: "watchman_merge_base",
+                    })
+                    .map(|s| s.to_owned());
+                Ok(Arc::new(
+                    NotifyFileWatcher::new(
+                        project_root,
+                        cells,
+                        ignore_specs,
+                        snapshot_path,
+                        merge_base_with,
+                    )
                     .buck_error_context("Creating notify file watcher")?,
-            )),
+                ))
+            }
             "fs_hash_crawler" => Ok(Arc::new(
-                FsHashCrawler::new(project_root, cells, ignore_specs)
+                FsHashCrawler::new(project_root, cells, ignore_specs, state_dir)
                     .buck_error_context("Creating fs_crawler file watcher")?,
             )),
             #[cfg(fbcode_build)]
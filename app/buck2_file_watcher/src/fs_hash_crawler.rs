@@ -8,49 +8,41 @@
  */
 
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
-use std::mem;
-use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use allocative::Allocative;
 use async_trait::async_trait;
-use blake3::Hash;
-use buck2_common::dice::file_ops::FileChangeTracker;
-use buck2_common::file_ops::FileType;
 use buck2_common::ignores::ignore_set::IgnoreSet;
-use buck2_common::invocation_paths::InvocationPaths;
-use buck2_core::cells::cell_path::CellPath;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellResolver;
-use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
-use buck2_core::fs::paths::file_name::FileNameBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::project::ProjectRoot;
-use buck2_core::fs::project_rel_path::ProjectRelativePath;
-use buck2_data::FileWatcherEventType;
-use buck2_data::FileWatcherKind;
-use buck2_error::BuckErrorContext;
 use buck2_events::dispatch::span_async;
-use compact_str::CompactString;
 use dice::DiceTransactionUpdater;
 use dupe::Dupe;
+use tracing::warn;
 
 use crate::file_watcher::FileWatcher;
+use crate::fs_index::FsIndex;
+use crate::fs_index::FsIndexFile;
+use crate::fs_index::IndexedFs;
 use crate::mergebase::Mergebase;
-use crate::stats::FileWatcherStats;
 
-// On each sync, recomputes hashes of all files in the repository.
-// Useful for tests on unreliable filesystems,
-// but probably not much elsewhere.
+/// Finds changes by polling the filesystem, for when no notification mechanism is reliable (for
+/// example on overlay filesystems in containers).
+///
+/// We keep an index of the mtime, size and content hash of every file in the repository, and
+/// persist it across daemon restarts. On each sync we stat everything, but only rehash files whose
+/// mtime or size changed, and only list directories whose mtime changed. What changed is appended
+/// to the persisted index's journal, rather than writing out the whole index every time.
 #[derive(Allocative)]
 pub struct FsHashCrawler {
-    root: ProjectRoot,
-    cells: CellResolver,
-    ignore_specs: HashMap<CellName, IgnoreSet>,
-    snapshot: Arc<Mutex<FsSnapshot>>,
+    fs: IndexedFs,
+    #[allocative(skip)]
+    file: Arc<Mutex<FsIndexFile>>,
+    index: Mutex<Arc<FsIndex>>,
 }
 
 impl FsHashCrawler {
@@ -58,13 +50,33 @@ impl FsHashCrawler {
         root: &ProjectRoot,
         cells: CellResolver,
         ignore_specs: HashMap<CellName, IgnoreSet>,
+        state_dir: &AbsNormPath,
     ) -> buck2_error::Result<Self> {
-        let snapshot = Arc::new(Mutex::new(FsSnapshot::build(root, &cells)?));
+        let fs = IndexedFs::new(root.dupe(), cells, Arc::new(ignore_specs));
+        let index_path = state_dir.join(ForwardRelativePath::unchecked_new("fs_hash_crawler"));
+        let (mut file, index) =
+            match FsIndexFile::load(index_path.clone(), root, &Mergebase::default()) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    warn!("Discarding unreadable file watcher index: {:#}", e);
+                    (FsIndexFile::new(index_path)?, None)
+                }
+            };
+        let index = match index {
+            // The first sync will pick up anything that changed while there was no daemon.
+            Some(index) => index,
+            None => {
+                let index = FsIndex::default().rescan(&fs)?;
+                if let Err(e) = file.save(&index, root, &Mergebase::default()) {
+                    warn!("Error saving file watcher index: {:#}", e);
+                }
+                index
+            }
+        };
         Ok(Self {
-            root: root.dupe(),
-            cells,
-            ignore_specs,
-            snapshot,
+            fs,
+            file: Arc::new(Mutex::new(file)),
+            index: Mutex::new(Arc::new(index)),
         })
     }
 
@@ -72,14 +84,31 @@ impl FsHashCrawler {
         &self,
         mut dice: DiceTransactionUpdater,
     ) -> buck2_error::Result<(buck2_data::FileWatcherStats, DiceTransactionUpdater)> {
-        let root = self.root.dupe();
-        let cells = self.cells.dupe();
-        let new_snapshot =
-            tokio::task::spawn_blocking(move || FsSnapshot::build(&root, &cells)).await??;
-        let mut guard = self.snapshot.lock().unwrap();
-        let old_snapshot = mem::replace(&mut *guard, new_snapshot);
-        let (stats, changes) = old_snapshot.get_updates_for_dice(&guard, &self.ignore_specs)?;
+        let fs = self.fs.dupe();
+        let file = self.file.dupe();
+        let old_index = self.index.lock().unwrap().dupe();
+        let (old_index, new_index) = tokio::task::spawn_blocking(move || {
+            let new_index = old_index.rescan(&fs)?;
+            let delta = old_index.delta(&new_index);
+            if !delta.is_empty() {
+                // Failing to persist the index only means that the next daemon has to rehash
+                // everything, so it's not worth failing the command over.
+                let mut file = file.lock().unwrap();
+                if let Err(e) = file.append(&new_index, delta, fs.root(), &Mergebase::default()) {
+                    warn!("Error saving file watcher index: {:#}", e);
+                    // Whatever did get written can't be trusted to be complete.
+                    if let Err(e) = file.remove() {
+                        warn!("Error removing file watcher index: {:#}", e);
+                    }
+                }
+            }
+            buck2_error::Ok((old_index, new_index))
+        })
+        .await??;
+        let (stats, changes) =
+            old_index.get_updates_for_dice(&new_index, self.fs.cells(), self.fs.ignore_specs())?;
         changes.write_to_dice(&mut dice)?;
+        *self.index.lock().unwrap() = Arc::new(new_index);
         Ok((stats, dice))
     }
 }
@@ -108,278 +137,3 @@ impl FileWatcher for FsHashCrawler {
         .await
     }
 }
-
-#[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
-struct FsEvent {
-    cell_path: CellPath,
-    event: FileWatcherEventType,
-    kind: FileWatcherKind,
-}
-
-#[derive(Allocative)]
-enum EntryInfo {
-    #[allocative(skip)]
-    File(Hash),
-    Directory,
-    Symlink,
-}
-
-impl EntryInfo {
-    fn to_file_watcher_kind(&self) -> FileWatcherKind {
-        match self {
-            EntryInfo::File(_) => FileWatcherKind::File,
-            EntryInfo::Directory => FileWatcherKind::Directory,
-            EntryInfo::Symlink => FileWatcherKind::Symlink,
-        }
-    }
-}
-
-#[derive(Allocative)]
-struct FsSnapshot(HashMap<CellPath, EntryInfo>);
-
-impl FsSnapshot {
-    fn build(root: &ProjectRoot, cells: &CellResolver) -> buck2_error::Result<Self> {
-        let mut snapshot = FsSnapshot(HashMap::new());
-        snapshot.build_fs_snapshot(root, cells, root.root())?;
-        Ok(snapshot)
-    }
-
-    fn add_entry(&mut self, cell: CellPath, info: EntryInfo) {
-        self.0.insert(cell, info);
-    }
-
-    fn get_updates(&self, new_snapshot: &FsSnapshot) -> buck2_error::Result<Vec<FsEvent>> {
-        let mut events = Vec::new();
-        for (cell_path, prev_info) in self.0.iter() {
-            if let Some(current_info) = new_snapshot.0.get(cell_path) {
-                match (current_info, prev_info) {
-                    (EntryInfo::File(cur), EntryInfo::File(prev)) if cur != prev => {
-                        events.push(FsEvent {
-                            cell_path: cell_path.to_owned(),
-                            event: FileWatcherEventType::Modify,
-                            kind: prev_info.to_file_watcher_kind(),
-                        });
-                    }
-                    _ => (),
-                }
-            } else {
-                events.push(FsEvent {
-                    cell_path: cell_path.to_owned(),
-                    event: FileWatcherEventType::Delete,
-                    kind: prev_info.to_file_watcher_kind(),
-                });
-            }
-        }
-        let new_entries = new_snapshot
-            .0
-            .iter()
-            .filter(|(path, _)| !self.0.contains_key(*path));
-        for (cell_path, info) in new_entries {
-            events.push(FsEvent {
-                cell_path: cell_path.to_owned(),
-                event: FileWatcherEventType::Create,
-                kind: info.to_file_watcher_kind(),
-            });
-        }
-        Ok(events)
-    }
-
-    fn get_updates_for_dice(
-        &self,
-        new_snapshot: &FsSnapshot,
-        ignore_specs: &HashMap<CellName, IgnoreSet>,
-    ) -> buck2_error::Result<(buck2_data::FileWatcherStats, FileChangeTracker)> {
-        let events = self.get_updates(new_snapshot)?;
-        let mut changed = FileChangeTracker::new();
-        let mut stats = FileWatcherStats::new(Default::default(), events.len());
-        let mut ignored = 0;
-        for event in events.into_iter() {
-            let ignore = ignore_specs
-                .get(&event.cell_path.cell())
-                .map_or(false, |i| i.is_match(event.cell_path.path()));
-
-            if ignore {
-                ignored += 1;
-                continue;
-            }
-
-            stats.add(event.cell_path.to_string(), event.event, event.kind);
-            match (event.event, event.kind) {
-                (
-                    FileWatcherEventType::Create,
-                    FileWatcherKind::File | FileWatcherKind::Symlink,
-                ) => {
-                    changed.file_added(event.cell_path);
-                }
-                (FileWatcherEventType::Create, FileWatcherKind::Directory) => {
-                    changed.dir_added(event.cell_path);
-                }
-                (
-                    FileWatcherEventType::Modify,
-                    FileWatcherKind::File | FileWatcherKind::Symlink,
-                ) => {
-                    changed.file_changed(event.cell_path);
-                }
-                (FileWatcherEventType::Modify, FileWatcherKind::Directory) => {
-                    changed.dir_changed(event.cell_path);
-                }
-                (
-                    FileWatcherEventType::Delete,
-                    FileWatcherKind::File | FileWatcherKind::Symlink,
-                ) => {
-                    changed.file_removed(event.cell_path);
-                }
-                (FileWatcherEventType::Delete, FileWatcherKind::Directory) => {
-                    changed.dir_removed(event.cell_path);
-                }
-            }
-        }
-        stats.add_ignored(ignored);
-        Ok((stats.finish(), changed))
-    }
-
-    fn build_fs_snapshot(
-        &mut self,
-        root: &ProjectRoot,
-        cells: &CellResolver,
-        disk_path: &AbsNormPath,
-    ) -> buck2_error::Result<()> {
-        for file in fs_util::read_dir(disk_path)? {
-            let file = file?;
-            let filetype = file.file_type()?;
-            let filename = file.file_name();
-
-            let filename = FileNameBuf::try_from(CompactString::new(
-                filename
-                    .to_str()
-                    .buck_error_context("Filename is not UTF-8")?,
-            ))
-            .with_buck_error_context(|| format!("Invalid filename: {}", disk_path.display()))?;
-
-            let disk_path = disk_path.join(filename);
-            let rel_path = root.relativize(&disk_path)?;
-            let cell_path = cells.get_cell_path(&rel_path)?;
-
-            // We ignore buck-out and .hg dirs, as those are uninteresting events caused by us.
-            if rel_path.starts_with(InvocationPaths::buck_out_dir_prefix())
-                || rel_path.starts_with(ProjectRelativePath::unchecked_new(".hg"))
-            {
-                continue;
-            }
-
-            let filetype = FileType::from(filetype);
-            match filetype {
-                FileType::File => {
-                    let hash = file_hash(disk_path.as_maybe_relativized())?;
-                    self.add_entry(cell_path, EntryInfo::File(hash));
-                }
-                FileType::Directory => {
-                    self.build_fs_snapshot(root, cells, &disk_path)?;
-                    self.add_entry(cell_path, EntryInfo::Directory);
-                }
-                FileType::Symlink => {
-                    self.add_entry(cell_path, EntryInfo::Symlink);
-                }
-                FileType::Unknown => (),
-            }
-        }
-        Ok(())
-    }
-}
-
-fn file_hash(path: &Path) -> buck2_error::Result<Hash> {
-    let mut reader = File::open(path)?;
-    let mut hasher = blake3::Hasher::new();
-
-    let mut buffer = [0; 16 * 1024];
-    loop {
-        let count = reader.read(&mut buffer)?;
-        if count == 0 {
-            break;
-        }
-        hasher.update(&buffer[..count]);
-    }
-
-    Ok(hasher.finalize())
-}
-
-#[cfg(test)]
-mod tests {
-    use std::collections::BTreeSet;
-
-    use buck2_core::cells::cell_path::CellPath;
-    use buck2_core::cells::cell_root_path::CellRootPathBuf;
-    use buck2_core::cells::name::CellName;
-    use buck2_core::cells::CellResolver;
-    use buck2_core::fs::fs_util;
-    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
-    use buck2_core::fs::paths::abs_path::AbsPathBuf;
-    use buck2_core::fs::project::ProjectRoot;
-    use buck2_core::fs::project_rel_path::ProjectRelativePath;
-    use buck2_data::FileWatcherEventType;
-    use buck2_data::FileWatcherKind;
-
-    use crate::fs_hash_crawler::FsEvent;
-    use crate::fs_hash_crawler::FsSnapshot;
-
-    #[tokio::test]
-    async fn test_fs_snapshot() -> buck2_error::Result<()> {
-        let cell_resolver = CellResolver::testing_with_name_and_path(
-            CellName::testing_new("root"),
-            CellRootPathBuf::testing_new(""),
-        );
-        let tempdir = tempfile::tempdir()?;
-        let root_path = fs_util::canonicalize(AbsNormPathBuf::new(tempdir.path().to_owned())?)?;
-        let proj_root = ProjectRoot::new(root_path)?;
-
-        let get_path = |path| -> buck2_error::Result<(AbsPathBuf, CellPath)> {
-            let path = ProjectRelativePath::new(path).unwrap();
-            let cell_path = cell_resolver.get_cell_path(path)?;
-            Ok((proj_root.resolve(path).into_abs_path_buf(), cell_path))
-        };
-        let dir1 = proj_root.resolve(ProjectRelativePath::new("dir1")?);
-        let (file1, file1_cell) = get_path("dir1/file1")?;
-        let (dir2, dir2_cell) = get_path("dir2")?;
-        let (file2, file2_cell) = get_path("dir2/file2")?;
-        let (file3, file3_cell) = get_path("dir1/file3")?;
-        fs_util::create_dir_all(dir1)?;
-        fs_util::write(&file1, "old content")?;
-        fs_util::create_dir_all(&dir2)?;
-        fs_util::write(file2, "old content")?;
-
-        let old_snapshot = FsSnapshot::build(&proj_root, &cell_resolver)?;
-        fs_util::write(file1, "new content")?;
-        fs_util::remove_all(dir2)?;
-        fs_util::write(file3, "new content")?;
-        let new_snapshot = FsSnapshot::build(&proj_root, &cell_resolver)?;
-        let events = old_snapshot.get_updates(&new_snapshot)?;
-
-        let expected = [
-            FsEvent {
-                cell_path: file1_cell,
-                event: FileWatcherEventType::Modify,
-                kind: FileWatcherKind::File,
-            },
-            FsEvent {
-                cell_path: file3_cell,
-                event: FileWatcherEventType::Create,
-                kind: FileWatcherKind::File,
-            },
-            FsEvent {
-                cell_path: dir2_cell,
-                event: FileWatcherEventType::Delete,
-                kind: FileWatcherKind::Directory,
-            },
-            FsEvent {
-                cell_path: file2_cell,
-                event: FileWatcherEventType::Delete,
-                kind: FileWatcherKind::File,
-            },
-        ];
-
-        let events = events.iter().collect::<BTreeSet<_>>();
-        let expected = expected.iter().collect::<BTreeSet<_>>();
-        assert_eq!(events, expected);
-        Ok(())
-    }
-}
@@ -0,0 +1,1146 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An index of the files in the repository, which can be cheaply brought up to date by polling
+//! the filesystem and persisted across daemon restarts.
+//!
+//! Each directory in the index carries a digest of everything below it, so that comparing two
+//! indexes only needs to look into the directories whose digests differ.
+//!
+//! A persisted index is a snapshot plus a journal of the changes made to it since, so that keeping
+//! it up to date doesn't mean writing out the whole index every time something changes.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use allocative::Allocative;
+use buck2_common::dice::file_ops::FileChangeTracker;
+use buck2_common::file_ops::FileType;
+use buck2_common::ignores::ignore_set::IgnoreSet;
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::CellResolver;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::paths::file_name::FileNameBuf;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_data::FileWatcherEventType;
+use buck2_data::FileWatcherKind;
+use buck2_error::conversion::from_any_with_tag;
+use buck2_error::BuckErrorContext;
+use dupe::Dupe;
+
+use crate::mergebase::Mergebase;
+use crate::stats::FileWatcherStats;
+
+/// Bump this whenever the format of `FsIndex` changes.
+const INDEX_VERSION: u32 = 2;
+
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub(crate) struct FsEvent {
+    pub(crate) cell_path: CellPath,
+    pub(crate) event: FileWatcherEventType,
+    pub(crate) kind: FileWatcherKind,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Allocative,
+    serde::Serialize,
+    serde::Deserialize
+)]
+struct Mtime {
+    secs: u64,
+    nanos: u32,
+}
+
+impl Mtime {
+    fn from_system_time(time: SystemTime) -> Self {
+        // Times before the epoch are not interesting enough to represent precisely, they just need
+        // to be stable.
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            secs: since_epoch.as_secs(),
+            nanos: since_epoch.subsec_nanos(),
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    Allocative,
+    serde::Serialize,
+    serde::Deserialize
+)]
+enum EntryInfo {
+    File(#[allocative(skip)] [u8; 32]),
+    Directory {
+        /// The names of the directory's children, so that we don't need to list it again while
+        /// its mtime stays the same.
+        children: Vec<String>,
+        /// A digest of the children and everything below them.
+        #[allocative(skip)]
+        digest: [u8; 32],
+    },
+    Symlink(String),
+}
+
+impl EntryInfo {
+    fn to_file_watcher_kind(&self) -> FileWatcherKind {
+        match self {
+            EntryInfo::File(_) => FileWatcherKind::File,
+            EntryInfo::Directory { .. } => FileWatcherKind::Directory,
+            EntryInfo::Symlink(_) => FileWatcherKind::Symlink,
+        }
+    }
+
+    /// Whether going from `self` to `other` is a modification of the entry. Changes to a
+    /// directory's children are reported on the children instead.
+    fn is_modified(&self, other: &EntryInfo) -> bool {
+        match (self, other) {
+            (EntryInfo::File(a), EntryInfo::File(b)) => a != b,
+            (EntryInfo::Directory { .. }, EntryInfo::Directory { .. }) => false,
+            (EntryInfo::Symlink(a), EntryInfo::Symlink(b)) => a != b,
+            _ => true,
+        }
+    }
+
+    fn children(&self) -> &[String] {
+        match self {
+            EntryInfo::Directory { children, .. } => children,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    Allocative,
+    serde::Serialize,
+    serde::Deserialize
+)]
+struct IndexEntry {
+    mtime: Mtime,
+    size: u64,
+    info: EntryInfo,
+}
+
+/// The part of the filesystem an index covers: everything under the project root, except for
+/// buck-out, VCS directories and whatever the cells' ignore specs match.
+#[derive(Clone, Dupe, Allocative)]
+pub(crate) struct IndexedFs {
+    root: ProjectRoot,
+    cells: CellResolver,
+    ignore_specs: Arc<HashMap<CellName, IgnoreSet>>,
+}
+
+impl IndexedFs {
+    pub(crate) fn new(
+        root: ProjectRoot,
+        cells: CellResolver,
+        ignore_specs: Arc<HashMap<CellName, IgnoreSet>>,
+    ) -> Self {
+        Self {
+            root,
+            cells,
+            ignore_specs,
+        }
+    }
+
+    pub(crate) fn root(&self) -> &ProjectRoot {
+        &self.root
+    }
+
+    pub(crate) fn cells(&self) -> &CellResolver {
+        &self.cells
+    }
+
+    pub(crate) fn ignore_specs(&self) -> &HashMap<CellName, IgnoreSet> {
+        &self.ignore_specs
+    }
+
+    /// Whether `path` is left out of the index.
+    fn is_excluded(&self, path: &ProjectRelativePath) -> bool {
+        // We ignore buck-out and VCS dirs, as those are uninteresting events, mostly caused by us
+        // or by source control.
+        if path.starts_with(InvocationPaths::buck_out_dir_prefix())
+            || path.starts_with(ProjectRelativePath::unchecked_new(".hg"))
+            || path.starts_with(ProjectRelativePath::unchecked_new(".git"))
+        {
+            return true;
+        }
+        match self.cells.get_cell_path(path) {
+            Ok(cell_path) => self
+                .ignore_specs
+                .get(&cell_path.cell())
+                .is_some_and(|i| i.is_match(cell_path.path())),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Default, Allocative, serde::Serialize, serde::Deserialize)]
+pub(crate) struct FsIndex {
+    /// When the scan that produced this index started, if there was one. Parts of the index that
+    /// were rescanned since keep the time of the original scan, which is the conservative choice.
+    scan_start: Option<Mtime>,
+    /// The names of the entries in the project root, which isn't an entry itself.
+    root_children: Vec<String>,
+    entries: HashMap<ProjectRelativePathBuf, IndexEntry>,
+}
+
+/// The entries of an index that changed, as recorded in the journal of a persisted index.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct FsIndexDelta {
+    root_children: Vec<String>,
+    /// `None` for entries that were removed.
+    entries: Vec<(ProjectRelativePathBuf, Option<IndexEntry>)>,
+}
+
+impl FsIndexDelta {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PersistedFsIndex {
+    version: u32,
+    project_root: String,
+    mergebase: Option<String>,
+    /// Incremented on every save, so that a journal left over from a previous snapshot is
+    /// recognized as such.
+    generation: u64,
+    index: FsIndex,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JournalRecord {
+    generation: u64,
+    mergebase: Option<String>,
+    delta: FsIndexDelta,
+}
+
+/// Where an index is persisted: a snapshot at `path`, and a journal of the changes made since next
+/// to it. The journal is folded into a new snapshot once it outgrows the snapshot.
+pub(crate) struct FsIndexFile {
+    path: AbsNormPathBuf,
+    journal: AbsNormPathBuf,
+    generation: u64,
+    snapshot_len: u64,
+    journal_len: u64,
+}
+
+impl FsIndexFile {
+    pub(crate) fn new(path: AbsNormPathBuf) -> buck2_error::Result<Self> {
+        let journal = AbsNormPathBuf::try_from(format!("{}.journal", path))?;
+        Ok(Self {
+            path,
+            journal,
+            generation: 0,
+            snapshot_len: 0,
+            journal_len: 0,
+        })
+    }
+
+    /// Load the index persisted at `path`. The index is `None` if there isn't one, or if it was
+    /// saved for a different checkout or mergebase.
+    pub(crate) fn load(
+        path: AbsNormPathBuf,
+        root: &ProjectRoot,
+        mergebase: &Mergebase,
+    ) -> buck2_error::Result<(Self, Option<FsIndex>)> {
+        let mut file = Self::new(path)?;
+        let Some(data) = fs_util::read_if_exists(&file.path)? else {
+            return Ok((file, None));
+        };
+        let persisted: PersistedFsIndex = bincode::deserialize(&data)
+            .map_err(|e| from_any_with_tag(e, buck2_error::ErrorTag::Tier0))
+            .with_buck_error_context(|| format!("Error deserializing `{}`", file.path))?;
+        // An index from another version or another checkout is useless, just start over.
+        if persisted.version != INDEX_VERSION || persisted.project_root != root.to_string() {
+            return Ok((file, None));
+        }
+        file.generation = persisted.generation;
+        file.snapshot_len = data.len() as u64;
+
+        let mut index = persisted.index;
+        let mut saved_mergebase = persisted.mergebase;
+        let journal = fs_util::read_if_exists(&file.journal)?.unwrap_or_default();
+        let mut rest = journal.as_slice();
+        // A daemon killed while appending leaves a truncated record at the end, which we drop
+        // along with everything after it.
+        while let Some(record) = read_journal_record(&mut rest) {
+            if record.generation != file.generation {
+                break;
+            }
+            saved_mergebase = record.mergebase;
+            index.apply(record.delta);
+            file.journal_len = (journal.len() - rest.len()) as u64;
+        }
+
+        if saved_mergebase != *mergebase.0 {
+            return Ok((file, None));
+        }
+        Ok((file, Some(index)))
+    }
+
+    /// Write out `index` as a new snapshot, dropping the journal.
+    pub(crate) fn save(
+        &mut self,
+        index: &FsIndex,
+        root: &ProjectRoot,
+        mergebase: &Mergebase,
+    ) -> buck2_error::Result<()> {
+        #[derive(serde::Serialize)]
+        struct PersistedFsIndexRef<'a> {
+            version: u32,
+            project_root: String,
+            mergebase: &'a Option<String>,
+            generation: u64,
+            index: &'a FsIndex,
+        }
+
+        let generation = self.generation + 1;
+        let data = bincode::serialize(&PersistedFsIndexRef {
+            version: INDEX_VERSION,
+            project_root: root.to_string(),
+            mergebase: &mergebase.0,
+            generation,
+            index,
+        })
+        .map_err(|e| from_any_with_tag(e, buck2_error::ErrorTag::Tier0))?;
+        if let Some(parent) = self.path.parent() {
+            fs_util::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so that a daemon killed halfway through doesn't leave a
+        // truncated index behind.
+        let tmp = AbsNormPathBuf::try_from(format!("{}.tmp", self.path))?;
+        fs_util::write(&tmp, &data)?;
+        fs_util::rename(&tmp, &self.path)?;
+        // The journal is ignored from now on, since it's for another generation.
+        fs_util::remove_all(&self.journal)?;
+
+        self.generation = generation;
+        self.snapshot_len = data.len() as u64;
+        self.journal_len = 0;
+        Ok(())
+    }
+
+    /// Record the changes in `delta`, which were made to `index`. Once the journal outgrows the
+    /// snapshot, `index` is saved as a new snapshot instead.
+    pub(crate) fn append(
+        &mut self,
+        index: &FsIndex,
+        delta: FsIndexDelta,
+        root: &ProjectRoot,
+        mergebase: &Mergebase,
+    ) -> buck2_error::Result<()> {
+        if self.generation == 0 || self.journal_len > self.snapshot_len {
+            return self.save(index, root, mergebase);
+        }
+
+        let record = bincode::serialize(&JournalRecord {
+            generation: self.generation,
+            mergebase: (*mergebase.0).clone(),
+            delta,
+        })
+        .map_err(|e| from_any_with_tag(e, buck2_error::ErrorTag::Tier0))?;
+        let mut data = Vec::with_capacity(8 + record.len());
+        data.extend_from_slice(&(record.len() as u64).to_le_bytes());
+        data.extend_from_slice(&record);
+
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.journal)
+            .with_buck_error_context(|| format!("Error opening `{}`", self.journal))?;
+        journal
+            .write_all(&data)
+            .with_buck_error_context(|| format!("Error writing `{}`", self.journal))?;
+        self.journal_len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Remove the persisted index, e.g. because it can't be kept up to date.
+    pub(crate) fn remove(&mut self) -> buck2_error::Result<()> {
+        fs_util::remove_all(&self.path)?;
+        fs_util::remove_all(&self.journal)?;
+        self.generation = 0;
+        Ok(())
+    }
+}
+
+/// Read the next complete record from `data`, advancing it past the record.
+fn read_journal_record(data: &mut &[u8]) -> Option<JournalRecord> {
+    let len = u64::from_le_bytes(data.get(..8)?.try_into().ok()?) as usize;
+    let record = bincode::deserialize(data.get(8..8usize.checked_add(len)?)?).ok()?;
+    *data = &data[8 + len..];
+    Some(record)
+}
+
+impl FsIndex {
+    /// Whether the two indexes describe the same filesystem state.
+    #[cfg(test)]
+    pub(crate) fn same_entries(&self, other: &FsIndex) -> bool {
+        self.root_children == other.root_children && self.entries == other.entries
+    }
+
+    /// Produce a new index for the current state of the filesystem, reusing what we can from this
+    /// one.
+    pub(crate) fn rescan(&self, fs: &IndexedFs) -> buck2_error::Result<FsIndex> {
+        let mut new = FsIndex {
+            scan_start: Some(Mtime::from_system_time(SystemTime::now())),
+            root_children: Vec::new(),
+            entries: HashMap::new(),
+        };
+        // The project root itself is not an entry, so always list it.
+        let root_children = list_dir_names(fs, ProjectRelativePath::empty())?;
+        for name in &root_children {
+            new.scan_entry(self, fs, child_path(ProjectRelativePath::empty(), name))?;
+        }
+        new.root_children = root_children;
+        Ok(new)
+    }
+
+    /// Bring the index up to date after `paths` changed on disk, rescanning each of them along
+    /// with everything below them, and refreshing the listing of their parents and the digests
+    /// of their ancestors. Returns what changed in the index.
+    pub(crate) fn update(
+        &mut self,
+        fs: &IndexedFs,
+        paths: impl IntoIterator<Item = ProjectRelativePathBuf>,
+    ) -> buck2_error::Result<FsIndexDelta> {
+        let mut changed = BTreeSet::new();
+        let mut relist = BTreeSet::new();
+        for path in paths {
+            if fs.is_excluded(&path) {
+                continue;
+            }
+            let Some(parent) = path.parent() else {
+                // The project root itself.
+                relist.insert(path);
+                continue;
+            };
+            relist.insert(parent.to_buf());
+
+            let mut fresh = FsIndex {
+                scan_start: self.scan_start,
+                root_children: Vec::new(),
+                entries: HashMap::new(),
+            };
+            fresh.scan_entry(self, fs, path.clone())?;
+            self.remove_subtree(&path, &mut changed);
+            for (path, entry) in fresh.entries {
+                changed.insert(path.clone());
+                self.entries.insert(path, entry);
+            }
+        }
+
+        // Parents need to be listed again, since their children may have been created or removed,
+        // and every directory above a change needs a new digest. Go bottom up, so that children
+        // have their new digest by the time their parent's is computed.
+        let mut dirs = BTreeSet::new();
+        for dir in &relist {
+            let mut dir: Option<&ProjectRelativePath> = Some(dir);
+            while let Some(d) = dir {
+                dirs.insert((d.iter().count(), d.to_buf()));
+                dir = d.parent();
+            }
+        }
+        for (_, dir) in dirs.into_iter().rev() {
+            if dir.is_empty() {
+                if relist.contains(&dir) {
+                    self.root_children = list_dir_names(fs, &dir)?;
+                }
+                continue;
+            }
+            let Some(mut entry) = self.entries.get(&dir).cloned() else {
+                continue;
+            };
+            let EntryInfo::Directory { mut children, .. } = entry.info else {
+                continue;
+            };
+            if relist.contains(&dir) {
+                let Some(metadata) = fs_util::symlink_metadata_if_exists(&fs.root.resolve(&dir))?
+                else {
+                    // It was removed, which has an event of its own.
+                    continue;
+                };
+                entry.mtime = Mtime::from_system_time(metadata.modified()?);
+                entry.size = metadata.len();
+                children = list_dir_names(fs, &dir)?;
+            }
+            let digest = self.directory_digest(&dir, &children);
+            entry.info = EntryInfo::Directory { children, digest };
+            if self.entries.get(&dir) != Some(&entry) {
+                self.entries.insert(dir.clone(), entry);
+                changed.insert(dir);
+            }
+        }
+
+        Ok(FsIndexDelta {
+            root_children: self.root_children.clone(),
+            entries: changed
+                .into_iter()
+                .map(|path| {
+                    let entry = self.entries.get(&path).cloned();
+                    (path, entry)
+                })
+                .collect(),
+        })
+    }
+
+    /// The changes that turn this index into `new_index`, to be journaled.
+    pub(crate) fn delta(&self, new_index: &FsIndex) -> FsIndexDelta {
+        let mut entries: Vec<_> = new_index
+            .entries
+            .iter()
+            .filter(|(path, entry)| self.entries.get(*path) != Some(*entry))
+            .map(|(path, entry)| (path.clone(), Some(entry.clone())))
+            .collect();
+        entries.extend(
+            self.entries
+                .keys()
+                .filter(|path| !new_index.entries.contains_key(*path))
+                .map(|path| (path.clone(), None)),
+        );
+        FsIndexDelta {
+            root_children: new_index.root_children.clone(),
+            entries,
+        }
+    }
+
+    fn apply(&mut self, delta: FsIndexDelta) {
+        self.root_children = delta.root_children;
+        for (path, entry) in delta.entries {
+            match entry {
+                Some(entry) => {
+                    self.entries.insert(path, entry);
+                }
+                None => {
+                    self.entries.remove(&path);
+                }
+            }
+        }
+    }
+
+    fn remove_subtree(
+        &mut self,
+        path: &ProjectRelativePath,
+        removed: &mut BTreeSet<ProjectRelativePathBuf>,
+    ) {
+        let Some(entry) = self.entries.remove(path) else {
+            return;
+        };
+        for child in entry.info.children() {
+            self.remove_subtree(&child_path(path, child), removed);
+        }
+        removed.insert(path.to_buf());
+    }
+
+    /// A digest of the given children of `dir` and everything below them, as far as the index
+    /// knows about them.
+    fn directory_digest(&self, dir: &ProjectRelativePath, children: &[String]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        for child in children {
+            hasher.update(child.as_bytes());
+            match self.entries.get(&child_path(dir, child)).map(|e| &e.info) {
+                Some(EntryInfo::File(hash)) => {
+                    hasher.update(b"\0f");
+                    hasher.update(hash);
+                }
+                Some(EntryInfo::Directory { digest, .. }) => {
+                    hasher.update(b"\0d");
+                    hasher.update(digest);
+                }
+                Some(EntryInfo::Symlink(target)) => {
+                    hasher.update(b"\0l");
+                    hasher.update(target.as_bytes());
+                    hasher.update(b"\0");
+                }
+                // Removed since the directory was listed.
+                None => {
+                    hasher.update(b"\0-");
+                }
+            }
+        }
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Look up the previous state of `path`, if it can be trusted to still be accurate.
+    fn get_unchanged(
+        &self,
+        path: &ProjectRelativePath,
+        mtime: Mtime,
+        size: u64,
+    ) -> Option<&EntryInfo> {
+        // mtimes have limited granularity, so a file that was modified in the same second as (or
+        // after) the previous scan started may have been modified again without its mtime
+        // changing. Don't trust those.
+        let scan_start = self.scan_start?;
+        if mtime.secs >= scan_start.secs {
+            return None;
+        }
+        let entry = self.entries.get(path)?;
+        (entry.mtime == mtime && entry.size == size).then_some(&entry.info)
+    }
+
+    fn scan_entry(
+        &mut self,
+        old: &FsIndex,
+        fs: &IndexedFs,
+        path: ProjectRelativePathBuf,
+    ) -> buck2_error::Result<()> {
+        let disk_path = fs.root.resolve(&path);
+        // The entry may have been deleted since we listed its parent.
+        let Some(metadata) = fs_util::symlink_metadata_if_exists(&disk_path)? else {
+            return Ok(());
+        };
+        let mtime = Mtime::from_system_time(metadata.modified()?);
+        let size = metadata.len();
+        let unchanged = old.get_unchanged(&path, mtime, size);
+
+        let info = match FileType::from(metadata.file_type()) {
+            FileType::File => match unchanged {
+                Some(info @ EntryInfo::File(_)) => info.clone(),
+                _ => EntryInfo::File(*file_hash(disk_path.as_maybe_relativized())?.as_bytes()),
+            },
+            FileType::Directory => {
+                let children = match unchanged {
+                    Some(EntryInfo::Directory { children, .. }) => children.clone(),
+                    _ => list_dir_names(fs, &path)?,
+                };
+                for child in &children {
+                    let child = FileName::new(child)?;
+                    self.scan_entry(old, fs, path.join(child.as_forward_rel_path()))?;
+                }
+                let digest = self.directory_digest(&path, &children);
+                EntryInfo::Directory { children, digest }
+            }
+            FileType::Symlink => match unchanged {
+                Some(info @ EntryInfo::Symlink(_)) => info.clone(),
+                _ => EntryInfo::Symlink(
+                    fs_util::read_link(&disk_path)?
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+            },
+            FileType::Unknown => return Ok(()),
+        };
+
+        self.entries.insert(path, IndexEntry { mtime, size, info });
+        Ok(())
+    }
+
+    /// The changes from this index to `new_index`. Directories with the same digest in both are
+    /// skipped without looking at what's below them.
+    pub(crate) fn get_updates(
+        &self,
+        new_index: &FsIndex,
+        cells: &CellResolver,
+    ) -> buck2_error::Result<Vec<FsEvent>> {
+        let mut events = Vec::new();
+        self.diff_children(
+            new_index,
+            cells,
+            ProjectRelativePath::empty(),
+            &self.root_children,
+            &new_index.root_children,
+            &mut events,
+        )?;
+        Ok(events)
+    }
+
+    fn diff_children(
+        &self,
+        new_index: &FsIndex,
+        cells: &CellResolver,
+        dir: &ProjectRelativePath,
+        prev_children: &[String],
+        current_children: &[String],
+        events: &mut Vec<FsEvent>,
+    ) -> buck2_error::Result<()> {
+        let names = prev_children
+            .iter()
+            .chain(current_children)
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            let path = child_path(dir, name);
+            let prev = self.entries.get(&path).map(|e| &e.info);
+            let current = new_index.entries.get(&path).map(|e| &e.info);
+            let mut push = |event, info: &EntryInfo| {
+                events.push(FsEvent {
+                    cell_path: cells.get_cell_path(&path)?,
+                    event,
+                    kind: info.to_file_watcher_kind(),
+                });
+                buck2_error::Ok(())
+            };
+            match (prev, current) {
+                (
+                    Some(EntryInfo::Directory {
+                        children: prev_names,
+                        digest: prev_digest,
+                    }),
+                    Some(EntryInfo::Directory {
+                        children: current_names,
+                        digest: current_digest,
+                    }),
+                ) => {
+                    if prev_digest != current_digest {
+                        self.diff_children(
+                            new_index,
+                            cells,
+                            &path,
+                            prev_names,
+                            current_names,
+                            events,
+                        )?;
+                    }
+                }
+                (Some(prev), Some(current)) if !prev.is_modified(current) => {}
+                (Some(prev), Some(current))
+                    if prev.to_file_watcher_kind() == current.to_file_watcher_kind() =>
+                {
+                    push(FileWatcherEventType::Modify, prev)?;
+                }
+                (prev, current) => {
+                    // The entry was removed, created, or changed type, e.g. a file was replaced
+                    // by a directory.
+                    if let Some(prev) = prev {
+                        push(FileWatcherEventType::Delete, prev)?;
+                    }
+                    if let Some(current) = current {
+                        push(FileWatcherEventType::Create, current)?;
+                    }
+                    self.diff_children(
+                        new_index,
+                        cells,
+                        &path,
+                        prev.map_or(Default::default(), EntryInfo::children),
+                        current.map_or(Default::default(), EntryInfo::children),
+                        events,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_updates_for_dice(
+        &self,
+        new_index: &FsIndex,
+        cells: &CellResolver,
+        ignore_specs: &HashMap<CellName, IgnoreSet>,
+    ) -> buck2_error::Result<(buck2_data::FileWatcherStats, FileChangeTracker)> {
+        let events = self.get_updates(new_index, cells)?;
+        let mut changed = FileChangeTracker::new();
+        let mut stats = FileWatcherStats::new(Default::default(), events.len());
+        let mut ignored = 0;
+        for event in events.into_iter() {
+            let ignore = ignore_specs
+                .get(&event.cell_path.cell())
+                .map_or(false, |i| i.is_match(event.cell_path.path()));
+
+            if ignore {
+                ignored += 1;
+                continue;
+            }
+
+            stats.add(event.cell_path.to_string(), event.event, event.kind);
+            match (event.event, event.kind) {
+                (
+                    FileWatcherEventType::Create,
+                    FileWatcherKind::File | FileWatcherKind::Symlink,
+                ) => {
+                    changed.file_added(event.cell_path);
+                }
+                (FileWatcherEventType::Create, FileWatcherKind::Directory) => {
+                    changed.dir_added(event.cell_path);
+                }
+                (
+                    FileWatcherEventType::Modify,
+                    FileWatcherKind::File | FileWatcherKind::Symlink,
+                ) => {
+                    changed.file_changed(event.cell_path);
+                }
+                (FileWatcherEventType::Modify, FileWatcherKind::Directory) => {
+                    changed.dir_changed(event.cell_path);
+                }
+                (
+                    FileWatcherEventType::Delete,
+                    FileWatcherKind::File | FileWatcherKind::Symlink,
+                ) => {
+                    changed.file_removed(event.cell_path);
+                }
+                (FileWatcherEventType::Delete, FileWatcherKind::Directory) => {
+                    changed.dir_removed(event.cell_path);
+                }
+            }
+        }
+        stats.add_ignored(ignored);
+        Ok((stats.finish(), changed))
+    }
+}
+
+fn child_path(dir: &ProjectRelativePath, name: &str) -> ProjectRelativePathBuf {
+    dir.join(FileName::unchecked_new(name).as_forward_rel_path())
+}
+
+fn list_dir_names(fs: &IndexedFs, dir: &ProjectRelativePath) -> buck2_error::Result<Vec<String>> {
+    Ok(list_dir(fs, dir)?
+        .into_iter()
+        .map(|name| name.as_str().to_owned())
+        .collect())
+}
+
+/// List the entries of a directory that we care about.
+fn list_dir(fs: &IndexedFs, dir: &ProjectRelativePath) -> buck2_error::Result<Vec<FileNameBuf>> {
+    let disk_path = fs.root.resolve(dir);
+    // The directory may have been deleted since we listed its parent.
+    let Some(read_dir) = fs_util::read_dir_if_exists(&disk_path)? else {
+        return Ok(Vec::new());
+    };
+    let mut names = Vec::new();
+    for file in read_dir {
+        let file = file?;
+        let filename = file.file_name();
+        let filename = FileName::new(
+            filename
+                .to_str()
+                .buck_error_context("Filename is not UTF-8")?,
+        )
+        .with_buck_error_context(|| format!("Invalid filename: {}", disk_path.display()))?;
+
+        if fs.is_excluded(&dir.join(filename.as_forward_rel_path())) {
+            continue;
+        }
+
+        names.push(filename.to_owned());
+    }
+    // Keep the children of directories in the index deterministic.
+    names.sort();
+    Ok(names)
+}
+
+fn file_hash(path: &Path) -> buck2_error::Result<blake3::Hash> {
+    let mut reader = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+
+    let mut buffer = [0; 16 * 1024];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use buck2_common::ignores::ignore_set::IgnoreSet;
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::fs::fs_util;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use buck2_core::fs::paths::abs_path::AbsPathBuf;
+    use buck2_core::fs::project::ProjectRoot;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+    use buck2_data::FileWatcherEventType;
+    use buck2_data::FileWatcherKind;
+    use dupe::Dupe;
+
+    use crate::fs_index::FsEvent;
+    use crate::fs_index::FsIndex;
+    use crate::fs_index::FsIndexFile;
+    use crate::fs_index::IndexedFs;
+    use crate::mergebase::Mergebase;
+
+    #[tokio::test]
+    async fn test_fs_snapshot() -> buck2_error::Result<()> {
+        let cell_resolver = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let tempdir = tempfile::tempdir()?;
+        let root_path = fs_util::canonicalize(AbsNormPathBuf::new(tempdir.path().to_owned())?)?;
+        let proj_root = ProjectRoot::new(root_path)?;
+        let fs = IndexedFs::new(
+            proj_root.dupe(),
+            cell_resolver.dupe(),
+            Arc::new(HashMap::new()),
+        );
+
+        let get_path = |path| -> buck2_error::Result<(AbsPathBuf, CellPath)> {
+            let path = ProjectRelativePath::new(path).unwrap();
+            let cell_path = cell_resolver.get_cell_path(path)?;
+            Ok((proj_root.resolve(path).into_abs_path_buf(), cell_path))
+        };
+        let dir1 = proj_root.resolve(ProjectRelativePath::new("dir1")?);
+        let (file1, file1_cell) = get_path("dir1/file1")?;
+        let (dir2, dir2_cell) = get_path("dir2")?;
+        let (file2, file2_cell) = get_path("dir2/file2")?;
+        let (file3, file3_cell) = get_path("dir1/file3")?;
+        fs_util::create_dir_all(dir1)?;
+        fs_util::write(&file1, "old content")?;
+        fs_util::create_dir_all(&dir2)?;
+        fs_util::write(file2, "old content")?;
+
+        let old_index = FsIndex::default().rescan(&fs)?;
+        fs_util::write(file1, "new content")?;
+        fs_util::remove_all(dir2)?;
+        fs_util::write(file3, "new content")?;
+        let new_index = old_index.rescan(&fs)?;
+        let events = old_index.get_updates(&new_index, &cell_resolver)?;
+
+        let expected = [
+            FsEvent {
+                cell_path: file1_cell,
+                event: FileWatcherEventType::Modify,
+                kind: FileWatcherKind::File,
+            },
+            FsEvent {
+                cell_path: file3_cell,
+                event: FileWatcherEventType::Create,
+                kind: FileWatcherKind::File,
+            },
+            FsEvent {
+                cell_path: dir2_cell,
+                event: FileWatcherEventType::Delete,
+                kind: FileWatcherKind::Directory,
+            },
+            FsEvent {
+                cell_path: file2_cell,
+                event: FileWatcherEventType::Delete,
+                kind: FileWatcherKind::File,
+            },
+        ];
+
+        let events = events.iter().collect::<BTreeSet<_>>();
+        let expected = expected.iter().collect::<BTreeSet<_>>();
+        assert_eq!(events, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fs_index_persisted() -> buck2_error::Result<()> {
+        let cell_resolver = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let tempdir = tempfile::tempdir()?;
+        let root_path = fs_util::canonicalize(AbsNormPathBuf::new(tempdir.path().to_owned())?)?;
+        let proj_root = ProjectRoot::new(root_path)?;
+        let fs = IndexedFs::new(
+            proj_root.dupe(),
+            cell_resolver.dupe(),
+            Arc::new(HashMap::new()),
+        );
+        let index_path = proj_root.resolve(ProjectRelativePath::new("buck-out/index")?);
+
+        let file = proj_root.resolve(ProjectRelativePath::new("dir/file")?);
+        fs_util::create_dir_all(file.parent().unwrap())?;
+        fs_util::write(&file, "old content")?;
+
+        let index = FsIndex::default().rescan(&fs)?;
+        FsIndexFile::new(index_path.clone())?.save(&index, &proj_root, &Mergebase::default())?;
+
+        // The index is written to buck-out, which must not show up in the index itself.
+        let (_file, loaded) =
+            FsIndexFile::load(index_path.clone(), &proj_root, &Mergebase::default())?;
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.entries, index.entries);
+        assert!(loaded.get_updates(&index, &cell_resolver)?.is_empty());
+
+        fs_util::write(&file, "new content, different size")?;
+        let new_index = loaded.rescan(&fs)?;
+        let events = loaded.get_updates(&new_index, &cell_resolver)?;
+        assert_eq!(
+            events,
+            vec![FsEvent {
+                cell_path: cell_resolver.get_cell_path(ProjectRelativePath::new("dir/file")?)?,
+                event: FileWatcherEventType::Modify,
+                kind: FileWatcherKind::File,
+            }]
+        );
+
+        // An index for a different project root is ignored.
+        let other_root = ProjectRoot::new(fs_util::canonicalize(AbsNormPathBuf::new(
+            tempdir.path().join("dir"),
+        )?)?)?;
+        assert!(
+            FsIndexFile::load(index_path.clone(), &other_root, &Mergebase::default())?
+                .1
+                .is_none()
+        );
+
+        // So is an index for a different mergebase.
+        let mergebase = Mergebase(Arc::new(Some("abcdef".to_owned())));
+        assert!(FsIndexFile::load(index_path, &proj_root, &mergebase)?
+            .1
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fs_index_excludes_ignored() -> buck2_error::Result<()> {
+        let cell_resolver = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let tempdir = tempfile::tempdir()?;
+        let root_path = fs_util::canonicalize(AbsNormPathBuf::new(tempdir.path().to_owned())?)?;
+        let proj_root = ProjectRoot::new(root_path)?;
+        let fs = IndexedFs::new(
+            proj_root.dupe(),
+            cell_resolver.dupe(),
+            Arc::new(HashMap::from([(
+                CellName::testing_new("root"),
+                IgnoreSet::from_ignore_spec("ignored", true)?,
+            )])),
+        );
+
+        for path in [".git/HEAD", "ignored/file", "src/file"] {
+            let path = proj_root.resolve(ProjectRelativePath::new(path)?);
+            fs_util::create_dir_all(path.parent().unwrap())?;
+            fs_util::write(&path, "content")?;
+        }
+
+        let index = FsIndex::default().rescan(&fs)?;
+        assert_eq!(index.root_children, vec!["src".to_owned()]);
+        assert_eq!(
+            index
+                .entries
+                .keys()
+                .map(|p| p.as_str())
+                .collect::<BTreeSet<_>>(),
+            BTreeSet::from(["src", "src/file"])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fs_index_update_journaled() -> buck2_error::Result<()> {
+        let cell_resolver = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let tempdir = tempfile::tempdir()?;
+        let root_path = fs_util::canonicalize(AbsNormPathBuf::new(tempdir.path().to_owned())?)?;
+        let proj_root = ProjectRoot::new(root_path)?;
+        let fs = IndexedFs::new(
+            proj_root.dupe(),
+            cell_resolver.dupe(),
+            Arc::new(HashMap::new()),
+        );
+        let index_path = proj_root.resolve(ProjectRelativePath::new("buck-out/index")?);
+
+        fs_util::create_dir_all(proj_root.resolve(ProjectRelativePath::new("dir/sub")?))?;
+        fs_util::write(
+            proj_root.resolve(ProjectRelativePath::new("dir/sub/file")?),
+            "old content",
+        )?;
+        fs_util::write(
+            proj_root.resolve(ProjectRelativePath::new("other")?),
+            "old content",
+        )?;
+
+        let mut index = FsIndex::default().rescan(&fs)?;
+        let mut file = FsIndexFile::new(index_path.clone())?;
+        file.save(&index, &proj_root, &Mergebase::default())?;
+        let saved = FsIndex::default().rescan(&fs)?;
+
+        fs_util::write(
+            proj_root.resolve(ProjectRelativePath::new("dir/sub/file")?),
+            "new content, different size",
+        )?;
+        fs_util::write(
+            proj_root.resolve(ProjectRelativePath::new("dir/sub/new")?),
+            "new content",
+        )?;
+        let delta = index.update(
+            &fs,
+            [
+                ProjectRelativePath::new("dir/sub/file")?.to_buf(),
+                ProjectRelativePath::new("dir/sub/new")?.to_buf(),
+            ],
+        )?;
+        assert!(index.same_entries(&index.rescan(&fs)?));
+        file.append(&index, delta, &proj_root, &Mergebase::default())?;
+
+        // A record cut short by the daemon being killed is dropped.
+        let journal = AbsNormPathBuf::try_from(format!("{}.journal", index_path))?;
+        let mut data = fs_util::read(&journal)?;
+        data.extend_from_slice(&[100, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+        fs_util::write(&journal, data)?;
+
+        let (_file, loaded) =
+            FsIndexFile::load(index_path.clone(), &proj_root, &Mergebase::default())?;
+        let loaded = loaded.unwrap();
+        assert!(loaded.same_entries(&index));
+
+        // Only the directories that changed are compared.
+        let events = saved.get_updates(&loaded, &cell_resolver)?;
+        let events = events.iter().collect::<BTreeSet<_>>();
+        let expected = [
+            FsEvent {
+                cell_path: cell_resolver
+                    .get_cell_path(ProjectRelativePath::new("dir/sub/file")?)?,
+                event: FileWatcherEventType::Modify,
+                kind: FileWatcherKind::File,
+            },
+            FsEvent {
+                cell_path: cell_resolver.get_cell_path(ProjectRelativePath::new("dir/sub/new")?)?,
+                event: FileWatcherEventType::Create,
+                kind: FileWatcherKind::File,
+            },
+        ];
+        assert_eq!(events, expected.iter().collect::<BTreeSet<_>>());
+
+        // Saving a new snapshot drops the journal.
+        file.save(&index, &proj_root, &Mergebase::default())?;
+        assert!(!fs_util::try_exists(&journal)?);
+        Ok(())
+    }
+}
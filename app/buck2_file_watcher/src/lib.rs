@@ -15,6 +15,7 @@ pub mod dep_files;
 mod edenfs;
 pub mod file_watcher;
 mod fs_hash_crawler;
+mod fs_index;
 pub mod mergebase;
 mod notify;
 mod stats;
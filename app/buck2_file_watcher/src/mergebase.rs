@@ -9,12 +9,55 @@
 
 use std::sync::Arc;
 
+use buck2_core::fs::project::ProjectRoot;
+use buck2_util::process::background_command;
 use dice::UserComputationData;
 use dupe::Dupe;
+use tracing::debug;
 
 #[derive(Clone, Default, Dupe)]
 pub struct Mergebase(pub Arc<Option<String>>); // Base revision
 
+impl Mergebase {
+    /// Ask source control for the mergebase of the checkout at `root`, for file watchers that
+    /// don't get one from their backend. That's the merge-base of the working copy's parent with
+    /// `merge_base_with` if set, or just the parent otherwise. It's unset if `root` isn't in a git
+    /// or hg checkout.
+    pub(crate) fn from_source_control(root: &ProjectRoot, merge_base_with: Option<&str>) -> Self {
+        let git_args = match merge_base_with {
+            Some(base) => vec!["merge-base", "HEAD", base],
+            None => vec!["rev-parse", "HEAD"],
+        };
+        let hg_revset = match merge_base_with {
+            Some(base) => format!("ancestor(., {})", base),
+            None => ".".to_owned(),
+        };
+        let hg_args = vec!["log", "-r", &hg_revset, "-T", "{node}"];
+
+        for (program, args) in [("git", git_args), ("hg", hg_args)] {
+            let output = background_command(program)
+                .args(args)
+                .current_dir(root.root())
+                .output();
+            match output {
+                Ok(output) if output.status.success() => {
+                    let revision = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+                    if !revision.is_empty() {
+                        return Self(Arc::new(Some(revision)));
+                    }
+                }
+                Ok(output) => debug!(
+                    "Not using {} for the mergebase: {}",
+                    program,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => debug!("Not using {} for the mergebase: {}", program, e),
+            }
+        }
+        Self::default()
+    }
+}
+
 pub trait SetMergebase {
     fn set_mergebase(&mut self, mergebase: Mergebase);
 }
@@ -8,7 +8,9 @@
  */
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::mem;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -21,7 +23,9 @@ use buck2_core::cells::cell_path::CellPath;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellResolver;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_data::FileWatcherEventType;
 use buck2_data::FileWatcherKind;
 use buck2_error::conversion::from_any_with_tag;
@@ -29,6 +33,7 @@ use buck2_events::dispatch::span_async;
 use dice::DiceTransactionUpdater;
 use dupe::Dupe;
 use notify::event::CreateKind;
+use notify::event::DataChange;
 use notify::event::MetadataKind;
 use notify::event::ModifyKind;
 use notify::event::RemoveKind;
@@ -37,8 +42,13 @@ use notify::RecommendedWatcher;
 use notify::Watcher;
 use starlark_map::ordered_set::OrderedSet;
 use tracing::info;
+use tracing::warn;
 
 use crate::file_watcher::FileWatcher;
+use crate::fs_index::FsEvent;
+use crate::fs_index::FsIndex;
+use crate::fs_index::FsIndexFile;
+use crate::fs_index::IndexedFs;
 use crate::mergebase::Mergebase;
 use crate::stats::FileWatcherStats;
 
@@ -59,6 +69,8 @@ struct NotifyFileData {
     ignored: u64,
     #[allocative(skip)]
     events: OrderedSet<(CellPath, EventKind)>,
+    /// Every path we got an event for, ignored or not, to keep the snapshot up to date.
+    paths: HashSet<ProjectRelativePathBuf>,
 }
 
 impl NotifyFileData {
@@ -66,6 +78,7 @@ impl NotifyFileData {
         Self {
             ignored: 0,
             events: OrderedSet::new(),
+            paths: HashSet::new(),
         }
     }
 
@@ -93,6 +106,7 @@ impl NotifyFileData {
                 continue;
             }
 
+            self.paths.insert(path.to_buf());
             let cell_path = cells.get_cell_path(&path)?;
             let ignore = ignore_specs
                 .get(&cell_path.cell())
@@ -113,6 +127,41 @@ impl NotifyFileData {
         Ok(())
     }
 
+    /// Record changes that happened while there was no daemon running, as found by diffing a
+    /// snapshot against the disk.
+    fn add_restored(&mut self, events: Vec<FsEvent>, ignore_specs: &HashMap<CellName, IgnoreSet>) {
+        for event in events {
+            let ignore = ignore_specs
+                .get(&event.cell_path.cell())
+                .map_or(false, |ignore| ignore.is_match(event.cell_path.path()));
+            if ignore {
+                self.ignored += 1;
+                continue;
+            }
+
+            let event_kind = match (event.event, event.kind) {
+                (
+                    FileWatcherEventType::Create,
+                    FileWatcherKind::File | FileWatcherKind::Symlink,
+                ) => EventKind::Create(CreateKind::File),
+                (FileWatcherEventType::Create, FileWatcherKind::Directory) => {
+                    EventKind::Create(CreateKind::Folder)
+                }
+                (FileWatcherEventType::Modify, _) => {
+                    EventKind::Modify(ModifyKind::Data(DataChange::Any))
+                }
+                (
+                    FileWatcherEventType::Delete,
+                    FileWatcherKind::File | FileWatcherKind::Symlink,
+                ) => EventKind::Remove(RemoveKind::File),
+                (FileWatcherEventType::Delete, FileWatcherKind::Directory) => {
+                    EventKind::Remove(RemoveKind::Folder)
+                }
+            };
+            self.events.insert((event.cell_path, event_kind));
+        }
+    }
+
     fn sync(self) -> (buck2_data::FileWatcherStats, FileChangeTracker) {
         // The changes that go into the DICE transaction
         let mut changed = FileChangeTracker::new();
@@ -228,27 +277,231 @@ impl NotifyFileData {
     }
 }
 
+/// A snapshot of the repository that lets a restarted daemon find out what changed while it
+/// wasn't watching. It is kept up to date with the paths notify reports as we sync, so that it's
+/// current whenever the daemon goes away.
+#[derive(Allocative)]
+struct NotifySnapshot {
+    fs: IndexedFs,
+    /// The snapshot left behind by the previous daemon, which the first sync diffs against the
+    /// disk.
+    restored: Mutex<Option<FsIndex>>,
+    /// Sends updates to the thread maintaining the persisted snapshot.
+    #[allocative(skip)]
+    updates: Mutex<mpsc::Sender<SnapshotUpdate>>,
+}
+
+enum SnapshotUpdate {
+    /// The state of the disk when the restored snapshot was diffed against it.
+    Restored(FsIndex),
+    /// Paths that notify reported changes for.
+    Changed(Vec<ProjectRelativePathBuf>),
+    /// Notify lost track of what changed, so the whole repo needs to be looked at again.
+    Rescan,
+}
+
+impl NotifySnapshot {
+    fn new(
+        fs: IndexedFs,
+        path: AbsNormPathBuf,
+        merge_base_with: Option<String>,
+    ) -> buck2_error::Result<Self> {
+        let root = fs.root();
+        // A snapshot taken relative to another revision would still diff correctly, but the
+        // changes it reports would be as big as the rebase, so we'd rather start over.
+        let mergebase = Mergebase::from_source_control(root, merge_base_with.as_deref());
+        let (file, restored) = match FsIndexFile::load(path.clone(), root, &mergebase) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!("Discarding unreadable file watcher snapshot: {:#}", e);
+                (FsIndexFile::new(path)?, None)
+            }
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let writer = SnapshotWriter {
+            fs: fs.dupe(),
+            merge_base_with,
+            file,
+            index: None,
+        };
+        // Without a previous snapshot to start from, the writer indexes the whole repo itself.
+        let scan = restored.is_none();
+        let spawned = std::thread::Builder::new()
+            .name("notify-snapshot".to_owned())
+            .spawn(move || writer.run(scan, receiver));
+        if let Err(e) = spawned {
+            warn!("Error spawning the file watcher snapshot thread: {:#}", e);
+        }
+
+        Ok(Self {
+            fs,
+            restored: Mutex::new(restored),
+            updates: Mutex::new(sender),
+        })
+    }
+
+    fn send(&self, update: SnapshotUpdate) {
+        // If the writer is gone, it already logged why.
+        let _ignored = self.updates.lock().unwrap().send(update);
+    }
+
+    /// Diff the restored snapshot against the disk, if that hasn't happened yet.
+    async fn take_restored_changes(&self) -> buck2_error::Result<Option<Vec<FsEvent>>> {
+        let Some(restored) = self.restored.lock().unwrap().take() else {
+            return Ok(None);
+        };
+        let fs = self.fs.dupe();
+        let (restored, current) = tokio::task::spawn_blocking(move || {
+            let current = restored.rescan(&fs)?;
+            buck2_error::Ok((restored, current))
+        })
+        .await??;
+        let events = restored.get_updates(&current, self.fs.cells())?;
+        self.send(SnapshotUpdate::Restored(current));
+        Ok(Some(events))
+    }
+}
+
+/// Keeps the persisted snapshot up to date, on its own thread.
+struct SnapshotWriter {
+    fs: IndexedFs,
+    merge_base_with: Option<String>,
+    file: FsIndexFile,
+    /// The state of the disk as far as we know, once we have indexed it.
+    index: Option<FsIndex>,
+}
+
+impl SnapshotWriter {
+    fn run(mut self, scan: bool, receiver: mpsc::Receiver<SnapshotUpdate>) {
+        if scan {
+            self.rescan();
+        }
+        while let Ok(update) = receiver.recv() {
+            match update {
+                SnapshotUpdate::Restored(index) => {
+                    // Write it out right away, so that a daemon killed before anything changes
+                    // doesn't diff against the old snapshot again.
+                    self.index = Some(index);
+                    self.save();
+                }
+                SnapshotUpdate::Changed(paths) => {
+                    // We don't have an index if indexing or restoring failed, try again.
+                    if self.index.is_none() {
+                        self.rescan();
+                    } else if let Err(e) = self.update(paths) {
+                        warn!("Error updating file watcher snapshot: {:#}", e);
+                        self.rescan();
+                    }
+                }
+                SnapshotUpdate::Rescan => self.rescan(),
+            }
+        }
+    }
+
+    fn mergebase(&self) -> Mergebase {
+        Mergebase::from_source_control(self.fs.root(), self.merge_base_with.as_deref())
+    }
+
+    fn update(&mut self, paths: Vec<ProjectRelativePathBuf>) -> buck2_error::Result<()> {
+        let Some(index) = &mut self.index else {
+            return Ok(());
+        };
+        let delta = index.update(&self.fs, paths)?;
+        if !delta.is_empty() {
+            let mergebase = self.mergebase();
+            let Some(index) = &self.index else {
+                return Ok(());
+            };
+            self.file.append(index, delta, self.fs.root(), &mergebase)?;
+        }
+        Ok(())
+    }
+
+    /// Index the whole repo again, reusing what we can from the current index.
+    fn rescan(&mut self) {
+        let current = match &self.index {
+            Some(index) => index.rescan(&self.fs),
+            None => FsIndex::default().rescan(&self.fs),
+        };
+        match current {
+            Ok(current) => {
+                self.index = Some(current);
+                self.save();
+            }
+            Err(e) => {
+                warn!("Error indexing the repo for the file watcher: {:#}", e);
+                self.index = None;
+                self.remove();
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(index) = &self.index else {
+            return;
+        };
+        let mergebase = self.mergebase();
+        if let Err(e) = self.file.save(index, self.fs.root(), &mergebase) {
+            warn!("Error saving file watcher snapshot: {:#}", e);
+            self.remove();
+        }
+    }
+
+    /// A snapshot we failed to keep up to date can't be trusted by the next daemon.
+    fn remove(&mut self) {
+        if let Err(e) = self.file.remove() {
+            warn!("Error removing file watcher snapshot: {:#}", e);
+        }
+    }
+}
+
 #[derive(Allocative)]
 pub struct NotifyFileWatcher {
     #[allocative(skip)]
     watcher: RecommendedWatcher,
     data: Arc<Mutex<buck2_error::Result<NotifyFileData>>>,
+    snapshot: Option<NotifySnapshot>,
+    /// Signalled whenever `data` records something for the next sync.
+    #[allocative(skip)]
+    changes: Arc<tokio::sync::watch::Sender<()>>,
 }
 
 impl NotifyFileWatcher {
+    /// If `snapshot_path` is set, a snapshot of the repository is kept up to date there, and the
+    /// next daemon reports everything that changed since as its first set of changes. The
+    /// snapshot is only used if the mergebase against `merge_base_with` hasn't changed.
     pub fn new(
         root: &ProjectRoot,
         cells: CellResolver,
         ignore_specs: HashMap<CellName, IgnoreSet>,
+        snapshot_path: Option<AbsNormPathBuf>,
+        merge_base_with: Option<String>,
     ) -> buck2_error::Result<Self> {
+        let ignore_specs = Arc::new(ignore_specs);
+        let snapshot = snapshot_path
+            .map(|path| {
+                NotifySnapshot::new(
+                    IndexedFs::new(root.dupe(), cells.dupe(), ignore_specs.dupe()),
+                    path,
+                    merge_base_with,
+                )
+            })
+            .transpose()?;
         let data = Arc::new(Mutex::new(Ok(NotifyFileData::new())));
         let data2 = data.dupe();
         let root2 = root.dupe();
+        let changes = Arc::new(tokio::sync::watch::channel(()).0);
+        let changes2 = changes.dupe();
         let mut watcher = notify::recommended_watcher(move |event| {
             let mut guard = data2.lock().unwrap();
             if let Ok(state) = &mut *guard {
+                let before = state.events.len();
                 if let Err(e) = state.process(event, &root2, &cells, &ignore_specs) {
                     *guard = Err(e);
+                    changes2.send_replace(());
+                } else if state.events.len() != before {
+                    changes2.send_replace(());
                 }
             }
         })
@@ -256,7 +509,25 @@ impl NotifyFileWatcher {
         watcher
             .watch(root.root().as_path(), notify::RecursiveMode::Recursive)
             .map_err(|e| from_any_with_tag(e, buck2_error::ErrorTag::Tier0))?;
-        Ok(Self { watcher, data })
+        Ok(Self {
+            watcher,
+            data,
+            snapshot,
+            changes,
+        })
+    }
+
+    async fn restore_snapshot(&self) -> buck2_error::Result<()> {
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(());
+        };
+        if let Some(events) = snapshot.take_restored_changes().await? {
+            let mut guard = self.data.lock().unwrap();
+            if let Ok(state) = &mut *guard {
+                state.add_restored(events, snapshot.fs.ignore_specs());
+            }
+        }
+        Ok(())
     }
 
     fn sync2(
@@ -265,7 +536,21 @@ impl NotifyFileWatcher {
     ) -> buck2_error::Result<(buck2_data::FileWatcherStats, DiceTransactionUpdater)> {
         let mut guard = self.data.lock().unwrap();
         let old = mem::replace(&mut *guard, Ok(NotifyFileData::new()));
-        let (stats, changes) = old?.sync();
+        let mut old = match old {
+            Ok(old) => old,
+            Err(e) => {
+                if let Some(snapshot) = &self.snapshot {
+                    snapshot.send(SnapshotUpdate::Rescan);
+                }
+                return Err(e);
+            }
+        };
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.send(SnapshotUpdate::Changed(
+                mem::take(&mut old.paths).into_iter().collect(),
+            ));
+        }
+        let (stats, changes) = old.sync();
         changes.write_to_dice(&mut dice)?;
         Ok((stats, dice))
     }
@@ -282,7 +567,11 @@ impl FileWatcher for NotifyFileWatcher {
                 provider: buck2_data::FileWatcherProvider::RustNotify as i32,
             },
             async {
-                let (stats, res) = match self.sync2(dice) {
+                let res = match self.restore_snapshot().await {
+                    Ok(()) => self.sync2(dice),
+                    Err(e) => Err(e),
+                };
+                let (stats, res) = match res {
                     Ok((stats, dice)) => {
                         let mergebase = Mergebase(Arc::new(stats.branched_from_revision.clone()));
                         ((Some(stats)), Ok((dice, mergebase)))
@@ -294,4 +583,8 @@ impl FileWatcher for NotifyFileWatcher {
         )
         .await
     }
+
+    fn subscribe_to_changes(&self) -> Option<tokio::sync::watch::Receiver<()>> {
+        Some(self.changes.subscribe())
+    }
 }
@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+use std::path::PathBuf;
+
 use buck2_common::convert::ProstDurationExt;
 use buck2_error::BuckErrorContext;
 use futures::stream::Stream;
@@ -50,6 +52,15 @@ where
             CommandEvent::Exit(GatherOutputStatus::SpawnFailed(reason)) => {
                 Data::SpawnFailed(buck2_forkserver_proto::SpawnFailedEvent { reason })
             }
+            CommandEvent::Exit(GatherOutputStatus::SandboxViolation {
+                exit_code,
+                execution_stats,
+                undeclared_paths,
+            }) => Data::SandboxViolation(buck2_forkserver_proto::SandboxViolationEvent {
+                exit_code,
+                undeclared_paths: undeclared_paths.into_iter().map(path_to_bytes).collect(),
+                execution_stats,
+            }),
         };
 
         buck2_forkserver_proto::CommandEvent { data: Some(data) }
@@ -97,6 +108,15 @@ where
             Data::SpawnFailed(buck2_forkserver_proto::SpawnFailedEvent { reason }) => {
                 CommandEvent::Exit(GatherOutputStatus::SpawnFailed(reason))
             }
+            Data::SandboxViolation(buck2_forkserver_proto::SandboxViolationEvent {
+                exit_code,
+                undeclared_paths,
+                execution_stats,
+            }) => CommandEvent::Exit(GatherOutputStatus::SandboxViolation {
+                exit_code,
+                execution_stats,
+                undeclared_paths: undeclared_paths.into_iter().map(bytes_to_path).collect(),
+            }),
         };
 
         Ok(event)
@@ -112,3 +132,25 @@ where
 
     s.map(|r| r.map_err(convert_err).and_then(convert_event))
 }
+
+#[cfg(unix)]
+fn path_to_bytes(path: PathBuf) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    path.into_os_string().into_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: PathBuf) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
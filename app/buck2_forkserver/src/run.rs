@@ -13,6 +13,7 @@ pub mod status_decoder;
 
 use std::borrow::Cow;
 use std::path::Path;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::process::Command;
 use std::process::ExitStatus;
@@ -53,6 +54,14 @@ pub enum GatherOutputStatus {
     TimedOut(Duration),
     Cancelled,
     SpawnFailed(String),
+    /// The command ran in a sandbox and looked up paths it had not declared. Unless the sandbox
+    /// was only tracing, those paths did not exist as far as the command could tell, so its exit
+    /// code is not meaningful.
+    SandboxViolation {
+        exit_code: i32,
+        execution_stats: Option<buck2_data::CommandExecutionStats>,
+        undeclared_paths: Vec<PathBuf>,
+    },
 }
 
 impl From<DecodedStatus> for GatherOutputStatus {
@@ -66,6 +75,15 @@ impl From<DecodedStatus> for GatherOutputStatus {
                 execution_stats,
             },
             DecodedStatus::SpawnFailed(v) => Self::SpawnFailed(v),
+            DecodedStatus::SandboxViolation {
+                exit_code,
+                execution_stats,
+                undeclared_paths,
+            } => Self::SandboxViolation {
+                exit_code,
+                execution_stats,
+                undeclared_paths,
+            },
         }
     }
 }
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::path::PathBuf;
 use std::process::ExitStatus;
 
 use async_trait::async_trait;
@@ -24,6 +25,14 @@ pub(crate) enum DecodedStatus {
 
     /// Spawn failed, provide the error.
     SpawnFailed(String),
+
+    /// The command exited, but it looked up paths it had not declared.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    SandboxViolation {
+        exit_code: i32,
+        execution_stats: Option<buck2_data::CommandExecutionStats>,
+        undeclared_paths: Vec<PathBuf>,
+    },
 }
 
 #[async_trait]
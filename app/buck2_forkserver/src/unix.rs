@@ -10,7 +10,9 @@
 mod command;
 mod launch;
 pub(crate) mod process_group;
+mod sandbox;
 mod service;
 
 pub use command::run_forkserver;
 pub use launch::launch_forkserver;
+pub use sandbox::undeclared_input_detection_supported;
@@ -0,0 +1,238 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Running commands in a sandbox that only exposes the paths they declared.
+//!
+//! The command gets its own user and mount namespaces, so this needs no privileges, only
+//! unprivileged user namespaces being enabled. See `mounts` for what the sandbox looks like, and
+//! `notify` for how we find out about lookups of paths that were not exposed.
+//!
+//! A sandbox can also be trace-only, in which case the command sees the host filesystem and we
+//! only find out about what it looked up.
+
+#[cfg(target_os = "linux")]
+mod mounts;
+#[cfg(target_os = "linux")]
+mod notify;
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+
+use async_trait::async_trait;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_path::AbsPath;
+use buck2_forkserver_proto::SandboxConfig;
+
+use crate::run::status_decoder::DecodedStatus;
+use crate::run::status_decoder::StatusDecoder;
+
+#[derive(Debug, buck2_error::Error)]
+#[buck2(tag = Input)]
+enum SandboxError {
+    #[error("Sandboxed execution is not supported on this platform")]
+    Unsupported,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    #[error("Paths exposed in a sandbox must be absolute, got `{0}`")]
+    RelativePath(String),
+}
+
+/// What is left to do once a sandboxed command exits.
+#[cfg(target_os = "linux")]
+pub(crate) struct SandboxedCommand {
+    monitor: notify::AccessMonitor,
+    staged: mounts::StagedOutputs,
+}
+
+#[cfg(target_os = "linux")]
+impl SandboxedCommand {
+    /// Move the outputs of the command into place, and return the undeclared paths it looked up.
+    /// The command must have exited by now.
+    pub(crate) async fn finish(self) -> buck2_error::Result<Vec<PathBuf>> {
+        let staged = self.staged;
+        tokio::task::spawn_blocking(move || staged.finish())
+            .await?
+            .map_err(buck2_error::Error::from)?;
+        self.monitor.finish().await
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) enum SandboxedCommand {}
+
+#[cfg(not(target_os = "linux"))]
+impl SandboxedCommand {
+    pub(crate) async fn finish(self) -> buck2_error::Result<Vec<PathBuf>> {
+        match self {}
+    }
+}
+
+/// Whether this host can tell us what commands look up, which trace-only sandboxes need.
+#[cfg(target_os = "linux")]
+pub fn undeclared_input_detection_supported() -> bool {
+    notify::AccessFilter::supported()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn undeclared_input_detection_supported() -> bool {
+    false
+}
+
+/// Make `cmd` run in a sandbox, and start watching what it looks up. `sandbox_root` is an empty
+/// directory the sandbox gets mounted over.
+///
+/// Returns `None` if the sandbox is trace-only and this host can't trace lookups: the command then
+/// runs unmonitored, since it sees the host filesystem either way.
+#[cfg(target_os = "linux")]
+pub(crate) fn sandbox_command(
+    cmd: &mut Command,
+    config: SandboxConfig,
+    cwd: &AbsPath,
+    sandbox_root: &AbsNormPath,
+) -> buck2_error::Result<Option<SandboxedCommand>> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    use std::os::unix::process::CommandExt;
+    use std::sync::Arc;
+
+    use buck2_error::BuckErrorContext;
+
+    use self::mounts::exposed_paths;
+    use self::mounts::MountPlan;
+    use self::notify::AccessFilter;
+    use self::notify::Exposure;
+
+    let trace_only = config.trace_only;
+    let to_path = |p: Vec<u8>| {
+        let p = PathBuf::from(OsString::from_vec(p));
+        if p.is_absolute() {
+            Ok(p)
+        } else {
+            Err(SandboxError::RelativePath(p.display().to_string()))
+        }
+    };
+
+    let read_only = config
+        .read_only_paths
+        .into_iter()
+        .map(to_path)
+        .collect::<Result<Vec<_>, _>>()?;
+    let writable = config
+        .writable_paths
+        .into_iter()
+        .map(to_path)
+        .collect::<Result<Vec<_>, _>>()?;
+    let project_root = to_path(config.project_root)?;
+
+    let mut plan = if trace_only {
+        None
+    } else {
+        Some(
+            MountPlan::new(sandbox_root.as_path(), &read_only, &writable, cwd.as_path())
+                .buck_error_context("Error preparing the sandbox")?,
+        )
+    };
+    let staged = plan
+        .as_mut()
+        .map(|plan| plan.take_staged())
+        .unwrap_or_default();
+    let filter = match AccessFilter::new().buck_error_context("Error preparing the sandbox")? {
+        Some(filter) => filter,
+        None if trace_only => {
+            tracing::warn!("Undeclared input detection is not supported, running unmonitored");
+            return Ok(None);
+        }
+        None => return Err(SandboxError::Unsupported.into()),
+    };
+
+    let exposed = exposed_paths(&read_only, &writable)
+        .into_iter()
+        .map(|(p, _)| p)
+        .collect();
+
+    let monitor = filter
+        .monitor(Exposure {
+            sandbox_root: sandbox_root.as_path().to_path_buf(),
+            project_root,
+            exposed,
+            cwd: cwd.as_path().to_path_buf(),
+        })
+        .buck_error_context("Error starting the sandbox monitor")?;
+
+    let setup = Arc::new((plan, filter));
+    // SAFETY: Both of those only make async-signal-safe calls.
+    unsafe {
+        cmd.pre_exec(move || {
+            let (plan, filter) = &*setup;
+            if let Some(plan) = plan {
+                plan.enter()?;
+            }
+            filter.install()
+        });
+    }
+
+    Ok(Some(SandboxedCommand { monitor, staged }))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn sandbox_command(
+    cmd: &mut Command,
+    config: SandboxConfig,
+    cwd: &AbsPath,
+    sandbox_root: &AbsNormPath,
+) -> buck2_error::Result<Option<SandboxedCommand>> {
+    let _unused = (cmd, cwd, sandbox_root);
+    if config.trace_only {
+        tracing::warn!("Undeclared input detection is not supported, running unmonitored");
+        Ok(None)
+    } else {
+        Err(SandboxError::Unsupported.into())
+    }
+}
+
+/// Turns the exit status of a sandboxed command into a violation if it looked up undeclared
+/// paths.
+pub(crate) struct SandboxStatusDecoder<D> {
+    inner: D,
+    sandboxed: SandboxedCommand,
+}
+
+impl<D> SandboxStatusDecoder<D> {
+    pub(crate) fn new(inner: D, sandboxed: SandboxedCommand) -> Self {
+        Self { inner, sandboxed }
+    }
+}
+
+#[async_trait]
+impl<D> StatusDecoder for SandboxStatusDecoder<D>
+where
+    D: StatusDecoder + Send,
+{
+    async fn decode_status(self, status: ExitStatus) -> buck2_error::Result<DecodedStatus> {
+        let decoded = self.inner.decode_status(status).await?;
+        let undeclared_paths = self.sandboxed.finish().await?;
+
+        Ok(match decoded {
+            DecodedStatus::Status {
+                exit_code,
+                execution_stats,
+            } if !undeclared_paths.is_empty() => DecodedStatus::SandboxViolation {
+                exit_code,
+                execution_stats,
+                undeclared_paths,
+            },
+            decoded => decoded,
+        })
+    }
+
+    async fn cancel(self) -> buck2_error::Result<()> {
+        // Dropping the monitor stops it, and dropping the staged outputs removes them.
+        self.inner.cancel().await
+    }
+}
@@ -0,0 +1,631 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Building the sandbox's filesystem.
+//!
+//! The sandbox is a tmpfs, with every exposed path bind-mounted at the same location it has on
+//! the host, and a private `/tmp`. Everything that needs allocating or looking at the host
+//! filesystem is done in [`MountPlan::new`], since [`MountPlan::enter`] runs between `fork` and
+//! `exec` and may only make async-signal-safe calls.
+//!
+//! Outputs that don't exist yet can't be bound, see [`StagedOutputs`] for how they are written.
+
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+
+/// Exposed no matter what the command declared.
+const ALWAYS_WRITABLE: &[&str] = &["/dev", "/proc"];
+
+struct Bind {
+    source: CString,
+    target: CString,
+    /// Flags for the read-only remount, if this is read-only.
+    remount_flags: Option<libc::c_ulong>,
+}
+
+struct Dir {
+    path: CString,
+    /// A staging directory on the host to bind over this one as soon as it's created, so that
+    /// whatever gets created in it afterwards ends up there.
+    staging: Option<CString>,
+}
+
+pub(crate) struct MountPlan {
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    root: CString,
+    tmp: CString,
+    /// Directories to create in the sandbox, parents first.
+    dirs: Vec<Dir>,
+    /// Empty files to create in the sandbox, to bind files over.
+    files: Vec<CString>,
+    binds: Vec<Bind>,
+    cwd: CString,
+    staged: StagedOutputs,
+}
+
+impl MountPlan {
+    /// `root` is an empty directory to mount the sandbox over. It is only mounted over in the
+    /// command's own mount namespace, so all commands can share it.
+    pub(crate) fn new(
+        root: &Path,
+        read_only: &[PathBuf],
+        writable: &[PathBuf],
+        cwd: &Path,
+    ) -> io::Result<Self> {
+        // SAFETY: Those can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+        let mut plan = Self {
+            uid_map: format!("{} {} 1", uid, uid).into_bytes(),
+            gid_map: format!("{} {} 1", gid, gid).into_bytes(),
+            root: cstring(root)?,
+            tmp: cstring(&root.join("tmp"))?,
+            dirs: Vec::new(),
+            files: Vec::new(),
+            binds: Vec::new(),
+            cwd: cstring(cwd)?,
+            staged: StagedOutputs::default(),
+        };
+
+        let read_only = bind_whole_dirs(read_only)?;
+
+        // Paths that don't exist can't be bound, and the command would not be able to see them
+        // anyway. Outputs are staged instead, grouped by their parent.
+        let mut exposed = Vec::new();
+        let mut missing_outputs = BTreeMap::<&Path, Vec<PathBuf>>::new();
+        let paths = exposed_paths(&read_only, writable);
+        for (path, writable) in &paths {
+            match fs::metadata(path) {
+                Ok(meta) => exposed.push((path.clone(), *writable, meta.is_dir())),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    if let (true, Some(parent)) = (*writable, path.parent()) {
+                        missing_outputs
+                            .entry(parent)
+                            .or_default()
+                            .push(path.clone());
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut dirs = BTreeSet::new();
+        // The working directory has to exist even if nothing under it was declared.
+        dirs.extend(cwd.ancestors());
+        for parent in missing_outputs.keys().copied() {
+            dirs.extend(parent.ancestors());
+        }
+
+        // Paths that are inside another bind mount already exist there, and don't need creating.
+        let mut covered = Covered::new(&exposed);
+        let mut locked = LockedFlags::default();
+        for (path, writable, is_dir) in &exposed {
+            let target = sandbox_path(root, path);
+            if let Some(parent) = path.parent() {
+                dirs.extend(parent.ancestors());
+            }
+            if *is_dir {
+                dirs.insert(path.as_path());
+            } else if !covered.is_covered(path) {
+                plan.files.push(cstring(&target)?);
+            }
+
+            let remount_flags = if *writable {
+                None
+            } else {
+                let flags = locked.get(path, *is_dir)?;
+                Some(libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | flags)
+            };
+
+            plan.binds.push(Bind {
+                source: cstring(path)?,
+                target: cstring(&target)?,
+                remount_flags,
+            });
+        }
+
+        // A `BTreeSet` of paths iterates parents before their children.
+        let mut covered = Covered::new(&exposed);
+        let exposed_dirs = exposed
+            .iter()
+            .map(|(p, _, _)| p.as_path())
+            .collect::<HashSet<_>>();
+        for dir in dirs {
+            if dir.parent().is_none() || covered.is_covered(dir) {
+                continue;
+            }
+            // A directory that is exposed itself is bound over later, and its contents with it.
+            let staging = match missing_outputs.remove(dir) {
+                Some(outputs) if !exposed_dirs.contains(dir) => {
+                    Some(cstring(&plan.staged.stage(dir, outputs)?)?)
+                }
+                _ => None,
+            };
+            plan.dirs.push(Dir {
+                path: cstring(&sandbox_path(root, dir))?,
+                staging,
+            });
+        }
+
+        Ok(plan)
+    }
+
+    /// The outputs the command is going to write to staging directories. This has to be taken
+    /// before the command runs, and finished after it exits.
+    pub(crate) fn take_staged(&mut self) -> StagedOutputs {
+        mem::take(&mut self.staged)
+    }
+
+    /// Create the sandbox and move into it.
+    ///
+    /// # Safety
+    ///
+    /// This must be called in a single-threaded child process, before `exec`.
+    pub(crate) unsafe fn enter(&self) -> io::Result<()> {
+        unsafe {
+            check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS))?;
+
+            // We get to map our own uid and gid, which is all we need to keep owning our files.
+            write_file(c"/proc/self/setgroups", b"deny")?;
+            write_file(c"/proc/self/uid_map", &self.uid_map)?;
+            write_file(c"/proc/self/gid_map", &self.gid_map)?;
+
+            // Don't let any of what follows propagate back to the host.
+            mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE)?;
+
+            let tmpfs_flags = libc::MS_NOSUID | libc::MS_NODEV;
+            mount(Some(c"tmpfs"), &self.root, Some(c"tmpfs"), tmpfs_flags)?;
+            mkdir(&self.tmp)?;
+            // This goes first so that exposed paths under `/tmp` (e.g. a project in a temporary
+            // directory) end up on top of it.
+            mount(Some(c"tmpfs"), &self.tmp, Some(c"tmpfs"), tmpfs_flags)?;
+
+            for dir in &self.dirs {
+                mkdir(&dir.path)?;
+                if let Some(staging) = &dir.staging {
+                    mount(Some(staging), &dir.path, None, libc::MS_BIND)?;
+                }
+            }
+
+            for file in &self.files {
+                let fd = libc::open(
+                    file.as_ptr(),
+                    libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                    0o644,
+                );
+                check(fd)?;
+                libc::close(fd);
+            }
+
+            for bind in &self.binds {
+                mount(
+                    Some(&bind.source),
+                    &bind.target,
+                    None,
+                    libc::MS_BIND | libc::MS_REC,
+                )?;
+                if let Some(flags) = bind.remount_flags {
+                    mount(None, &bind.target, None, flags)?;
+                }
+            }
+
+            check(libc::chroot(self.root.as_ptr()))?;
+            check(libc::chdir(self.cwd.as_ptr()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Outputs that don't exist before the command runs can't be bound into the sandbox, and binding
+/// their parent directory instead would expose everything else in it. So the parent is a fresh
+/// staging directory next to it on the host, and the outputs the command wrote there are moved
+/// into place once it exits. Staging directories are removed when this is dropped.
+#[derive(Default)]
+pub(crate) struct StagedOutputs {
+    /// Each staging directory, and the outputs that belong in the directory it stands in for.
+    dirs: Vec<(PathBuf, Vec<PathBuf>)>,
+}
+
+impl StagedOutputs {
+    fn stage(&mut self, parent: &Path, outputs: Vec<PathBuf>) -> io::Result<PathBuf> {
+        // Being next to the outputs means moving them into place is a rename.
+        let staging = parent.join(format!(".sandbox-{:016x}", rand::random::<u64>()));
+        fs::create_dir(&staging)?;
+        self.dirs.push((staging.clone(), outputs));
+        Ok(staging)
+    }
+
+    /// Move the outputs the command wrote into place.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        for (staging, outputs) in &self.dirs {
+            for output in outputs {
+                let Some(name) = output.file_name() else {
+                    continue;
+                };
+                let staged = staging.join(name);
+                match fs::symlink_metadata(&staged) {
+                    Ok(_) => fs::rename(&staged, output)?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StagedOutputs {
+    fn drop(&mut self) {
+        for (staging, _) in &self.dirs {
+            // This also removes whatever undeclared files the command left there.
+            let _ignored = fs::remove_dir_all(staging);
+        }
+    }
+}
+
+/// Everything that gets bound into the sandbox, sorted so that parents come before their
+/// children, along with whether it is writable. Paths that are inside another path with the same
+/// access are dropped.
+pub(crate) fn exposed_paths(read_only: &[PathBuf], writable: &[PathBuf]) -> Vec<(PathBuf, bool)> {
+    let mut paths = read_only
+        .iter()
+        .map(|p| (p.clone(), false))
+        .chain(ALWAYS_WRITABLE.iter().map(|p| (PathBuf::from(p), true)))
+        .chain(writable.iter().map(|p| (p.clone(), true)))
+        .collect::<Vec<_>>();
+
+    // If a path is exposed both ways, it's writable.
+    paths.sort();
+    paths.dedup_by(|b, a| {
+        if a.0 == b.0 {
+            a.1 |= b.1;
+            true
+        } else {
+            false
+        }
+    });
+
+    let mut res: Vec<(PathBuf, bool)> = Vec::with_capacity(paths.len());
+    for (path, writable) in paths {
+        let redundant = res
+            .iter()
+            .rev()
+            .find(|(p, _)| path.starts_with(p))
+            .is_some_and(|(_, w)| *w == writable);
+        if !redundant {
+            res.push((path, writable));
+        }
+    }
+    res
+}
+
+/// Replaces the read-only paths that make up the whole of a directory with that directory,
+/// working up from the deepest directories, so that an action with many inputs doesn't need a bind
+/// mount for each of them.
+fn bind_whole_dirs(read_only: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut paths = read_only.iter().cloned().collect::<BTreeSet<_>>();
+    let depth = |p: &Path| p.components().count();
+    let mut dirs = paths
+        .iter()
+        .filter_map(|p| p.parent())
+        .map(|p| (depth(p), p.to_path_buf()))
+        .collect::<BTreeSet<_>>();
+
+    while let Some((_, dir)) = dirs.pop_last() {
+        // Directories that were declared are bound whole already, and the root never is.
+        if paths.contains(&dir) || dir.parent().is_none() {
+            continue;
+        }
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut whole = false;
+        for entry in entries {
+            whole = paths.contains(&entry?.path());
+            if !whole {
+                break;
+            }
+        }
+        if !whole {
+            continue;
+        }
+
+        // Paths sort right after the directory they are in.
+        let inside = paths
+            .range(dir.clone()..)
+            .take_while(|p| p.starts_with(&dir))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in inside {
+            paths.remove(&path);
+        }
+        if let Some(parent) = dir.parent() {
+            dirs.insert((depth(parent), parent.to_path_buf()));
+        }
+        paths.insert(dir);
+    }
+
+    Ok(paths.into_iter().collect())
+}
+
+/// Tells whether paths are strictly inside one of the exposed paths, in a single walk over the
+/// exposed paths. Both have to be sorted, which puts paths right after the paths they are in.
+struct Covered<'a> {
+    exposed: std::iter::Peekable<slice::Iter<'a, (PathBuf, bool, bool)>>,
+    /// The exposed paths walked over that contain the last path asked about, outermost first.
+    enclosing: Vec<&'a Path>,
+}
+
+impl<'a> Covered<'a> {
+    fn new(exposed: &'a [(PathBuf, bool, bool)]) -> Self {
+        Self {
+            exposed: exposed.iter().peekable(),
+            enclosing: Vec::new(),
+        }
+    }
+
+    /// `path` must not sort before the paths asked about previously.
+    fn is_covered(&mut self, path: &Path) -> bool {
+        while let Some((next, _, _)) = self.exposed.next_if(|(p, _, _)| p.as_path() <= path) {
+            self.leave(next);
+            self.enclosing.push(next);
+        }
+        self.leave(path);
+        self.enclosing.iter().any(|p| *p != path)
+    }
+
+    /// Drop the exposed paths that `path` isn't in.
+    fn leave(&mut self, path: &Path) {
+        while self.enclosing.last().is_some_and(|p| !path.starts_with(p)) {
+            self.enclosing.pop();
+        }
+    }
+}
+
+/// [`locked_flags`] of exposed paths, looked up once per directory rather than once per file:
+/// a file is almost never a mount point of its own, so it has the flags of its directory.
+#[derive(Default)]
+struct LockedFlags(HashMap<PathBuf, libc::c_ulong>);
+
+impl LockedFlags {
+    fn get(&mut self, path: &Path, is_dir: bool) -> io::Result<libc::c_ulong> {
+        let dir = match path.parent() {
+            Some(parent) if !is_dir => parent,
+            _ => path,
+        };
+        match self.0.entry(dir.to_path_buf()) {
+            Entry::Occupied(e) => Ok(*e.get()),
+            Entry::Vacant(e) => Ok(*e.insert(locked_flags(dir)?)),
+        }
+    }
+}
+
+fn sandbox_path(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// In a user namespace, we're not allowed to clear those flags on mounts we inherited, so a
+/// remount has to carry them over.
+fn locked_flags(path: &Path) -> io::Result<libc::c_ulong> {
+    let path = cstring(path)?;
+    // SAFETY: `statvfs` is plain data, and gets filled in on success.
+    let stat = unsafe {
+        let mut stat = std::mem::zeroed::<libc::statvfs>();
+        check(libc::statvfs(path.as_ptr(), &mut stat))?;
+        stat
+    };
+
+    let mut flags = 0;
+    for (st, ms) in [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if stat.f_flag & st != 0 {
+            flags |= ms;
+        }
+    }
+    Ok(flags)
+}
+
+fn check(res: libc::c_int) -> io::Result<()> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+unsafe fn mount(
+    source: Option<&CString>,
+    target: &CString,
+    fstype: Option<&CString>,
+    flags: libc::c_ulong,
+) -> io::Result<()> {
+    let source = source.map_or(ptr::null(), |s| s.as_ptr());
+    let fstype = fstype.map_or(ptr::null(), |s| s.as_ptr());
+    check(unsafe { libc::mount(source, target.as_ptr(), fstype, flags, ptr::null()) })
+}
+
+unsafe fn mkdir(path: &CString) -> io::Result<()> {
+    match check(unsafe { libc::mkdir(path.as_ptr(), 0o755) }) {
+        Err(e) if e.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+        res => res,
+    }
+}
+
+unsafe fn write_file(path: &std::ffi::CStr, data: &[u8]) -> io::Result<()> {
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        check(fd)?;
+        let written = libc::write(fd, data.as_ptr().cast(), data.len());
+        libc::close(fd);
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_exposed_paths() {
+        let exposed = exposed_paths(
+            &paths(&["/usr", "/usr/lib", "/repo/src/a.c", "/repo/out/dep"]),
+            &paths(&["/repo/out", "/repo/src/a.c"]),
+        );
+        assert_eq!(
+            exposed,
+            vec![
+                (PathBuf::from("/dev"), true),
+                (PathBuf::from("/proc"), true),
+                (PathBuf::from("/repo/out"), true),
+                (PathBuf::from("/repo/out/dep"), false),
+                (PathBuf::from("/repo/src/a.c"), true),
+                (PathBuf::from("/usr"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mount_plan() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let repo = tempdir.path().join("repo");
+        fs::create_dir_all(repo.join("src"))?;
+        fs::create_dir_all(repo.join("out"))?;
+        fs::write(repo.join("src/a.c"), "")?;
+        // Not declared, so `src` can't be bound whole.
+        fs::write(repo.join("src/b.c"), "")?;
+
+        let root = Path::new("/sandbox");
+        let plan = MountPlan::new(
+            root,
+            &[repo.join("src/a.c"), repo.join("missing")],
+            &[repo.join("out")],
+            &repo,
+        )?;
+
+        let sandboxed = |p: &Path| cstring(&sandbox_path(root, p)).unwrap();
+
+        let dirs = plan.dirs.iter().map(|d| d.path.clone()).collect::<Vec<_>>();
+        assert_eq!(plan.files, vec![sandboxed(&repo.join("src/a.c"))]);
+        assert!(dirs.contains(&sandboxed(&repo)));
+        assert!(dirs.contains(&sandboxed(&repo.join("src"))));
+        assert!(dirs.contains(&sandboxed(&repo.join("out"))));
+        assert!(!dirs.iter().any(|d| d.as_bytes().ends_with(b"missing")));
+        assert!(plan.dirs.iter().all(|d| d.staging.is_none()));
+
+        let binds = plan
+            .binds
+            .iter()
+            .map(|b| (b.source.clone(), b.remount_flags.is_some()))
+            .collect::<Vec<_>>();
+        assert!(binds.contains(&(cstring(&repo.join("src/a.c"))?, true)));
+        assert!(binds.contains(&(cstring(&repo.join("out"))?, false)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_outputs_are_staged() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let repo = tempdir.path().join("repo");
+        let out = repo.join("out");
+        fs::create_dir_all(&out)?;
+        // Another action's output, which must not be exposed.
+        fs::write(out.join("other"), "")?;
+
+        let root = Path::new("/sandbox");
+        let mut plan = MountPlan::new(root, &[], &[out.join("a"), out.join("b")], &repo)?;
+        let dir = plan
+            .dirs
+            .iter()
+            .find(|d| d.path == cstring(&sandbox_path(root, &out)).unwrap())
+            .unwrap();
+        let staging = PathBuf::from(dir.staging.as_ref().unwrap().to_str().unwrap());
+        assert_eq!(staging.parent(), Some(out.as_path()));
+        assert!(!plan
+            .binds
+            .iter()
+            .any(|b| b.source == cstring(&out).unwrap()));
+
+        // What the command would do.
+        fs::write(staging.join("a"), "a")?;
+        fs::write(staging.join("undeclared"), "")?;
+
+        let staged = plan.take_staged();
+        staged.finish()?;
+        assert_eq!(fs::read_to_string(out.join("a"))?, "a");
+        assert!(!out.join("b").exists());
+        assert!(!staging.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_whole_dirs() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let repo = tempdir.path().join("repo");
+        for dir in ["src/a", "src/b", "lib"] {
+            fs::create_dir_all(repo.join(dir))?;
+        }
+        for file in ["src/a/1.c", "src/a/2.c", "src/b/1.c", "lib/1.c", "lib/2.c"] {
+            fs::write(repo.join(file), "")?;
+        }
+
+        let read_only = bind_whole_dirs(&[
+            repo.join("src/a/1.c"),
+            repo.join("src/a/2.c"),
+            repo.join("src/b/1.c"),
+            repo.join("lib/1.c"),
+        ])?;
+        // `src` is made whole by `src/a` and `src/b`, but `lib/2.c` wasn't declared.
+        assert_eq!(read_only, vec![repo.join("lib/1.c"), repo.join("src")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_covered() {
+        let exposed = [("/a", false), ("/a/b", true), ("/c", false)]
+            .map(|(p, w)| (PathBuf::from(p), w, true));
+        let mut covered = Covered::new(&exposed);
+        let asked = ["/", "/a", "/a/b", "/a/b/c", "/a/d", "/ab", "/c/d"]
+            .map(|p| covered.is_covered(Path::new(p)));
+        assert_eq!(asked, [false, false, true, true, true, false, true]);
+    }
+}
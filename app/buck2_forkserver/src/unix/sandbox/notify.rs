@@ -0,0 +1,683 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Finding out which paths a sandboxed command looked up, using seccomp user notifications
+//! (Linux 5.5+, for letting the syscall continue once we've looked at it).
+//!
+//! Right before `exec`, the command installs a filter that turns path-taking syscalls into
+//! notifications, and sends the listener for those back to the forkserver. There, a thread reads
+//! the path out of each notification and lets the syscall proceed. Nothing is denied here: what
+//! isn't exposed doesn't exist in the sandbox in the first place.
+
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::net::UnixStream;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use dupe::Dupe;
+
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+const SECCOMP_GET_ACTION_AVAIL: libc::c_uint = 2;
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_ulong = 1 << 3;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const SECCOMP_USER_NOTIF_FLAG_CONTINUE: u32 = 1;
+
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+const SECCOMP_IOCTL_NOTIF_ID_VALID: libc::c_ulong = 0x4008_2102;
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_RET_K: u16 = 0x06;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// How many paths to report at most. Past that, the command is clearly broken in the sandbox.
+const MAX_REPORTED_PATHS: usize = 1000;
+
+/// How many looked up paths to remember, so that repeated lookups aren't checked again.
+const MAX_SEEN_PATHS: usize = 100_000;
+
+/// How often the monitor checks whether it was asked to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[repr(C)]
+struct SeccompData {
+    nr: libc::c_int,
+    arch: u32,
+    instruction_pointer: u64,
+    args: [u64; 6],
+}
+
+#[repr(C)]
+struct SeccompNotif {
+    id: u64,
+    pid: u32,
+    flags: u32,
+    data: SeccompData,
+}
+
+#[repr(C)]
+struct SeccompNotifResp {
+    id: u64,
+    val: i64,
+    error: i32,
+    flags: u32,
+}
+
+/// A syscall that takes a path, and where to find it in its arguments.
+#[derive(Clone, Copy)]
+struct PathSyscall {
+    nr: libc::c_long,
+    /// The directory relative paths are resolved against, if not the working directory.
+    dirfd: Option<usize>,
+    path: usize,
+}
+
+impl PathSyscall {
+    const fn plain(nr: libc::c_long) -> Self {
+        Self {
+            nr,
+            dirfd: None,
+            path: 0,
+        }
+    }
+
+    const fn at(nr: libc::c_long) -> Self {
+        Self {
+            nr,
+            dirfd: Some(0),
+            path: 1,
+        }
+    }
+}
+
+fn path_syscalls() -> Vec<PathSyscall> {
+    let mut syscalls = vec![
+        PathSyscall::at(libc::SYS_openat),
+        PathSyscall::at(libc::SYS_openat2),
+        PathSyscall::at(libc::SYS_newfstatat),
+        PathSyscall::at(libc::SYS_statx),
+        PathSyscall::at(libc::SYS_faccessat),
+        PathSyscall::at(libc::SYS_faccessat2),
+        PathSyscall::at(libc::SYS_readlinkat),
+        PathSyscall::at(libc::SYS_execveat),
+        PathSyscall::plain(libc::SYS_execve),
+        PathSyscall::plain(libc::SYS_chdir),
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    syscalls.extend([
+        PathSyscall::plain(libc::SYS_open),
+        PathSyscall::plain(libc::SYS_stat),
+        PathSyscall::plain(libc::SYS_lstat),
+        PathSyscall::plain(libc::SYS_access),
+        PathSyscall::plain(libc::SYS_readlink),
+    ]);
+
+    syscalls
+}
+
+/// The filter a sandboxed command installs, and the socket it sends the listener through.
+pub(crate) struct AccessFilter {
+    program: Vec<libc::sock_filter>,
+    syscalls: Vec<PathSyscall>,
+    sender: OwnedFd,
+    receiver: UnixStream,
+}
+
+impl AccessFilter {
+    /// Whether this architecture and kernel are supported.
+    pub(crate) fn supported() -> bool {
+        AUDIT_ARCH.is_some() && kernel_supported()
+    }
+
+    /// Returns `None` if this architecture or kernel is not supported.
+    pub(crate) fn new() -> io::Result<Option<Self>> {
+        let arch = match AUDIT_ARCH {
+            Some(arch) if kernel_supported() => arch,
+            _ => return Ok(None),
+        };
+
+        let syscalls = path_syscalls();
+        let stmt = |code, k| libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+
+        // Load the arch and let anything else through, then load the syscall number and notify
+        // (at the very end) if it's one of ours.
+        let mut program = vec![
+            stmt(BPF_LD_W_ABS, mem::offset_of!(SeccompData, arch) as u32),
+            libc::sock_filter {
+                code: BPF_JMP_JEQ_K,
+                jt: 1,
+                jf: 0,
+                k: arch,
+            },
+            stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+            stmt(BPF_LD_W_ABS, mem::offset_of!(SeccompData, nr) as u32),
+        ];
+        for (i, syscall) in syscalls.iter().enumerate() {
+            program.push(libc::sock_filter {
+                code: BPF_JMP_JEQ_K,
+                jt: (syscalls.len() - i) as u8,
+                jf: 0,
+                k: syscall.nr as u32,
+            });
+        }
+        program.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+        program.push(stmt(BPF_RET_K, SECCOMP_RET_USER_NOTIF));
+
+        let (sender, receiver) = UnixStream::pair()?;
+
+        Ok(Some(Self {
+            program,
+            syscalls,
+            sender: sender.into(),
+            receiver,
+        }))
+    }
+
+    /// Install the filter and send the listener to the forkserver.
+    ///
+    /// # Safety
+    ///
+    /// This must be called in the child process, right before `exec`: everything the process does
+    /// after this will wait on the monitor.
+    pub(crate) unsafe fn install(&self) -> io::Result<()> {
+        unsafe {
+            // Required to install a filter without being privileged.
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let prog = libc::sock_fprog {
+                len: self.program.len() as libc::c_ushort,
+                filter: self.program.as_ptr().cast_mut(),
+            };
+            let listener = libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                &prog as *const libc::sock_fprog,
+            );
+            if listener < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let listener = listener as RawFd;
+            let res = send_fd(self.sender.as_raw_fd(), listener);
+            libc::close(listener);
+            res
+        }
+    }
+
+    /// Start watching. This must happen before the command is spawned, since the command can't
+    /// `exec` until something answers its notifications.
+    pub(crate) fn monitor(&self, exposure: Exposure) -> io::Result<AccessMonitor> {
+        let receiver = self.receiver.try_clone()?;
+        let syscalls = self.syscalls.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = std::thread::Builder::new()
+            .name("sandbox-monitor".to_owned())
+            .spawn({
+                let stop = stop.dupe();
+                move || {
+                    // If the command never got to send a listener, this returns when all the
+                    // copies of the sending end are closed: ours is dropped after spawning.
+                    match recv_fd(&receiver) {
+                        Ok(Some(listener)) => watch(listener, &syscalls, &exposure, &stop),
+                        Ok(None) | Err(_) => BTreeSet::new(),
+                    }
+                }
+            })?;
+
+        Ok(AccessMonitor {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Whether the kernel can tell us about lookups and then let them proceed: user notifications
+/// are Linux 5.0+, and letting the syscall continue after one is 5.5+. There is no way to ask
+/// about the latter short of trying, so we go by the version.
+fn kernel_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let action = SECCOMP_RET_USER_NOTIF;
+        // SAFETY: This only reads `action`.
+        let notif_available = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_GET_ACTION_AVAIL,
+                0,
+                &action as *const u32,
+            )
+        } == 0;
+        let release = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+        notif_available && kernel_version(&release).is_some_and(|v| v >= (5, 5))
+    })
+}
+
+/// The major and minor version in a kernel release like `5.15.0-91-generic`.
+fn kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.trim().split(|c: char| !c.is_ascii_digit());
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// What a sandbox exposes, to tell undeclared lookups from the rest.
+pub(crate) struct Exposure {
+    /// Where the sandbox root is on the host, so paths read from `/proc` can be translated.
+    pub(crate) sandbox_root: PathBuf,
+    pub(crate) project_root: PathBuf,
+    /// Sorted, which puts paths right after the paths they are in.
+    pub(crate) exposed: BTreeSet<PathBuf>,
+    /// This exists in the sandbox even if nothing in it was declared, but its contents don't.
+    pub(crate) cwd: PathBuf,
+}
+
+impl Exposure {
+    fn is_undeclared(&self, path: &Path) -> bool {
+        path.starts_with(&self.project_root)
+            && !self.cwd.starts_with(path)
+            && !self.is_exposed(path)
+            && fs::symlink_metadata(path).is_ok()
+    }
+
+    /// Whether `path` is in an exposed path, or a parent of one. Looking up a parent of something
+    /// that was exposed is fine: it exists in the sandbox.
+    fn is_exposed(&self, path: &Path) -> bool {
+        path.ancestors().any(|p| self.exposed.contains(p))
+            || self
+                .exposed
+                .range::<Path, _>(path..)
+                .next()
+                .is_some_and(|p| p.starts_with(path))
+    }
+
+    /// Turn a path the kernel gave us for a sandboxed process into the path the process sees.
+    fn unsandboxed(&self, path: PathBuf) -> PathBuf {
+        match path.strip_prefix(&self.sandbox_root) {
+            Ok(p) => Path::new("/").join(p),
+            Err(_) => path,
+        }
+    }
+}
+
+pub(crate) struct AccessMonitor {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<BTreeSet<PathBuf>>>,
+}
+
+impl AccessMonitor {
+    /// Stop watching, and return the undeclared paths that were looked up. The command must have
+    /// exited by now.
+    pub(crate) async fn finish(mut self) -> buck2_error::Result<Vec<PathBuf>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(Vec::new()),
+        };
+        let paths = tokio::task::spawn_blocking(move || thread.join())
+            .await?
+            .map_err(|_| {
+                buck2_error::buck2_error!(buck2_error::ErrorTag::Tier0, "Sandbox monitor panicked")
+            })?;
+        Ok(paths.into_iter().collect())
+    }
+}
+
+impl Drop for AccessMonitor {
+    fn drop(&mut self) {
+        // The thread exits on its own once it notices.
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn watch(
+    listener: OwnedFd,
+    syscalls: &[PathSyscall],
+    exposure: &Exposure,
+    stop: &AtomicBool,
+) -> BTreeSet<PathBuf> {
+    let mut undeclared = BTreeSet::new();
+    // Commands tend to look up the same paths over and over.
+    let mut seen = HashSet::new();
+
+    while !stop.load(Ordering::Relaxed) {
+        let mut pollfd = libc::pollfd {
+            fd: listener.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: We pass one valid pollfd.
+        let ready = unsafe { libc::poll(&mut pollfd, 1, POLL_INTERVAL.as_millis() as _) };
+        if ready < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            break;
+        }
+        if ready == 0 {
+            continue;
+        }
+        if pollfd.revents & libc::POLLIN == 0 {
+            // POLLHUP: everything that had the filter is gone.
+            break;
+        }
+
+        // SAFETY: The kernel requires this to be zeroed, and fills it in.
+        let mut notif = unsafe { mem::zeroed::<SeccompNotif>() };
+        // SAFETY: Valid fd and struct for this ioctl.
+        if unsafe {
+            libc::ioctl(
+                listener.as_raw_fd(),
+                SECCOMP_IOCTL_NOTIF_RECV as _,
+                &mut notif as *mut SeccompNotif,
+            )
+        } < 0
+        {
+            // The process may have been killed between poll and this, which is fine.
+            continue;
+        }
+
+        let path = resolve_path(&notif, syscalls, exposure);
+
+        // If the id isn't valid anymore, the pid might have been reused while we were reading, so
+        // whatever we read can't be trusted.
+        // SAFETY: Valid fd and pointer for those ioctls.
+        let valid = unsafe {
+            libc::ioctl(
+                listener.as_raw_fd(),
+                SECCOMP_IOCTL_NOTIF_ID_VALID as _,
+                &notif.id as *const u64,
+            )
+        } == 0;
+
+        let resp = SeccompNotifResp {
+            id: notif.id,
+            val: 0,
+            error: 0,
+            flags: SECCOMP_USER_NOTIF_FLAG_CONTINUE,
+        };
+        // SAFETY: Same as above. This fails if the process is gone, which is fine.
+        unsafe {
+            libc::ioctl(
+                listener.as_raw_fd(),
+                SECCOMP_IOCTL_NOTIF_SEND as _,
+                &resp as *const SeccompNotifResp,
+            )
+        };
+
+        if let (true, Some(path)) = (valid, path) {
+            if seen.len() >= MAX_SEEN_PATHS {
+                seen.clear();
+            }
+            if undeclared.len() < MAX_REPORTED_PATHS
+                && !seen.contains(&path)
+                && exposure.is_undeclared(&path)
+            {
+                undeclared.insert(path.clone());
+            }
+            seen.insert(path);
+        }
+    }
+
+    undeclared
+}
+
+fn resolve_path(
+    notif: &SeccompNotif,
+    syscalls: &[PathSyscall],
+    exposure: &Exposure,
+) -> Option<PathBuf> {
+    let syscall = syscalls
+        .iter()
+        .find(|s| s.nr == notif.data.nr as libc::c_long)?;
+
+    let mem = File::open(format!("/proc/{}/mem", notif.pid)).ok()?;
+    let path = read_c_string(&mem, notif.data.args[syscall.path])?;
+    if path.is_absolute() {
+        return Some(normalize(&path));
+    }
+    if path.as_os_str().is_empty() {
+        // `AT_EMPTY_PATH`, this is about the fd itself.
+        return None;
+    }
+
+    let base = match syscall.dirfd.map(|i| notif.data.args[i] as libc::c_int) {
+        Some(fd) if fd != libc::AT_FDCWD => format!("/proc/{}/fd/{}", notif.pid, fd),
+        _ => format!("/proc/{}/cwd", notif.pid),
+    };
+    let base = exposure.unsandboxed(fs::read_link(base).ok()?);
+    Some(normalize(&base.join(path)))
+}
+
+/// Read a NUL-terminated string out of another process's memory. This reads in chunks that don't
+/// cross page boundaries, since the next page might not be mapped.
+fn read_c_string(mem: &File, mut addr: u64) -> Option<PathBuf> {
+    const CHUNK: u64 = 256;
+    const PAGE: u64 = 4096;
+
+    let mut res = Vec::new();
+    let mut buf = [0u8; CHUNK as usize];
+    while res.len() < libc::PATH_MAX as usize {
+        let len = CHUNK.min(PAGE - addr % PAGE) as usize;
+        let read = mem.read_at(&mut buf[..len], addr).ok()?;
+        if read == 0 {
+            return None;
+        }
+        if let Some(end) = buf[..read].iter().position(|b| *b == 0) {
+            res.extend_from_slice(&buf[..end]);
+            return Some(PathBuf::from(OsString::from_vec(res)));
+        }
+        res.extend_from_slice(&buf[..read]);
+        addr += read as u64;
+    }
+    None
+}
+
+/// Lexically resolve `.` and `..`. Symlinks aren't followed, but this is what the command asked
+/// for, which is what we report.
+fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                res.pop();
+            }
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Async-signal-safe, since this runs in the child.
+unsafe fn send_fd(socket: RawFd, fd: RawFd) -> io::Result<()> {
+    #[repr(C, align(8))]
+    struct Control([u8; 64]);
+
+    unsafe {
+        let mut control = Control([0; 64]);
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr().cast(),
+            iov_len: byte.len(),
+        };
+
+        let mut msg = mem::zeroed::<libc::msghdr>();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.0.as_mut_ptr().cast();
+        msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        libc::CMSG_DATA(cmsg).cast::<RawFd>().write_unaligned(fd);
+
+        if libc::sendmsg(socket, &msg, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Returns `None` if the other end was closed without sending anything.
+fn recv_fd(socket: &UnixStream) -> io::Result<Option<OwnedFd>> {
+    #[repr(C, align(8))]
+    struct Control([u8; 64]);
+
+    // SAFETY: Everything passed to `recvmsg` outlives the call, and we only look at the control
+    // message if the kernel says there is one.
+    unsafe {
+        let mut control = Control([0; 64]);
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr().cast(),
+            iov_len: byte.len(),
+        };
+
+        let mut msg = mem::zeroed::<libc::msghdr>();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.0.as_mut_ptr().cast();
+        msg.msg_controllen = control.0.len() as _;
+
+        loop {
+            let received = libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC);
+            if received < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if received == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            return Ok(None);
+        }
+        let fd = libc::CMSG_DATA(cmsg).cast::<RawFd>().read_unaligned();
+        Ok(Some(OwnedFd::from_raw_fd(fd)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("/repo/./src/../buck-out/a")),
+            Path::new("/repo/buck-out/a")
+        );
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn test_is_undeclared() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let repo = tempdir.path().join("repo");
+        fs::create_dir_all(repo.join("src"))?;
+        fs::write(repo.join("src/a.c"), "")?;
+        fs::write(repo.join("src/b.c"), "")?;
+        fs::write(repo.join("BUCK"), "")?;
+
+        let exposure = Exposure {
+            sandbox_root: PathBuf::from("/sandbox"),
+            project_root: repo.clone(),
+            exposed: BTreeSet::from([repo.join("src/a.c"), repo.join("src-gen")]),
+            cwd: repo.clone(),
+        };
+
+        assert!(!exposure.is_undeclared(&repo.join("src/a.c")));
+        // Parents of what was exposed.
+        assert!(!exposure.is_undeclared(&repo));
+        assert!(!exposure.is_undeclared(&repo.join("src")));
+        // Not there on the host either.
+        assert!(!exposure.is_undeclared(&repo.join("src/c.c")));
+        // Outside of the project.
+        assert!(!exposure.is_undeclared(tempdir.path()));
+
+        assert!(exposure.is_undeclared(&repo.join("src/b.c")));
+        // Sharing a prefix with what was exposed doesn't make it a parent.
+        fs::write(repo.join("sr"), "")?;
+        assert!(exposure.is_undeclared(&repo.join("sr")));
+        // The working directory exists, but its contents weren't exposed.
+        assert!(exposure.is_undeclared(&repo.join("BUCK")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_kernel_version() {
+        assert_eq!(kernel_version("5.4.0-150-generic\n"), Some((5, 4)));
+        assert_eq!(kernel_version("6.18.44"), Some((6, 18)));
+        assert_eq!(kernel_version("garbage"), None);
+    }
+
+    #[test]
+    fn test_unsandboxed() {
+        let exposure = Exposure {
+            sandbox_root: PathBuf::from("/state/sandbox"),
+            project_root: PathBuf::from("/repo"),
+            exposed: BTreeSet::new(),
+            cwd: PathBuf::from("/repo"),
+        };
+        assert_eq!(
+            exposure.unsandboxed(PathBuf::from("/state/sandbox/repo/src")),
+            Path::new("/repo/src")
+        );
+        assert_eq!(
+            exposure.unsandboxed(PathBuf::from("/repo/src")),
+            Path::new("/repo/src")
+        );
+    }
+}
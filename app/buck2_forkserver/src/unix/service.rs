@@ -55,6 +55,8 @@ use crate::run::stream_command_events;
 use crate::run::timeout_into_cancellation;
 use crate::run::DefaultKillProcess;
 use crate::run::GatherOutputStatus;
+use crate::unix::sandbox::sandbox_command;
+use crate::unix::sandbox::SandboxStatusDecoder;
 
 // Not quite BoxStream: it has to be Sync (...)
 type RunStream =
@@ -68,6 +70,9 @@ pub struct UnixForkserverService {
 
     /// Systemd runner for resource control
     systemd_runner: Option<SystemdRunner>,
+
+    /// Where sandboxes get mounted, each in its command's own mount namespace.
+    sandbox_root: AbsNormPathBuf,
 }
 
 impl UnixForkserverService {
@@ -84,10 +89,13 @@ impl UnixForkserverService {
                 // for this we inherit slice
                 ParentSlice::Inherit("forkserver".to_owned()),
             ))?;
+        let sandbox_root = state_dir.join(ForwardRelativePath::unchecked_new("sandbox"));
+        fs_util::create_dir_all(&sandbox_root)?;
         Ok(Self {
             log_reload_handle,
             miniperf,
             systemd_runner,
+            sandbox_root,
         })
     }
 }
@@ -131,6 +139,7 @@ impl Forkserver for UnixForkserverService {
                 std_redirects,
                 graceful_shutdown_timeout_s,
                 action_digest,
+                sandbox,
             } = msg;
 
             let exe = OsStr::from_bytes(&exe);
@@ -144,7 +153,16 @@ impl Forkserver for UnixForkserverService {
                 .buck_error_context("Invalid timeout")?;
 
             let exe = maybe_absolutize_exe(exe, cwd)?;
-            let systemd_context = self.systemd_runner.as_ref().zip(action_digest);
+
+            // Neither Miniperf nor systemd are available in a sandbox that isolates the command.
+            // A trace-only sandbox leaves the host filesystem visible, so they work as usual.
+            let isolated = sandbox.as_ref().is_some_and(|s| !s.trace_only);
+            let enable_miniperf = enable_miniperf && !isolated;
+            let systemd_context = if isolated {
+                None
+            } else {
+                self.systemd_runner.as_ref().zip(action_digest)
+            };
 
             let (mut cmd, miniperf_output) =
                 match (enable_miniperf, &self.miniperf, &systemd_context) {
@@ -206,6 +224,11 @@ impl Forkserver for UnixForkserverService {
                 cmd.env("MINIPERF_READ_CGROUP", "1");
             }
 
+            let sandboxed = match sandbox {
+                Some(sandbox) => sandbox_command(&mut cmd, sandbox, cwd, &self.sandbox_root)?,
+                None => None,
+            };
+
             let stream_stdio = std_redirects.is_none();
             let mut cmd = ProcessCommand::new(cmd);
             if let Some(std_redirects) = std_redirects {
@@ -219,26 +242,46 @@ impl Forkserver for UnixForkserverService {
 
             let cancellation = select(timeout.boxed(), cancel.boxed()).map(|r| r.factor_first().0);
 
-            let stream = match miniperf_output {
-                Some(out) => stream_command_events(
+            let kill_process = DefaultKillProcess {
+                graceful_shutdown_timeout_s,
+            };
+
+            let stream = match (sandboxed, miniperf_output) {
+                (Some(sandboxed), Some(out)) => stream_command_events(
                     process_group,
                     cancellation,
-                    MiniperfStatusDecoder::new(out),
-                    DefaultKillProcess {
-                        graceful_shutdown_timeout_s,
-                    },
+                    SandboxStatusDecoder::new(MiniperfStatusDecoder::new(out), sandboxed),
+                    kill_process,
+                    stream_stdio,
+                )?
+                .left_stream()
+                .left_stream(),
+                (Some(sandboxed), None) => stream_command_events(
+                    process_group,
+                    cancellation,
+                    SandboxStatusDecoder::new(DefaultStatusDecoder, sandboxed),
+                    kill_process,
                     stream_stdio,
                 )?
+                .right_stream()
                 .left_stream(),
-                None => stream_command_events(
+                (None, Some(out)) => stream_command_events(
+                    process_group,
+                    cancellation,
+                    MiniperfStatusDecoder::new(out),
+                    kill_process,
+                    stream_stdio,
+                )?
+                .left_stream()
+                .right_stream(),
+                (None, None) => stream_command_events(
                     process_group,
                     cancellation,
                     DefaultStatusDecoder,
-                    DefaultKillProcess {
-                        graceful_shutdown_timeout_s,
-                    },
+                    kill_process,
                     stream_stdio,
                 )?
+                .right_stream()
                 .right_stream(),
             };
             let stream = encode_event_stream(stream);
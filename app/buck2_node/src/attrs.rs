@@ -29,6 +29,7 @@ pub mod id;
 pub mod inspect_options;
 pub mod internal;
 pub mod json;
+pub mod select_resolution;
 pub mod serialize;
 pub mod spec;
 pub mod testing;
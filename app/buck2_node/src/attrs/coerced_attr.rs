@@ -603,7 +603,7 @@ impl CoercedAttr {
         }
     }
 
-    fn select<'a>(
+    pub(crate) fn select<'a>(
         ctx: &dyn AttrConfigurationContext,
         select: &'a CoercedSelector,
     ) -> buck2_error::Result<&'a CoercedAttr> {
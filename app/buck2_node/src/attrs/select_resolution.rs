@@ -0,0 +1,178 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Records which branch of each `select()` an attribute took in some configuration, and why.
+
+use std::collections::BTreeMap;
+
+use buck2_core::configuration::config_setting::ConfigSettingData;
+use serde::Serialize;
+
+use crate::attrs::coerced_attr::CoercedAttr;
+use crate::attrs::coerced_attr::CoercedSelector;
+use crate::attrs::coerced_attr::CoercedSelectorKeyRef;
+use crate::attrs::configuration_context::AttrConfigurationContext;
+use crate::configuration::resolved::ConfigurationSettingKey;
+
+/// A select key that matched the configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SelectKeyMatch {
+    pub key: String,
+    /// The constraint settings the key requires, and the values the configuration has for them.
+    pub constraints: BTreeMap<String, String>,
+    /// The buckconfigs the key requires, and the values they are set to.
+    pub buckconfigs: BTreeMap<String, String>,
+}
+
+impl SelectKeyMatch {
+    fn new(key: &ConfigurationSettingKey, data: &ConfigSettingData) -> SelectKeyMatch {
+        SelectKeyMatch {
+            key: key.to_string(),
+            constraints: data
+                .constraints
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            buckconfigs: data.buckconfigs.clone(),
+        }
+    }
+}
+
+/// How one `select()` was resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SelectResolution {
+    /// The key whose value was used, `DEFAULT` if no key matched.
+    pub selected: String,
+    /// All the keys that matched. When there is more than one, the selected key is the most
+    /// specific of them.
+    pub matched: Vec<SelectKeyMatch>,
+}
+
+impl CoercedAttr {
+    /// Resolutions of the `select()`s this attribute evaluates in the given context, in the order
+    /// they are evaluated. Selects in branches that are not taken are not included, selects in
+    /// taken branches are.
+    pub fn select_resolutions(
+        &self,
+        ctx: &dyn AttrConfigurationContext,
+    ) -> buck2_error::Result<Vec<SelectResolution>> {
+        let mut resolutions = Vec::new();
+        self.collect_select_resolutions(ctx, &mut resolutions)?;
+        Ok(resolutions)
+    }
+
+    fn collect_select_resolutions(
+        &self,
+        ctx: &dyn AttrConfigurationContext,
+        resolutions: &mut Vec<SelectResolution>,
+    ) -> buck2_error::Result<()> {
+        match self {
+            CoercedAttr::Selector(select) => {
+                let value = Self::select(ctx, select)?;
+                resolutions.push(Self::select_resolution(ctx, select, value));
+                value.collect_select_resolutions(ctx, resolutions)
+            }
+            CoercedAttr::Concat(items) => items
+                .iter()
+                .try_for_each(|item| item.collect_select_resolutions(ctx, resolutions)),
+            CoercedAttr::List(list) => list
+                .iter()
+                .try_for_each(|item| item.collect_select_resolutions(ctx, resolutions)),
+            CoercedAttr::Tuple(tuple) => tuple
+                .iter()
+                .try_for_each(|item| item.collect_select_resolutions(ctx, resolutions)),
+            CoercedAttr::Dict(dict) => dict.iter().try_for_each(|(k, v)| {
+                k.collect_select_resolutions(ctx, resolutions)?;
+                v.collect_select_resolutions(ctx, resolutions)
+            }),
+            CoercedAttr::OneOf(box item, _) => item.collect_select_resolutions(ctx, resolutions),
+            _ => Ok(()),
+        }
+    }
+
+    fn select_resolution(
+        ctx: &dyn AttrConfigurationContext,
+        select: &CoercedSelector,
+        value: &CoercedAttr,
+    ) -> SelectResolution {
+        let resolved_cfg_settings = ctx.resolved_cfg_settings();
+        let matched = select
+            .entries
+            .iter()
+            .filter_map(|(k, _)| {
+                resolved_cfg_settings
+                    .setting_matches(k)
+                    .map(|data| SelectKeyMatch::new(k, data))
+            })
+            .collect();
+        // Compare by address: several keys may have equal values.
+        let selected = match select.entries.iter().find(|(_, v)| std::ptr::eq(v, value)) {
+            Some((k, _)) => k.to_string(),
+            None => CoercedSelectorKeyRef::DEFAULT_KEY_STR.to_owned(),
+        };
+        SelectResolution { selected, matched }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_util::arc_str::ArcSlice;
+
+    use crate::attrs::attr_type::list::ListLiteral;
+    use crate::attrs::coerced_attr::CoercedAttr;
+    use crate::attrs::coerced_attr::CoercedSelector;
+    use crate::attrs::testing::configuration_ctx;
+    use crate::configuration::resolved::ConfigurationSettingKey;
+
+    fn select(entries: &[(&str, CoercedAttr)], default: Option<CoercedAttr>) -> CoercedAttr {
+        CoercedAttr::Selector(Box::new(
+            CoercedSelector::new(
+                ArcSlice::from_iter(
+                    entries
+                        .iter()
+                        .map(|(k, v)| (ConfigurationSettingKey::testing_parse(k), v.clone())),
+                ),
+                default,
+            )
+            .unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_select_resolutions() {
+        // `root//other:config` matches the testing configuration, `root//some:config` doesn't.
+        let inner = select(&[("root//other:config", CoercedAttr::Int(1))], None);
+        let attr = CoercedAttr::List(ListLiteral(ArcSlice::new([
+            select(&[("root//other:config", inner)], None),
+            select(
+                &[("root//some:config", CoercedAttr::Int(2))],
+                Some(CoercedAttr::Int(3)),
+            ),
+        ])));
+
+        let resolutions = attr.select_resolutions(&configuration_ctx()).unwrap();
+        let resolutions: Vec<_> = resolutions
+            .iter()
+            .map(|r| {
+                (
+                    r.selected.as_str(),
+                    r.matched.iter().map(|m| m.key.as_str()).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("root//other:config", vec!["root//other:config"]),
+                ("root//other:config", vec!["root//other:config"]),
+                ("DEFAULT", vec![]),
+            ],
+            resolutions
+        );
+    }
+}
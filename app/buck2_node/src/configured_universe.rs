@@ -85,13 +85,22 @@ impl<'a> CqueryUniverseInner<'a> {
 
     fn build_inner(
         universe: &'a TargetSet<ConfiguredTargetNode>,
+        mut charge: impl FnMut() -> buck2_error::Result<()>,
     ) -> buck2_error::Result<CqueryUniverseInner<'a>> {
         let mut targets: BTreeMap<
             PackageLabel,
             BTreeMap<&TargetNameRef, BTreeSet<LabelIndexed<ConfiguredTargetNodeRef>>>,
         > = BTreeMap::new();
+        let mut error = None;
 
         configured_node_visit_all_deps(universe.iter().map(|t| t.as_ref()), |target| {
+            if error.is_some() {
+                return;
+            }
+            if let Err(e) = charge() {
+                error = Some(e);
+                return;
+            }
             let label = target.label();
             let package_targets: &mut _ = targets
                 .entry(label.pkg().dupe())
@@ -108,6 +117,9 @@ impl<'a> CqueryUniverseInner<'a> {
             assert!(inserted, "Visited targets must be unique");
         });
 
+        if let Some(e) = error {
+            return Err(e);
+        }
         Ok(CqueryUniverseInner::new(targets))
     }
 }
@@ -132,10 +144,19 @@ impl CqueryUniverse {
 
     pub fn build(
         universe: &TargetSet<ConfiguredTargetNode>,
+    ) -> buck2_error::Result<CqueryUniverse> {
+        Self::build_charged(universe, || Ok(()))
+    }
+
+    /// Like `build`, but calls `charge` for every target added to the universe, and fails as soon
+    /// as it does.
+    pub fn build_charged(
+        universe: &TargetSet<ConfiguredTargetNode>,
+        charge: impl FnMut() -> buck2_error::Result<()>,
     ) -> buck2_error::Result<CqueryUniverse> {
         span(buck2_data::CqueryUniverseBuildStart {}, || {
             let r = SelfRef::try_new(universe.clone(), |universe| {
-                CqueryUniverseInner::build_inner(universe)
+                CqueryUniverseInner::build_inner(universe, charge)
             })
             .map(|data| CqueryUniverse { data });
             (r, buck2_data::CqueryUniverseBuildEnd {})
@@ -381,4 +402,40 @@ mod tests {
             )))
         );
     }
+
+    #[test]
+    fn test_build_charged() {
+        let nodes = TargetSet::from_iter(["foo//bar:a", "foo//bar:b"].map(|label| {
+            ConfiguredTargetNode::testing_new(
+                ConfiguredTargetLabel::testing_parse(label, ConfigurationData::testing_new()),
+                "idris_library",
+                ExecutionPlatformResolution::new(None, Vec::new()),
+                vec![],
+                vec![],
+                None,
+            )
+        }));
+
+        let mut charged = 0;
+        let universe = CqueryUniverse::build_charged(&nodes, || {
+            charged += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!((charged, universe.len()), (2, 2));
+
+        let mut charged = 0;
+        let res = CqueryUniverse::build_charged(&nodes, || {
+            charged += 1;
+            if charged > 1 {
+                return Err(buck2_error::buck2_error!(
+                    buck2_error::ErrorTag::Input,
+                    "over budget"
+                ));
+            }
+            Ok(())
+        });
+        assert!(res.is_err());
+        assert_eq!(charged, 2);
+    }
 }
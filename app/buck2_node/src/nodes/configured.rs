@@ -55,6 +55,7 @@ use crate::attrs::configured_attr_full::ConfiguredAttrFull;
 use crate::attrs::configured_traversal::ConfiguredAttrTraversal;
 use crate::attrs::inspect_options::AttrInspectOptions;
 use crate::attrs::internal::TESTS_ATTRIBUTE_FIELD;
+use crate::attrs::select_resolution::SelectResolution;
 use crate::bzl_or_bxl_path::BzlOrBxlPath;
 use crate::call_stack::StarlarkCallStack;
 use crate::call_stack::StarlarkTargetCallStackRoot;
@@ -483,6 +484,13 @@ impl ConfiguredTargetNode {
         self.as_ref().get(attr, opts)
     }
 
+    pub fn select_resolutions(
+        &self,
+        opts: AttrInspectOptions,
+    ) -> buck2_error::Result<Vec<(&str, Vec<SelectResolution>)>> {
+        self.as_ref().select_resolutions(opts)
+    }
+
     pub fn call_stack(&self) -> Option<String> {
         match &self.0.target_node {
             TargetNodeOrForward::TargetNode(n) => n.call_stack(),
@@ -733,6 +741,23 @@ impl<'a> ConfiguredTargetNodeRef<'a> {
         })
     }
 
+    /// How the `select()`s of each attribute were resolved in this target's configuration.
+    /// Attributes without selects are omitted.
+    pub fn select_resolutions(
+        self,
+        opts: AttrInspectOptions,
+    ) -> buck2_error::Result<Vec<(&'a str, Vec<SelectResolution>)>> {
+        let ctx = self.attr_configuration_context();
+        let mut resolutions = Vec::new();
+        for a in self.0.get().target_node.attrs(opts) {
+            let attr_resolutions = a.value.select_resolutions(&ctx)?;
+            if !attr_resolutions.is_empty() {
+                resolutions.push((a.name, attr_resolutions));
+            }
+        }
+        Ok(resolutions)
+    }
+
     pub fn get(self, attr: &str, opts: AttrInspectOptions) -> Option<ConfiguredAttrFull<'a>> {
         self.0.get().target_node.attr_or_none(attr, opts).map(|v| {
             v.configure(&self.attr_configuration_context())
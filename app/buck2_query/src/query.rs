@@ -10,5 +10,6 @@
 pub mod buck_types;
 pub mod environment;
 pub mod graph;
+pub mod snapshot;
 pub mod syntax;
 pub mod traversal;
@@ -21,11 +21,14 @@ use dupe::Dupe;
 use dupe::OptionDupedExt;
 use futures::stream::FuturesUnordered;
 use futures::stream::TryStreamExt;
+use starlark_map::unordered_map::UnorderedMap;
 
 use crate::query::graph::async_bfs::async_bfs_find_path;
 use crate::query::graph::graph::Graph;
 use crate::query::graph::node::LabeledNode;
 use crate::query::graph::node::NodeKey;
+use crate::query::graph::paths::PathOrder;
+use crate::query::graph::paths::PathsCycle;
 use crate::query::graph::successors::AsyncChildVisitor;
 use crate::query::graph::successors::GraphSuccessors;
 use crate::query::syntax::simple::eval::error::QueryError;
@@ -207,6 +210,63 @@ pub trait QueryEnvironment: Send + Sync {
         Ok(target_set)
     }
 
+    /// The nodes of the `k` best paths from `from` to `to`, in order from top to bottom, best
+    /// path first.
+    async fn best_paths(
+        &self,
+        from: &TargetSet<Self::Target>,
+        to: &TargetSet<Self::Target>,
+        filter: Option<&dyn TraversalFilter<Self::Target>>,
+        weight: &(dyn Fn(&Self::Target) -> buck2_error::Result<u64> + Send + Sync),
+        k: usize,
+        order: PathOrder,
+    ) -> buck2_error::Result<Vec<Vec<Self::Target>>> {
+        let graph = Graph::build_stable_dfs(
+            &QueryEnvironmentAsNodeLookup { env: self },
+            from.iter().map(|n| n.node_key().clone()),
+            QueryTargetFilteredDepsSuccesors { filter },
+        )
+        .await?;
+
+        let weights = graph
+            .nodes()
+            .map(|t| Ok((t.node_key().clone(), weight(t)?)))
+            .collect::<buck2_error::Result<UnorderedMap<_, _>>>()?;
+        let paths = graph
+            .best_paths(
+                from.iter().map(|n| n.node_key().clone()),
+                |t| to.contains(t.node_key()),
+                |t| weights[t.node_key()],
+                k,
+                order,
+            )
+            .map_err(|PathsCycle(t)| QueryError::PathsThroughCycle(t.node_key().to_string()))?;
+        Ok(paths
+            .into_iter()
+            .map(|path| path.nodes.into_iter().cloned().collect())
+            .collect())
+    }
+
+    async fn paths_count(
+        &self,
+        from: &TargetSet<Self::Target>,
+        to: &TargetSet<Self::Target>,
+        filter: Option<&dyn TraversalFilter<Self::Target>>,
+    ) -> buck2_error::Result<u64> {
+        let graph = Graph::build_stable_dfs(
+            &QueryEnvironmentAsNodeLookup { env: self },
+            from.iter().map(|n| n.node_key().clone()),
+            QueryTargetFilteredDepsSuccesors { filter },
+        )
+        .await?;
+
+        Ok(graph
+            .count_paths(from.iter().map(|n| n.node_key().clone()), |t| {
+                to.contains(t.node_key())
+            })
+            .map_err(|PathsCycle(t)| QueryError::PathsThroughCycle(t.node_key().to_string()))?)
+    }
+
     async fn allbuildfiles(
         &self,
         _universe: &TargetSet<Self::Target>,
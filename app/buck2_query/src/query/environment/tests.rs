@@ -427,3 +427,54 @@ async fn test_rdeps() -> buck2_error::Result<()> {
 
     Ok(())
 }
+
+/// Follows every dep except the ones to `skip`.
+struct SkipFilter<'a> {
+    env: &'a TestEnv,
+    skip: u64,
+}
+
+#[async_trait]
+impl<'a> TraversalFilter<TestTarget> for SkipFilter<'a> {
+    async fn get_children(
+        &self,
+        target: &TestTarget,
+    ) -> buck2_error::Result<TargetSet<TestTarget>> {
+        let mut children = TargetSet::new();
+        for dep in target.deps.iter() {
+            if dep.0 != self.skip {
+                children.insert(<TestEnv as NodeLookup<TestTarget>>::get(self.env, dep)?);
+            }
+        }
+        Ok(children)
+    }
+}
+
+#[tokio::test]
+async fn test_filtered_paths() -> buck2_error::Result<()> {
+    let mut env = TestEnvBuilder::default();
+    env.edge(1, 2);
+    env.edge(2, 4);
+    env.edge(1, 3);
+    env.edge(3, 4);
+    let env = env.build();
+    let filter = SkipFilter { env: &env, skip: 2 };
+
+    let path = env
+        .allpaths(&env.set("1")?, &env.set("4")?, Some(&filter))
+        .await?;
+    assert_eq!(path, env.set("1,3,4")?);
+
+    let path = env
+        .somepath(&env.set("1")?, &env.set("4")?, Some(&filter))
+        .await?;
+    assert_eq!(path, env.set("1,3,4")?);
+
+    let filter = SkipFilter { env: &env, skip: 4 };
+    let path = env
+        .somepath(&env.set("1")?, &env.set("4")?, Some(&filter))
+        .await?;
+    assert_eq!(path, TargetSet::new());
+
+    Ok(())
+}
@@ -12,6 +12,7 @@ pub mod bfs;
 pub mod dfs;
 pub(crate) mod graph;
 pub mod node;
+pub mod paths;
 pub mod successors;
 pub(crate) mod vec_as_map;
 pub(crate) mod vec_as_set;
@@ -18,6 +18,11 @@ use starlark_map::Hashed;
 use crate::query::graph::dfs::dfs_postorder_impl;
 use crate::query::graph::dfs::dfs_preorder;
 use crate::query::graph::node::LabeledNode;
+use crate::query::graph::paths::best_paths;
+use crate::query::graph::paths::count_paths;
+use crate::query::graph::paths::PathOrder;
+use crate::query::graph::paths::PathsCycle;
+use crate::query::graph::paths::WeightedPath;
 use crate::query::graph::successors::AsyncChildVisitor;
 use crate::query::graph::successors::GraphSuccessors;
 use crate::query::graph::vec_as_map::VecAsMap;
@@ -45,6 +50,10 @@ impl<N: LabeledNode> Graph<N> {
             .get(node)
             .map(|index| &self.nodes[*index as usize].node)
     }
+
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &N> {
+        self.nodes.iter().map(|node| &node.node)
+    }
 }
 
 struct GraphBuilder<N: LabeledNode> {
@@ -300,6 +309,48 @@ impl<T: LabeledNode> Graph<T> {
         )
     }
 
+    /// Number of distinct paths from the roots to the nodes matching `is_target`.
+    pub(crate) fn count_paths(
+        &self,
+        roots: impl IntoIterator<Item = T::Key>,
+        is_target: impl Fn(&T) -> bool,
+    ) -> Result<u64, PathsCycle<&T>> {
+        count_paths(
+            roots.into_iter().map(|root| self.node_to_index[&root]),
+            GraphSuccessorsImpl { graph: self },
+            |index| is_target(&self.nodes[*index as usize].node),
+        )
+        .map_err(|PathsCycle(index)| PathsCycle(&self.nodes[index as usize].node))
+    }
+
+    /// The `k` best paths from the roots to the nodes matching `is_target`.
+    pub(crate) fn best_paths(
+        &self,
+        roots: impl IntoIterator<Item = T::Key>,
+        is_target: impl Fn(&T) -> bool,
+        weight: impl Fn(&T) -> u64,
+        k: usize,
+        order: PathOrder,
+    ) -> Result<Vec<WeightedPath<&T>>, PathsCycle<&T>> {
+        let node = |index: u32| &self.nodes[index as usize].node;
+        let paths = best_paths(
+            roots.into_iter().map(|root| self.node_to_index[&root]),
+            GraphSuccessorsImpl { graph: self },
+            |index| is_target(node(*index)),
+            |index| weight(node(*index)),
+            k,
+            order,
+        )
+        .map_err(|PathsCycle(index)| PathsCycle(node(index)))?;
+        Ok(paths
+            .into_iter()
+            .map(|path| WeightedPath {
+                weight: path.weight,
+                nodes: path.nodes.into_iter().map(node).collect(),
+            })
+            .collect())
+    }
+
     /// Create a graph from the given roots up to the given max depth.
     ///
     /// Zero depth means only the roots.
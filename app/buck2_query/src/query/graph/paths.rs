@@ -0,0 +1,299 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Counting and ranking the paths between nodes.
+//!
+//! Both work by dynamic programming over the nodes in postorder, so the number of paths never
+//! needs to be materialized. That only works for acyclic graphs: a cycle on a path that ends at a
+//! target is reported as an error. Cycles elsewhere are ignored.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::query::graph::successors::GraphSuccessors;
+
+/// A node that is part of a cycle on a path from the roots to a target.
+#[derive(Debug, PartialEq)]
+pub struct PathsCycle<N>(pub N);
+
+/// Which paths are preferred by [`best_paths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathOrder {
+    Lightest,
+    Heaviest,
+}
+
+/// A path and the sum of the weights of its nodes.
+#[derive(Debug, PartialEq)]
+pub struct WeightedPath<N> {
+    pub weight: u64,
+    pub nodes: Vec<N>,
+}
+
+enum VisitState {
+    Visiting,
+    Done,
+}
+
+/// The nodes reachable from `roots` that can reach a node for which `is_target` is true. Paths
+/// only go through those.
+fn nodes_on_paths<N: Copy + Eq + Hash>(
+    roots: &[N],
+    successors: &impl GraphSuccessors<N>,
+    is_target: impl Fn(&N) -> bool,
+) -> HashSet<N> {
+    let mut parents: HashMap<N, Vec<N>> = HashMap::new();
+    let mut reached: HashSet<N> = roots.iter().copied().collect();
+    let mut stack = roots.to_vec();
+    let mut targets = Vec::new();
+    while let Some(node) = stack.pop() {
+        if is_target(&node) {
+            targets.push(node);
+        }
+        successors.for_each_successor(&node, |child| {
+            parents.entry(*child).or_default().push(node);
+            if reached.insert(*child) {
+                stack.push(*child);
+            }
+        });
+    }
+
+    let mut on_paths: HashSet<N> = targets.iter().copied().collect();
+    while let Some(node) = targets.pop() {
+        for parent in parents.get(&node).into_iter().flatten() {
+            if on_paths.insert(*parent) {
+                targets.push(*parent);
+            }
+        }
+    }
+    on_paths
+}
+
+/// The nodes of `nodes` reachable from `roots` without leaving `nodes`, children before parents.
+fn acyclic_postorder<N: Copy + Eq + Hash>(
+    roots: &[N],
+    nodes: &HashSet<N>,
+    successors: &impl GraphSuccessors<N>,
+) -> Result<Vec<N>, PathsCycle<N>> {
+    let children = |node: &N| {
+        let mut children = Vec::new();
+        successors.for_each_successor(node, |c| {
+            if nodes.contains(c) {
+                children.push(*c);
+            }
+        });
+        // Popped from the back, so reverse to visit in order.
+        children.reverse();
+        children
+    };
+
+    let mut state: HashMap<N, VisitState> = HashMap::new();
+    let mut order = Vec::new();
+    for &root in roots {
+        if !nodes.contains(&root) || state.contains_key(&root) {
+            continue;
+        }
+        state.insert(root, VisitState::Visiting);
+        let mut stack = vec![(root, children(&root))];
+        while let Some((node, remaining)) = stack.last_mut() {
+            let node = *node;
+            match remaining.pop() {
+                Some(child) => match state.get(&child) {
+                    Some(VisitState::Visiting) => return Err(PathsCycle(child)),
+                    Some(VisitState::Done) => {}
+                    None => {
+                        state.insert(child, VisitState::Visiting);
+                        stack.push((child, children(&child)));
+                    }
+                },
+                None => {
+                    state.insert(node, VisitState::Done);
+                    order.push(node);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    Ok(order)
+}
+
+fn dedup_roots<N: Copy + Eq + Hash>(roots: impl IntoIterator<Item = N>) -> Vec<N> {
+    let mut seen = HashSet::new();
+    roots
+        .into_iter()
+        .filter(|root| seen.insert(*root))
+        .collect()
+}
+
+/// Count the distinct paths that start at one of `roots` and end at a node for which `is_target`
+/// is true. Saturates at `u64::MAX`.
+pub fn count_paths<N: Copy + Eq + Hash>(
+    roots: impl IntoIterator<Item = N>,
+    successors: impl GraphSuccessors<N>,
+    is_target: impl Fn(&N) -> bool,
+) -> Result<u64, PathsCycle<N>> {
+    let roots = dedup_roots(roots);
+    let nodes = nodes_on_paths(&roots, &successors, &is_target);
+    let order = acyclic_postorder(&roots, &nodes, &successors)?;
+
+    let mut counts: HashMap<N, u64> = HashMap::with_capacity(order.len());
+    for node in order {
+        let mut count = u64::from(is_target(&node));
+        successors.for_each_successor(&node, |child| {
+            count = count.saturating_add(counts.get(child).copied().unwrap_or_default());
+        });
+        counts.insert(node, count);
+    }
+
+    Ok(roots.iter().fold(0u64, |total, root| {
+        total.saturating_add(counts.get(root).copied().unwrap_or_default())
+    }))
+}
+
+/// The `k` lightest or heaviest paths that start at one of `roots` and end at a node for which
+/// `is_target` is true, best first. The weight of a path is the sum of the weights of its nodes,
+/// so with a weight of 1 for every node, the lightest paths are the shortest ones.
+pub fn best_paths<N: Copy + Eq + Hash>(
+    roots: impl IntoIterator<Item = N>,
+    successors: impl GraphSuccessors<N>,
+    is_target: impl Fn(&N) -> bool,
+    weight: impl Fn(&N) -> u64,
+    k: usize,
+    order: PathOrder,
+) -> Result<Vec<WeightedPath<N>>, PathsCycle<N>> {
+    /// The continuation of a path: the child it continues at, and which of the best paths of
+    /// that child. `None` if the path ends here.
+    type Next<N> = Option<(N, usize)>;
+
+    let sort = |candidates: &mut Vec<(u64, Next<N>)>| {
+        match order {
+            PathOrder::Lightest => candidates.sort_by_key(|(w, _)| *w),
+            PathOrder::Heaviest => candidates.sort_by_key(|(w, _)| std::cmp::Reverse(*w)),
+        }
+        candidates.truncate(k);
+    };
+
+    let roots = dedup_roots(roots);
+    let nodes = nodes_on_paths(&roots, &successors, &is_target);
+    let postorder = acyclic_postorder(&roots, &nodes, &successors)?;
+
+    // For every node, the `k` best paths starting at that node.
+    let mut best: HashMap<N, Vec<(u64, Next<N>)>> = HashMap::with_capacity(postorder.len());
+    for node in postorder {
+        let w = weight(&node);
+        let mut candidates = Vec::new();
+        if is_target(&node) {
+            candidates.push((w, None));
+        }
+        successors.for_each_successor(&node, |child| {
+            let Some(child_best) = best.get(child) else {
+                return;
+            };
+            for (i, (child_weight, _)) in child_best.iter().enumerate() {
+                candidates.push((w.saturating_add(*child_weight), Some((*child, i))));
+            }
+        });
+        sort(&mut candidates);
+        best.insert(node, candidates);
+    }
+
+    let mut candidates = Vec::new();
+    for root in roots {
+        for (i, (w, _)) in best.get(&root).into_iter().flatten().enumerate() {
+            candidates.push((*w, Some((root, i))));
+        }
+    }
+    sort(&mut candidates);
+
+    Ok(candidates
+        .into_iter()
+        .map(|(weight, mut next)| {
+            let mut nodes = Vec::new();
+            while let Some((node, i)) = next {
+                nodes.push(node);
+                next = best[&node][i].1;
+            }
+            WeightedPath { weight, nodes }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Edges(Vec<(u32, u32)>);
+
+    impl GraphSuccessors<u32> for &Edges {
+        fn for_each_successor(&self, node: &u32, mut cb: impl FnMut(&u32)) {
+            for (from, to) in &self.0 {
+                if from == node {
+                    cb(to);
+                }
+            }
+        }
+    }
+
+    /// Two routes from 0 to 4: a short one through 1 and a long one through 2 and 3.
+    fn diamond() -> Edges {
+        Edges(vec![(0, 1), (1, 4), (0, 2), (2, 3), (3, 4)])
+    }
+
+    #[test]
+    fn test_count_paths() {
+        let edges = diamond();
+        assert_eq!(Ok(2), count_paths([0], &edges, |n| *n == 4));
+        // The path ending at 1 counts as well as the ones going on to 4.
+        assert_eq!(Ok(3), count_paths([0], &edges, |n| *n == 4 || *n == 1));
+        assert_eq!(Ok(0), count_paths([4], &edges, |n| *n == 0));
+
+        let cycle = Edges(vec![(0, 1), (1, 0)]);
+        assert_eq!(Err(PathsCycle(0)), count_paths([0], &cycle, |n| *n == 1));
+        // A cycle that can't reach the target doesn't matter.
+        let side_cycle = Edges(vec![(0, 1), (0, 2), (2, 3), (3, 2)]);
+        assert_eq!(Ok(1), count_paths([0, 0], &side_cycle, |n| *n == 1));
+    }
+
+    #[test]
+    fn test_best_paths() {
+        let edges = diamond();
+        let shortest = best_paths([0], &edges, |n| *n == 4, |_| 1, 2, PathOrder::Lightest);
+        assert_eq!(
+            Ok(vec![
+                WeightedPath {
+                    weight: 3,
+                    nodes: vec![0, 1, 4]
+                },
+                WeightedPath {
+                    weight: 4,
+                    nodes: vec![0, 2, 3, 4]
+                },
+            ]),
+            shortest
+        );
+
+        // Node 1 is heavy enough to make the short route the heaviest.
+        let heaviest = best_paths(
+            [0],
+            &edges,
+            |n| *n == 4,
+            |n| if *n == 1 { 10 } else { 1 },
+            1,
+            PathOrder::Heaviest,
+        );
+        assert_eq!(
+            Ok(vec![WeightedPath {
+                weight: 12,
+                nodes: vec![0, 1, 4]
+            }]),
+            heaviest
+        );
+    }
+}
@@ -0,0 +1,703 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A read-only query environment over a snapshot of the unconfigured target graph.
+//!
+//! A snapshot is written by `buck2 targets --streaming --snapshot`: a header line followed by
+//! one JSON line per target. It records everything uquery functions look at, so queries can
+//! be answered from it later without a daemon or a checkout of the repository.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use buck2_core::build_file_path::BuildFilePath;
+use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::paths::CellRelativePath;
+use buck2_core::cells::paths::CellRelativePathBuf;
+use buck2_core::configuration::compatibility::MaybeCompatible;
+use buck2_core::fs::paths::file_name::FileNameBuf;
+use buck2_core::package::PackageLabel;
+use buck2_core::pattern::pattern::lex_target_pattern;
+use buck2_core::pattern::pattern::PatternData;
+use buck2_core::pattern::pattern_type::TargetPatternExtra;
+use buck2_core::target::label::label::TargetLabel;
+use buck2_error::BuckErrorContext;
+use dupe::Dupe;
+use indexmap::IndexSet;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::query::environment::QueryEnvironment;
+use crate::query::environment::QueryEnvironmentAsNodeLookup;
+use crate::query::environment::QueryEnvironmentError;
+use crate::query::environment::QueryTarget;
+use crate::query::graph::node::LabeledNode;
+use crate::query::graph::successors::AsyncChildVisitor;
+use crate::query::syntax::simple::eval::error::QueryError;
+use crate::query::syntax::simple::eval::file_set::FileNode;
+use crate::query::syntax::simple::eval::file_set::FileSet;
+use crate::query::syntax::simple::eval::set::TargetSet;
+use crate::query::traversal::async_depth_first_postorder_traversal;
+use crate::query::traversal::async_depth_limited_traversal;
+
+/// Version of the snapshot format, bumped on incompatible changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(buck2_error::Error, Debug)]
+#[buck2(input)]
+enum SnapshotError {
+    #[error("Snapshot is empty")]
+    Empty,
+    #[error("Not a target graph snapshot, expecting it to start with a `buck.snapshot` header")]
+    MissingHeader,
+    #[error("Snapshot version {0} is not supported, expecting version {SNAPSHOT_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Expecting a fully qualified target label, got `{0}`")]
+    InvalidLabel(String),
+    #[error("Expecting a path of the form `cell//path`, got `{0}`")]
+    InvalidPath(String),
+}
+
+/// The first line of a snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    #[serde(rename = "buck.snapshot")]
+    pub version: u32,
+    /// Cell that patterns without a cell, like `//foo:bar`, refer to.
+    pub root_cell: String,
+}
+
+/// A target, as written to a snapshot.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnapshotTarget {
+    pub label: String,
+    #[serde(rename = "type")]
+    pub rule_type: String,
+    /// File name of the build file in the target's package.
+    pub buildfile: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oncall: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub target_deps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exec_deps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub toolchain_deps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configuration_deps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub attrs: serde_json::Map<String, serde_json::Value>,
+}
+
+fn parse_label(label: &str) -> buck2_error::Result<TargetLabel> {
+    let parts = lex_target_pattern::<TargetPatternExtra>(label, false)?;
+    match (parts.cell_alias, parts.pattern.reject_ambiguity()?) {
+        (
+            Some(cell),
+            PatternData::TargetInPackage {
+                package,
+                target_name,
+                extra: TargetPatternExtra,
+            },
+        ) if !cell.is_empty() => Ok(TargetLabel::new(
+            PackageLabel::new(
+                CellName::unchecked_new(cell)?,
+                CellRelativePath::new(package),
+            ),
+            target_name.as_ref(),
+        )),
+        _ => Err(SnapshotError::InvalidLabel(label.to_owned()).into()),
+    }
+}
+
+fn parse_cell_path(path: &str) -> buck2_error::Result<CellPath> {
+    let (cell, rel) = path
+        .split_once("//")
+        .ok_or_else(|| SnapshotError::InvalidPath(path.to_owned()))?;
+    Ok(CellPath::new(
+        CellName::unchecked_new(cell)?,
+        CellRelativePathBuf::try_from(rel.to_owned())?,
+    ))
+}
+
+fn parse_labels(labels: &[String]) -> buck2_error::Result<Vec<TargetLabel>> {
+    labels.iter().map(|l| parse_label(l)).collect()
+}
+
+#[derive(Debug)]
+struct SnapshotNodeData {
+    label: TargetLabel,
+    rule_type: String,
+    buildfile_path: BuildFilePath,
+    deps: Vec<TargetLabel>,
+    target_deps: Vec<TargetLabel>,
+    exec_deps: Vec<TargetLabel>,
+    toolchain_deps: Vec<TargetLabel>,
+    configuration_deps: Vec<TargetLabel>,
+    tests: Vec<TargetLabel>,
+    inputs: Vec<CellPath>,
+    special_attrs: Vec<(&'static str, serde_json::Value)>,
+    attrs: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A target read from a snapshot.
+#[derive(Debug, Clone, Dupe)]
+pub struct SnapshotNode(Arc<SnapshotNodeData>);
+
+impl SnapshotNode {
+    fn new(target: SnapshotTarget) -> buck2_error::Result<SnapshotNode> {
+        let label = parse_label(&target.label)?;
+        let buildfile_path =
+            BuildFilePath::new(label.pkg(), FileNameBuf::try_from(target.buildfile)?);
+        let special_attrs = vec![
+            (
+                "buck.type",
+                serde_json::Value::from(target.rule_type.as_str()),
+            ),
+            (
+                "buck.configuration_deps",
+                serde_json::Value::from(target.configuration_deps.clone()),
+            ),
+            ("buck.deps", serde_json::Value::from(target.deps.clone())),
+            (
+                "buck.package",
+                serde_json::Value::from(buildfile_path.to_string()),
+            ),
+            ("buck.oncall", serde_json::Value::from(target.oncall)),
+        ];
+        Ok(SnapshotNode(Arc::new(SnapshotNodeData {
+            deps: parse_labels(&target.deps)?,
+            target_deps: parse_labels(&target.target_deps)?,
+            exec_deps: parse_labels(&target.exec_deps)?,
+            toolchain_deps: parse_labels(&target.toolchain_deps)?,
+            configuration_deps: parse_labels(&target.configuration_deps)?,
+            tests: parse_labels(&target.tests)?,
+            inputs: target
+                .inputs
+                .iter()
+                .map(|p| parse_cell_path(p))
+                .collect::<buck2_error::Result<_>>()?,
+            label,
+            rule_type: target.rule_type,
+            buildfile_path,
+            special_attrs,
+            attrs: target.attrs,
+        })))
+    }
+}
+
+impl LabeledNode for SnapshotNode {
+    type Key = TargetLabel;
+
+    fn node_key(&self) -> &Self::Key {
+        &self.0.label
+    }
+}
+
+impl QueryTarget for SnapshotNode {
+    type Attr<'a> = serde_json::Value;
+
+    fn inputs_for_each<E, F: FnMut(CellPath) -> Result<(), E>>(
+        &self,
+        mut func: F,
+    ) -> Result<(), E> {
+        for input in &self.0.inputs {
+            func(input.clone())?;
+        }
+        Ok(())
+    }
+
+    fn rule_type(&self) -> Cow<str> {
+        Cow::Borrowed(&self.0.rule_type)
+    }
+
+    fn name(&self) -> Cow<str> {
+        Cow::Borrowed(self.0.label.name().as_str())
+    }
+
+    fn buildfile_path(&self) -> &BuildFilePath {
+        &self.0.buildfile_path
+    }
+
+    fn deps<'a>(&'a self) -> impl Iterator<Item = &'a Self::Key> + Send + 'a {
+        self.0.deps.iter()
+    }
+
+    fn exec_deps<'a>(&'a self) -> impl Iterator<Item = &'a Self::Key> + Send + 'a {
+        self.0.exec_deps.iter()
+    }
+
+    fn target_deps<'a>(&'a self) -> impl Iterator<Item = &'a Self::Key> + Send + 'a {
+        self.0.target_deps.iter()
+    }
+
+    fn configuration_deps<'a>(&'a self) -> impl Iterator<Item = &'a Self::Key> + Send + 'a {
+        self.0.configuration_deps.iter()
+    }
+
+    fn toolchain_deps<'a>(&'a self) -> impl Iterator<Item = &'a Self::Key> + Send + 'a {
+        self.0.toolchain_deps.iter()
+    }
+
+    fn tests<'a>(&'a self) -> Option<impl Iterator<Item = Self::Key> + Send + 'a> {
+        Some(self.0.tests.iter().map(|t| t.dupe()))
+    }
+
+    fn attr_any_matches(
+        attr: &Self::Attr<'_>,
+        filter: &dyn Fn(&str) -> buck2_error::Result<bool>,
+    ) -> buck2_error::Result<bool> {
+        match attr {
+            serde_json::Value::Null => Ok(false),
+            serde_json::Value::Bool(b) => Ok(filter(if *b { "True" } else { "False" })?
+                || filter(if *b { "true" } else { "false" })?),
+            serde_json::Value::Number(n) => filter(&n.to_string()),
+            serde_json::Value::String(s) => filter(s),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    if Self::attr_any_matches(item, filter)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            serde_json::Value::Object(entries) => {
+                for (k, v) in entries {
+                    if filter(k)? || Self::attr_any_matches(v, filter)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    fn special_attrs_for_each<E, F: FnMut(&str, &Self::Attr<'_>) -> Result<(), E>>(
+        &self,
+        mut func: F,
+    ) -> Result<(), E> {
+        for (name, attr) in &self.0.special_attrs {
+            func(name, attr)?;
+        }
+        Ok(())
+    }
+
+    fn attrs_for_each<E, F: FnMut(&str, &Self::Attr<'_>) -> Result<(), E>>(
+        &self,
+        mut func: F,
+    ) -> Result<(), E> {
+        for (name, attr) in &self.0.attrs {
+            func(name, attr)?;
+        }
+        Ok(())
+    }
+
+    fn defined_attrs_for_each<E, F: FnMut(&str, &Self::Attr<'_>) -> Result<(), E>>(
+        &self,
+        func: F,
+    ) -> Result<(), E> {
+        // Whether defaults are included is decided when the snapshot is written.
+        self.attrs_for_each(func)
+    }
+
+    fn map_attr<R, F: FnMut(Option<&Self::Attr<'_>>) -> R>(&self, key: &str, mut func: F) -> R {
+        func(self.0.attrs.get(key))
+    }
+}
+
+/// The target graph read from a snapshot.
+pub struct QuerySnapshot {
+    root_cell: CellName,
+    /// Targets by package, so the targets under a directory are a range of packages.
+    targets: BTreeMap<PackageLabel, BTreeMap<TargetLabel, SnapshotNode>>,
+    /// The targets that have a file as an input, for `owner`.
+    owners: HashMap<CellPath, Vec<SnapshotNode>>,
+}
+
+impl QuerySnapshot {
+    pub fn read(reader: impl BufRead) -> buck2_error::Result<QuerySnapshot> {
+        let mut lines = reader.lines();
+        let header = lines.next().ok_or(SnapshotError::Empty)??;
+        let header: SnapshotHeader =
+            serde_json::from_str(&header).map_err(|_| SnapshotError::MissingHeader)?;
+        if header.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header.version).into());
+        }
+
+        let mut targets: BTreeMap<PackageLabel, BTreeMap<TargetLabel, SnapshotNode>> =
+            BTreeMap::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let node = serde_json::from_str(&line)
+                .map_err(buck2_error::Error::from)
+                .and_then(SnapshotNode::new)
+                // The header is line 1.
+                .with_buck_error_context(|| format!("Error reading line {} of snapshot", i + 2))?;
+            targets
+                .entry(node.0.label.pkg())
+                .or_default()
+                .insert(node.0.label.dupe(), node);
+        }
+
+        let mut owners: HashMap<CellPath, Vec<SnapshotNode>> = HashMap::new();
+        for node in targets.values().flat_map(|targets| targets.values()) {
+            for input in &node.0.inputs {
+                owners.entry(input.clone()).or_default().push(node.dupe());
+            }
+        }
+
+        Ok(QuerySnapshot {
+            root_cell: CellName::unchecked_new(&header.root_cell)?,
+            targets,
+            owners,
+        })
+    }
+
+    fn cell(&self, cell_alias: Option<&str>) -> buck2_error::Result<CellName> {
+        match cell_alias {
+            // There are no cell aliases in a snapshot, and no working directory either, so
+            // relative patterns are relative to the root cell.
+            None | Some("") => Ok(self.root_cell),
+            Some(cell) => CellName::unchecked_new(cell),
+        }
+    }
+
+    fn package_targets(&self, package: PackageLabel) -> impl Iterator<Item = &SnapshotNode> {
+        self.targets
+            .get(&package)
+            .into_iter()
+            .flat_map(|t| t.values())
+    }
+
+    /// The targets in `package` and the packages below it.
+    fn recursive_targets(&self, package: PackageLabel) -> impl Iterator<Item = &SnapshotNode> {
+        // Packages are ordered by cell and then path, so the ones whose path starts with the
+        // same characters are a range. That range can hold siblings like `foo-bar` for `foo`,
+        // so check the components too.
+        self.targets
+            .range(package..)
+            .take_while(move |(p, _)| {
+                p.cell_name() == package.cell_name()
+                    && p.as_cell_path()
+                        .path()
+                        .as_str()
+                        .starts_with(package.as_cell_path().path().as_str())
+            })
+            .filter(move |(p, _)| p.as_cell_path().starts_with(package.as_cell_path()))
+            .flat_map(|(_, targets)| targets.values())
+    }
+}
+
+#[async_trait]
+impl QueryEnvironment for QuerySnapshot {
+    type Target = SnapshotNode;
+
+    async fn get_node(&self, target: &TargetLabel) -> buck2_error::Result<SnapshotNode> {
+        match self
+            .targets
+            .get(&target.pkg())
+            .and_then(|targets| targets.get(target))
+        {
+            Some(node) => Ok(node.dupe()),
+            None => Err(QueryEnvironmentError::missing_target(
+                target,
+                self.package_targets(target.pkg())
+                    .map(|node| node.0.label.name().as_str()),
+            )
+            .into()),
+        }
+    }
+
+    async fn get_node_for_default_configured_target(
+        &self,
+        _target: &TargetLabel,
+    ) -> buck2_error::Result<MaybeCompatible<SnapshotNode>> {
+        Err(QueryError::FunctionUnimplemented(
+            "get_node_for_default_configured_target() only for CqueryEnvironment",
+        )
+        .into())
+    }
+
+    async fn eval_literals(
+        &self,
+        literals: &[&str],
+    ) -> buck2_error::Result<TargetSet<SnapshotNode>> {
+        let mut result = TargetSet::new();
+        for literal in literals {
+            let parts = lex_target_pattern::<TargetPatternExtra>(literal, false)?;
+            let cell = self.cell(parts.cell_alias)?;
+            match parts.pattern.reject_ambiguity()? {
+                PatternData::Recursive { package } => {
+                    let package = PackageLabel::new(cell, CellRelativePath::new(package));
+                    for node in self.recursive_targets(package) {
+                        result.insert(node.dupe());
+                    }
+                }
+                PatternData::AllTargetsInPackage { package } => {
+                    let package = PackageLabel::new(cell, CellRelativePath::new(package));
+                    for node in self.package_targets(package) {
+                        result.insert(node.dupe());
+                    }
+                }
+                PatternData::TargetInPackage {
+                    package,
+                    target_name,
+                    extra: TargetPatternExtra,
+                } => {
+                    let label = TargetLabel::new(
+                        PackageLabel::new(cell, CellRelativePath::new(package)),
+                        target_name.as_ref(),
+                    );
+                    result.insert(self.get_node(&label).await?);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    async fn eval_file_literal(&self, literal: &str) -> buck2_error::Result<FileSet> {
+        let path = match literal.split_once("//") {
+            Some(("", path)) => CellPath::new(
+                self.root_cell,
+                CellRelativePathBuf::try_from(path.to_owned())?,
+            ),
+            Some(_) => parse_cell_path(literal)?,
+            None => CellPath::new(
+                self.root_cell,
+                CellRelativePathBuf::try_from(literal.to_owned())?,
+            ),
+        };
+        Ok(FileSet::new(IndexSet::from([FileNode(path)])))
+    }
+
+    async fn dfs_postorder(
+        &self,
+        root: &TargetSet<SnapshotNode>,
+        traversal_delegate: impl AsyncChildVisitor<SnapshotNode>,
+        visit: impl FnMut(SnapshotNode) -> buck2_error::Result<()> + Send,
+    ) -> buck2_error::Result<()> {
+        async_depth_first_postorder_traversal(
+            &QueryEnvironmentAsNodeLookup { env: self },
+            root.iter_names(),
+            traversal_delegate,
+            visit,
+        )
+        .await
+    }
+
+    async fn depth_limited_traversal(
+        &self,
+        root: &TargetSet<SnapshotNode>,
+        delegate: impl AsyncChildVisitor<SnapshotNode>,
+        visit: impl FnMut(SnapshotNode) -> buck2_error::Result<()> + Send,
+        depth: u32,
+    ) -> buck2_error::Result<()> {
+        async_depth_limited_traversal(
+            &QueryEnvironmentAsNodeLookup { env: self },
+            root.iter_names(),
+            delegate,
+            visit,
+            depth,
+        )
+        .await
+    }
+
+    async fn owner(&self, paths: &FileSet) -> buck2_error::Result<TargetSet<SnapshotNode>> {
+        let mut result = TargetSet::new();
+        for path in paths.iter() {
+            for node in self.owners.get(path).into_iter().flatten() {
+                result.insert(node.dupe());
+            }
+        }
+        Ok(result)
+    }
+
+    async fn targets_in_buildfile(
+        &self,
+        paths: &FileSet,
+    ) -> buck2_error::Result<TargetSet<SnapshotNode>> {
+        let mut result = TargetSet::new();
+        for node in self.targets.values().flat_map(|targets| targets.values()) {
+            if paths.contains(&FileNode(node.0.buildfile_path.path())) {
+                result.insert(node.dupe());
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::snapshot::QuerySnapshot;
+    use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+    use crate::query::syntax::simple::eval::limits::QueryLimits;
+    use crate::query::syntax::simple::eval::limits::QueryLimitsTracker;
+    use crate::query::syntax::simple::eval::values::QueryEvaluationValue;
+    use crate::query::syntax::simple::functions::DefaultQueryFunctionsModule;
+
+    const SNAPSHOT: &str = r#"{"buck.snapshot":1,"root_cell":"root"}
+{"label":"root//app:app","type":"cxx_binary","buildfile":"BUCK","deps":["root//lib:a"],"target_deps":["root//lib:a"],"inputs":["root//app/main.cpp"],"attrs":{"name":"app","labels":["prod"]}}
+{"label":"root//lib:a","type":"cxx_library","buildfile":"BUCK","deps":["root//lib:b"],"target_deps":["root//lib:b"],"tests":["root//lib:a_test"],"inputs":["root//lib/a.cpp"],"attrs":{"name":"a"}}
+{"label":"root//lib:b","type":"cxx_library","buildfile":"BUCK","inputs":["root//lib/b.cpp"],"attrs":{"name":"b","labels":["prod"],"weight":"-1"}}
+{"label":"root//lib:a_test","type":"cxx_test","buildfile":"BUCK","deps":["root//lib:a"],"target_deps":["root//lib:a"],"attrs":{"name":"a_test"}}
+"#;
+
+    async fn eval(query: &str) -> buck2_error::Result<Vec<String>> {
+        let snapshot = QuerySnapshot::read(SNAPSHOT.as_bytes())?;
+        let functions = DefaultQueryFunctionsModule::new();
+        let mut result: Vec<String> = match QueryEvaluator::new(&snapshot, &functions)
+            .eval_query(query)
+            .await?
+        {
+            QueryEvaluationValue::TargetSet(targets) => {
+                targets.iter_names().map(|t| t.to_string()).collect()
+            }
+            QueryEvaluationValue::FileSet(files) => files.iter().map(|f| f.to_string()).collect(),
+            QueryEvaluationValue::Integer(i) => vec![i.to_string()],
+            QueryEvaluationValue::Paths(paths) => paths
+                .iter()
+                .map(|path| {
+                    path.iter_names()
+                        .map(|t| t.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" -> ")
+                })
+                .collect(),
+        };
+        result.sort();
+        Ok(result)
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_queries() -> buck2_error::Result<()> {
+        assert_eq!(
+            vec!["root//lib:a", "root//lib:a_test", "root//lib:b"],
+            eval("//lib:").await?
+        );
+        assert_eq!(
+            vec!["root//lib:a", "root//lib:a_test", "root//lib:b"],
+            eval("//lib/...").await?
+        );
+        assert_eq!(
+            vec!["root//app:app", "root//lib:a", "root//lib:b"],
+            eval("deps(//app:app)").await?
+        );
+        assert_eq!(
+            vec!["root//lib:a", "root//lib:b"],
+            eval("kind(cxx_library, //...)").await?
+        );
+        assert_eq!(
+            vec!["root//app:app", "root//lib:b"],
+            eval("attrfilter(labels, prod, //...)").await?
+        );
+        assert_eq!(vec!["root//lib:a"], eval("owner(lib/a.cpp)").await?);
+        assert_eq!(vec!["root//lib:a_test"], eval("testsof(//lib:a)").await?);
+        assert_eq!(
+            vec!["root//app:app", "root//lib:a", "root//lib:a_test"],
+            eval("rdeps(//..., //lib:a)").await?
+        );
+        assert_eq!(vec!["root//lib/a.cpp"], eval("inputs(//lib:a)").await?);
+        assert_eq!(vec!["1"], eval("paths_count(//app:app, //lib:b)").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_paths() -> buck2_error::Result<()> {
+        assert_eq!(
+            vec!["root//lib:a -> root//lib:b", "root//lib:b"],
+            eval("shortest_paths(//..., //lib:b, 2)").await?
+        );
+        assert_eq!(
+            vec!["root//app:app -> root//lib:a -> root//lib:b"],
+            eval("heaviest_paths(//app:app, //lib:b, 3, labels)").await?
+        );
+        assert_eq!(
+            Vec::<String>::new(),
+            eval("shortest_paths(//app:app, //lib:b, 1, '', kind(cxx_binary, $deps))").await?
+        );
+        let err = eval("heaviest_paths(//app:app, //lib:b, 1, weight)")
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("`-1` is not a valid path weight"),
+            "{:#}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_traversal_budget() -> buck2_error::Result<()> {
+        async fn eval_with_budget(
+            query: &str,
+            max_traversal_nodes: u64,
+        ) -> buck2_error::Result<()> {
+            let snapshot = QuerySnapshot::read(SNAPSHOT.as_bytes())?;
+            let functions = DefaultQueryFunctionsModule::new();
+            QueryEvaluator::new(&snapshot, &functions)
+                .with_limits(QueryLimitsTracker::new(QueryLimits {
+                    max_traversal_nodes: Some(max_traversal_nodes),
+                    ..Default::default()
+                }))
+                .eval_query(query)
+                .await?;
+            Ok(())
+        }
+
+        // `deps(//app:app)` reaches three targets.
+        eval_with_budget("deps(//app:app)", 3).await?;
+        let err = eval_with_budget("deps(//app:app)", 2).await.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("query loaded more than 2 targets"),
+            "{:#}",
+            err
+        );
+        let err = eval_with_budget("rdeps(//..., //lib:b)", 2)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("query loaded more than 2 targets"),
+            "{:#}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_missing_target() {
+        let err = eval("//lib:c").await.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Missing target `root//lib:c`"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_snapshot_without_header() {
+        let err = QuerySnapshot::read(r#"{"label":"root//lib:a"}"#.as_bytes())
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("buck.snapshot"), "{:#}", err);
+    }
+}
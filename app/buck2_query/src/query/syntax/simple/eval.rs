@@ -11,6 +11,7 @@ pub mod error;
 pub mod evaluator;
 pub mod file_set;
 pub mod label_indexed;
+pub mod limits;
 pub mod literals;
 pub mod multi_query;
 pub mod set;
@@ -9,6 +9,8 @@
 
 //! Implementation of the cli and query_* attr query language.
 
+use std::time::Duration;
+
 use buck2_core::fs::project::ProjectRoot;
 use buck2_query_parser::spanned::Spanned;
 
@@ -19,6 +21,8 @@ use buck2_query_parser::spanned::Spanned;
 pub enum QueryError {
     #[error("unknown function `{0}`")]
     UnknownFunction(String),
+    #[error("unbound variable `${0}`")]
+    UnboundVariable(String),
     #[error("binary op `{0}` unsupported in this context")]
     UnsupportedBinaryOp(String),
     #[error("expected a literal, got value of type `{actual}`")]
@@ -54,6 +58,24 @@ pub enum QueryError {
         "Operation + requires either two set types, or one set and one string, got `{0}` and `{1}`"
     )]
     UnionIncompatibleTypes(&'static str, &'static str),
+    #[error("Cannot count or rank paths through a dependency cycle, `{0}` is part of one")]
+    PathsThroughCycle(String),
+    #[error(
+        "`{value}` is not a valid path weight (attribute `{attr}` of `{target}`), weights must be non-negative integers"
+    )]
+    InvalidPathWeight {
+        attr: String,
+        target: String,
+        value: String,
+    },
+    #[error("query evaluated to {actual} results, more than the limit of {limit}")]
+    TooManyResults { actual: u64, limit: u64 },
+    #[error("query loaded more than {0} targets and files in total")]
+    TraversalBudgetExceeded(u64),
+    #[error("query evaluation did not finish within {0:?}, while evaluating this expression")]
+    Timeout(Duration),
+    #[error("query evaluation did not finish within {0:?}, while loading targets")]
+    LoadTimeout(Duration),
     /// Used to propagate up an inner error. The inner span will mark where the inner error was (which itself may be the
     /// propagation of another error). This error will end up in a Spanned that indicates where this error (the propagation) occurs.
     /// Since QueryError has an impl for `From<Spanned<QueryError>>`, just propagating inner eval errors via `?` will hit this case (and
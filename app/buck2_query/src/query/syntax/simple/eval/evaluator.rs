@@ -12,6 +12,7 @@
 use buck2_query_parser::parse_expr;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::Expr;
+use dupe::Dupe;
 use futures::FutureExt;
 use gazebo::prelude::*;
 use gazebo::variants::VariantName;
@@ -21,19 +22,44 @@ use crate::query::environment::QueryEnvironment;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::file_set::FileNode;
 use crate::query::syntax::simple::eval::file_set::FileSet;
+use crate::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use crate::query::syntax::simple::eval::set::TargetSet;
 use crate::query::syntax::simple::eval::values::QueryEvaluationValue;
 use crate::query::syntax::simple::eval::values::QueryResult;
 use crate::query::syntax::simple::eval::values::QueryValue;
+use crate::query::syntax::simple::functions::bindings::MacroDefinition;
+use crate::query::syntax::simple::functions::bindings::VariableBindings;
+use crate::query::syntax::simple::functions::AugmentedQueryFunctions;
 use crate::query::syntax::simple::functions::QueryFunctions;
 pub struct QueryEvaluator<'e, Env: QueryEnvironment> {
     env: &'e Env,
     functions: &'e dyn QueryFunctions<Env = Env>,
+    limits: QueryLimitsTracker,
 }
 
 impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
     pub fn new(env: &'e Env, functions: &'e dyn QueryFunctions<Env = Env>) -> Self {
-        Self { env, functions }
+        Self {
+            env,
+            functions,
+            limits: QueryLimitsTracker::unlimited(),
+        }
+    }
+
+    pub fn with_limits(self, limits: QueryLimitsTracker) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// An evaluator for a nested scope, like the body of a `let`, sharing the limits of this one.
+    pub fn with_functions<'a>(
+        &'a self,
+        functions: &'a dyn QueryFunctions<Env = Env>,
+    ) -> QueryEvaluator<'a, Env> {
+        QueryEvaluator {
+            env: self.env,
+            functions,
+            limits: self.limits.dupe(),
+        }
     }
 
     pub fn env(&self) -> &Env {
@@ -44,6 +70,10 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
         self.functions
     }
 
+    pub(crate) fn limits(&self) -> &QueryLimitsTracker {
+        &self.limits
+    }
+
     async fn resolve_literal(&self, literal: &str) -> buck2_error::Result<TargetSet<Env::Target>> {
         self.env.eval_literals(&[literal]).await
     }
@@ -105,6 +135,38 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
 
                 Ok(files.into())
             }
+            Expr::Variable(name) => match self.functions.get_variable(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(QueryError::UnboundVariable((*name).to_owned())),
+            },
+            Expr::Let { name, value, body } => {
+                // The value is evaluated once here, however often the body refers to it.
+                let value = self.eval(value).await?.value;
+                let functions = AugmentedQueryFunctions::augment(
+                    self.functions,
+                    Box::new(VariableBindings::new(vec![(name.fragment(), value)])),
+                );
+                let evaluator = self.with_functions(&functions);
+                Ok(evaluator.eval(body).await?.value)
+            }
+            Expr::Define {
+                name,
+                params,
+                body,
+                rest,
+            } => {
+                let functions = AugmentedQueryFunctions::augment(
+                    self.functions,
+                    Box::new(MacroDefinition::new(
+                        name.fragment(),
+                        params,
+                        body,
+                        self.functions,
+                    )),
+                );
+                let evaluator = self.with_functions(&functions);
+                Ok(evaluator.eval(rest).await?.value)
+            }
         }
     }
 
@@ -114,7 +176,26 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
     ) -> std::pin::Pin<
         Box<dyn std::future::Future<Output = QueryResult<QueryValue<Env::Target>>> + Send + 'a>,
     > {
-        async move { expr.span(self.eval_internal(&expr.value).await) }.boxed()
+        async move {
+            let value = match self.limits.deadline() {
+                // When the deadline passes, the innermost expression that is still being evaluated
+                // times out first, so the error points at it.
+                Some((deadline, timeout)) => {
+                    tokio::time::timeout_at(deadline, self.eval_internal(&expr.value))
+                        .await
+                        .unwrap_or(Err(QueryError::Timeout(timeout)))
+                }
+                None => self.eval_internal(&expr.value).await,
+            };
+            let literal = matches!(expr.value, Expr::Set(_) | Expr::FileSet(_));
+            expr.span(value.and_then(|value| {
+                if literal {
+                    self.limits.charge_literal(&value)?;
+                }
+                Ok(value)
+            }))
+        }
+        .boxed()
     }
 
     pub async fn eval_query<'a>(
@@ -132,7 +213,8 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
         &self,
         expr: &Spanned<Expr<'a>>,
     ) -> QueryResult<QueryEvaluationValue<Env::Target>> {
-        self.eval(expr)
+        let value: QueryResult<QueryEvaluationValue<Env::Target>> = self
+            .eval(expr)
             .await?
             .async_into_map_res(|value| async move {
                 match value {
@@ -143,12 +225,15 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
                     )),
                     QueryValue::TargetSet(targets) => Ok(QueryEvaluationValue::TargetSet(targets)),
                     QueryValue::FileSet(files) => Ok(QueryEvaluationValue::FileSet(files)),
-                    _ => Err(QueryError::InvalidType {
-                        expected: "targets",
-                        actual: value.variant_name(),
-                    }),
+                    QueryValue::Integer(value) => Ok(QueryEvaluationValue::Integer(value)),
+                    QueryValue::Paths(paths) => Ok(QueryEvaluationValue::Paths(paths)),
                 }
             })
-            .await
+            .await;
+        // Only the final result counts towards `max_results`, sub-expressions may be larger.
+        value?.into_map_res(|value| {
+            self.limits.check_result(&value)?;
+            Ok(value)
+        })
     }
 }
@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Guards against queries that are much more expensive than intended, like an accidental
+//! `deps(//...)`. The timeout and traversal budget also cover loading the targets a query refers to
+//! (and, for cquery, its universe). When a limit is hit, evaluation stops with an error on the
+//! sub-expression that hit it.
+
+use std::future::Future;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use dupe::Dupe;
+use tokio::time::Instant;
+
+use crate::query::environment::QueryTarget;
+use crate::query::syntax::simple::eval::error::QueryError;
+use crate::query::syntax::simple::eval::values::QueryEvaluationValue;
+use crate::query::syntax::simple::eval::values::QueryValue;
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryLimits {
+    /// The most targets or files the query may evaluate to. Sub-expressions may be larger.
+    pub max_results: Option<u64>,
+    /// The most targets that traversals like `deps` may reach in total, plus the targets and files
+    /// of set literals. This bounds how much of the graph a query loads: traversals count targets
+    /// as they reach them, and stop once this is exceeded.
+    pub max_traversal_nodes: Option<u64>,
+    /// How long the whole evaluation may take.
+    pub timeout: Option<Duration>,
+}
+
+struct QueryLimitsData {
+    limits: QueryLimits,
+    deadline: Option<Instant>,
+    traversed: AtomicU64,
+}
+
+/// The limits of one evaluation and how much of them it used up, shared by the evaluators of all
+/// its sub-expressions (and all the queries of a multi-query).
+#[derive(Clone, Dupe, Default)]
+pub struct QueryLimitsTracker(Option<Arc<QueryLimitsData>>);
+
+impl QueryLimitsTracker {
+    /// Start tracking an evaluation. The timeout starts now.
+    pub fn new(limits: QueryLimits) -> Self {
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        Self(Some(Arc::new(QueryLimitsData {
+            limits,
+            deadline,
+            traversed: AtomicU64::new(0),
+        })))
+    }
+
+    pub fn unlimited() -> Self {
+        Self(None)
+    }
+
+    pub(crate) fn deadline(&self) -> Option<(Instant, Duration)> {
+        let data = self.0.as_ref()?;
+        Some((data.deadline?, data.limits.timeout?))
+    }
+
+    /// Whether traversals need to count the targets they reach.
+    pub(crate) fn has_traversal_budget(&self) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|data| data.limits.max_traversal_nodes.is_some())
+    }
+
+    /// Run `fut`, which loads what the query needs before it can be evaluated, within the deadline.
+    pub async fn within_deadline<T>(
+        &self,
+        fut: impl Future<Output = buck2_error::Result<T>>,
+    ) -> buck2_error::Result<T> {
+        match self.deadline() {
+            Some((deadline, timeout)) => tokio::time::timeout_at(deadline, fut)
+                .await
+                .unwrap_or_else(|_| Err(QueryError::LoadTimeout(timeout).into())),
+            None => fut.await,
+        }
+    }
+
+    /// Count targets reached by a traversal, or the values of a literal, towards the traversal
+    /// budget.
+    pub fn charge_traversal(&self, count: u64) -> Result<(), QueryError> {
+        let Some(data) = &self.0 else {
+            return Ok(());
+        };
+        let total = data.traversed.fetch_add(count, Ordering::Relaxed) + count;
+        if let Some(limit) = data.limits.max_traversal_nodes {
+            if total > limit {
+                return Err(QueryError::TraversalBudgetExceeded(limit));
+            }
+        }
+        Ok(())
+    }
+
+    /// Count the values of a set literal towards the traversal budget.
+    pub(crate) fn charge_literal<T: QueryTarget>(
+        &self,
+        value: &QueryValue<T>,
+    ) -> Result<(), QueryError> {
+        let len = match value {
+            QueryValue::TargetSet(targets) => targets.len(),
+            QueryValue::FileSet(files) => files.len(),
+            _ => return Ok(()),
+        };
+        self.charge_traversal(len as u64)
+    }
+
+    /// Check the result of the whole query against `max_results`.
+    pub(crate) fn check_result<T: QueryTarget>(
+        &self,
+        value: &QueryEvaluationValue<T>,
+    ) -> Result<(), QueryError> {
+        let Some(limit) = self.0.as_ref().and_then(|data| data.limits.max_results) else {
+            return Ok(());
+        };
+        let len = match value {
+            QueryEvaluationValue::TargetSet(targets) => targets.len(),
+            QueryEvaluationValue::FileSet(files) => files.len(),
+            QueryEvaluationValue::Paths(paths) => paths.iter().map(|p| p.len()).sum(),
+            QueryEvaluationValue::Integer(_) => return Ok(()),
+        } as u64;
+        if len > limit {
+            return Err(QueryError::TooManyResults { actual: len, limit });
+        }
+        Ok(())
+    }
+}
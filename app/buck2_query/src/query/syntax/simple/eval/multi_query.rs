@@ -43,6 +43,12 @@ impl<T: QueryTarget> MultiQueryResult<T> {
                 (QueryEvaluationValue::FileSet(value), QueryEvaluationValue::FileSet(results)) => {
                     results.insert_all(&value)
                 }
+                (QueryEvaluationValue::Integer(value), QueryEvaluationValue::Integer(results)) => {
+                    *results = results.saturating_add(value)
+                }
+                (QueryEvaluationValue::Paths(value), QueryEvaluationValue::Paths(results)) => {
+                    results.extend(value)
+                }
                 _ => unreachable!(
                     "no queries should return different types for different literals, but somehow that happened for `{}` and `{}`",
                     first_literal, name
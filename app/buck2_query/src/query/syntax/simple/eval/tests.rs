@@ -12,6 +12,7 @@
 #![cfg(test)]
 
 use std::borrow::Cow;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use buck2_core::build_file_path::BuildFilePath;
@@ -20,6 +21,7 @@ use buck2_core::configuration::compatibility::MaybeCompatible;
 use buck2_query_parser::parse_expr;
 use derive_more::Display;
 use dupe::Dupe;
+use indexmap::IndexSet;
 
 use crate::query::environment::QueryEnvironment;
 use crate::query::environment::QueryTarget;
@@ -28,8 +30,12 @@ use crate::query::graph::node::NodeKey;
 use crate::query::graph::successors::AsyncChildVisitor;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::eval::file_set::FileNode;
 use crate::query::syntax::simple::eval::file_set::FileSet;
+use crate::query::syntax::simple::eval::limits::QueryLimits;
+use crate::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use crate::query::syntax::simple::eval::set::TargetSet;
+use crate::query::syntax::simple::eval::values::QueryValue;
 use crate::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 
 #[derive(Clone, Hash, PartialEq, Eq, Debug, Display)]
@@ -161,8 +167,10 @@ impl QueryEnvironment for Env {
         unimplemented!()
     }
 
-    async fn eval_file_literal(&self, _literal: &str) -> buck2_error::Result<FileSet> {
-        unimplemented!()
+    async fn eval_file_literal(&self, literal: &str) -> buck2_error::Result<FileSet> {
+        Ok(FileSet::new(IndexSet::from([FileNode(
+            CellPath::testing_new(&format!("root//{}", literal)),
+        )])))
     }
 
     async fn dfs_postorder(
@@ -216,3 +224,138 @@ pub async fn test_missing_arg() -> buck2_error::Result<()> {
     }
     Ok(())
 }
+
+async fn eval_to_value(input: &str) -> buck2_error::Result<QueryValue<Target>> {
+    let parsed = parse_expr(input)?;
+    match QueryEvaluator::new(&Env, &DefaultQueryFunctionsModule::new())
+        .eval(&parsed)
+        .await
+    {
+        Ok(v) => Ok(v.value),
+        Err(e) => Err(QueryError::convert_error(e, input)),
+    }
+}
+
+#[tokio::test]
+pub async fn test_let_and_define() -> buck2_error::Result<()> {
+    assert_eq!(
+        QueryValue::Integer(1),
+        eval_to_value("let x = 1 in $x").await?
+    );
+    assert_eq!(
+        QueryValue::Integer(2),
+        eval_to_value("let x = 1 in let x = 2 in $x").await?
+    );
+    assert_eq!(
+        QueryValue::Integer(3),
+        eval_to_value("define f(a, b) = $b; define g(a) = f(1, $a); let x = 3 in g($x)").await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_binding_errors() -> buck2_error::Result<()> {
+    for (input, expected) in [
+        ("$x", "unbound variable `$x`"),
+        // Macros don't see the variables of their callers.
+        ("define f() = $x; let x = 1 in f()", "unbound variable `$x`"),
+        // Nor can they call themselves.
+        ("define f(a) = f($a); f(1)", "unknown function `f`"),
+        (
+            "define f(a) = $a; f(1, 2)",
+            "too many args. function `f` accepts maximum 1 args, got 2",
+        ),
+    ] {
+        let msg = format!("{:#}", eval_to_value(input).await.unwrap_err());
+        assert!(
+            msg.contains(expected),
+            "Expected error for `{}` to contain `{}`, got `{}`",
+            input,
+            expected,
+            msg
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_limits() -> buck2_error::Result<()> {
+    async fn eval_with_limits(input: &str, limits: QueryLimits) -> buck2_error::Result<()> {
+        let parsed = parse_expr(input)?;
+        match QueryEvaluator::new(&Env, &DefaultQueryFunctionsModule::new())
+            .with_limits(QueryLimitsTracker::new(limits))
+            .eval_parsed_query(&parsed)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(QueryError::convert_error(e, input)),
+        }
+    }
+
+    let max_results = QueryLimits {
+        max_results: Some(2),
+        ..Default::default()
+    };
+    // Only the final result is limited, not the sub-expressions.
+    eval_with_limits("fileset(a b c) - fileset(c)", max_results.clone()).await?;
+    let query = "fileset(a) + fileset(b c)";
+    let msg = format!(
+        "{:#}",
+        eval_with_limits(query, max_results).await.unwrap_err()
+    );
+    assert!(
+        msg.contains("query evaluated to 3 results, more than the limit of 2"),
+        "{}",
+        msg
+    );
+    assert!(
+        msg.contains(&format!("^{}^", "-".repeat(query.len() - 2))),
+        "{}",
+        msg
+    );
+
+    let max_traversal_nodes = QueryLimits {
+        max_traversal_nodes: Some(3),
+        ..Default::default()
+    };
+    eval_with_limits("fileset(a b c)", max_traversal_nodes.clone()).await?;
+    let msg = format!(
+        "{:#}",
+        eval_with_limits("fileset(a b) + fileset(c d)", max_traversal_nodes)
+            .await
+            .unwrap_err()
+    );
+    assert!(
+        msg.contains("query loaded more than 3 targets and files in total"),
+        "{}",
+        msg
+    );
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_within_deadline() {
+    let limits = QueryLimitsTracker::new(QueryLimits {
+        timeout: Some(Duration::from_millis(10)),
+        ..Default::default()
+    });
+    let msg = format!(
+        "{:#}",
+        limits
+            .within_deadline(async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            })
+            .await
+            .unwrap_err()
+    );
+    assert!(msg.contains("while loading targets"), "{}", msg);
+
+    assert_eq!(
+        QueryLimitsTracker::unlimited()
+            .within_deadline(async { Ok(1) })
+            .await
+            .unwrap(),
+        1
+    );
+}
@@ -37,12 +37,14 @@ impl<T: QueryTarget> QueryEvaluationResult<T> {
 }
 
 /// Used as a value in query evaluation, may appear in arguments to functions, results of functions etc.
-#[derive(Debug, VariantName, Eq, PartialEq)]
+#[derive(Debug, Clone, VariantName, Eq, PartialEq)]
 pub enum QueryValue<T: QueryTarget> {
     String(String),
     Integer(u64),
     TargetSet(TargetSet<T>),
     FileSet(FileSet),
+    /// The separate paths found by `shortest_paths` and `heaviest_paths`, best first.
+    Paths(Vec<TargetSet<T>>),
 }
 
 /// Used as a value in query evaluation where sets are valid, may appear in arguments to functions, results of functions etc.
@@ -59,6 +61,10 @@ pub enum QueryValueSet<T: QueryTarget> {
 pub enum QueryEvaluationValue<T: QueryTarget> {
     TargetSet(TargetSet<T>),
     FileSet(FileSet),
+    /// Result of a query function that counts something, like `paths_count`.
+    Integer(u64),
+    /// The paths found by `shortest_paths` and `heaviest_paths`, best first.
+    Paths(Vec<TargetSet<T>>),
 }
 
 impl<T: QueryTarget> QueryEvaluationValue<T> {
@@ -75,7 +81,12 @@ impl<T: QueryTarget> QueryEvaluationValue<T> {
 
     pub(crate) fn targets(&self) -> impl Iterator<Item = buck2_error::Result<&T>> {
         match self {
-            QueryEvaluationValue::TargetSet(targets) => Either::Left(targets.iter().map(Ok)),
+            QueryEvaluationValue::TargetSet(targets) => {
+                Either::Left(Either::Left(targets.iter().map(Ok)))
+            }
+            QueryEvaluationValue::Paths(paths) => {
+                Either::Left(Either::Right(paths.iter().flat_map(|p| p.iter()).map(Ok)))
+            }
             v => Either::Right(iter::once(Err(QueryError::InvalidType {
                 expected: "targets",
                 actual: v.variant_name(),
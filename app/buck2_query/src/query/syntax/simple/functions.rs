@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::cell::RefCell;
 use std::fmt;
 use std::fmt::Debug;
 use std::marker::PhantomData;
@@ -18,16 +19,22 @@ use buck2_query_derive::query_module;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::BinaryOp;
 use buck2_query_parser::Expr;
+use dupe::Dupe;
 use gazebo::variants::VariantName;
 
 use crate::query::environment::QueryEnvironment;
+use crate::query::environment::QueryTarget;
+use crate::query::graph::node::LabeledNode;
+use crate::query::graph::paths::PathOrder;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
 use crate::query::syntax::simple::eval::file_set::FileSet;
+use crate::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use crate::query::syntax::simple::eval::set::TargetSet;
 use crate::query::syntax::simple::eval::values::QueryResult;
 use crate::query::syntax::simple::eval::values::QueryValue;
 use crate::query::syntax::simple::eval::values::QueryValueSet;
+use crate::query::syntax::simple::functions::bindings::MacroDefinition;
 use crate::query::syntax::simple::functions::deps::DepsFunction;
 use crate::query::syntax::simple::functions::docs::ModuleDescription;
 use crate::query::syntax::simple::functions::helpers::CapturedExpr;
@@ -35,6 +42,7 @@ use crate::query::syntax::simple::functions::helpers::QueryArgType;
 use crate::query::syntax::simple::functions::helpers::QueryBinaryOp;
 use crate::query::syntax::simple::functions::helpers::QueryFunction;
 
+pub mod bindings;
 pub mod deps;
 pub mod description;
 pub mod docs;
@@ -55,6 +63,14 @@ pub trait QueryFunctions: Debug + Send + Sync {
     fn get(&self, name: &str) -> Option<&dyn QueryFunction<Self::Env>>;
 
     fn get_op(&self, op: BinaryOp) -> Option<&dyn QueryBinaryOp<Self::Env>>;
+
+    /// The value of a variable bound by `let` or by a macro parameter.
+    fn get_variable(
+        &self,
+        _name: &str,
+    ) -> Option<&QueryValue<<Self::Env as QueryEnvironment>::Target>> {
+        None
+    }
 }
 
 pub trait QueryFunctionsVisitLiterals: Debug + Send + Sync {
@@ -71,8 +87,9 @@ impl<F: QueryFunctions> QueryFunctionsVisitLiterals for F {
         visitor: &mut dyn QueryLiteralVisitor,
         expr: &Spanned<Expr>,
     ) -> QueryResult<()> {
-        fn visit_literals_recurse<F: QueryFunctions>(
-            this: &F,
+        // These take `dyn` functions since `define` augments them as we recurse.
+        fn visit_literals_recurse<Env: QueryEnvironment>(
+            this: &dyn QueryFunctions<Env = Env>,
             visitor: &mut dyn QueryLiteralVisitor,
             expr: &Expr,
         ) -> Result<(), QueryError> {
@@ -116,6 +133,24 @@ impl<F: QueryFunctions> QueryFunctionsVisitLiterals for F {
                     Ok(())
                 }
                 Expr::FileSet(_args) => Ok(()),
+                Expr::Variable(_) => Ok(()),
+                Expr::Let { value, body, .. } => {
+                    visit_literals_item(this, visitor, value, true)?;
+                    visit_literals_item(this, visitor, body, true)
+                }
+                Expr::Define {
+                    name,
+                    params,
+                    body,
+                    rest,
+                } => {
+                    visit_literals_item(this, visitor, body, true)?;
+                    let functions = AugmentedQueryFunctions::augment(
+                        this,
+                        Box::new(MacroDefinition::new(name.fragment(), params, body, this)),
+                    );
+                    visit_literals_item(&functions, visitor, rest, true)
+                }
                 Expr::String(..) | Expr::Integer(..) => {
                     panic!(
                         "This shouldn't be called with literals, they should be handled in the caller"
@@ -124,8 +159,8 @@ impl<F: QueryFunctions> QueryFunctionsVisitLiterals for F {
             }
         }
 
-        fn visit_literals_item<F: QueryFunctions>(
-            this: &F,
+        fn visit_literals_item<Env: QueryEnvironment>(
+            this: &dyn QueryFunctions<Env = Env>,
             visitor: &mut dyn QueryLiteralVisitor,
             expr: &Spanned<Expr>,
             is_target_expr: bool,
@@ -207,6 +242,12 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
     /// ```
     /// shows all the paths between any target with rule type `java_library` in the repository and the target `//foo:bar`.
     ///
+    /// An optional third argument restricts which dependencies are followed, in the same way as for `deps`. For example:
+    /// ```text
+    /// $ buck2 cquery "allpaths('//foo:bar', '//foo/bar/lib:baz', exec_deps())"
+    /// ```
+    /// only includes the paths that go through execution dependencies.
+    ///
     /// We recommend using it with the `--output-format=dot` parameter to generate a [Graphviz](https://graphviz.org/) [DOT](https://graphviz.org/doc/info/lang.html) file that can then be rendered as an image.
     ///
     /// ```text
@@ -222,9 +263,8 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         to: TargetSet<Env::Target>,
         captured_expr: Option<CapturedExpr<'_>>,
     ) -> QueryFuncResult<Env> {
-        Ok(self
-            .implementation
-            .allpaths(
+        Ok(traversals(evaluator)
+            .invoke_allpaths(
                 evaluator.env(),
                 evaluator.functions(),
                 &from,
@@ -244,6 +284,9 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
     ///
     /// If multiple paths exist, the returned path is unspecified. If no path exists, an empty set is returned.
     ///
+    /// Like for `allpaths`, an optional third argument restricts which dependencies are followed, e.g.
+    /// `somepath(//buck2:buck2, //buck2/app/buck2_node:buck2_node, target_deps())`.
+    ///
     /// For example:
     ///
     /// ```text
@@ -261,9 +304,8 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         to: TargetSet<Env::Target>,
         captured_expr: Option<CapturedExpr<'_>>,
     ) -> QueryFuncResult<Env> {
-        Ok(self
-            .implementation
-            .somepath(
+        Ok(traversals(evaluator)
+            .invoke_somepath(
                 evaluator.env(),
                 evaluator.functions(),
                 &from,
@@ -274,6 +316,121 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
             .into())
     }
 
+    /// The `k` shortest dependency paths between two sets of targets.
+    ///
+    /// Like `somepath`, but returns up to `k` paths, shortest first. Each path is kept separate in the output, and
+    /// within each path, results are in order from top to bottom (upstream to downstream).
+    ///
+    /// With the optional fourth argument, the length of a path is the sum of the weights of its targets instead of
+    /// the number of targets. The weight of a target is the value of the given attribute if that is an integer, and
+    /// otherwise the number of values of the attribute (e.g. the number of `srcs`). Targets without the attribute
+    /// weigh nothing. Attributes that are numbers but not non-negative integers are an error.
+    ///
+    /// Like for `allpaths`, an optional fifth argument restricts which dependencies are followed. Pass `''` as the
+    /// attribute to use it without weights.
+    ///
+    /// Paths through a dependency cycle can't be ranked, so reaching a cycle from *from* is an error.
+    ///
+    /// For example:
+    ///
+    /// ```text
+    /// $ buck2 uquery 'shortest_paths(//buck2:buck2, //buck2/app/buck2_node:buck2_node, 3)'
+    /// ```
+    /// returns the three shortest paths from `//buck2:buck2` to `//buck2/app/buck2_node:buck2_node`.
+    async fn shortest_paths(
+        &self,
+        evaluator: &QueryEvaluator<'_, Env>,
+        from: TargetSet<Env::Target>,
+        to: TargetSet<Env::Target>,
+        k: u64,
+        weight_attr: Option<String>,
+        captured_expr: Option<CapturedExpr<'_>>,
+    ) -> QueryFuncResult<Env> {
+        Ok(QueryValue::Paths(
+            traversals(evaluator)
+                .invoke_best_paths(
+                    evaluator.env(),
+                    evaluator.functions(),
+                    &from,
+                    &to,
+                    k,
+                    weight_attr.as_deref().filter(|attr| !attr.is_empty()),
+                    PathOrder::Lightest,
+                    captured_expr.as_ref(),
+                )
+                .await?,
+        ))
+    }
+
+    /// The `k` heaviest dependency paths between two sets of targets.
+    ///
+    /// Like `shortest_paths` with a weight attribute, but returns the paths with the largest total weight first. This
+    /// finds the heaviest chain of dependencies, for example:
+    ///
+    /// ```text
+    /// $ buck2 uquery 'heaviest_paths(//foo:bin, //foo/..., 1, srcs)'
+    /// ```
+    /// returns the path from `//foo:bin` into `//foo/...` with the most source files along it.
+    ///
+    /// Like for `allpaths`, an optional fifth argument restricts which dependencies are followed.
+    async fn heaviest_paths(
+        &self,
+        evaluator: &QueryEvaluator<'_, Env>,
+        from: TargetSet<Env::Target>,
+        to: TargetSet<Env::Target>,
+        k: u64,
+        weight_attr: String,
+        captured_expr: Option<CapturedExpr<'_>>,
+    ) -> QueryFuncResult<Env> {
+        Ok(QueryValue::Paths(
+            traversals(evaluator)
+                .invoke_best_paths(
+                    evaluator.env(),
+                    evaluator.functions(),
+                    &from,
+                    &to,
+                    k,
+                    Some(&weight_attr),
+                    PathOrder::Heaviest,
+                    captured_expr.as_ref(),
+                )
+                .await?,
+        ))
+    }
+
+    /// Number of distinct dependency paths between two sets of targets.
+    ///
+    /// Counts the paths that `allpaths` would return without materializing them, which is useful to see how
+    /// entangled two parts of the graph are. Like for `allpaths`, an optional third argument restricts which
+    /// dependencies are followed.
+    ///
+    /// Paths through a dependency cycle can't be counted, so reaching a cycle from *from* is an error.
+    ///
+    /// For example:
+    ///
+    /// ```text
+    /// $ buck2 uquery 'paths_count(//buck2:buck2, //buck2/app/buck2_node:buck2_node)'
+    /// ```
+    async fn paths_count(
+        &self,
+        evaluator: &QueryEvaluator<'_, Env>,
+        from: TargetSet<Env::Target>,
+        to: TargetSet<Env::Target>,
+        captured_expr: Option<CapturedExpr<'_>>,
+    ) -> QueryFuncResult<Env> {
+        Ok(QueryValue::Integer(
+            traversals(evaluator)
+                .invoke_paths_count(
+                    evaluator.env(),
+                    evaluator.functions(),
+                    &from,
+                    &to,
+                    captured_expr.as_ref(),
+                )
+                .await?,
+        ))
+    }
+
     /// Rule attribute filtering.
     ///
     /// Evaluates the given [*target expression*](#target-expression) and filters the resulting build targets to those where the specified attribute contains the specified value.
@@ -411,9 +568,8 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         depth: Option<u64>,
         captured_expr: Option<CapturedExpr<'_>>,
     ) -> QueryFuncResult<Env> {
-        Ok(self
-            .implementation
-            .deps(
+        Ok(traversals(evaluator)
+            .invoke_deps(
                 evaluator.env(),
                 evaluator.functions(),
                 &targets,
@@ -524,9 +680,8 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         depth: Option<u64>,
         captured_expr: Option<CapturedExpr<'_>>,
     ) -> QueryFuncResult<Env> {
-        Ok(self
-            .implementation
-            .rdeps(
+        Ok(traversals(evaluator)
+            .invoke_rdeps(
                 evaluator.env(),
                 evaluator.functions(),
                 &universe,
@@ -546,7 +701,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
     // of a deps functions 3rd parameter expr. When used in that context, the QueryFunctions will be augmented to
     // have non-erroring implementations.
 
-    /// A filter function that can be used in the query expression of `deps` query function.
+    /// A filter function that can be used in the query expression of the `deps`, `rdeps`, `allpaths` and `somepath` query functions.
     /// Returns the output of deps function for the immediate dependencies of the given targets. Output is equivalent to `deps(<targets>, 1)`.
     ///
     /// Example:
@@ -555,7 +710,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Err(QueryError::NotAvailableInContext("first_order_deps"))
     }
 
-    /// A filter function that can be used in the query expression of `deps` query function.
+    /// A filter function that can be used in the query expression of the `deps`, `rdeps`, `allpaths` and `somepath` query functions.
     /// Returns the target dependencies of each dependency of the given targets, excluding any configuration, toolchain and execution dependencies (build time dependencies)
     /// like compiler used as a part of the build.
     ///
@@ -565,7 +720,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Err(QueryError::NotAvailableInContext("target_deps"))
     }
 
-    /// A filter function that can be used in the query expression of `deps` query function.
+    /// A filter function that can be used in the query expression of the `deps`, `rdeps`, `allpaths` and `somepath` query functions.
     /// Returns the output of deps function for execution dependencies (build time dependencies), ex. compiler used as a part of the build.
     ///
     /// Example:
@@ -574,7 +729,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Err(QueryError::NotAvailableInContext("exec_deps"))
     }
 
-    /// A filter function that can be used in the query expression of `deps` query function.
+    /// A filter function that can be used in the query expression of the `deps`, `rdeps`, `allpaths` and `somepath` query functions.
     /// Returns the output of deps function for configuration dependencies (that appear as conditions in selects).
     ///
     /// Example:
@@ -583,7 +738,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Err(QueryError::NotAvailableInContext("configuration_deps"))
     }
 
-    /// A filter function that can be used in the query expression of `deps` query function.
+    /// A filter function that can be used in the query expression of the `deps`, `rdeps`, `allpaths` and `somepath` query functions.
     /// Returns the output of deps function for toolchain dependencies.
     ///
     /// Example:
@@ -655,6 +810,49 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
     }
 }
 
+/// The traversals of the functions of an evaluation, which count the targets they reach towards its
+/// limits.
+fn traversals<Env: QueryEnvironment>(evaluator: &QueryEvaluator<'_, Env>) -> DepsFunction<Env> {
+    DepsFunction {
+        _marker: PhantomData,
+        limits: evaluator.limits().dupe(),
+    }
+}
+
+/// The weight of a target for `shortest_paths` and `heaviest_paths`: the value of the attribute if it is an
+/// integer, and otherwise the number of values of the attribute.
+pub(crate) fn attr_weight<T: QueryTarget>(target: &T, attr: &str) -> buck2_error::Result<u64> {
+    target.map_attr(attr, |val| match val {
+        None => Ok(0),
+        Some(v) => {
+            let values = RefCell::new(Vec::new());
+            T::attr_any_matches(v, &|value| {
+                values.borrow_mut().push(value.to_owned());
+                Ok(false)
+            })?;
+            match values.into_inner().as_slice() {
+                [value] => match value.parse() {
+                    Ok(weight) => Ok(weight),
+                    // A number that isn't a valid weight, like a negative one, rather than a single value to count.
+                    Err(_) if looks_like_number(value) => Err(QueryError::InvalidPathWeight {
+                        attr: attr.to_owned(),
+                        target: target.node_key().to_string(),
+                        value: value.clone(),
+                    }
+                    .into()),
+                    Err(_) => Ok(1),
+                },
+                values => Ok(values.len() as u64),
+            }
+        }
+    })
+}
+
+fn looks_like_number(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.')
+        && value.parse::<f64>().is_ok()
+}
+
 #[derive(Allocative)]
 #[allocative(bound = "")]
 pub struct DefaultQueryFunctions<Env: QueryEnvironment> {
@@ -680,6 +878,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
     ) -> Result<TargetSet<Env::Target>, QueryError> {
         Ok(DepsFunction::<Env> {
             _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
         }
         .invoke_allpaths(env, functions, from, to, captured_expr)
         .await?)
@@ -695,11 +894,82 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
     ) -> Result<TargetSet<Env::Target>, QueryError> {
         Ok(DepsFunction::<Env> {
             _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
         }
         .invoke_somepath(env, functions, from, to, captured_expr)
         .await?)
     }
 
+    pub async fn shortest_paths(
+        &self,
+        env: &Env,
+        functions: &dyn QueryFunctions<Env = Env>,
+        from: &TargetSet<Env::Target>,
+        to: &TargetSet<Env::Target>,
+        k: u64,
+        weight_attr: Option<&str>,
+        captured_expr: Option<&CapturedExpr<'_>>,
+    ) -> buck2_error::Result<Vec<TargetSet<Env::Target>>> {
+        DepsFunction::<Env> {
+            _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
+        }
+        .invoke_best_paths(
+            env,
+            functions,
+            from,
+            to,
+            k,
+            weight_attr,
+            PathOrder::Lightest,
+            captured_expr,
+        )
+        .await
+    }
+
+    pub async fn heaviest_paths(
+        &self,
+        env: &Env,
+        functions: &dyn QueryFunctions<Env = Env>,
+        from: &TargetSet<Env::Target>,
+        to: &TargetSet<Env::Target>,
+        k: u64,
+        weight_attr: &str,
+        captured_expr: Option<&CapturedExpr<'_>>,
+    ) -> buck2_error::Result<Vec<TargetSet<Env::Target>>> {
+        DepsFunction::<Env> {
+            _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
+        }
+        .invoke_best_paths(
+            env,
+            functions,
+            from,
+            to,
+            k,
+            Some(weight_attr),
+            PathOrder::Heaviest,
+            captured_expr,
+        )
+        .await
+    }
+
+    pub async fn paths_count(
+        &self,
+        env: &Env,
+        functions: &dyn QueryFunctions<Env = Env>,
+        from: &TargetSet<Env::Target>,
+        to: &TargetSet<Env::Target>,
+        captured_expr: Option<&CapturedExpr<'_>>,
+    ) -> Result<u64, QueryError> {
+        Ok(DepsFunction::<Env> {
+            _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
+        }
+        .invoke_paths_count(env, functions, from, to, captured_expr)
+        .await?)
+    }
+
     pub fn attrfilter(
         &self,
         attr: &str,
@@ -758,6 +1028,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
     ) -> buck2_error::Result<TargetSet<Env::Target>> {
         DepsFunction::<Env> {
             _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
         }
         .invoke_deps(env, functions, targets, depth, captured_expr)
         .await
@@ -815,6 +1086,7 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
     ) -> buck2_error::Result<TargetSet<Env::Target>> {
         DepsFunction::<Env> {
             _marker: PhantomData,
+            limits: QueryLimitsTracker::unlimited(),
         }
         .invoke_rdeps(env, functions, universe, targets, depth, captured_expr)
         .await
@@ -938,4 +1210,11 @@ impl<'a, Env: QueryEnvironment> QueryFunctions for AugmentedQueryFunctions<'a, E
             Some(v) => Some(v),
         }
     }
+
+    fn get_variable(&self, name: &str) -> Option<&QueryValue<Env::Target>> {
+        match self.extra.get_variable(name) {
+            None => self.inner.get_variable(name),
+            Some(v) => Some(v),
+        }
+    }
 }
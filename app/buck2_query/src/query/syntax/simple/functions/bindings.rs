@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Names bound by `let` and `define` expressions. These are layered over the functions of the
+//! enclosing scope with [AugmentedQueryFunctions].
+
+use std::fmt;
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use buck2_query_parser::span::Span;
+use buck2_query_parser::BinaryOp;
+use buck2_query_parser::SpannedExpr;
+
+use crate::query::environment::QueryEnvironment;
+use crate::query::syntax::simple::eval::error::QueryError;
+use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::eval::values::QueryValue;
+use crate::query::syntax::simple::functions::helpers::QueryArgType;
+use crate::query::syntax::simple::functions::helpers::QueryBinaryOp;
+use crate::query::syntax::simple::functions::helpers::QueryFunction;
+use crate::query::syntax::simple::functions::AugmentedQueryFunctions;
+use crate::query::syntax::simple::functions::QueryFunctions;
+
+/// Variables bound by `let` or by the parameters of a macro. The values are evaluated once, when
+/// they are bound.
+pub(crate) struct VariableBindings<'a, Env: QueryEnvironment> {
+    variables: Vec<(&'a str, QueryValue<Env::Target>)>,
+}
+
+impl<'a, Env: QueryEnvironment> VariableBindings<'a, Env> {
+    pub(crate) fn new(variables: Vec<(&'a str, QueryValue<Env::Target>)>) -> Self {
+        Self { variables }
+    }
+}
+
+impl<'a, Env: QueryEnvironment> Debug for VariableBindings<'a, Env> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VariableBindings")
+            .field(
+                "variables",
+                &self.variables.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, Env: QueryEnvironment> QueryFunctions for VariableBindings<'a, Env> {
+    type Env = Env;
+
+    fn get(&self, _name: &str) -> Option<&dyn QueryFunction<Env>> {
+        None
+    }
+
+    fn get_op(&self, _op: BinaryOp) -> Option<&dyn QueryBinaryOp<Env>> {
+        None
+    }
+
+    fn get_variable(&self, name: &str) -> Option<&QueryValue<Env::Target>> {
+        self.variables
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+}
+
+/// A macro bound by `define`.
+pub(crate) struct MacroDefinition<'a, Env: QueryEnvironment> {
+    name: &'a str,
+    params: &'a [Span<'a>],
+    body: &'a SpannedExpr<'a>,
+    /// The functions visible where the macro was defined. The body is evaluated against these
+    /// rather than the ones at the call site, so a macro can't see the variables of its caller or
+    /// call itself.
+    scope: &'a dyn QueryFunctions<Env = Env>,
+}
+
+impl<'a, Env: QueryEnvironment> MacroDefinition<'a, Env> {
+    pub(crate) fn new(
+        name: &'a str,
+        params: &'a [Span<'a>],
+        body: &'a SpannedExpr<'a>,
+        scope: &'a dyn QueryFunctions<Env = Env>,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            scope,
+        }
+    }
+}
+
+impl<'a, Env: QueryEnvironment> Debug for MacroDefinition<'a, Env> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MacroDefinition")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, Env: QueryEnvironment> QueryFunctions for MacroDefinition<'a, Env> {
+    type Env = Env;
+
+    fn get(&self, name: &str) -> Option<&dyn QueryFunction<Env>> {
+        if name == self.name {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    fn get_op(&self, _op: BinaryOp) -> Option<&dyn QueryBinaryOp<Env>> {
+        None
+    }
+}
+
+#[async_trait]
+impl<'a, Env: QueryEnvironment> QueryFunction<Env> for MacroDefinition<'a, Env> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    async fn invoke(
+        &self,
+        evaluator: &QueryEvaluator<Env>,
+        args: &[SpannedExpr<'_>],
+    ) -> Result<QueryValue<Env::Target>, QueryError> {
+        if args.len() > self.params.len() {
+            return Err(QueryError::TooManyArgs {
+                function: self.name.to_owned(),
+                max: self.params.len(),
+                actual: args.len(),
+            });
+        }
+        if args.len() < self.params.len() {
+            return Err(QueryError::TooFewArgs {
+                function: self.name.to_owned(),
+                min: self.params.len(),
+                actual: args.len(),
+            });
+        }
+
+        // Arguments are evaluated once in the caller's scope, no matter how often the body
+        // refers to them.
+        let values =
+            buck2_util::future::try_join_all(args.iter().map(|arg| evaluator.eval(arg))).await?;
+        let bindings = VariableBindings::new(
+            self.params
+                .iter()
+                .map(|param| param.fragment())
+                .zip(values.into_iter().map(|v| v.value))
+                .collect(),
+        );
+        let functions = AugmentedQueryFunctions::augment(self.scope, Box::new(bindings));
+        let evaluator = evaluator.with_functions(&functions);
+        Ok(evaluator.eval(self.body).await?.value)
+    }
+
+    fn arg_type(&self, idx: usize) -> Result<QueryArgType, QueryError> {
+        if idx < self.params.len() {
+            Ok(QueryArgType::Value)
+        } else {
+            Err(QueryError::TooManyArgs {
+                function: self.name.to_owned(),
+                max: self.params.len(),
+                actual: idx + 1,
+            })
+        }
+    }
+}
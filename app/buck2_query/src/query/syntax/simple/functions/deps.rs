@@ -13,16 +13,20 @@ use std::marker::PhantomData;
 
 use async_trait::async_trait;
 use buck2_query_derive::query_module;
+use dupe::Dupe;
 use gazebo::variants::VariantName;
 
 use crate::query::environment::QueryEnvironment;
 use crate::query::environment::QueryTarget;
 use crate::query::environment::TraversalFilter;
+use crate::query::graph::paths::PathOrder;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use crate::query::syntax::simple::eval::set::TargetSet;
 use crate::query::syntax::simple::eval::values::QueryEvaluationValue;
 use crate::query::syntax::simple::eval::values::QueryValue;
+use crate::query::syntax::simple::functions::attr_weight;
 use crate::query::syntax::simple::functions::helpers::CapturedExpr;
 use crate::query::syntax::simple::functions::AugmentedQueryFunctions;
 use crate::query::syntax::simple::functions::QueryFunctions;
@@ -83,54 +87,70 @@ impl<'a, Env: QueryEnvironment> DepsContextFunctions<'a, Env> {
 
 pub(crate) struct DepsFunction<Env: QueryEnvironment> {
     pub(crate) _marker: PhantomData<Env>,
+    /// The limits of the evaluation the traversals are part of. Traversals count the targets they
+    /// reach towards its traversal budget.
+    pub(crate) limits: QueryLimitsTracker,
 }
 
+/// Follows the dependencies selected by the filter expression of a traversal, or all of them, and
+/// counts the targets it is asked for towards the traversal budget.
 struct Filter<'a, Env: QueryEnvironment> {
     inner_env: &'a Env,
     functions: &'a dyn QueryFunctions<Env = Env>,
-    expr: &'a CapturedExpr<'a>,
+    expr: Option<&'a CapturedExpr<'a>>,
+    limits: &'a QueryLimitsTracker,
 }
 
 impl<'a, Env: QueryEnvironment> DepsFunction<Env> {
+    /// The filter for a traversal, `None` if it follows all the dependencies and doesn't need to
+    /// count what it reaches.
     fn make_filter(
         &'a self,
         env: &'a Env,
         functions: &'a dyn QueryFunctions<Env = Env>,
         captured_expr: Option<&'a CapturedExpr>,
     ) -> Option<Filter<'a, Env>> {
-        match captured_expr {
-            Some(expr) => {
-                #[async_trait]
-                #[allow(non_local_definitions)]
-                impl<'a, T: QueryTarget, Env: QueryEnvironment<Target = T>> TraversalFilter<T> for Filter<'a, Env> {
-                    async fn get_children(&self, target: &T) -> buck2_error::Result<TargetSet<T>> {
-                        let augmented_functions = AugmentedQueryFunctions::augment(
-                            self.functions,
-                            Box::new(DepsContextFunctions { target }),
-                        );
-                        let evaluator = QueryEvaluator::new(self.inner_env, &augmented_functions);
-                        match evaluator.eval_parsed_query(self.expr.expr).await {
-                            Ok(v) => match v.value {
-                                QueryEvaluationValue::TargetSet(v) => Ok(v),
-                                v => Err(QueryError::InvalidType {
-                                    expected: "targets",
-                                    actual: v.variant_name(),
-                                }
-                                .into()),
-                            },
-                            Err(e) => Err(QueryError::drop_spans(e)),
+        #[async_trait]
+        #[allow(non_local_definitions)]
+        impl<'a, T: QueryTarget, Env: QueryEnvironment<Target = T>> TraversalFilter<T> for Filter<'a, Env> {
+            async fn get_children(&self, target: &T) -> buck2_error::Result<TargetSet<T>> {
+                self.limits.charge_traversal(1)?;
+                let Some(expr) = self.expr else {
+                    let deps = futures::future::try_join_all(
+                        target.deps().map(|dep| self.inner_env.get_node(dep)),
+                    )
+                    .await?;
+                    return Ok(deps.into_iter().collect());
+                };
+                let augmented_functions = AugmentedQueryFunctions::augment(
+                    self.functions,
+                    Box::new(DepsContextFunctions { target }),
+                );
+                let evaluator = QueryEvaluator::new(self.inner_env, &augmented_functions)
+                    .with_limits(self.limits.dupe());
+                match evaluator.eval_parsed_query(expr.expr).await {
+                    Ok(v) => match v.value {
+                        QueryEvaluationValue::TargetSet(v) => Ok(v),
+                        v => Err(QueryError::InvalidType {
+                            expected: "targets",
+                            actual: v.variant_name(),
                         }
-                    }
+                        .into()),
+                    },
+                    Err(e) => Err(QueryError::drop_spans(e)),
                 }
-
-                Some(Filter::<'a, Env> {
-                    inner_env: &env,
-                    functions,
-                    expr,
-                })
             }
-            None => None,
         }
+
+        if captured_expr.is_none() && !self.limits.has_traversal_budget() {
+            return None;
+        }
+        Some(Filter::<'a, Env> {
+            inner_env: &env,
+            functions,
+            expr: captured_expr,
+            limits: &self.limits,
+        })
     }
 
     pub(crate) async fn invoke_deps(
@@ -197,4 +217,62 @@ impl<'a, Env: QueryEnvironment> DepsFunction<Env> {
 
         env.allpaths(from, to, filter_ref).await
     }
+
+    pub(crate) async fn invoke_paths_count(
+        &self,
+        env: &Env,
+        functions: &dyn QueryFunctions<Env = Env>,
+        from: &TargetSet<Env::Target>,
+        to: &TargetSet<Env::Target>,
+        captured_expr: Option<&CapturedExpr<'_>>,
+    ) -> buck2_error::Result<u64> {
+        let filter = self.make_filter(&env, functions, captured_expr);
+        let filter_ref = filter
+            .as_ref()
+            .map(|v| v as &dyn TraversalFilter<Env::Target>);
+
+        env.paths_count(from, to, filter_ref).await
+    }
+
+    pub(crate) async fn invoke_best_paths(
+        &self,
+        env: &Env,
+        functions: &dyn QueryFunctions<Env = Env>,
+        from: &TargetSet<Env::Target>,
+        to: &TargetSet<Env::Target>,
+        k: u64,
+        weight_attr: Option<&str>,
+        order: PathOrder,
+        captured_expr: Option<&CapturedExpr<'_>>,
+    ) -> buck2_error::Result<Vec<TargetSet<Env::Target>>> {
+        let filter = self.make_filter(&env, functions, captured_expr);
+        let filter_ref = filter
+            .as_ref()
+            .map(|v| v as &dyn TraversalFilter<Env::Target>);
+        let weight = |target: &Env::Target| match weight_attr {
+            Some(attr) => attr_weight(target, attr),
+            None => Ok(1),
+        };
+
+        let paths = env
+            .best_paths(
+                from,
+                to,
+                filter_ref,
+                &weight,
+                k.try_into().unwrap_or(usize::MAX),
+                order,
+            )
+            .await?;
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let mut targets = TargetSet::new();
+                for target in path {
+                    targets.insert(target);
+                }
+                targets
+            })
+            .collect())
+    }
 }
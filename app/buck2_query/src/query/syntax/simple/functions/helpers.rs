@@ -129,7 +129,7 @@ impl QueryArgType {
 
 #[async_trait]
 pub trait QueryFunction<Env: QueryEnvironment>: Send + Sync {
-    fn name(&self) -> &'static str;
+    fn name(&self) -> &str;
 
     async fn invoke(
         &self,
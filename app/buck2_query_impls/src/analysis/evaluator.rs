@@ -14,6 +14,7 @@ use buck2_error::BuckErrorContext;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_query::query::environment::QueryEnvironment;
 use buck2_query::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use buck2_query::query::syntax::simple::eval::literals::extract_target_literals;
 use buck2_query::query::syntax::simple::eval::multi_query::MultiQueryResult;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
@@ -21,6 +22,7 @@ use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
 use buck2_query::query::syntax::simple::functions::QueryFunctions;
 use buck2_query_parser::multi_query::MaybeMultiQuery;
 use buck2_query_parser::multi_query::MultiQueryItem;
+use dupe::Dupe;
 use futures::Future;
 
 pub(crate) async fn eval_query<
@@ -32,16 +34,19 @@ pub(crate) async fn eval_query<
     functions: &F,
     query: &str,
     query_args: &[String],
+    limits: &QueryLimitsTracker,
     environment: impl Fn(Vec<String>) -> Fut + Send + Sync,
 ) -> buck2_error::Result<QueryEvaluationResult<Env::Target>> {
     let query = MaybeMultiQuery::parse(query, query_args)?;
+    // The queries of a multi-query share the limits, like they share the deadline.
     match query {
         MaybeMultiQuery::MultiQuery(queries) => {
-            let results = process_multi_query(dispatcher, functions, environment, &queries).await?;
+            let results =
+                process_multi_query(dispatcher, functions, environment, &queries, limits).await?;
             Ok(QueryEvaluationResult::Multiple(results))
         }
         MaybeMultiQuery::SingleQuery(query) => {
-            let result = eval_single_query(functions, &query, environment).await?;
+            let result = eval_single_query(functions, &query, environment, limits).await?;
             Ok(QueryEvaluationResult::Single(result))
         }
     }
@@ -55,6 +60,7 @@ async fn eval_single_query<
     functions: &F,
     query: &str,
     environment: impl Fn(Vec<String>) -> Fut,
+    limits: &QueryLimitsTracker,
 ) -> buck2_error::Result<QueryEvaluationValue<<Env as QueryEnvironment>::Target>>
 where
     F: QueryFunctions<Env = Env>,
@@ -62,8 +68,12 @@ where
     Fut: Future<Output = buck2_error::Result<Env>>,
 {
     let literals = extract_target_literals(functions, query)?;
-    let env = environment(literals).await?;
-    QueryEvaluator::new(&env, functions).eval_query(query).await
+    // Loading the literals (and for cquery, the universe) can be most of the work.
+    let env = limits.within_deadline(environment(literals)).await?;
+    QueryEvaluator::new(&env, functions)
+        .with_limits(limits.dupe())
+        .eval_query(query)
+        .await
 }
 
 async fn process_multi_query<Env, EnvFut, Qf>(
@@ -71,6 +81,7 @@ async fn process_multi_query<Env, EnvFut, Qf>(
     functions: &Qf,
     env: impl Fn(Vec<String>) -> EnvFut + Send + Sync,
     queries: &[MultiQueryItem],
+    limits: &QueryLimitsTracker,
 ) -> buck2_error::Result<MultiQueryResult<Env::Target>>
 where
     Qf: QueryFunctions<Env = Env>,
@@ -86,7 +97,7 @@ where
                 let env = &env;
                 scope.spawn_cancellable(
                     async move {
-                        let result = eval_single_query(functions, &query.query, env);
+                        let result = eval_single_query(functions, &query.query, env, limits);
                         let result: buck2_error::Result<_> = result.await.map_err(|e| e.into());
                         (i, arg, result)
                     },
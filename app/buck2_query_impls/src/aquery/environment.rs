@@ -13,9 +13,12 @@ use async_trait::async_trait;
 use buck2_artifact::actions::key::ActionKey;
 use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_build_api::actions::query::ActionQueryNodeRef;
+use buck2_build_api::actions::RegisteredAction;
 use buck2_build_api::analysis::AnalysisResult;
 use buck2_build_api::artifact_groups::ArtifactGroup;
 use buck2_core::configuration::compatibility::MaybeCompatible;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_query::query::environment::QueryEnvironment;
 use buck2_query::query::graph::successors::AsyncChildVisitor;
@@ -48,6 +51,15 @@ pub(crate) trait AqueryDelegate: Send + Sync {
         artifacts: &[ArtifactGroup],
     ) -> buck2_error::Result<Vec<ActionQueryNode>>;
 
+    fn artifact_fs(&self) -> &ArtifactFs;
+
+    /// The files an action reads, both source files and outputs of other actions, including the
+    /// ones it gets through transitive sets.
+    async fn input_paths(
+        &self,
+        action: &RegisteredAction,
+    ) -> buck2_error::Result<Vec<ProjectRelativePathBuf>>;
+
     async fn get_target_set_from_analysis(
         &self,
         configured_label: &ConfiguredProvidersLabel,
@@ -14,6 +14,8 @@ use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_common::events::HasEvents;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::global_cfg_options::GlobalCfgOptions;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use dice::LinearRecomputeDiceComputations;
 use dupe::Dupe;
@@ -35,6 +37,7 @@ impl AqueryEvaluator<'_, '_> {
         &self,
         query: &str,
         query_args: &[String],
+        limits: QueryLimits,
     ) -> buck2_error::Result<QueryEvaluationResult<ActionQueryNode>> {
         let functions = aquery_functions();
 
@@ -47,6 +50,7 @@ impl AqueryEvaluator<'_, '_> {
             &functions,
             query,
             query_args,
+            &QueryLimitsTracker::new(limits),
             |literals| async move {
                 let resolved_literals = PreresolvedQueryLiterals::pre_resolve(
                     &**self.dice_query_delegate.query_data(),
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Debug;
 use std::marker::PhantomData;
@@ -14,6 +15,7 @@ use std::marker::PhantomData;
 use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_build_api::actions::query::ActionQueryNodeData;
 use buck2_query::query::syntax::simple::eval::error::QueryError;
+use buck2_query::query::syntax::simple::eval::file_set::FileSet;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::eval::values::QueryValue;
 use buck2_query::query::syntax::simple::functions::helpers::QueryBinaryOp;
@@ -23,6 +25,7 @@ use buck2_query::query::syntax::simple::functions::QueryFunctions;
 use buck2_query::query_module;
 use buck2_query_parser::BinaryOp;
 use dupe::Dupe;
+use regex::Regex;
 
 use crate::aquery::environment::AqueryEnvironment;
 
@@ -130,4 +133,87 @@ impl<'a> AqueryFunctions<'a> {
 
         Ok(res.into())
     }
+    /// Filter actions by their command line.
+    ///
+    /// Returns the actions whose arguments, joined by spaces, match the given regex. The match is
+    /// unanchored, so the regex can match part of an argument or span several of them. Actions
+    /// that don't run a command never match.
+    ///
+    /// For example, `argv_regex('-DNDEBUG', deps(//foo:bar))` returns the actions building
+    /// `//foo:bar` that are passed `-DNDEBUG`.
+    pub(crate) async fn argv_regex(
+        &self,
+        regex: String,
+        actions: TargetSet<ActionQueryNode>,
+    ) -> Result<QueryValue<ActionQueryNode>, QueryError> {
+        let regex = Regex::new(&regex).map_err(buck2_error::Error::from)?;
+        let mut res = TargetSet::new();
+        for node in &actions {
+            if node
+                .argv()?
+                .is_some_and(|argv| regex.is_match(&argv.join(" ")))
+            {
+                res.insert(node.dupe());
+            }
+        }
+        Ok(res.into())
+    }
+
+    /// Filter actions by category.
+    ///
+    /// Returns the actions whose category (e.g. `cxx_compile`) matches the given regex.
+    pub(crate) async fn category(
+        &self,
+        regex: String,
+        actions: TargetSet<ActionQueryNode>,
+    ) -> Result<QueryValue<ActionQueryNode>, QueryError> {
+        Ok(actions.attrregexfilter("category", &regex)?.into())
+    }
+
+    /// Filter actions by identifier.
+    ///
+    /// Returns the actions whose identifier (e.g. the source file of a `cxx_compile` action)
+    /// matches the given regex.
+    pub(crate) async fn identifier(
+        &self,
+        regex: String,
+        actions: TargetSet<ActionQueryNode>,
+    ) -> Result<QueryValue<ActionQueryNode>, QueryError> {
+        Ok(actions.attrregexfilter("identifier", &regex)?.into())
+    }
+
+    /// Actions that read the given files.
+    ///
+    /// Returns the actions in *universe* that have one of the given files among their inputs,
+    /// including the inputs they get through transitive sets. The files can be source files, or
+    /// outputs of other actions given by their path under `buck-out`.
+    ///
+    /// For example, `consumers(category(cxx_compile, deps(//foo:bar)), foo/lib.h)` returns the
+    /// compile actions of `//foo:bar` and its dependencies that read `foo/lib.h`.
+    pub(crate) async fn consumers(
+        &self,
+        env: &AqueryEnvironment<'a>,
+        universe: TargetSet<ActionQueryNode>,
+        files: FileSet,
+    ) -> Result<QueryValue<ActionQueryNode>, QueryError> {
+        let artifact_fs = env.delegate.artifact_fs();
+        let files = files
+            .iter()
+            .map(|file| artifact_fs.resolve_cell_path(file.as_ref()))
+            .collect::<buck2_error::Result<HashSet<_>>>()?;
+
+        let inputs = buck2_util::future::try_join_all(universe.iter().filter_map(|node| {
+            let action = node.action()?;
+            Some(async move { buck2_error::Ok((node, env.delegate.input_paths(action).await?)) })
+        }))
+        .await?;
+
+        let mut res = TargetSet::new();
+        for (node, paths) in inputs {
+            if paths.iter().any(|path| files.contains(path)) {
+                res.insert(node.dupe());
+            }
+        }
+        Ok(res.into())
+    }
 }
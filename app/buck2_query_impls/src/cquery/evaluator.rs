@@ -18,6 +18,8 @@ use buck2_error::BuckErrorContext;
 use buck2_events::dispatch::console_message;
 use buck2_node::configured_universe::CqueryUniverse;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use dice::DiceComputations;
@@ -40,6 +42,7 @@ pub(crate) async fn eval_cquery(
     query_args: &[String],
     target_universe: Option<&[String]>,
     collect_universes: bool,
+    limits: QueryLimits,
 ) -> buck2_error::Result<(
     QueryEvaluationResult<ConfiguredTargetNode>,
     Option<Vec<Arc<CqueryUniverse>>>,
@@ -51,16 +54,20 @@ pub(crate) async fn eval_cquery(
         .dupe();
     let functions = DefaultQueryFunctionsModule::new();
     let dice_query_delegate = &dice_query_delegate;
+    // Building the universe is limited like the evaluation of the query itself.
+    let limits = &QueryLimitsTracker::new(limits);
 
     let target_universe = match target_universe {
         None => None,
         Some(target_universe) => Some(Arc::new(
-            build_cquery_universe_from_literals(
-                target_universe,
-                dice_query_delegate.query_data(),
-                &mut dice_query_delegate.ctx(),
-            )
-            .await?,
+            limits
+                .within_deadline(build_cquery_universe_from_literals(
+                    target_universe,
+                    dice_query_delegate.query_data(),
+                    &mut dice_query_delegate.ctx(),
+                    limits,
+                ))
+                .await?,
         )),
     };
 
@@ -93,6 +100,7 @@ pub(crate) async fn eval_cquery(
         &functions,
         query,
         query_args,
+        limits,
         |literals| async move {
             let (resolved_literals, universe) = match target_universe {
                 None => {
@@ -110,6 +118,7 @@ pub(crate) async fn eval_cquery(
                         &literals,
                         dice_query_delegate.query_data(),
                         &mut dice_query_delegate.ctx(),
+                        limits,
                     )
                         .await?;
 
@@ -177,15 +186,17 @@ pub(crate) async fn preresolve_literals_and_build_universe(
     Ok((universe, resolved_literals))
 }
 
+/// Every target in the universe counts towards the traversal budget.
 async fn build_cquery_universe_from_literals(
     universe: &[String],
     query_literals: &DiceQueryData,
     ctx: &mut DiceComputations<'_>,
+    limits: &QueryLimitsTracker,
 ) -> buck2_error::Result<CqueryUniverse> {
     let refs: Vec<_> = universe.map(|v| v.as_str());
     let universe_resolved = query_literals.eval_literals(&refs, ctx).await?;
 
-    CqueryUniverse::build(&universe_resolved)
+    CqueryUniverse::build_charged(&universe_resolved, || Ok(limits.charge_traversal(1)?))
 }
 
 // This will first resolve the universe to configured nodes and then gather all
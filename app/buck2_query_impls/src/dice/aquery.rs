@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::HashSet;
 use std::future::Future;
 use std::hash::Hash;
 use std::sync::Arc;
@@ -20,6 +21,7 @@ use buck2_build_api::actions::query::ActionInput;
 use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_build_api::actions::query::ActionQueryNodeRef;
 use buck2_build_api::actions::query::SetProjectionInputs;
+use buck2_build_api::actions::RegisteredAction;
 use buck2_build_api::analysis::calculation::RuleAnalysisCalculation;
 use buck2_build_api::analysis::AnalysisResult;
 use buck2_build_api::artifact_groups::ArtifactGroup;
@@ -28,6 +30,7 @@ use buck2_build_api::artifact_groups::TransitiveSetProjectionKey;
 use buck2_build_api::keep_going::KeepGoing;
 use buck2_core::configuration::compatibility::MaybeCompatible;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::pattern::pattern::ParsedPattern;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_node::target_calculation::ConfiguredTargetCalculation;
@@ -193,6 +196,31 @@ async fn convert_inputs<'c, 'a, Iter: IntoIterator<Item = &'a ArtifactGroup>>(
     Ok(deps)
 }
 
+/// The paths of the artifacts that `inputs` expand to, including the ones in transitive sets.
+async fn input_paths(
+    ctx: &mut DiceComputations<'_>,
+    artifact_fs: &ArtifactFs,
+    inputs: Vec<ArtifactGroup>,
+) -> buck2_error::Result<Vec<ProjectRelativePathBuf>> {
+    let mut paths = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = inputs;
+    while let Some(input) = queue.pop() {
+        match input.resolved_artifact(ctx).await? {
+            ResolvedArtifactGroup::Artifact(artifact) => {
+                paths.push(artifact.get_path().resolve(artifact_fs)?);
+            }
+            ResolvedArtifactGroup::TransitiveSetProjection(key) => {
+                if visited.insert(key.dupe()) {
+                    let set = key.key.lookup(ctx).await?;
+                    queue.extend(set.get_projection_sub_inputs(key.projection)?);
+                }
+            }
+        }
+    }
+    Ok(paths)
+}
+
 fn compute_tset_node<'c>(
     node_cache: DiceAqueryNodesCache,
     ctx: &'c mut DiceComputations<'_>,
@@ -324,6 +352,22 @@ impl<'c, 'd> AqueryDelegate for DiceAqueryDelegate<'c, 'd> {
         buck2_util::future::try_join_all(refs.iter().map(|n| self.get_node(n))).await
     }
 
+    fn artifact_fs(&self) -> &ArtifactFs {
+        &self.query_data.artifact_fs
+    }
+
+    async fn input_paths(
+        &self,
+        action: &RegisteredAction,
+    ) -> buck2_error::Result<Vec<ProjectRelativePathBuf>> {
+        input_paths(
+            &mut self.base_delegate.ctx(),
+            &self.query_data.artifact_fs,
+            action.inputs()?.into_owned(),
+        )
+        .await
+    }
+
     async fn get_target_set_from_analysis(
         &self,
         configured_label: &ConfiguredProvidersLabel,
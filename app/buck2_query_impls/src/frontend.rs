@@ -19,6 +19,7 @@ use buck2_node::configured_universe::CqueryUniverse;
 use buck2_node::configured_universe::UNIVERSE_FROM_LITERALS;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use dice::DiceComputations;
 
@@ -42,11 +43,12 @@ impl QueryFrontend for QueryFrontendImpl {
         working_dir: &ProjectRelativePath,
         query: &str,
         query_args: &[String],
+        limits: QueryLimits,
     ) -> buck2_error::Result<QueryEvaluationResult<TargetNode>> {
         Ok(ctx
             .with_linear_recompute(|ctx| async move {
                 let evaluator = get_uquery_evaluator(&ctx, working_dir).await?;
-                evaluator.eval_query(query, query_args).await
+                evaluator.eval_query(query, query_args, limits).await
             })
             .await?)
     }
@@ -65,6 +67,7 @@ impl QueryFrontend for QueryFrontendImpl {
         global_cfg_options: GlobalCfgOptions,
         target_universe: Option<&[String]>,
         collect_universes: bool,
+        limits: QueryLimits,
     ) -> buck2_error::Result<(
         QueryEvaluationResult<ConfiguredTargetNode>,
         Option<Vec<Arc<CqueryUniverse>>>,
@@ -86,6 +89,7 @@ impl QueryFrontend for QueryFrontendImpl {
                     query_args,
                     target_universe.as_ref().map(|v| &v[..]),
                     collect_universes,
+                    limits,
                 )
                 .await
             })
@@ -99,11 +103,12 @@ impl QueryFrontend for QueryFrontendImpl {
         query: &str,
         query_args: &[String],
         global_cfg_options: GlobalCfgOptions,
+        limits: QueryLimits,
     ) -> buck2_error::Result<QueryEvaluationResult<ActionQueryNode>> {
         Ok(ctx
             .with_linear_recompute(|ctx| async move {
                 let evaluator = get_aquery_evaluator(&ctx, working_dir, global_cfg_options).await?;
-                evaluator.eval_query(query, query_args).await
+                evaluator.eval_query(query, query_args, limits).await
             })
             .await?)
     }
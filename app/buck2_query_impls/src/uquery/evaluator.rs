@@ -14,6 +14,8 @@ use buck2_common::events::HasEvents;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::global_cfg_options::GlobalCfgOptions;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimitsTracker;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use dice::LinearRecomputeDiceComputations;
@@ -35,6 +37,7 @@ impl UqueryEvaluator<'_, '_> {
         &self,
         query: &str,
         query_args: &[String],
+        limits: QueryLimits,
     ) -> buck2_error::Result<QueryEvaluationResult<TargetNode>> {
         eval_query(
             self.dice_query_delegate
@@ -45,6 +48,7 @@ impl UqueryEvaluator<'_, '_> {
             &self.functions,
             query,
             query_args,
+            &QueryLimitsTracker::new(limits),
             |literals| async move {
                 let resolved_literals = PreresolvedQueryLiterals::pre_resolve(
                     &**self.dice_query_delegate.query_data(),
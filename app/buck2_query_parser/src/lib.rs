@@ -31,6 +31,13 @@
 //!        | EXPR ' + ' EXPR
 //!        | EXPR ' except ' EXPR
 //!        | EXPR ' - ' EXPR
+//!        | '$' NAME
+//!        | 'let' NAME '=' EXPR 'in' EXPR
+//!        | 'define' NAME '(' NAME ( ',' NAME ) * ')' '=' EXPR ';' EXPR
+//!
+//! # `let` binds the value of an expression to a variable, and `define` binds a macro whose
+//! # arguments are available as variables in its body. In both cases the binding is visible in
+//! # the trailing expression, which extends as far as possible.
 //!
 //! # word is much broader than a normal identifier-like thing would allow since we don't want to require
 //! # quoting targets "@fbcode//some:target" or common regexes ".*" or filenames "Foo.java".
@@ -42,6 +49,8 @@
 //! INTEGER ::= "0" | ("1-9" "0-9"*)
 //!
 //! FUNCTION_NAME ::= "a-zA-Z_" "a-zA-Z0-9_" *
+//!
+//! NAME ::= "a-zA-Z_" "a-zA-Z0-9_" *
 //! ```
 
 pub mod multi_query;
@@ -84,8 +93,6 @@ use nom::IResult;
 use crate::span::Span;
 use crate::spanned::Spanned;
 
-// TODO(cjhopman): Add `LET WORD = expr IN expr`
-
 // TODO(cjhopman): We should switch to our own error type here. VerboseError doesn't even allow us to construct
 // our own error messages (so, for example, we can't have a good error message for too large integers) and doesn't
 // support propagating anyhow or std errors (and since we can't do a custom message, we can't even capture them as a string).
@@ -124,6 +131,19 @@ pub enum Expr<'a> {
     BinaryOpSequence(Box<SpannedExpr<'a>>, Vec<(BinaryOp, SpannedExpr<'a>)>),
     Set(Vec<Span<'a>>),
     FileSet(Vec<Span<'a>>),
+    /// A reference to a variable bound by `let` or by a macro parameter, without the leading `$`.
+    Variable(&'a str),
+    Let {
+        name: Span<'a>,
+        value: Box<SpannedExpr<'a>>,
+        body: Box<SpannedExpr<'a>>,
+    },
+    Define {
+        name: Span<'a>,
+        params: Vec<Span<'a>>,
+        body: Box<SpannedExpr<'a>>,
+        rest: Box<SpannedExpr<'a>>,
+    },
 }
 
 impl Display for Expr<'_> {
@@ -180,6 +200,25 @@ impl Display for Expr<'_> {
                 }
                 f.write_str(")")?;
             }
+            Expr::Variable(name) => write!(f, "${}", name)?,
+            Expr::Let { name, value, body } => {
+                write!(f, "let {} = {} in {}", name.fragment(), value, body)?;
+            }
+            Expr::Define {
+                name,
+                params,
+                body,
+                rest,
+            } => {
+                write!(f, "define {}(", name.fragment())?;
+                for (i, v) in params.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    f.write_str(v.fragment())?;
+                }
+                write!(f, ") = {}; {}", body, rest)?;
+            }
         }
         Ok(())
     }
@@ -275,10 +314,13 @@ fn single_expr<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spann
     // parse an expression from the beginning of the input and check after if there's a "trailing" infix operator.
     let (input, left_expr) = alt((
         preceded(char('('), cut(terminated(expr, char(')')))),
+        expr_let,
+        expr_define,
         expr_set,
         expr_fileset,
         expr_function,
         expr_int,
+        expr_variable,
         expr_word,
     ))(input)?;
 
@@ -327,6 +369,23 @@ fn expr_word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spanned
     })(input)
 }
 
+/// Tries to parse an Expr::Variable. A word that only looks like a variable (like `$x.y`) is
+/// left to be parsed as a string.
+fn expr_variable<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    spanned(|input| {
+        let (remaining, word) = non_quoted_word(input)?;
+        match word.fragment().strip_prefix('$') {
+            Some(name) if all_consuming::<_, _, (), _>(name_ident)(Span::new(name)).is_ok() => {
+                Ok((remaining, Expr::Variable(name)))
+            }
+            _ => Err(nom::Err::Error(nom::error::ParseError::from_error_kind(
+                input,
+                ErrorKind::Verify,
+            ))),
+        }
+    })(input)
+}
+
 /// Tries to parse an Expr::Integer
 fn expr_int<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
     spanned(|input| {
@@ -352,11 +411,19 @@ fn expr_int<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedE
     })(input)
 }
 
-fn word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
-    fn non_quoted_word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
-        recognize(many1(alt((alphanumeric1, is_a("*/@.-_:$#%")))))(input)
-    }
+fn non_quoted_word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
+    recognize(many1(alt((alphanumeric1, is_a("*/@.-_:$#%")))))(input)
+}
 
+/// Parses an identifier-like name, as used for functions, variables and macro parameters.
+fn name_ident<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
+    ))(input)
+}
+
+fn word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
     alt((
         preceded(
             char('\''),
@@ -438,10 +505,7 @@ fn expr_function<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spa
     }
 
     spanned(|input| {
-        let (input, function_name) = recognize(pair(
-            alt((alpha1, tag("_"))),
-            many0(alt((alphanumeric1, tag("_")))),
-        ))(input)?;
+        let (input, function_name) = name_ident(input)?;
         let (input, _) = char('(')(input)?;
         cut(move |input| {
             let (input, args) = terminated(function_args, char(')'))(input)?;
@@ -456,6 +520,59 @@ fn expr_function<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spa
     })(input)
 }
 
+/// Tries to parse an Expr::Let. Will fail if it detects an unfinished "let NAME ="
+fn expr_let<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    spanned(|input| {
+        let (input, _) = terminated(tag("let"), multispace1)(input)?;
+        let (input, name) = terminated(name_ident, multispace0)(input)?;
+        let (input, _) = char('=')(input)?;
+        cut(move |input| {
+            let (input, value) = expr(input)?;
+            let (input, _) = terminated(tag("in"), multispace1)(input)?;
+            let (input, body) = expr(input)?;
+            Ok((
+                input,
+                Expr::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                },
+            ))
+        })(input)
+    })(input)
+}
+
+/// Tries to parse an Expr::Define. Will fail if it detects an unfinished "define NAME("
+fn expr_define<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    fn params<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Vec<Span<'a>>, E> {
+        separated_list0(
+            terminated(tag(","), multispace0),
+            terminated(name_ident, multispace0),
+        )(input)
+    }
+
+    spanned(|input| {
+        let (input, _) = terminated(tag("define"), multispace1)(input)?;
+        let (input, name) = name_ident(input)?;
+        let (input, _) = char('(')(input)?;
+        cut(move |input| {
+            let (input, params) = delimited(multispace0, params, char(')'))(input)?;
+            let (input, _) = delimited(multispace0, char('='), multispace0)(input)?;
+            let (input, body) = terminated(expr, char(';'))(input)?;
+            let (input, rest) = expr(input)?;
+            Ok((
+                input,
+                Expr::Define {
+                    name,
+                    params,
+                    body: Box::new(body),
+                    rest: Box::new(rest),
+                },
+            ))
+        })(input)
+    })(input)
+}
+
 #[cfg(test)]
 mod tests {
     use std::fmt::Debug;
@@ -622,6 +739,101 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_variable() -> buck2_error::Result<()> {
+        run_tests(
+            expr_variable,
+            &["$x", "$_foo1", "$Foo_Bar"],
+            // Anything that isn't exactly `$NAME` is left for expr_word.
+            &["x", "$", "$1", "$x.y", "regex.*$#", "'$x'", ""],
+            &[],
+        );
+
+        match parse_expr("'$x'") {
+            Ok(Spanned {
+                value: Expr::String("$x"),
+                ..
+            }) => {}
+            v => panic!("expected '$x', got `{:?}`", v),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_let() -> buck2_error::Result<()> {
+        run_tests(
+            expr_let,
+            &[
+                "let x = a in $x",
+                "let x=deps(a) in $x + rdeps(b, $x)",
+                "let x = a in let y = b in $x ^ $y",
+            ],
+            // As long as we don't match "let NAME =", it should be recoverable
+            &["let", "let x", "letx = a in $x", "let + b", ""],
+            &["let x = a", "let x = in b", "let x = a in", "let x = a inb"],
+        );
+
+        match parse_expr("let x = deps(a) in $x + b") {
+            Ok(Spanned {
+                value: Expr::Let { name, value, body },
+                ..
+            }) => {
+                assert_eq!("x", name.fragment());
+                assert!(matches!(value.value, Expr::Function { .. }));
+                // The body extends as far as possible.
+                assert!(matches!(body.value, Expr::BinaryOpSequence(..)));
+            }
+            v => panic!("expected let expr, got `{:?}`", v),
+        }
+
+        // A target named `let` can still be used.
+        match parse_expr("let + b") {
+            Ok(Spanned {
+                value: Expr::BinaryOpSequence(..),
+                ..
+            }) => {}
+            v => panic!("expected union expr, got `{:?}`", v),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_define() -> buck2_error::Result<()> {
+        run_tests(
+            expr_define,
+            &[
+                "define f(a, b) = $a + $b; f(x, y)",
+                "define f() = x; f()",
+                "define f(a) = $a;\ndefine g(b) = f($b);\ng(c)",
+            ],
+            &["define", "define f", "define(a)", ""],
+            &[
+                "define f(a",
+                "define f(a) = $a",
+                "define f(a) $a; f(b)",
+                "define f(a) = $a;",
+            ],
+        );
+
+        match parse_expr("define f(a, b) = $a + $b; f(x, y)") {
+            Ok(Spanned {
+                value: Expr::Define {
+                    name, params, rest, ..
+                },
+                ..
+            }) => {
+                assert_eq!("f", name.fragment());
+                assert_eq!(
+                    vec!["a", "b"],
+                    params.iter().map(|p| p.fragment()).collect::<Vec<_>>()
+                );
+                assert!(matches!(rest.value, Expr::Function { .. }));
+            }
+            v => panic!("expected define expr, got `{:?}`", v),
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_integer() -> buck2_error::Result<()> {
         run_tests(expr_int, &["0", "1234"], &["w123", ".1", ""], &["0123"]);
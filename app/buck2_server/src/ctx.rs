@@ -60,6 +60,7 @@ use buck2_core::facebook_only;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
 use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::fs::paths::file_name::FileNameBuf;
 use buck2_core::fs::project::ProjectRoot;
@@ -107,6 +108,7 @@ use buck2_util::truncate::truncate_container;
 use buck2_validation::enabled_optional_validations_key::SetEnabledOptionalValidations;
 use dice::DiceComputations;
 use dice::DiceData;
+use dice::DiceEquality;
 use dice::DiceTransactionUpdater;
 use dice::UserComputationData;
 use dice::UserCycleDetector;
@@ -645,9 +647,32 @@ impl<'a, 's> DiceCommandUpdater<'a, 's> {
             })?
             .or(Some(10));
 
+        let local_sandbox_toolchain_paths = root_config
+            .parse_list::<String>(BuckconfigKeyRef {
+                section: "buck2",
+                property: "sandbox_toolchain_paths",
+            })?
+            .map(|paths| {
+                paths
+                    .into_iter()
+                    .map(AbsPathBuf::new)
+                    .collect::<buck2_error::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .map(Arc::new);
+
+        let detect_undeclared_inputs = root_config
+            .parse::<bool>(BuckconfigKeyRef {
+                section: "buck2",
+                property: "detect_undeclared_inputs",
+            })?
+            .unwrap_or(false);
+
         let executor_global_knobs = ExecutorGlobalKnobs {
             enable_miniperf,
             log_action_keys,
+            local_sandbox_toolchain_paths,
+            detect_undeclared_inputs,
         };
 
         let host_sharing_broker =
@@ -728,6 +753,8 @@ impl<'a, 's> DiceCommandUpdater<'a, 's> {
             self.cmd_ctx.base_context.daemon.io.project_root().dupe(),
             worker_pool,
             self.cmd_ctx.base_context.daemon.paranoid.dupe(),
+            self.cmd_ctx.base_context.daemon.local_disk_cache.dupe(),
+            self.cmd_ctx.base_context.daemon.hybrid_history.dupe(),
             self.materialize_failed_inputs,
             override_use_case,
             self.cmd_ctx.base_context.daemon.memory_tracker.dupe(),
@@ -813,6 +840,17 @@ impl<'a> ServerCommandContextTrait for ServerCommandContext<'a> {
         self.base_context.daemon.materializer.dupe()
     }
 
+    fn subscribe_to_dice_versions(&self) -> tokio::sync::watch::Receiver<Option<DiceEquality>> {
+        self.base_context
+            .daemon
+            .dice_manager
+            .subscribe_to_versions()
+    }
+
+    fn subscribe_to_file_changes(&self) -> Option<tokio::sync::watch::Receiver<()>> {
+        self.base_context.daemon.file_watcher.subscribe_to_changes()
+    }
+
     /// Provides a DiceTransaction, initialized on first use and shared after initialization.
     async fn dice_accessor<'s>(
         &'s self,
@@ -15,6 +15,7 @@ pub mod dice_dump;
 pub mod disk_state;
 pub mod forkserver;
 pub(crate) mod io_provider;
+pub(crate) mod log_retention;
 mod multi_event_stream;
 pub mod panic;
 pub mod server;
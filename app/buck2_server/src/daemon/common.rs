@@ -50,8 +50,12 @@ use buck2_execute_impl::executors::action_cache_upload_permission_checker::Actio
 use buck2_execute_impl::executors::caching::CacheUploader;
 use buck2_execute_impl::executors::hybrid::FallbackTracker;
 use buck2_execute_impl::executors::hybrid::HybridExecutor;
+use buck2_execute_impl::executors::hybrid_history::HybridHistory;
 use buck2_execute_impl::executors::local::LocalExecutor;
 use buck2_execute_impl::executors::local_actions_throttle::LocalActionsThrottle;
+use buck2_execute_impl::executors::local_disk_cache::LocalDiskActionCache;
+use buck2_execute_impl::executors::local_disk_cache::LocalDiskCacheChecker;
+use buck2_execute_impl::executors::local_disk_cache::LocalDiskCacheWriter;
 use buck2_execute_impl::executors::re::ReExecutor;
 use buck2_execute_impl::executors::stacked::StackedExecutor;
 use buck2_execute_impl::executors::to_re_platform::RePlatformFieldsToRePlatform;
@@ -95,6 +99,8 @@ pub struct CommandExecutorFactory {
     project_root: ProjectRoot,
     worker_pool: Arc<WorkerPool>,
     paranoid: Option<ParanoidDownloader>,
+    local_disk_cache: Option<LocalDiskActionCache>,
+    hybrid_history: Option<Arc<HybridHistory>>,
     materialize_failed_inputs: bool,
     /// Cache permission checks per command.
     cache_upload_permission_checker: Arc<ActionCacheUploadPermissionChecker>,
@@ -119,6 +125,8 @@ impl CommandExecutorFactory {
         project_root: ProjectRoot,
         worker_pool: Arc<WorkerPool>,
         paranoid: Option<ParanoidDownloader>,
+        local_disk_cache: Option<LocalDiskActionCache>,
+        hybrid_history: Option<Arc<HybridHistory>>,
         materialize_failed_inputs: bool,
         re_use_case_override: Option<RemoteExecutorUseCase>,
         memory_tracker: Option<Arc<MemoryTracker>>,
@@ -146,6 +154,8 @@ impl CommandExecutorFactory {
             project_root,
             worker_pool,
             paranoid,
+            local_disk_cache,
+            hybrid_history,
             materialize_failed_inputs,
             cache_upload_permission_checker,
             fallback_tracker: Arc::new(FallbackTracker::new()),
@@ -192,9 +202,47 @@ impl HasCommandExecutor for CommandExecutorFactory {
                 self.forkserver.dupe(),
                 self.executor_global_knobs.dupe(),
                 worker_pool,
+                options.use_sandbox,
             )
         };
 
+        // Local execution, preceded by a lookup in the local disk cache if one is configured.
+        let local_executor_with_disk_cache =
+            |options: &LocalExecutorOptions| -> Arc<dyn PreparedCommandExecutor> {
+                let local = local_executor_new(options);
+                let Some(cache) = &self.local_disk_cache else {
+                    return Arc::new(local);
+                };
+
+                let optional: Arc<dyn PreparedCommandOptionalExecutor> = if self.skip_cache_read {
+                    Arc::new(NoOpCommandOptionalExecutor {})
+                } else {
+                    Arc::new(LocalDiskCacheChecker {
+                        cache: cache.dupe(),
+                        artifact_fs: artifact_fs.clone(),
+                        materializer: self.materializer.dupe(),
+                        blocking_executor: self.blocking_executor.dupe(),
+                    })
+                };
+
+                if self.skip_cache_write {
+                    Arc::new(StackedExecutor {
+                        optional,
+                        fallback: local,
+                    })
+                } else {
+                    Arc::new(StackedExecutor {
+                        optional,
+                        fallback: LocalDiskCacheWriter {
+                            cache: cache.dupe(),
+                            artifact_fs: artifact_fs.clone(),
+                            blocking_executor: self.blocking_executor.dupe(),
+                            inner: local,
+                        },
+                    })
+                }
+            };
+
         if !buck2_core::is_open_source() && !cfg!(fbcode_build) {
             static WARN: OnceLock<()> = OnceLock::new();
             WARN.get_or_init(|| {
@@ -210,7 +258,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
             }
 
             return Ok(CommandExecutorResponse {
-                executor: Arc::new(local_executor_new(&LocalExecutorOptions::default())),
+                executor: local_executor_with_disk_cache(&LocalExecutorOptions::default()),
                 platform: Default::default(),
                 cache_checker: Arc::new(NoOpCommandOptionalExecutor {}),
                 cache_uploader: Arc::new(NoOpCacheUploader {}),
@@ -247,7 +295,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                     None
                 } else {
                     Some(CommandExecutorResponse {
-                        executor: Arc::new(local_executor_new(local)),
+                        executor: local_executor_with_disk_cache(local),
                         platform: Default::default(),
                         cache_checker: Arc::new(NoOpCommandOptionalExecutor {}),
                         cache_uploader: Arc::new(NoOpCacheUploader {}),
@@ -316,7 +364,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                 let executor: Option<Arc<dyn PreparedCommandExecutor>> =
                     match &remote_options.executor {
                         RemoteEnabledExecutor::Local(local) if !self.strategy.ban_local() => {
-                            Some(Arc::new(local_executor_new(local)))
+                            Some(local_executor_with_disk_cache(local))
                         }
                         RemoteEnabledExecutor::Remote(remote) if !self.strategy.ban_remote() => {
                             Some(Arc::new(remote_executor_new(
@@ -367,6 +415,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                                     low_pass_filter,
                                     fallback_tracker,
                                     local_actions_throttle,
+                                    history: None,
                                 }))
                             } else {
                                 Some(Arc::new(HybridExecutor {
@@ -378,6 +427,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                                     low_pass_filter,
                                     fallback_tracker,
                                     local_actions_throttle,
+                                    history: self.hybrid_history.dupe(),
                                 }))
                             }
                         }
@@ -0,0 +1,42 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_event_log::retention::apply_log_retention;
+use buck2_event_log::retention::LogRetentionConfig;
+
+/// Periodically evict event logs according to the configured retention policy. Logs that are
+/// still being written, by any daemon or client, are never evicted.
+pub(crate) fn spawn_log_retention(paths: &InvocationPaths, config: LogRetentionConfig) {
+    let log_dir = paths.log_dir();
+    let record_path = paths.log_evictions_path();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let log_dir = log_dir.clone();
+            let record_path = record_path.clone();
+            let config = config.clone();
+            let res = tokio::task::spawn_blocking(move || {
+                apply_log_retention(&log_dir, &record_path, &config)
+            })
+            .await;
+            match res {
+                Ok(Ok(evicted)) => {
+                    if !evicted.is_empty() {
+                        tracing::debug!("Evicted {} event logs", evicted.len());
+                    }
+                }
+                Ok(Err(e)) => tracing::warn!("Error applying event log retention: {:#}", e),
+                Err(e) => tracing::warn!("Event log retention panicked: {:#}", e),
+            }
+        }
+    });
+}
@@ -9,6 +9,7 @@
 
 use std::collections::HashMap;
 use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -35,6 +36,8 @@ use buck2_core::buck2_env;
 use buck2_core::cells::name::CellName;
 use buck2_core::facebook_only;
 use buck2_core::fs::cwd::WorkingDirectory;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::is_open_source;
@@ -42,6 +45,7 @@ use buck2_core::rollout_percentage::RolloutPercentage;
 use buck2_core::tag_result;
 use buck2_error::buck2_error;
 use buck2_error::BuckErrorContext;
+use buck2_event_log::retention::LogRetentionConfig;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_events::sink::remote;
 use buck2_events::sink::tee::TeeSink;
@@ -53,6 +57,8 @@ use buck2_execute::execute::blocking::BuckBlockingExecutor;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_execute::re::manager::ReConnectionManager;
+use buck2_execute_impl::executors::hybrid_history::HybridHistory;
+use buck2_execute_impl::executors::local_disk_cache::LocalDiskActionCache;
 use buck2_execute_impl::materializers::deferred::clean_stale::CleanStaleConfig;
 use buck2_execute_impl::materializers::deferred::AccessTimesUpdates;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializer;
@@ -86,6 +92,7 @@ use crate::daemon::disk_state::maybe_initialize_materializer_sqlite_db;
 use crate::daemon::disk_state::DiskStateOptions;
 use crate::daemon::forkserver::maybe_launch_forkserver;
 use crate::daemon::io_provider::create_io_provider;
+use crate::daemon::log_retention::spawn_log_retention;
 use crate::daemon::panic::DaemonStatePanicDiceDump;
 use crate::daemon::server::BuckdServerInitPreferences;
 
@@ -177,6 +184,14 @@ pub struct DaemonStateData {
     /// If enabled, paranoid RE downloads.
     pub paranoid: Option<ParanoidDownloader>,
 
+    /// If configured, the on-disk cache of locally executed actions.
+    pub local_disk_cache: Option<LocalDiskActionCache>,
+
+    /// If enabled, how long hybrid actions took on each executor, used to decide whether to race
+    /// them.
+    #[allocative(skip)]
+    pub hybrid_history: Option<Arc<HybridHistory>>,
+
     /// Spawner
     pub spawner: Arc<BuckSpawner>,
 
@@ -537,6 +552,7 @@ impl DaemonState {
             let file_watcher = <dyn FileWatcher>::new(
                 fb,
                 paths.project_root(),
+                &paths.file_watcher_state_path(),
                 root_config,
                 cells.dupe(),
                 ignore_specs,
@@ -584,6 +600,54 @@ impl DaemonState {
                 None
             };
 
+            let local_disk_cache = match root_config.get(BuckconfigKeyRef {
+                section: "buck2",
+                property: "local_action_cache_dir",
+            }) {
+                Some(dir) => {
+                    let root = if Path::new(dir).is_absolute() {
+                        AbsNormPathBuf::new(PathBuf::from(dir))?
+                    } else {
+                        fs.root().join(ForwardRelativePath::new(dir)?)
+                    };
+                    let max_bytes = root_config
+                        .parse(BuckconfigKeyRef {
+                            section: "buck2",
+                            property: "local_action_cache_max_bytes",
+                        })?
+                        .unwrap_or(10 << 30);
+                    Some(LocalDiskActionCache::new(root, max_bytes))
+                }
+                None => None,
+            };
+
+            let adaptive_hybrid_scheduling = root_config
+                .parse(BuckconfigKeyRef {
+                    section: "buck2",
+                    property: "adaptive_hybrid_scheduling",
+                })?
+                .unwrap_or(false);
+            let hybrid_history = if adaptive_hybrid_scheduling {
+                let dir = paths.hybrid_history_path();
+                match (blocking_executor.dupe() as Arc<dyn BlockingExecutor>)
+                    .execute_io_inline(|| HybridHistory::open(&dir))
+                    .await
+                {
+                    Ok(history) => Some(Arc::new(history)),
+                    Err(e) => {
+                        // Racing every action is slower, but it still works.
+                        tracing::warn!(
+                            "Disabling adaptive hybrid scheduling: error opening `{}`: {:#}",
+                            dir,
+                            e
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
             let remote_dep_files_enabled = root_config
                 .parse(BuckconfigKeyRef {
                     section: "build",
@@ -600,6 +664,8 @@ impl DaemonState {
                     disk_state_options.sqlite_materializer_state
                 ),
                 format!("paranoid:{}", paranoid.is_some()),
+                format!("local-disk-cache:{}", local_disk_cache.is_some()),
+                format!("adaptive-hybrid-scheduling:{}", hybrid_history.is_some()),
                 format!("remote-dep-files:{}", remote_dep_files_enabled),
                 #[cfg(fbcode_build)]
                 format!(
@@ -612,6 +678,10 @@ impl DaemonState {
                 ),
             ];
             let system_warning_config = SystemWarningConfig::from_config(root_config)?;
+
+            if let Some(log_retention_config) = LogRetentionConfig::from_buck_config(root_config)? {
+                spawn_log_retention(&paths, log_retention_config);
+            }
             // Kick off an initial sync eagerly. This gets Watchamn to start watching the path we care
             // about (potentially kicking off an initial crawl).
 
@@ -639,6 +709,8 @@ impl DaemonState {
                 enable_restarter,
                 http_client,
                 paranoid,
+                local_disk_cache,
+                hybrid_history,
                 spawner: Arc::new(BuckSpawner::new(daemon_state_data_rt)),
                 tags,
                 system_warning_config,
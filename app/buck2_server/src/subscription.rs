@@ -7,14 +7,31 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::Arc;
 use std::time::Duration;
 
+use buck2_build_api::query::oneshot::QUERY_FRONTEND;
+use buck2_cli_proto::TargetCfg;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::global_cfg_options::GlobalCfgOptions;
 use buck2_error::BuckErrorContext;
 use buck2_events::dispatch::span_async;
+use buck2_futures::spawn::spawn_dropcancel;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_server_ctx::commands::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
+use buck2_server_ctx::ctx::ServerCommandDiceContext;
+use buck2_server_ctx::global_cfg_options::global_cfg_options_from_client_context;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
 use buck2_server_ctx::streaming_request_handler::StreamingRequestHandler;
+use dice::DiceEquality;
+use dice::DiceTransaction;
+use dupe::Dupe;
+use futures::future::BoxFuture;
+use futures::future::Fuse;
+use futures::future::FusedFuture;
 use futures::future::FutureExt;
 use gazebo::prelude::*;
 use tokio::time::MissedTickBehavior;
@@ -50,9 +67,23 @@ pub(crate) async fn run_subscription_server_command(
 
             let mut wants_active_commands = false;
 
+            let mut queries = BTreeMap::<String, QuerySubscription>::new();
+            // The DICE version the queries were last evaluated at.
+            let mut evaluated_at = None;
+            // The queries being evaluated, if any. This runs alongside the other subscriptions.
+            let mut evaluation: Fuse<BoxFuture<'_, QueryEvaluation>> = Fuse::terminated();
+
+            // Whether the file watcher saw changes since the last evaluation started.
+            let mut files_changed = false;
+
             let mut ticker = tokio::time::interval(Duration::from_millis(100));
             ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
+            // The queries are only re-evaluated when something may have invalidated their results:
+            // another command moved DICE to a new version, or the file watcher saw changes.
+            let mut dice_versions = Some(ctx.subscribe_to_dice_versions());
+            let mut file_changes = ctx.subscribe_to_file_changes();
+
             let disconnect = loop {
                 futures::select! {
                     message = req.message().fuse() => {
@@ -73,6 +104,16 @@ pub(crate) async fn run_subscription_server_command(
                             Request::SubscribeToActiveCommands(buck2_subscription_proto::SubscribeToActiveCommands {}) => {
                                 wants_active_commands = true;
                             }
+                            Request::SubscribeToQuery(request) => {
+                                queries.insert(request.id.clone(), QuerySubscription { request: Arc::new(request), last: None });
+                                if evaluation.is_terminated() {
+                                    files_changed = false;
+                                    evaluation = eval_subscribed_queries(ctx, &queries, evaluated_at).fuse();
+                                }
+                            }
+                            Request::UnsubscribeFromQuery(buck2_subscription_proto::UnsubscribeFromQuery { id }) => {
+                                queries.remove(&id);
+                            }
                         }
                     }
                     path = materializer_subscription.next_materialization().fuse() => {
@@ -93,6 +134,47 @@ pub(crate) async fn run_subscription_server_command(
                             });
                         }
                     }
+                    _ = next_change(&mut dice_versions).fuse() => {
+                        let version = dice_versions.as_mut().and_then(|v| *v.borrow_and_update());
+                        if evaluation.is_terminated() && !queries.is_empty() && version != evaluated_at {
+                            evaluation = eval_subscribed_queries(ctx, &queries, evaluated_at).fuse();
+                        }
+                    }
+                    _ = next_change(&mut file_changes).fuse() => {
+                        files_changed = true;
+                        if evaluation.is_terminated() && !queries.is_empty() {
+                            files_changed = false;
+                            evaluation = eval_subscribed_queries(ctx, &queries, evaluated_at).fuse();
+                        }
+                    }
+                    QueryEvaluation { version, results } = evaluation => {
+                        evaluated_at = version;
+                        for (request, result) in results {
+                            // Skip the queries that were unsubscribed or replaced while they were
+                            // being evaluated.
+                            let Some(query) = queries
+                                .get_mut(&request.id)
+                                .filter(|query| Arc::ptr_eq(&query.request, &request))
+                            else {
+                                continue;
+                            };
+                            if let Some(update) = query.update(result) {
+                                partial_result_dispatcher.emit(buck2_cli_proto::SubscriptionResponseWrapper {
+                                    response: Some(buck2_subscription_proto::SubscriptionResponse {
+                                        response: Some(update.into())
+                                    })
+                                });
+                            }
+                        }
+                        // Catch up with what changed while the queries were being evaluated, and
+                        // with the queries subscribed to meanwhile.
+                        let latest = dice_versions.as_mut().and_then(|v| *v.borrow_and_update());
+                        let stale = files_changed || (evaluated_at.is_some() && latest != evaluated_at);
+                        if !queries.is_empty() && (stale || queries.values().any(|query| query.last.is_none())) {
+                            files_changed = false;
+                            evaluation = eval_subscribed_queries(ctx, &queries, evaluated_at).fuse();
+                        }
+                    }
                 }
             };
 
@@ -115,6 +197,208 @@ pub(crate) async fn run_subscription_server_command(
     .await
 }
 
+/// Wait for the next notification from `receiver`. Once its sender is gone, this stops watching it
+/// and never resolves again.
+async fn next_change<T>(receiver: &mut Option<tokio::sync::watch::Receiver<T>>) {
+    if let Some(r) = receiver {
+        if r.changed().await.is_ok() {
+            return;
+        }
+    }
+    *receiver = None;
+    futures::future::pending().await
+}
+
+/// A query registered with `SubscribeToQuery`.
+struct QuerySubscription {
+    request: Arc<buck2_subscription_proto::SubscribeToQuery>,
+    /// The result that was last sent to the client, `None` until the first one is.
+    last: Option<Result<BTreeSet<String>, String>>,
+}
+
+impl QuerySubscription {
+    /// Record a new result of the query, and return the notification to send for it, if it
+    /// differs from the last one.
+    fn update(
+        &mut self,
+        result: buck2_error::Result<BTreeSet<String>>,
+    ) -> Option<buck2_subscription_proto::QueryResult> {
+        let result = result.map_err(|e| format!("{:#}", e));
+        let mut update = buck2_subscription_proto::QueryResult {
+            id: self.request.id.clone(),
+            ..Default::default()
+        };
+        match (&self.last, &result) {
+            (Some(Ok(last)), Ok(targets)) => {
+                if last == targets {
+                    return None;
+                }
+                update.added = targets.difference(last).cloned().collect();
+                update.removed = last.difference(targets).cloned().collect();
+            }
+            (Some(Err(last)), Err(e)) if last == e => return None,
+            (_, Ok(targets)) => {
+                update.full = true;
+                update.added = targets.iter().cloned().collect();
+            }
+            (_, Err(e)) => update.error = Some(e.clone()),
+        }
+        self.last = Some(result);
+        Some(update)
+    }
+}
+
+/// The results of evaluating the subscribed queries.
+struct QueryEvaluation {
+    /// The DICE version the queries were evaluated at, `None` if that failed before any of them
+    /// were.
+    version: Option<DiceEquality>,
+    results: Vec<(
+        Arc<buck2_subscription_proto::SubscribeToQuery>,
+        buck2_error::Result<BTreeSet<String>>,
+    )>,
+}
+
+/// Evaluate the subscribed queries against the current state of the repository. This syncs the
+/// file watcher first, so that changes made since the last evaluation invalidate DICE. If nothing
+/// was invalidated since `evaluated_at`, only the queries that were never evaluated are, since the
+/// results of the others can't have changed. Otherwise DICE only recomputes what was invalidated.
+///
+/// Each query is evaluated in its own task.
+fn eval_subscribed_queries<'a>(
+    ctx: &'a dyn ServerCommandContextTrait,
+    queries: &BTreeMap<String, QuerySubscription>,
+    evaluated_at: Option<DiceEquality>,
+) -> BoxFuture<'a, QueryEvaluation> {
+    let queries: Vec<_> = queries
+        .values()
+        .map(|query| (query.request.dupe(), query.last.is_some()))
+        .collect();
+    async move {
+        let evaluation = ctx
+            .with_dice_ctx(|server_ctx, mut dice| {
+                let queries = queries.clone();
+                async move {
+                    let version = dice.equality_token();
+                    let mut tasks = Vec::new();
+                    for (request, evaluated) in queries {
+                        if evaluated && evaluated_at == Some(version) {
+                            continue;
+                        }
+                        let global_cfg_options = global_cfg_options_from_client_context(
+                            &TargetCfg {
+                                target_platform: request.target_platform.clone(),
+                                cli_modifiers: request.cli_modifiers.clone(),
+                            },
+                            server_ctx,
+                            &mut dice,
+                        )
+                        .await;
+                        let task_dice = dice.dupe();
+                        let working_dir = server_ctx.working_dir().to_buf();
+                        let task_request = request.dupe();
+                        let data = dice.per_transaction_data();
+                        let task = spawn_dropcancel(
+                            move |_cancel| {
+                                async move {
+                                    eval_subscribed_query(
+                                        task_dice,
+                                        &working_dir,
+                                        &task_request,
+                                        global_cfg_options?,
+                                    )
+                                    .await
+                                }
+                                .boxed()
+                            },
+                            &*data.spawner,
+                            data,
+                        );
+                        tasks.push(async move {
+                            let result = task
+                                .await
+                                .map_err(buck2_error::Error::from)
+                                .and_then(|result| result);
+                            (request, result)
+                        });
+                    }
+                    Ok(QueryEvaluation {
+                        version: Some(version),
+                        results: futures::future::join_all(tasks).await,
+                    })
+                }
+            })
+            .await;
+        match evaluation {
+            Ok(evaluation) => evaluation,
+            // Report the error for all the queries, and evaluate them all again next time.
+            Err(e) => QueryEvaluation {
+                version: None,
+                results: queries
+                    .into_iter()
+                    .map(|(request, _)| (request, Err(e.clone())))
+                    .collect(),
+            },
+        }
+    }
+    .boxed()
+}
+
+async fn eval_subscribed_query(
+    mut dice: DiceTransaction,
+    working_dir: &ProjectRelativePath,
+    request: &buck2_subscription_proto::SubscribeToQuery,
+    global_cfg_options: GlobalCfgOptions,
+) -> buck2_error::Result<BTreeSet<String>> {
+    use buck2_subscription_proto::QueryKind;
+
+    let kind = QueryKind::from_i32(request.kind)
+        .buck_error_context("Invalid query kind")
+        .tag(buck2_error::ErrorTag::Input)?;
+
+    let limits = QueryLimits {
+        max_results: request.max_results,
+        max_traversal_nodes: request.max_traversal_nodes,
+        timeout: request.timeout_ms.map(Duration::from_millis),
+    };
+
+    let query_frontend = QUERY_FRONTEND.get()?;
+    match kind {
+        QueryKind::Uquery => {
+            let result = query_frontend
+                .eval_uquery(&mut dice, working_dir, &request.query, &[], limits)
+                .await?;
+            result
+                .targets()
+                .map(|t| buck2_error::Ok(t?.label().to_string()))
+                .collect()
+        }
+        QueryKind::Cquery => {
+            let target_universe = if request.target_universe.is_empty() {
+                None
+            } else {
+                Some(&request.target_universe[..])
+            };
+            let (result, _universes) = query_frontend
+                .eval_cquery(
+                    &mut dice,
+                    working_dir,
+                    &request.query,
+                    &[],
+                    global_cfg_options,
+                    target_universe,
+                    false,
+                    limits,
+                )
+                .await?;
+            result
+                .targets()
+                .map(|t| buck2_error::Ok(t?.label().to_string()))
+                .collect()
+        }
+    }
+}
+
 fn active_commands_snapshot() -> buck2_subscription_proto::ActiveCommandsSnapshot {
     let active_commands = active_commands::active_commands()
         .iter()
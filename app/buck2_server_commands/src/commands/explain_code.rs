@@ -30,6 +30,7 @@ use buck2_explain::ActionEntryData;
 use buck2_explain::ChangedFilesEntryData;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
 use buck2_query::query::syntax::simple::eval::label_indexed::LabelIndexedSet;
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::global_cfg_options::global_cfg_options_from_client_context;
 use dice::DiceTransaction;
@@ -207,6 +208,7 @@ pub(crate) async fn explain(
                 global_cfg_options.dupe(),
                 target_universe,
                 false, // collect universes
+                QueryLimits::default(),
             )
             .await?;
 
@@ -230,6 +232,7 @@ pub(crate) async fn explain(
                     global_cfg_options.dupe(),
                     Some(&[req.target.clone()]), // target universe
                     false,
+                    QueryLimits::default(),
                 )
                 .await?;
 
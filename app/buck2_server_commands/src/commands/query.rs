@@ -14,6 +14,8 @@ pub(crate) mod query_target_ext;
 pub(crate) mod starlark_profile;
 pub mod uquery;
 
+use buck2_query::query::syntax::simple::eval::limits::QueryLimits;
+
 #[derive(Debug, buck2_error::Error)]
 #[buck2(tag = Input)]
 enum QueryCommandError {
@@ -21,4 +23,26 @@ enum QueryCommandError {
         "query result was a set of files and one or more --output-attribute was requested, but files have not attributes"
     )]
     FileSetHasNoAttributes,
+    #[error(
+        "query result was a number and one or more --output-attribute was requested, but numbers have no attributes"
+    )]
+    IntegerHasNoAttributes,
+    #[error("Invalid query timeout")]
+    InvalidTimeout,
+}
+
+fn query_limits(limits: Option<&buck2_cli_proto::QueryLimits>) -> buck2_error::Result<QueryLimits> {
+    let Some(limits) = limits else {
+        return Ok(QueryLimits::default());
+    };
+    Ok(QueryLimits {
+        max_results: limits.max_results,
+        max_traversal_nodes: limits.max_traversal_nodes,
+        timeout: limits
+            .timeout
+            .clone()
+            .map(std::time::Duration::try_from)
+            .transpose()
+            .map_err(|_| QueryCommandError::InvalidTimeout)?,
+    })
 }
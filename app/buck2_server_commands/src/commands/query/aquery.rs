@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::io::Write;
 
 use async_trait::async_trait;
@@ -14,6 +15,7 @@ use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_common::dice::cells::HasCellResolver;
 use buck2_error::BuckErrorContext;
+use buck2_node::attrs::select_resolution::SelectResolution;
 use buck2_query::query::environment::AttrFmtOptions;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
@@ -25,13 +27,25 @@ use dice::DiceTransaction;
 
 use crate::commands::query::printer::QueryResultPrinter;
 use crate::commands::query::printer::ShouldPrintProviders;
+use crate::commands::query::query_limits;
 use crate::commands::query::query_target_ext::QueryCommandTarget;
+use crate::target_hash::BuckTargetHash;
 
 impl QueryCommandTarget for ActionQueryNode {
     fn call_stack(&self) -> Option<String> {
         None
     }
 
+    fn target_hash(&self) -> Option<BuckTargetHash> {
+        None
+    }
+
+    fn select_resolutions(
+        &self,
+    ) -> buck2_error::Result<Option<BTreeMap<&str, Vec<SelectResolution>>>> {
+        Ok(None)
+    }
+
     fn attr_to_string_alternate(&self, _options: AttrFmtOptions, attr: &Self::Attr<'_>) -> String {
         format!("{:#}", attr)
     }
@@ -108,7 +122,10 @@ async fn aquery(
     )?;
 
     let buck2_cli_proto::AqueryRequest {
-        query, query_args, ..
+        query,
+        query_args,
+        limits,
+        ..
     } = request;
 
     let global_cfg_options = global_cfg_options_from_client_context(
@@ -129,6 +146,7 @@ async fn aquery(
             query,
             query_args,
             global_cfg_options,
+            query_limits(limits.as_ref())?,
         )
         .await?;
 
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::io::Write;
 
 use async_trait::async_trait;
@@ -24,6 +25,8 @@ use buck2_error::BuckErrorContext;
 use buck2_node::attrs::display::AttrDisplayWithContext;
 use buck2_node::attrs::display::AttrDisplayWithContextExt;
 use buck2_node::attrs::fmt_context::AttrFmtContext;
+use buck2_node::attrs::inspect_options::AttrInspectOptions;
+use buck2_node::attrs::select_resolution::SelectResolution;
 use buck2_node::attrs::serialize::AttrSerializeWithContext;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
 use buck2_query::query::environment::AttrFmtOptions;
@@ -41,14 +44,31 @@ use dupe::Dupe;
 use crate::commands::query::printer::ProviderLookUp;
 use crate::commands::query::printer::QueryResultPrinter;
 use crate::commands::query::printer::ShouldPrintProviders;
+use crate::commands::query::query_limits;
 use crate::commands::query::query_target_ext::QueryCommandTarget;
 use crate::commands::query::starlark_profile::write_query_profile_for_targets;
+use crate::target_hash::BuckTargetHash;
+use crate::target_hash::TargetHashes;
 
 impl QueryCommandTarget for ConfiguredTargetNode {
     fn call_stack(&self) -> Option<String> {
         ConfiguredTargetNode::call_stack(self)
     }
 
+    fn target_hash(&self) -> Option<BuckTargetHash> {
+        Some(TargetHashes::compute_immediate_one(self, true))
+    }
+
+    fn select_resolutions(
+        &self,
+    ) -> buck2_error::Result<Option<BTreeMap<&str, Vec<SelectResolution>>>> {
+        Ok(Some(
+            ConfiguredTargetNode::select_resolutions(self, AttrInspectOptions::All)?
+                .into_iter()
+                .collect(),
+        ))
+    }
+
     fn attr_to_string_alternate(&self, options: AttrFmtOptions, attr: &Self::Attr<'_>) -> String {
         format!(
             "{:#}",
@@ -148,7 +168,10 @@ async fn cquery(
         &cell_resolver,
         &request.output_attributes,
         request.unstable_output_format,
-    )?;
+    )?
+    .with_target_hashes(request.target_hash)
+    .with_edge_kinds(request.edge_kinds)
+    .with_select_resolutions(request.select_resolutions);
 
     let CqueryRequest {
         query,
@@ -157,6 +180,7 @@ async fn cquery(
         context,
         show_providers,
         target_cfg,
+        limits,
         ..
     } = request;
     // The request will always have a universe value, an empty one indicates the user didn't provide a universe.
@@ -193,6 +217,7 @@ async fn cquery(
             global_cfg_options,
             target_universe,
             profile_mode.is_some(),
+            query_limits(limits.as_ref())?,
         )
         .await?;
 
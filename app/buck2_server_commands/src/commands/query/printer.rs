@@ -21,6 +21,7 @@ use buck2_build_api::interpreter::rule_defs::provider::collection::FrozenProvide
 use buck2_cli_proto::QueryOutputFormat;
 use buck2_core::cells::CellResolver;
 use buck2_core::configuration::compatibility::MaybeCompatible;
+use buck2_node::attrs::select_resolution::SelectResolution;
 use buck2_query::query::environment::AttrFmtOptions;
 use buck2_query::query::environment::QueryTarget;
 use buck2_query::query::environment::QueryTargets;
@@ -43,10 +44,16 @@ use serde::Serializer;
 
 use crate::commands::query::query_target_ext::QueryCommandTarget;
 use crate::commands::query::QueryCommandError;
+use crate::cypher::Cypher;
 use crate::dot::targets::DotTargetGraph;
 use crate::dot::Dot;
 use crate::dot::DotCompact;
+use crate::graphml::GraphMl;
 use crate::html::Html;
+use crate::mermaid::Mermaid;
+use crate::target_graph::TargetGraph;
+use crate::target_graph::TargetGraphEdgeKind;
+use crate::target_hash::BuckTargetHash;
 
 #[derive(Copy_, Dupe_, Clone_, UnpackVariants)]
 pub(crate) enum ShouldPrintProviders<'a, T> {
@@ -67,6 +74,9 @@ pub(crate) struct QueryResultPrinter<'a> {
     resolver: &'a CellResolver,
     attributes: Option<RegexSet>,
     output_format: QueryOutputFormat,
+    target_hashes: bool,
+    edge_kinds: bool,
+    select_resolutions: bool,
 }
 
 struct TargetSetJsonPrinter<'a, T: QueryTarget> {
@@ -74,18 +84,32 @@ struct TargetSetJsonPrinter<'a, T: QueryTarget> {
     is_complex: bool,
 }
 
-impl<'a, T: QueryTarget> TargetSetJsonPrinter<'a, T> {
+impl<'a, T: QueryCommandTarget> TargetSetJsonPrinter<'a, T> {
     async fn new(
         target_call_stacks: bool,
+        target_hashes: bool,
+        edge_kinds: bool,
+        select_resolutions: bool,
         print_providers: ShouldPrintProviders<'a, T>,
         attributes: &'a Option<RegexSet>,
         targets: &'a TargetSet<T>,
     ) -> buck2_error::Result<TargetSetJsonPrinter<'a, T>> {
         Ok(TargetSetJsonPrinter {
-            value: printable_targets(targets, print_providers, attributes, target_call_stacks)
-                .await?,
+            value: printable_targets(
+                targets,
+                print_providers,
+                attributes,
+                target_call_stacks,
+                target_hashes,
+                edge_kinds,
+                select_resolutions,
+            )
+            .await?,
             is_complex: attributes.is_some()
                 || target_call_stacks
+                || target_hashes
+                || edge_kinds
+                || select_resolutions
                 || print_providers.unpack_yes().is_some(),
         })
     }
@@ -96,6 +120,16 @@ struct PrintableQueryTarget<'a, T: QueryTarget> {
     attributes: &'a Option<RegexSet>,
     providers: Option<FrozenProviderCollectionValue>,
     target_call_stacks: bool,
+    target_hash: Option<BuckTargetHash>,
+    /// Edges to the other targets in the result, with the kind of dep they are.
+    edges: Option<Vec<PrintableEdge>>,
+    select_resolutions: Option<BTreeMap<&'a str, Vec<SelectResolution>>>,
+}
+
+#[derive(Serialize)]
+struct PrintableEdge {
+    to: String,
+    kind: &'static str,
 }
 
 impl<'a, T: QueryTarget> PrintableQueryTarget<'a, T> {
@@ -180,6 +214,18 @@ impl<'a, T: QueryCommandTarget> Serialize for PrintableQueryTarget<'a, T> {
             map.serialize_entry("buck.providers", providers)?;
         }
 
+        if let Some(target_hash) = &self.target_hash {
+            map.serialize_entry("buck.target_hash", &target_hash.to_string())?;
+        }
+
+        if let Some(edges) = &self.edges {
+            map.serialize_entry("buck.edges", edges)?;
+        }
+
+        if let Some(select_resolutions) = &self.select_resolutions {
+            map.serialize_entry("buck.select_resolutions", select_resolutions)?;
+        }
+
         map.end()
     }
 }
@@ -257,9 +303,60 @@ impl<'a> QueryResultPrinter<'a> {
             resolver,
             attributes,
             output_format,
+            target_hashes: false,
+            edge_kinds: false,
+            select_resolutions: false,
         })
     }
 
+    /// Include the `buck.target_hash` of each target in JSON output.
+    pub fn with_target_hashes(self, target_hashes: bool) -> Self {
+        Self {
+            target_hashes,
+            ..self
+        }
+    }
+
+    /// Label the edges in dot output, and include them in JSON output as `buck.edges`, with the
+    /// kind of dep they are.
+    pub fn with_edge_kinds(self, edge_kinds: bool) -> Self {
+        Self { edge_kinds, ..self }
+    }
+
+    /// Include, for each attribute with `select()`s, how they were resolved in JSON output as
+    /// `buck.select_resolutions`.
+    pub fn with_select_resolutions(self, select_resolutions: bool) -> Self {
+        Self {
+            select_resolutions,
+            ..self
+        }
+    }
+
+    /// Paths are printed in JSON as a list with an entry per path, like a target set.
+    async fn paths_json_printers<'c, T: QueryCommandTarget>(
+        &'c self,
+        paths: &'c [TargetSet<T>],
+        call_stack: bool,
+        print_providers: ShouldPrintProviders<'c, T>,
+    ) -> buck2_error::Result<Vec<TargetSetJsonPrinter<'c, T>>> {
+        let mut printers = Vec::with_capacity(paths.len());
+        for path in paths {
+            printers.push(
+                TargetSetJsonPrinter::new(
+                    call_stack,
+                    self.target_hashes,
+                    self.edge_kinds,
+                    self.select_resolutions,
+                    print_providers,
+                    &self.attributes,
+                    path,
+                )
+                .await?,
+            );
+        }
+        Ok(printers)
+    }
+
     pub async fn print_multi_output<'b, T: QueryCommandTarget, W: std::io::Write>(
         &self,
         mut output: W,
@@ -285,6 +382,9 @@ impl<'a> QueryResultPrinter<'a> {
                                 &arg,
                                 &TargetSetJsonPrinter::new(
                                     target_call_stacks,
+                                    self.target_hashes,
+                                    self.edge_kinds,
+                                    self.select_resolutions,
                                     print_providers,
                                     &self.attributes,
                                     &targets,
@@ -298,6 +398,19 @@ impl<'a> QueryResultPrinter<'a> {
                                     value: &files,
                                 },
                             )?,
+                            QueryEvaluationValue::Integer(value) => {
+                                seq.serialize_entry(&arg, &value)?
+                            }
+                            QueryEvaluationValue::Paths(paths) => seq.serialize_entry(
+                                &arg,
+                                &self
+                                    .paths_json_printers(
+                                        &paths,
+                                        target_call_stacks,
+                                        print_providers,
+                                    )
+                                    .await?,
+                            )?,
                         },
                         Err(e) => {
                             seq.serialize_entry(
@@ -336,9 +449,16 @@ impl<'a> QueryResultPrinter<'a> {
         match result {
             QueryEvaluationValue::TargetSet(targets) => match self.output_format {
                 QueryOutputFormat::Default => {
-                    for target in
-                        printable_targets(&targets, print_providers, &self.attributes, call_stack)
-                            .await?
+                    for target in printable_targets(
+                        &targets,
+                        print_providers,
+                        &self.attributes,
+                        call_stack,
+                        false,
+                        false,
+                        false,
+                    )
+                    .await?
                     {
                         writeln!(&mut output, "{}", target)?;
                     }
@@ -383,6 +503,9 @@ impl<'a> QueryResultPrinter<'a> {
                     let mut ser = serde_json::Serializer::pretty(&mut output);
                     TargetSetJsonPrinter::new(
                         call_stack,
+                        self.target_hashes,
+                        self.edge_kinds,
+                        self.select_resolutions,
                         print_providers,
                         &self.attributes,
                         &targets,
@@ -398,6 +521,7 @@ impl<'a> QueryResultPrinter<'a> {
                         &DotTargetGraph {
                             targets,
                             attributes: self.attributes.clone(),
+                            edge_kinds: self.edge_kinds,
                         },
                         &mut output,
                     )?;
@@ -408,10 +532,20 @@ impl<'a> QueryResultPrinter<'a> {
                         &DotTargetGraph {
                             targets,
                             attributes: self.attributes.clone(),
+                            edge_kinds: self.edge_kinds,
                         },
                         &mut output,
                     )?;
                 }
+                QueryOutputFormat::Graphml => {
+                    GraphMl::render(&TargetGraph::new(&targets, &self.attributes)?, &mut output)?;
+                }
+                QueryOutputFormat::Mermaid => {
+                    Mermaid::render(&TargetGraph::new(&targets, &self.attributes)?, &mut output)?;
+                }
+                QueryOutputFormat::Cypher => {
+                    Cypher::render(&TargetGraph::new(&targets, &self.attributes)?, &mut output)?;
+                }
             },
             QueryEvaluationValue::FileSet(files) => {
                 if self.attributes.is_some() {
@@ -456,25 +590,112 @@ impl<'a> QueryResultPrinter<'a> {
                             "html output for files not implemented yet"
                         ));
                     }
+                    QueryOutputFormat::Graphml
+                    | QueryOutputFormat::Mermaid
+                    | QueryOutputFormat::Cypher => {
+                        return Err(buck2_error::buck2_error!(
+                            buck2_error::ErrorTag::Unimplemented,
+                            "graph output formats are not supported for files"
+                        ));
+                    }
                 }
             }
+            QueryEvaluationValue::Integer(value) => {
+                if self.attributes.is_some() {
+                    return Err(QueryCommandError::IntegerHasNoAttributes.into());
+                }
+                match self.output_format {
+                    QueryOutputFormat::Default
+                    | QueryOutputFormat::Starlark
+                    | QueryOutputFormat::Json => {
+                        writeln!(&mut output, "{}", value)?;
+                    }
+                    _ => {
+                        return Err(buck2_error::buck2_error!(
+                            buck2_error::ErrorTag::Input,
+                            "only default and json output are supported for numbers"
+                        ));
+                    }
+                }
+            }
+            QueryEvaluationValue::Paths(paths) => match self.output_format {
+                QueryOutputFormat::Default => {
+                    for (i, path) in paths.iter().enumerate() {
+                        if i > 0 {
+                            writeln!(&mut output)?;
+                        }
+                        for target in printable_targets(
+                            path,
+                            print_providers,
+                            &self.attributes,
+                            call_stack,
+                            false,
+                            false,
+                            false,
+                        )
+                        .await?
+                        {
+                            writeln!(&mut output, "{}", target)?;
+                        }
+                    }
+                }
+                QueryOutputFormat::Json => {
+                    let printers = self
+                        .paths_json_printers(&paths, call_stack, print_providers)
+                        .await?;
+                    let mut ser = serde_json::Serializer::pretty(&mut output);
+                    printers.serialize(&mut ser)?;
+                    std::mem::drop(ser);
+                    // need to add a newline to flush the output.
+                    writeln!(&mut output)?;
+                }
+                _ => {
+                    return Err(buck2_error::buck2_error!(
+                        buck2_error::ErrorTag::Input,
+                        "only default and json output are supported for paths"
+                    ));
+                }
+            },
         }
 
         Ok(())
     }
 }
 
-async fn printable_targets<'a, T: QueryTarget>(
+async fn printable_targets<'a, T: QueryCommandTarget>(
     targets: &'a TargetSet<T>,
     print_providers: ShouldPrintProviders<'a, T>,
     attributes: &'a Option<RegexSet>,
     target_call_stacks: bool,
+    target_hashes: bool,
+    edge_kinds: bool,
+    select_resolutions: bool,
 ) -> buck2_error::Result<Vec<PrintableQueryTarget<'a, T>>> {
     futures::future::join_all(targets.iter().map(|t| async move {
         Ok(PrintableQueryTarget {
             value: t,
             attributes,
             target_call_stacks,
+            target_hash: if target_hashes { t.target_hash() } else { None },
+            edges: if edge_kinds {
+                Some(
+                    TargetGraphEdgeKind::deps_of(t)
+                        .into_iter()
+                        .filter(|(dep, _)| targets.contains(dep))
+                        .map(|(dep, kind)| PrintableEdge {
+                            to: dep.to_string(),
+                            kind: kind.as_str(),
+                        })
+                        .collect(),
+                )
+            } else {
+                None
+            },
+            select_resolutions: if select_resolutions {
+                t.select_resolutions()?
+            } else {
+                None
+            },
             providers: match print_providers {
                 ShouldPrintProviders::No => None,
                 ShouldPrintProviders::Yes(lookup) => {
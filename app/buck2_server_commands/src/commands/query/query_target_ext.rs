@@ -7,16 +7,28 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::fmt::Formatter;
 
+use buck2_node::attrs::select_resolution::SelectResolution;
 use buck2_query::query::environment::AttrFmtOptions;
 use buck2_query::query::environment::QueryTarget;
 use dupe::Dupe;
 
+use crate::target_hash::BuckTargetHash;
+
 /// Extensions of `QueryTarget` needed in query commands.
 pub(crate) trait QueryCommandTarget: QueryTarget {
     fn call_stack(&self) -> Option<String>;
 
+    /// The hash used by `buck2 targets --show-target-hash`, not including dependencies or inputs.
+    fn target_hash(&self) -> Option<BuckTargetHash>;
+
+    /// How the `select()`s of each attribute were resolved, for targets that are configured.
+    fn select_resolutions(
+        &self,
+    ) -> buck2_error::Result<Option<BTreeMap<&str, Vec<SelectResolution>>>>;
+
     #[allow(dead_code)]
     fn attr_to_string_alternate(&self, _options: AttrFmtOptions, attr: &Self::Attr<'_>) -> String;
 
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::io::Write;
 
 use async_trait::async_trait;
@@ -18,6 +19,7 @@ use buck2_error::BuckErrorContext;
 use buck2_node::attrs::display::AttrDisplayWithContext;
 use buck2_node::attrs::display::AttrDisplayWithContextExt;
 use buck2_node::attrs::fmt_context::AttrFmtContext;
+use buck2_node::attrs::select_resolution::SelectResolution;
 use buck2_node::attrs::serialize::AttrSerializeWithContext;
 use buck2_node::nodes::unconfigured::TargetNode;
 use buck2_node::nodes::unconfigured::TargetNodeData;
@@ -32,13 +34,26 @@ use dupe::Dupe;
 
 use crate::commands::query::printer::QueryResultPrinter;
 use crate::commands::query::printer::ShouldPrintProviders;
+use crate::commands::query::query_limits;
 use crate::commands::query::query_target_ext::QueryCommandTarget;
+use crate::target_hash::BuckTargetHash;
+use crate::target_hash::TargetHashes;
 
 impl QueryCommandTarget for TargetNode {
     fn call_stack(&self) -> Option<String> {
         TargetNodeData::call_stack(self)
     }
 
+    fn target_hash(&self) -> Option<BuckTargetHash> {
+        Some(TargetHashes::compute_immediate_one(self, true))
+    }
+
+    fn select_resolutions(
+        &self,
+    ) -> buck2_error::Result<Option<BTreeMap<&str, Vec<SelectResolution>>>> {
+        Ok(None)
+    }
+
     fn attr_to_string_alternate(&self, options: AttrFmtOptions, attr: &Self::Attr<'_>) -> String {
         format!(
             "{:#}",
@@ -130,12 +145,14 @@ async fn uquery(
         &cell_resolver,
         &request.output_attributes,
         request.unstable_output_format,
-    )?;
+    )?
+    .with_target_hashes(request.target_hash);
 
     let UqueryRequest {
         query,
         query_args,
         context,
+        limits,
         ..
     } = request;
 
@@ -145,7 +162,13 @@ async fn uquery(
 
     let query_result = QUERY_FRONTEND
         .get()?
-        .eval_uquery(&mut ctx, server_ctx.working_dir(), query, query_args)
+        .eval_uquery(
+            &mut ctx,
+            server_ctx.working_dir(),
+            query,
+            query_args,
+            query_limits(limits.as_ref())?,
+        )
         .await?;
 
     match query_result {
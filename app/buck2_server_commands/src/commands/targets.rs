@@ -211,7 +211,7 @@ async fn targets_with_output(
         }
         Some(targets_request::Targets::Other(other)) => {
             if other.streaming {
-                let formatter = create_formatter(request, other)?;
+                let formatter = create_formatter(request, other, cell_resolver.root_cell())?;
                 let hashing = match TargetHashGraphType::from_i32(other.target_hash_graph_type)
                     .expect("buck cli should send valid target hash graph type")
                 {
@@ -237,7 +237,7 @@ async fn targets_with_output(
                     serialized_targets_output: String::new(),
                 })
             } else {
-                let formatter = create_formatter(request, other)?;
+                let formatter = create_formatter(request, other, cell_resolver.root_cell())?;
                 let global_cfg_options = global_cfg_options_from_client_context(
                     request
                         .target_cfg
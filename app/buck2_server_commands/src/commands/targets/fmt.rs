@@ -18,6 +18,7 @@ use buck2_cli_proto::HasClientContext;
 use buck2_cli_proto::TargetsRequest;
 use buck2_core::bzl::ImportPath;
 use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::name::CellName;
 use buck2_core::package::PackageLabel;
 use buck2_error::internal_error;
 use buck2_error::BuckErrorContext;
@@ -33,6 +34,9 @@ use buck2_node::nodes::attributes::TARGET_HASH;
 use buck2_node::nodes::attributes::TYPE;
 use buck2_node::nodes::unconfigured::TargetNodeRef;
 use buck2_node::super_package::SuperPackage;
+use buck2_query::query::snapshot::SnapshotHeader;
+use buck2_query::query::snapshot::SnapshotTarget;
+use buck2_query::query::snapshot::SNAPSHOT_VERSION;
 use buck2_util::indent::indent;
 use gazebo::prelude::SliceExt;
 use regex::RegexSet;
@@ -366,6 +370,52 @@ impl TargetFormatter for StatsFormat {
     }
 }
 
+/// Writes a snapshot of the target graph that `buck2 uquery --snapshot` can query.
+struct SnapshotFormat {
+    root_cell: CellName,
+    attr_inspect_opts: AttrInspectOptions,
+}
+
+impl TargetFormatter for SnapshotFormat {
+    fn begin(&self, buffer: &mut String) {
+        let header = SnapshotHeader {
+            version: SNAPSHOT_VERSION,
+            root_cell: self.root_cell.to_string(),
+        };
+        writeln!(buffer, "{}", serde_json::to_string(&header).unwrap()).unwrap();
+    }
+
+    fn target(&self, target_info: TargetInfo<'_>, buffer: &mut String) {
+        let node = target_info.node;
+        let pkg = node.label().pkg();
+        let target = SnapshotTarget {
+            label: node.label().to_string(),
+            rule_type: node.rule_type().to_string(),
+            buildfile: node.buildfile_path().filename().to_string(),
+            oncall: node.oncall().map(|x| x.to_owned()),
+            deps: node.deps().map(|t| t.to_string()).collect(),
+            target_deps: node.target_deps().map(|t| t.to_string()).collect(),
+            exec_deps: node.exec_deps().map(|t| t.to_string()).collect(),
+            toolchain_deps: node.toolchain_deps().map(|t| t.to_string()).collect(),
+            configuration_deps: node
+                .get_configuration_deps()
+                .map(|t| t.0.target().to_string())
+                .collect(),
+            tests: node
+                .to_owned()
+                .tests()
+                .map(|t| t.target().to_string())
+                .collect(),
+            inputs: node.inputs().map(|p| p.to_string()).collect(),
+            attrs: node
+                .attrs(self.attr_inspect_opts)
+                .map(|a| (a.name.to_owned(), value_to_json(a.value, pkg).unwrap()))
+                .collect(),
+        };
+        writeln!(buffer, "{}", serde_json::to_string(&target).unwrap()).unwrap();
+    }
+}
+
 struct TargetNameFormat {
     target_call_stacks: bool,
     target_hash_graph_type: TargetHashGraphType,
@@ -397,6 +447,7 @@ pub(crate) fn print_target_call_stack_after_target(out: &mut String, call_stack:
 pub(crate) fn create_formatter(
     request: &TargetsRequest,
     other: &targets_request::Other,
+    root_cell: CellName,
 ) -> buck2_error::Result<Arc<dyn TargetFormatter>> {
     let output_format = OutputFormat::from_i32(request.output_format)
         .internal_error("Invalid value of `output_format`")?;
@@ -418,6 +469,14 @@ pub(crate) fn create_formatter(
     match output_format {
         OutputFormat::Unknown => Err(internal_error!("`output_format` is not set")),
         OutputFormat::Stats => Ok(Arc::new(StatsFormat)),
+        OutputFormat::Snapshot => Ok(Arc::new(SnapshotFormat {
+            root_cell,
+            attr_inspect_opts: if other.include_default_attributes {
+                AttrInspectOptions::All
+            } else {
+                AttrInspectOptions::DefinedOnly
+            },
+        })),
         OutputFormat::Text => Ok(Arc::new(TargetNameFormat {
             target_call_stacks,
             target_hash_graph_type: TargetHashGraphType::from_i32(other.target_hash_graph_type)
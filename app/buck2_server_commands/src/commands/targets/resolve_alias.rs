@@ -14,6 +14,8 @@
 enum ResolveAliasError {
     #[error("`--stat` format is not supported by `--resolve-alias`")]
     StatFormatNotSupported,
+    #[error("`--snapshot` format is not supported by `--resolve-alias`")]
+    SnapshotFormatNotSupported,
 }
 
 use std::collections::HashMap;
@@ -161,6 +163,9 @@ pub(crate) async fn targets_resolve_aliases(
             &json_writer as &dyn ResolveAliasFormatter
         }
         OutputFormat::Stats => return Err(ResolveAliasError::StatFormatNotSupported.into()),
+        OutputFormat::Snapshot => {
+            return Err(ResolveAliasError::SnapshotFormatNotSupported.into());
+        }
     };
 
     let mut needs_separator = false;
@@ -0,0 +1,104 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Writes target graphs as a Cypher `CREATE` statement (see
+//! <https://neo4j.com/docs/cypher-manual/current/clauses/create/>), for loading into Neo4j.
+//!
+//! Targets become `Target` nodes with a `label` property and a property per selected attribute.
+//! Deps become `TARGET_DEP`, `EXEC_DEP`, `TOOLCHAIN_DEP` or `CONFIGURATION_DEP` relationships.
+
+use std::io::Write;
+
+use crate::target_graph::TargetGraph;
+
+fn escape_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Attribute names may contain characters (like `.`) that aren't allowed in plain names.
+fn escape_name(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+pub struct Cypher {}
+
+impl Cypher {
+    pub(crate) fn render<W: Write>(graph: &TargetGraph, mut w: W) -> buck2_error::Result<()> {
+        if graph.nodes.is_empty() {
+            return Ok(());
+        }
+
+        for (i, node) in graph.nodes.iter().enumerate() {
+            write!(
+                w,
+                "CREATE (n{}:Target {{label: {}",
+                i,
+                escape_string(&node.label)
+            )?;
+            for (name, value) in node.attrs.iter() {
+                write!(w, ", {}: {}", escape_name(name), escape_string(value))?;
+            }
+            writeln!(w, "}})")?;
+        }
+        for (i, node) in graph.nodes.iter().enumerate() {
+            for edge in &node.edges {
+                writeln!(
+                    w,
+                    "CREATE (n{})-[:{}_DEP]->(n{})",
+                    i,
+                    edge.kind.as_str().to_uppercase(),
+                    edge.to
+                )?;
+            }
+        }
+        writeln!(w, ";")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark_map::small_map::SmallMap;
+
+    use super::*;
+    use crate::target_graph::TargetGraphEdge;
+    use crate::target_graph::TargetGraphEdgeKind;
+    use crate::target_graph::TargetGraphNode;
+
+    #[test]
+    fn test_render() {
+        let graph = TargetGraph {
+            nodes: vec![
+                TargetGraphNode {
+                    label: "root//:a".to_owned(),
+                    attrs: SmallMap::from_iter([("buck.type".to_owned(), "it's".to_owned())]),
+                    edges: vec![TargetGraphEdge {
+                        to: 1,
+                        kind: TargetGraphEdgeKind::Configuration,
+                    }],
+                },
+                TargetGraphNode {
+                    label: "root//:b".to_owned(),
+                    attrs: SmallMap::new(),
+                    edges: Vec::new(),
+                },
+            ],
+        };
+        let mut out = Vec::new();
+        Cypher::render(&graph, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"CREATE (n0:Target {label: 'root//:a', `buck.type`: 'it\'s'})
+CREATE (n1:Target {label: 'root//:b'})
+CREATE (n0)-[:CONFIGURATION_DEP]->(n1)
+;
+"#
+        );
+    }
+}
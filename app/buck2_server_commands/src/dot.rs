@@ -69,6 +69,17 @@ pub(crate) trait DotNode {
 pub struct DotEdge<'a> {
     from: &'a str,
     to: &'a str,
+    label: Option<&'a str>,
+}
+
+impl<'a> DotEdge<'a> {
+    /// The attribute list to write after the edge, if any.
+    fn attrs(&self) -> String {
+        match self.label {
+            Some(label) => format!(" [label={}]", escape_id(label)),
+            None => String::new(),
+        }
+    }
 }
 
 pub(crate) trait DotDigraph<'a> {
@@ -122,7 +133,13 @@ impl Dot {
             let attrs = node.attrs()?;
             writeln!(w, "  {} [{}];", escape_id(&node.id()), attrs)?;
             graph.for_each_edge(node, |edge| {
-                writeln!(w, "  {} -> {};", escape_id(edge.from), escape_id(edge.to))?;
+                writeln!(
+                    w,
+                    "  {} -> {}{};",
+                    escape_id(edge.from),
+                    escape_id(edge.to),
+                    edge.attrs()
+                )?;
                 Ok(())
             })?;
             Ok(())
@@ -168,9 +185,10 @@ impl DotCompact {
             graph.for_each_edge(node, |edge| {
                 writeln!(
                     w,
-                    "  {} -> {};",
+                    "  {} -> {}{};",
                     name_to_number(&escape_id(edge.from)),
-                    name_to_number(&escape_id(edge.to))
+                    name_to_number(&escape_id(edge.to)),
+                    edge.attrs()
                 )?;
                 Ok(())
             })?;
@@ -19,6 +19,7 @@ use crate::dot::DotDigraph;
 use crate::dot::DotEdge;
 use crate::dot::DotNode;
 use crate::dot::DotNodeAttrs;
+use crate::target_graph::TargetGraphEdgeKind;
 
 pub struct DotTargetGraphNode<'a, T: QueryTarget>(&'a T, &'a DotTargetGraph<T>);
 
@@ -26,6 +27,8 @@ pub struct DotTargetGraphNode<'a, T: QueryTarget>(&'a T, &'a DotTargetGraph<T>);
 pub struct DotTargetGraph<T: QueryTarget> {
     pub targets: TargetSet<T>,
     pub attributes: Option<RegexSet>,
+    /// Label edges with the kind of dep they are.
+    pub edge_kinds: bool,
 }
 
 impl<'a, T: QueryCommandTarget> DotDigraph<'a> for DotTargetGraph<T> {
@@ -50,12 +53,26 @@ impl<'a, T: QueryCommandTarget> DotDigraph<'a> for DotTargetGraph<T> {
         node: &Self::Node,
         mut f: F,
     ) -> buck2_error::Result<()> {
+        if self.edge_kinds {
+            for (dep, kind) in TargetGraphEdgeKind::deps_of(node.0) {
+                if self.targets.contains(dep) {
+                    f(&DotEdge {
+                        from: &node.0.node_key().to_string(),
+                        to: &dep.to_string(),
+                        label: Some(kind.as_str()),
+                    })?;
+                }
+            }
+            return Ok(());
+        }
+
         for dep in node.0.deps() {
             // Only include edges to other nodes within the subgraph.
             if self.targets.contains(dep) {
                 f(&DotEdge {
                     from: &node.0.node_key().to_string(),
                     to: &dep.to_string(),
+                    label: None,
                 })?;
             }
         }
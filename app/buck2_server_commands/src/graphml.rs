@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Writes target graphs as GraphML (see <http://graphml.graphdrawing.org/>), e.g. for importing
+//! into Neo4j or yEd.
+
+use std::io::Write;
+
+use starlark_map::small_set::SmallSet;
+
+use crate::target_graph::TargetGraph;
+
+fn escape(value: &str) -> String {
+    let mut s = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' => s.push_str("&gt;"),
+            '"' => s.push_str("&quot;"),
+            '\'' => s.push_str("&apos;"),
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+pub struct GraphMl {}
+
+impl GraphMl {
+    pub(crate) fn render<W: Write>(graph: &TargetGraph, mut w: W) -> buck2_error::Result<()> {
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+
+        // Keys have to be declared before the graph. Node attributes use their index as the id,
+        // as attribute names aren't necessarily valid ids.
+        let mut attr_names = SmallSet::new();
+        for node in &graph.nodes {
+            for name in node.attrs.keys() {
+                attr_names.insert(name.as_str());
+            }
+        }
+        writeln!(
+            w,
+            r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#
+        )?;
+        for (i, name) in attr_names.iter().enumerate() {
+            writeln!(
+                w,
+                r#"  <key id="a{}" for="node" attr.name="{}" attr.type="string"/>"#,
+                i,
+                escape(name)
+            )?;
+        }
+        writeln!(
+            w,
+            r#"  <key id="kind" for="edge" attr.name="kind" attr.type="string"/>"#
+        )?;
+
+        writeln!(w, r#"  <graph id="result_graph" edgedefault="directed">"#)?;
+        for (i, node) in graph.nodes.iter().enumerate() {
+            writeln!(w, r#"    <node id="n{}">"#, i)?;
+            writeln!(
+                w,
+                r#"      <data key="label">{}</data>"#,
+                escape(&node.label)
+            )?;
+            for (name, value) in node.attrs.iter() {
+                let key = attr_names
+                    .get_index_of(&name.as_str())
+                    .expect("attribute keys are declared for all nodes");
+                writeln!(w, r#"      <data key="a{}">{}</data>"#, key, escape(value))?;
+            }
+            writeln!(w, "    </node>")?;
+        }
+        for (i, node) in graph.nodes.iter().enumerate() {
+            for edge in &node.edges {
+                writeln!(w, r#"    <edge source="n{}" target="n{}">"#, i, edge.to)?;
+                writeln!(w, r#"      <data key="kind">{}</data>"#, edge.kind.as_str())?;
+                writeln!(w, "    </edge>")?;
+            }
+        }
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark_map::small_map::SmallMap;
+
+    use super::*;
+    use crate::target_graph::TargetGraphEdge;
+    use crate::target_graph::TargetGraphEdgeKind;
+    use crate::target_graph::TargetGraphNode;
+
+    #[test]
+    fn test_render() {
+        let graph = TargetGraph {
+            nodes: vec![
+                TargetGraphNode {
+                    label: "root//:a".to_owned(),
+                    attrs: SmallMap::from_iter([("name".to_owned(), "a<b>".to_owned())]),
+                    edges: vec![TargetGraphEdge {
+                        to: 1,
+                        kind: TargetGraphEdgeKind::Exec,
+                    }],
+                },
+                TargetGraphNode {
+                    label: "root//:b".to_owned(),
+                    attrs: SmallMap::new(),
+                    edges: Vec::new(),
+                },
+            ],
+        };
+        let mut out = Vec::new();
+        GraphMl::render(&graph, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="label" for="node" attr.name="label" attr.type="string"/>
+  <key id="a0" for="node" attr.name="name" attr.type="string"/>
+  <key id="kind" for="edge" attr.name="kind" attr.type="string"/>
+  <graph id="result_graph" edgedefault="directed">
+    <node id="n0">
+      <data key="label">root//:a</data>
+      <data key="a0">a&lt;b&gt;</data>
+    </node>
+    <node id="n1">
+      <data key="label">root//:b</data>
+    </node>
+    <edge source="n0" target="n1">
+      <data key="kind">exec</data>
+    </edge>
+  </graph>
+</graphml>
+"#
+        );
+    }
+}
@@ -16,9 +16,13 @@
 #![feature(try_blocks)]
 
 pub mod commands;
+pub mod cypher;
 pub mod dot;
+pub mod graphml;
 pub mod html;
 pub(crate) mod json;
+pub mod mermaid;
+pub(crate) mod target_graph;
 pub mod target_hash;
 
 pub fn init_late_bindings() {
@@ -0,0 +1,104 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Writes target graphs as Mermaid flowcharts (see <https://mermaid.js.org/syntax/flowchart.html>),
+//! which render inline in Markdown on most code hosts.
+
+use std::io::Write;
+
+use crate::target_graph::TargetGraph;
+use crate::target_graph::TargetGraphEdgeKind;
+
+/// Labels are written as quoted strings, in which Mermaid supports entity codes for characters
+/// that would otherwise end the string or be interpreted as HTML.
+fn escape(value: &str) -> String {
+    let mut s = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => s.push_str("#quot;"),
+            '<' => s.push_str("#lt;"),
+            '>' => s.push_str("#gt;"),
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+pub struct Mermaid {}
+
+impl Mermaid {
+    pub(crate) fn render<W: Write>(graph: &TargetGraph, mut w: W) -> buck2_error::Result<()> {
+        writeln!(w, "flowchart TD")?;
+        for (i, node) in graph.nodes.iter().enumerate() {
+            let mut label = escape(&node.label);
+            for (name, value) in node.attrs.iter() {
+                label.push_str("<br/>");
+                label.push_str(&escape(&format!("{} = {}", name, value)));
+            }
+            writeln!(w, "  n{}[\"{}\"]", i, label)?;
+        }
+        // Target deps are drawn as plain arrows, the other kinds of deps as labelled dotted ones.
+        for (i, node) in graph.nodes.iter().enumerate() {
+            for edge in &node.edges {
+                match edge.kind {
+                    TargetGraphEdgeKind::Target => writeln!(w, "  n{} --> n{}", i, edge.to)?,
+                    kind => writeln!(w, "  n{} -.->|{}| n{}", i, kind.as_str(), edge.to)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark_map::small_map::SmallMap;
+
+    use super::*;
+    use crate::target_graph::TargetGraphEdge;
+    use crate::target_graph::TargetGraphNode;
+
+    #[test]
+    fn test_render() {
+        let graph = TargetGraph {
+            nodes: vec![
+                TargetGraphNode {
+                    label: "root//:a".to_owned(),
+                    attrs: SmallMap::from_iter([("name".to_owned(), "\"a\"".to_owned())]),
+                    edges: vec![
+                        TargetGraphEdge {
+                            to: 1,
+                            kind: TargetGraphEdgeKind::Target,
+                        },
+                        TargetGraphEdge {
+                            to: 1,
+                            kind: TargetGraphEdgeKind::Toolchain,
+                        },
+                    ],
+                },
+                TargetGraphNode {
+                    label: "root//:b".to_owned(),
+                    attrs: SmallMap::new(),
+                    edges: Vec::new(),
+                },
+            ],
+        };
+        let mut out = Vec::new();
+        Mermaid::render(&graph, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"flowchart TD
+  n0["root//:a<br/>name = #quot;a#quot;"]
+  n1["root//:b"]
+  n0 --> n1
+  n0 -.->|toolchain| n1
+"#
+        );
+    }
+}
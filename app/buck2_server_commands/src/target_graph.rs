@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The subgraph of a query result that the graph output formats (GraphML, Mermaid, Cypher) write.
+//! Edges carry the kind of dependency they come from.
+
+use std::collections::HashMap;
+
+use buck2_query::query::environment::AttrFmtOptions;
+use buck2_query::query::environment::QueryTarget;
+use buck2_query::query::environment::QueryTargets;
+use buck2_query::query::syntax::simple::eval::set::TargetSet;
+use regex::RegexSet;
+use starlark_map::small_map::SmallMap;
+
+use crate::commands::query::query_target_ext::QueryCommandTarget;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TargetGraphEdgeKind {
+    Target,
+    Exec,
+    Toolchain,
+    Configuration,
+}
+
+impl TargetGraphEdgeKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            TargetGraphEdgeKind::Target => "target",
+            TargetGraphEdgeKind::Exec => "exec",
+            TargetGraphEdgeKind::Toolchain => "toolchain",
+            TargetGraphEdgeKind::Configuration => "configuration",
+        }
+    }
+
+    /// The deps of `target`, with their kind. A dep that is a dep of several kinds appears once
+    /// for each.
+    pub(crate) fn deps_of<T: QueryTarget>(target: &T) -> Vec<(&T::Key, TargetGraphEdgeKind)> {
+        let mut deps = Vec::new();
+        let by_kind = [
+            (
+                TargetGraphEdgeKind::Target,
+                target.target_deps().collect::<Vec<_>>(),
+            ),
+            (TargetGraphEdgeKind::Exec, target.exec_deps().collect()),
+            (
+                TargetGraphEdgeKind::Toolchain,
+                target.toolchain_deps().collect(),
+            ),
+            (
+                TargetGraphEdgeKind::Configuration,
+                target.configuration_deps().collect(),
+            ),
+        ];
+        for (kind, keys) in by_kind {
+            for key in keys {
+                if !deps.iter().any(|(k, d)| *k == key && *d == kind) {
+                    deps.push((key, kind));
+                }
+            }
+        }
+        deps
+    }
+}
+
+pub(crate) struct TargetGraphEdge {
+    /// Index of the node the edge points at.
+    pub(crate) to: usize,
+    pub(crate) kind: TargetGraphEdgeKind,
+}
+
+pub(crate) struct TargetGraphNode {
+    pub(crate) label: String,
+    /// The attributes selected with `--output-attribute`, formatted like for `--dot`.
+    pub(crate) attrs: SmallMap<String, String>,
+    pub(crate) edges: Vec<TargetGraphEdge>,
+}
+
+pub(crate) struct TargetGraph {
+    pub(crate) nodes: Vec<TargetGraphNode>,
+}
+
+impl TargetGraph {
+    pub(crate) fn new<T: QueryCommandTarget>(
+        targets: &TargetSet<T>,
+        attributes: &Option<RegexSet>,
+    ) -> buck2_error::Result<TargetGraph> {
+        let index: HashMap<String, usize> = targets
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.node_key().to_string(), i))
+            .collect();
+
+        let mut nodes = Vec::with_capacity(targets.len());
+        for target in targets.iter() {
+            let mut attrs = SmallMap::new();
+            if let Some(attr_regex) = attributes {
+                QueryTargets::for_all_attrs::<buck2_error::Error, _, _>(
+                    target,
+                    |attr_name, attr_value| {
+                        if attr_regex.is_match(attr_name) {
+                            attrs.insert(
+                                attr_name.to_owned(),
+                                target
+                                    .attr_display(
+                                        attr_value,
+                                        AttrFmtOptions {
+                                            exclude_quotes: true,
+                                        },
+                                    )
+                                    .to_string(),
+                            );
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+
+            let edges = TargetGraphEdgeKind::deps_of(target)
+                .into_iter()
+                // Only include edges to other nodes within the subgraph.
+                .filter_map(|(dep, kind)| {
+                    index
+                        .get(&dep.to_string())
+                        .map(|to| TargetGraphEdge { to: *to, kind })
+                })
+                .collect();
+
+            nodes.push(TargetGraphNode {
+                label: target.node_key().to_string(),
+                attrs,
+                edges,
+            });
+        }
+
+        Ok(TargetGraph { nodes })
+    }
+}
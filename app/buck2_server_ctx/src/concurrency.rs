@@ -109,6 +109,9 @@ pub struct ConcurrencyHandler {
     dice: Arc<Dice>,
     /// Used to prevent commands (clean --stale) from running in parallel with dice commands
     exclusive_command_lock: ExclusiveCommandLock,
+    /// The version of the last transaction a command committed, `None` until one does.
+    #[allocative(skip)]
+    versions: tokio::sync::watch::Sender<Option<DiceEquality>>,
 }
 
 #[derive(Allocative)]
@@ -329,9 +332,16 @@ impl ConcurrencyHandler {
             cond: Condvar::new(),
             dice,
             exclusive_command_lock: ExclusiveCommandLock::new(),
+            versions: tokio::sync::watch::channel(None).0,
         })
     }
 
+    /// Notifications of the DICE versions that commands run at, whenever one runs at a different
+    /// version than the last.
+    pub fn subscribe_to_versions(&self) -> tokio::sync::watch::Receiver<Option<DiceEquality>> {
+        self.versions.subscribe()
+    }
+
     /// Enters a critical section that requires concurrent command synchronization,
     /// and runs the given `exec` function in the critical section.
     pub async fn enter<F, Fut, R>(
@@ -491,6 +501,15 @@ impl ConcurrencyHandler {
                     }
                     .await?;
 
+                    let version = Some(transaction.equality_token());
+                    self.versions.send_if_modified(|last| {
+                        if *last == version {
+                            return false;
+                        }
+                        *last = version;
+                        true
+                    });
+
                     if let Some(active) = active {
                         let is_same_state = transaction.equivalent(&active.version);
 
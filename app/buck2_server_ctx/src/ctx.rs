@@ -33,6 +33,7 @@ use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_futures::cancellation::CancellationContext;
 use dice::DiceComputations;
+use dice::DiceEquality;
 use dice::DiceTransaction;
 use dupe::Dupe;
 
@@ -58,6 +59,13 @@ pub trait ServerCommandContextTrait: Send + Sync {
 
     fn materializer(&self) -> Arc<dyn Materializer>;
 
+    /// Notifications of the DICE versions that commands run at.
+    fn subscribe_to_dice_versions(&self) -> tokio::sync::watch::Receiver<Option<DiceEquality>>;
+
+    /// Notifications of changes that the file watcher has seen but not synced to DICE yet. `None`
+    /// if the file watcher only finds out about changes when it syncs.
+    fn subscribe_to_file_changes(&self) -> Option<tokio::sync::watch::Receiver<()>>;
+
     /// exposes the dice for scoped access, but isn't intended to be callable by anyone
     async fn dice_accessor<'a>(
         &'a self,
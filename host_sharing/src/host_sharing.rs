@@ -174,6 +174,12 @@ impl HostSharingBroker {
         self.num_machine_permits
     }
 
+    /// How many permits are free right now. Other requests may take them before the next call to
+    /// `acquire`.
+    pub fn available_permits(&self) -> usize {
+        self.permits.permits()
+    }
+
     pub async fn acquire(
         &self,
         host_sharing_requirements: &HostSharingRequirements,
@@ -46,13 +46,18 @@ use re_grpc_proto::build::bazel::remote::execution::v2::Digest;
 use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteOperationMetadata;
 use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteRequest as GExecuteRequest;
 use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteResponse as GExecuteResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecutedActionMetadata;
 use re_grpc_proto::build::bazel::remote::execution::v2::FindMissingBlobsRequest;
 use re_grpc_proto::build::bazel::remote::execution::v2::FindMissingBlobsResponse;
 use re_grpc_proto::build::bazel::remote::execution::v2::GetActionResultRequest;
 use re_grpc_proto::build::bazel::remote::execution::v2::GetCapabilitiesRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputDirectory;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputFile;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputSymlink;
 use re_grpc_proto::build::bazel::remote::execution::v2::RequestMetadata;
 use re_grpc_proto::build::bazel::remote::execution::v2::ResultsCachePolicy;
 use re_grpc_proto::build::bazel::remote::execution::v2::ToolDetails;
+use re_grpc_proto::build::bazel::remote::execution::v2::UpdateActionResultRequest;
 use re_grpc_proto::google::bytestream::byte_stream_client::ByteStreamClient;
 use re_grpc_proto::google::bytestream::ReadRequest;
 use re_grpc_proto::google::bytestream::ReadResponse;
@@ -118,6 +123,13 @@ fn check_status(status: Status) -> Result<(), REClientError> {
     })
 }
 
+fn ttimestamp_to(ts: TTimestamp) -> Option<::prost_types::Timestamp> {
+    Some(::prost_types::Timestamp {
+        seconds: ts.seconds,
+        nanos: ts.nanos,
+    })
+}
+
 fn ttimestamp_from(ts: Option<::prost_types::Timestamp>) -> TTimestamp {
     match ts {
         Some(timestamp) => TTimestamp {
@@ -590,10 +602,35 @@ impl REClient {
 
     pub async fn write_action_result(
         &self,
-        _metadata: RemoteExecutionMetadata,
-        _request: WriteActionResultRequest,
+        metadata: RemoteExecutionMetadata,
+        request: WriteActionResultRequest,
     ) -> anyhow::Result<WriteActionResultResponse> {
-        Err(anyhow::anyhow!("Not supported"))
+        let mut client = self.grpc_clients.action_cache_client.clone();
+
+        let res = client
+            .update_action_result(with_re_metadata(
+                UpdateActionResultRequest {
+                    instance_name: self.instance_name.as_str().to_owned(),
+                    action_digest: Some(tdigest_to(request.action_digest)),
+                    action_result: Some(convert_t_action_result2(request.action_result)),
+                    ..Default::default()
+                },
+                metadata,
+                self.runtime_opts.use_fbcode_metadata,
+            ))
+            .await
+            // Keep the code: a cache that refuses writes answers `PERMISSION_DENIED`, which isn't
+            // an error for the permission check.
+            .map_err(|status| REClientError {
+                code: TCode(status.code() as i32),
+                message: status.message().to_owned(),
+                group: TCodeReasonGroup::UNKNOWN,
+            })?;
+
+        Ok(WriteActionResultResponse {
+            actual_action_result: convert_action_result(res.into_inner())?,
+            ttl_seconds: 0,
+        })
     }
 
     pub async fn execute_with_progress(
@@ -1012,6 +1049,72 @@ fn convert_action_result(action_result: ActionResult) -> anyhow::Result<TActionR
     Ok(action_result)
 }
 
+fn convert_t_action_result2(t_action_result: TActionResult2) -> ActionResult {
+    let t_execution_metadata = t_action_result.execution_metadata;
+    let execution_metadata = ExecutedActionMetadata {
+        worker: t_execution_metadata.worker,
+        queued_timestamp: ttimestamp_to(t_execution_metadata.queued_timestamp),
+        worker_start_timestamp: ttimestamp_to(t_execution_metadata.worker_start_timestamp),
+        worker_completed_timestamp: ttimestamp_to(t_execution_metadata.worker_completed_timestamp),
+        input_fetch_start_timestamp: ttimestamp_to(
+            t_execution_metadata.input_fetch_start_timestamp,
+        ),
+        input_fetch_completed_timestamp: ttimestamp_to(
+            t_execution_metadata.input_fetch_completed_timestamp,
+        ),
+        execution_start_timestamp: ttimestamp_to(t_execution_metadata.execution_start_timestamp),
+        execution_completed_timestamp: ttimestamp_to(
+            t_execution_metadata.execution_completed_timestamp,
+        ),
+        output_upload_start_timestamp: ttimestamp_to(
+            t_execution_metadata.output_upload_start_timestamp,
+        ),
+        output_upload_completed_timestamp: ttimestamp_to(
+            t_execution_metadata.output_upload_completed_timestamp,
+        ),
+        ..Default::default()
+    };
+
+    let output_files = t_action_result
+        .output_files
+        .into_map(|output_file| OutputFile {
+            path: output_file.name,
+            digest: Some(tdigest_to(output_file.digest.digest)),
+            is_executable: output_file.executable,
+            ..Default::default()
+        });
+
+    let output_symlinks =
+        t_action_result
+            .output_symlinks
+            .into_map(|output_symlink| OutputSymlink {
+                path: output_symlink.name,
+                target: output_symlink.target,
+                ..Default::default()
+            });
+
+    let output_directories = t_action_result
+        .output_directories
+        .into_map(|output_directory| OutputDirectory {
+            path: output_directory.path,
+            tree_digest: Some(tdigest_to(output_directory.tree_digest)),
+            ..Default::default()
+        });
+
+    ActionResult {
+        output_files,
+        output_symlinks,
+        output_directories,
+        exit_code: t_action_result.exit_code,
+        stdout_raw: t_action_result.stdout_raw.unwrap_or_default(),
+        stdout_digest: t_action_result.stdout_digest.map(tdigest_to),
+        stderr_raw: t_action_result.stderr_raw.unwrap_or_default(),
+        stderr_digest: t_action_result.stderr_digest.map(tdigest_to),
+        execution_metadata: Some(execution_metadata),
+        ..Default::default()
+    }
+}
+
 async fn download_impl<Byt, BytRet, Cas>(
     instance_name: &InstanceName,
     request: DownloadRequest,
@@ -2481,4 +2584,58 @@ mod tests {
         assert_eq!(substitute_env_vars_impl("FOO", getter).unwrap(), "FOO");
         assert!(substitute_env_vars_impl("$FOO$BAZ", getter).is_err());
     }
+
+    #[test]
+    fn test_action_result_roundtrip() {
+        let digest = |hash: &str| Digest {
+            hash: hash.to_owned(),
+            size_bytes: 3,
+        };
+        let timestamp = |seconds| {
+            Some(::prost_types::Timestamp {
+                seconds,
+                nanos: 100,
+            })
+        };
+        let action_result = ActionResult {
+            output_files: vec![OutputFile {
+                path: "out/file".to_owned(),
+                digest: Some(digest("aa")),
+                is_executable: true,
+                ..Default::default()
+            }],
+            output_symlinks: vec![OutputSymlink {
+                path: "out/link".to_owned(),
+                target: "file".to_owned(),
+                ..Default::default()
+            }],
+            output_directories: vec![OutputDirectory {
+                path: "out/dir".to_owned(),
+                tree_digest: Some(digest("bb")),
+                ..Default::default()
+            }],
+            exit_code: 1,
+            stdout_raw: b"out".to_vec(),
+            stderr_digest: Some(digest("cc")),
+            execution_metadata: Some(ExecutedActionMetadata {
+                worker: "worker".to_owned(),
+                queued_timestamp: timestamp(1),
+                worker_start_timestamp: timestamp(2),
+                worker_completed_timestamp: timestamp(3),
+                input_fetch_start_timestamp: timestamp(4),
+                input_fetch_completed_timestamp: timestamp(5),
+                execution_start_timestamp: timestamp(6),
+                execution_completed_timestamp: timestamp(7),
+                output_upload_start_timestamp: timestamp(8),
+                output_upload_completed_timestamp: timestamp(9),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            convert_t_action_result2(convert_action_result(action_result.clone()).unwrap()),
+            action_result
+        );
+    }
 }
@@ -0,0 +1,71 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::Arc;
+
+use re_grpc_proto::build::bazel::remote::execution::v2::action_cache_server::ActionCache;
+use re_grpc_proto::build::bazel::remote::execution::v2::ActionResult;
+use re_grpc_proto::build::bazel::remote::execution::v2::GetActionResultRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::UpdateActionResultRequest;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+
+use crate::storage::to_status;
+use crate::storage::Storage;
+
+pub(crate) struct ActionCacheService {
+    pub(crate) storage: Arc<Storage>,
+    /// Whether clients may write action results. Results of actions this server executes are
+    /// cached either way.
+    pub(crate) update_enabled: bool,
+}
+
+#[tonic::async_trait]
+impl ActionCache for ActionCacheService {
+    async fn get_action_result(
+        &self,
+        request: Request<GetActionResultRequest>,
+    ) -> Result<Response<ActionResult>, Status> {
+        let action_digest = request.into_inner().action_digest.unwrap_or_default();
+        match self
+            .storage
+            .get_action_result(&action_digest)
+            .await
+            .map_err(to_status)?
+        {
+            Some(action_result) => Ok(Response::new(action_result)),
+            None => Err(Status::not_found(format!(
+                "No result for action `{}:{}`",
+                action_digest.hash, action_digest.size_bytes
+            ))),
+        }
+    }
+
+    async fn update_action_result(
+        &self,
+        request: Request<UpdateActionResultRequest>,
+    ) -> Result<Response<ActionResult>, Status> {
+        if !self.update_enabled {
+            return Err(Status::permission_denied(
+                "This server does not accept action results from clients",
+            ));
+        }
+        let request = request.into_inner();
+        let action_digest = request.action_digest.unwrap_or_default();
+        let action_result = request
+            .action_result
+            .ok_or_else(|| Status::invalid_argument("Missing `action_result`"))?;
+        self.storage
+            .put_action_result(&action_digest, &action_result)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(action_result))
+    }
+}
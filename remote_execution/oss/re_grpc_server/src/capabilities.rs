@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use re_grpc_proto::build::bazel::remote::execution::v2::capabilities_server::Capabilities;
+use re_grpc_proto::build::bazel::remote::execution::v2::symlink_absolute_path_strategy;
+use re_grpc_proto::build::bazel::remote::execution::v2::ActionCacheUpdateCapabilities;
+use re_grpc_proto::build::bazel::remote::execution::v2::CacheCapabilities;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecutionCapabilities;
+use re_grpc_proto::build::bazel::remote::execution::v2::GetCapabilitiesRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::ServerCapabilities;
+use re_grpc_proto::build::bazel::semver::SemVer;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+
+use crate::storage::DigestFunction;
+
+pub(crate) struct CapabilitiesService {
+    pub(crate) digest_function: DigestFunction,
+    pub(crate) max_batch_total_size_bytes: i64,
+    pub(crate) exec_enabled: bool,
+    pub(crate) action_cache_update_enabled: bool,
+}
+
+#[tonic::async_trait]
+impl Capabilities for CapabilitiesService {
+    async fn get_capabilities(
+        &self,
+        _request: Request<GetCapabilitiesRequest>,
+    ) -> Result<Response<ServerCapabilities>, Status> {
+        let version = |minor| SemVer {
+            major: 2,
+            minor,
+            patch: 0,
+            prerelease: String::new(),
+        };
+
+        Ok(Response::new(ServerCapabilities {
+            cache_capabilities: Some(CacheCapabilities {
+                digest_functions: vec![self.digest_function.to_proto() as i32],
+                action_cache_update_capabilities: Some(ActionCacheUpdateCapabilities {
+                    update_enabled: self.action_cache_update_enabled,
+                }),
+                cache_priority_capabilities: None,
+                max_batch_total_size_bytes: self.max_batch_total_size_bytes,
+                symlink_absolute_path_strategy: symlink_absolute_path_strategy::Value::Allowed
+                    as i32,
+                supported_compressors: Vec::new(),
+                supported_batch_update_compressors: Vec::new(),
+            }),
+            execution_capabilities: Some(ExecutionCapabilities {
+                digest_function: self.digest_function.to_proto() as i32,
+                exec_enabled: self.exec_enabled,
+                execution_priority_capabilities: None,
+                supported_node_properties: Vec::new(),
+            }),
+            deprecated_api_version: None,
+            low_api_version: Some(version(0)),
+            high_api_version: Some(version(2)),
+        }))
+    }
+}
@@ -0,0 +1,521 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The `ContentAddressableStorage` and `ByteStream` services, which both serve blobs.
+
+use std::collections::HashSet;
+use std::io::SeekFrom;
+use std::sync::Arc;
+
+use futures::stream;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use re_grpc_proto::build::bazel::remote::execution::v2::batch_read_blobs_response;
+use re_grpc_proto::build::bazel::remote::execution::v2::batch_update_blobs_response;
+use re_grpc_proto::build::bazel::remote::execution::v2::compressor;
+use re_grpc_proto::build::bazel::remote::execution::v2::content_addressable_storage_server::ContentAddressableStorage;
+use re_grpc_proto::build::bazel::remote::execution::v2::BatchReadBlobsRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::BatchReadBlobsResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::BatchUpdateBlobsRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::BatchUpdateBlobsResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::Digest;
+use re_grpc_proto::build::bazel::remote::execution::v2::Directory;
+use re_grpc_proto::build::bazel::remote::execution::v2::FindMissingBlobsRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::FindMissingBlobsResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::GetTreeRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::GetTreeResponse;
+use re_grpc_proto::google::bytestream::byte_stream_server::ByteStream;
+use re_grpc_proto::google::bytestream::QueryWriteStatusRequest;
+use re_grpc_proto::google::bytestream::QueryWriteStatusResponse;
+use re_grpc_proto::google::bytestream::ReadRequest;
+use re_grpc_proto::google::bytestream::ReadResponse;
+use re_grpc_proto::google::bytestream::WriteRequest;
+use re_grpc_proto::google::bytestream::WriteResponse;
+use re_grpc_proto::google::rpc::Code;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tonic::Streaming;
+
+use crate::storage::to_rpc_status;
+use crate::storage::to_status;
+use crate::storage::Storage;
+use crate::storage::StorageError;
+
+/// Size of the chunks `ByteStream.Read` sends.
+const READ_CHUNK_BYTES: usize = 1 << 20;
+
+pub(crate) struct CasService {
+    pub(crate) storage: Arc<Storage>,
+    /// The most bytes of blobs a batch read may ask for, as advertised in the capabilities.
+    pub(crate) max_batch_total_size_bytes: i64,
+}
+
+fn not_found(digest: &Digest) -> Status {
+    Status::not_found(StorageError::MissingBlob(digest.clone()).to_string())
+}
+
+#[tonic::async_trait]
+impl ContentAddressableStorage for CasService {
+    async fn find_missing_blobs(
+        &self,
+        request: Request<FindMissingBlobsRequest>,
+    ) -> Result<Response<FindMissingBlobsResponse>, Status> {
+        let mut missing_blob_digests = Vec::new();
+        for digest in request.into_inner().blob_digests {
+            if !self.storage.has_blob(&digest).await.map_err(to_status)? {
+                missing_blob_digests.push(digest);
+            }
+        }
+        Ok(Response::new(FindMissingBlobsResponse {
+            missing_blob_digests,
+        }))
+    }
+
+    async fn batch_update_blobs(
+        &self,
+        request: Request<BatchUpdateBlobsRequest>,
+    ) -> Result<Response<BatchUpdateBlobsResponse>, Status> {
+        let mut responses = Vec::new();
+        for request in request.into_inner().requests {
+            let digest = request.digest.unwrap_or_default();
+            let res = if request.compressor != compressor::Value::Identity as i32 {
+                Err(anyhow::anyhow!("Compressed uploads are not supported"))
+            } else {
+                self.storage.write_blob(&digest, &request.data).await
+            };
+            responses.push(batch_update_blobs_response::Response {
+                digest: Some(digest),
+                status: Some(to_rpc_status(res)),
+            });
+        }
+        Ok(Response::new(BatchUpdateBlobsResponse { responses }))
+    }
+
+    async fn batch_read_blobs(
+        &self,
+        request: Request<BatchReadBlobsRequest>,
+    ) -> Result<Response<BatchReadBlobsResponse>, Status> {
+        let digests = request.into_inner().digests;
+        let total_size = digests
+            .iter()
+            .fold(0i64, |total, d| total.saturating_add(d.size_bytes.max(0)));
+        if total_size > self.max_batch_total_size_bytes {
+            return Err(Status::invalid_argument(format!(
+                "Batch read of {} bytes is larger than the limit of {} bytes",
+                total_size, self.max_batch_total_size_bytes
+            )));
+        }
+
+        let mut responses = Vec::new();
+        for digest in digests {
+            let (data, status) = match self.storage.read_blob(&digest).await {
+                Ok(Some(data)) => (data, to_rpc_status(Ok(()))),
+                Ok(None) => (
+                    Vec::new(),
+                    re_grpc_proto::google::rpc::Status {
+                        code: Code::NotFound as i32,
+                        message: not_found(&digest).message().to_owned(),
+                        details: Vec::new(),
+                    },
+                ),
+                Err(e) => (Vec::new(), to_rpc_status(Err(e))),
+            };
+            responses.push(batch_read_blobs_response::Response {
+                digest: Some(digest),
+                data,
+                compressor: compressor::Value::Identity as i32,
+                status: Some(status),
+            });
+        }
+        Ok(Response::new(BatchReadBlobsResponse { responses }))
+    }
+
+    type GetTreeStream = BoxStream<'static, Result<GetTreeResponse, Status>>;
+
+    /// Returns the whole tree in a single page.
+    async fn get_tree(
+        &self,
+        request: Request<GetTreeRequest>,
+    ) -> Result<Response<Self::GetTreeStream>, Status> {
+        let root_digest = request.into_inner().root_digest.unwrap_or_default();
+
+        // Identical subdirectories share a digest, so only send each one once.
+        let mut visited = HashSet::new();
+        let mut directories = Vec::new();
+        let mut queue = vec![root_digest];
+        while let Some(digest) = queue.pop() {
+            if !visited.insert((digest.hash.clone(), digest.size_bytes)) {
+                continue;
+            }
+            let directory: Directory = self
+                .storage
+                .read_message(&digest)
+                .await
+                .map_err(to_status)?
+                .ok_or_else(|| not_found(&digest))?;
+            queue.extend(
+                directory
+                    .directories
+                    .iter()
+                    .filter_map(|d| d.digest.clone()),
+            );
+            directories.push(directory);
+        }
+
+        let response = GetTreeResponse {
+            directories,
+            next_page_token: String::new(),
+        };
+        Ok(Response::new(stream::once(async { Ok(response) }).boxed()))
+    }
+}
+
+/// Extract the digest from a ByteStream resource name. Those look like
+/// `{instance_name}/blobs/{hash}/{size}` for reads, and
+/// `{instance_name}/uploads/{uuid}/blobs/{hash}/{size}` for writes, where the instance name is
+/// optional and may contain slashes.
+fn parse_resource_name(resource_name: &str) -> Result<Digest, Status> {
+    let invalid = || Status::invalid_argument(format!("Invalid resource name `{}`", resource_name));
+
+    let parts: Vec<&str> = resource_name.split('/').collect();
+    if parts.contains(&"compressed-blobs") {
+        return Err(Status::invalid_argument(
+            "Compressed blobs are not supported",
+        ));
+    }
+    let blobs = parts
+        .iter()
+        .position(|p| *p == "blobs")
+        .ok_or_else(invalid)?;
+    let (Some(hash), Some(size)) = (parts.get(blobs + 1), parts.get(blobs + 2)) else {
+        return Err(invalid());
+    };
+    Ok(Digest {
+        hash: (*hash).to_owned(),
+        size_bytes: size.parse().map_err(|_| invalid())?,
+    })
+}
+
+pub(crate) struct ByteStreamService {
+    pub(crate) storage: Arc<Storage>,
+}
+
+#[tonic::async_trait]
+impl ByteStream for ByteStreamService {
+    type ReadStream = BoxStream<'static, Result<ReadResponse, Status>>;
+
+    async fn read(
+        &self,
+        request: Request<ReadRequest>,
+    ) -> Result<Response<Self::ReadStream>, Status> {
+        let request = request.into_inner();
+        let digest = parse_resource_name(&request.resource_name)?;
+        let blob = if self.storage.is_empty_blob(&digest).map_err(to_status)? {
+            None
+        } else {
+            Some(
+                self.storage
+                    .open_blob(&digest)
+                    .await
+                    .map_err(to_status)?
+                    .ok_or_else(|| not_found(&digest))?,
+            )
+        };
+
+        let size = blob.as_ref().map_or(0, |(_, size)| *size);
+        let offset = u64::try_from(request.read_offset)
+            .ok()
+            .filter(|offset| *offset <= size)
+            .ok_or_else(|| Status::out_of_range("Invalid read_offset"))?;
+        let len = match u64::try_from(request.read_limit) {
+            Ok(0) => size - offset,
+            Ok(limit) => limit.min(size - offset),
+            Err(_) => return Err(Status::invalid_argument("Invalid read_limit")),
+        };
+        let Some((mut file, _)) = blob.filter(|_| len != 0) else {
+            return Ok(Response::new(stream::empty().boxed()));
+        };
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(|e| Status::internal(format!("Error seeking in blob: {}", e)))?;
+
+        // Read the blob as the client consumes it, rather than all at once.
+        let chunks = stream::try_unfold((file, len), |(mut file, remaining)| async move {
+            if remaining == 0 {
+                return Ok(None);
+            }
+            let mut data = vec![0; remaining.min(READ_CHUNK_BYTES as u64) as usize];
+            let n = file
+                .read(&mut data)
+                .await
+                .map_err(|e| Status::internal(format!("Error reading blob: {}", e)))?;
+            if n == 0 {
+                return Err(Status::data_loss("Blob was truncated while reading"));
+            }
+            data.truncate(n);
+            Ok(Some((ReadResponse { data }, (file, remaining - n as u64))))
+        });
+        Ok(Response::new(chunks.boxed()))
+    }
+
+    async fn write(
+        &self,
+        request: Request<Streaming<WriteRequest>>,
+    ) -> Result<Response<WriteResponse>, Status> {
+        let mut requests = request.into_inner();
+        let mut writer = self.storage.blob_writer().await.map_err(to_status)?;
+        let mut digest = None;
+
+        while let Some(request) = requests.next().await {
+            let request = request?;
+            if digest.is_none() {
+                digest = Some(parse_resource_name(&request.resource_name)?);
+            }
+            if request.write_offset != writer.size() {
+                return Err(Status::invalid_argument(format!(
+                    "Expected write_offset {}, got {}",
+                    writer.size(),
+                    request.write_offset
+                )));
+            }
+            writer.write(&request.data).await.map_err(to_status)?;
+
+            if request.finish_write {
+                let digest = digest
+                    .as_ref()
+                    .ok_or_else(|| Status::internal("No digest"))?;
+                let committed_size = writer.size();
+                writer.commit(Some(digest)).await.map_err(to_status)?;
+                return Ok(Response::new(WriteResponse { committed_size }));
+            }
+        }
+
+        Err(Status::invalid_argument(
+            "Write ended without `finish_write`",
+        ))
+    }
+
+    /// Writes can't be resumed, so all there is to report is whether the blob made it.
+    async fn query_write_status(
+        &self,
+        request: Request<QueryWriteStatusRequest>,
+    ) -> Result<Response<QueryWriteStatusResponse>, Status> {
+        let digest = parse_resource_name(&request.into_inner().resource_name)?;
+        if self.storage.has_blob(&digest).await.map_err(to_status)? {
+            Ok(Response::new(QueryWriteStatusResponse {
+                committed_size: digest.size_bytes,
+                complete: true,
+            }))
+        } else {
+            Err(not_found(&digest))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use re_grpc_proto::build::bazel::remote::execution::v2::batch_update_blobs_request;
+    use re_grpc_proto::build::bazel::remote::execution::v2::DirectoryNode;
+
+    use super::*;
+    use crate::storage::DigestFunction;
+
+    #[test]
+    fn test_parse_resource_name() {
+        let digest = parse_resource_name("blobs/abc/3").unwrap();
+        assert_eq!((digest.hash.as_str(), digest.size_bytes), ("abc", 3));
+
+        let digest = parse_resource_name("some/instance/uploads/uuid/blobs/abc/3").unwrap();
+        assert_eq!((digest.hash.as_str(), digest.size_bytes), ("abc", 3));
+
+        let digest = parse_resource_name("instance/blobs/abc/3/some/metadata").unwrap();
+        assert_eq!((digest.hash.as_str(), digest.size_bytes), ("abc", 3));
+
+        assert!(parse_resource_name("instance/blobs/abc").is_err());
+        assert!(parse_resource_name("instance/blobs/abc/x").is_err());
+        assert!(parse_resource_name("instance/compressed-blobs/zstd/abc/3").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_update_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(
+            Storage::new(dir.path().to_owned(), DigestFunction::Sha256)
+                .await
+                .unwrap(),
+        );
+        let cas = CasService {
+            storage: storage.clone(),
+            max_batch_total_size_bytes: 1 << 20,
+        };
+
+        let good = storage.digest_function().digest(b"good");
+        let bad = storage.digest_function().digest(b"bad");
+
+        let missing = cas
+            .find_missing_blobs(Request::new(FindMissingBlobsRequest {
+                instance_name: String::new(),
+                blob_digests: vec![good.clone(), bad.clone()],
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .missing_blob_digests;
+        assert_eq!(missing, vec![good.clone(), bad.clone()]);
+
+        let statuses: Vec<_> = cas
+            .batch_update_blobs(Request::new(BatchUpdateBlobsRequest {
+                instance_name: String::new(),
+                requests: vec![
+                    batch_update_blobs_request::Request {
+                        digest: Some(good.clone()),
+                        data: b"good".to_vec(),
+                        compressor: 0,
+                    },
+                    batch_update_blobs_request::Request {
+                        digest: Some(bad.clone()),
+                        data: b"not bad".to_vec(),
+                        compressor: 0,
+                    },
+                ],
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .responses
+            .into_iter()
+            .map(|r| r.status.unwrap().code)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![Code::Ok as i32, Code::InvalidArgument as i32]
+        );
+
+        let responses = cas
+            .batch_read_blobs(Request::new(BatchReadBlobsRequest {
+                instance_name: String::new(),
+                digests: vec![good, bad],
+                acceptable_compressors: Vec::new(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .responses;
+        assert_eq!(responses[0].data, b"good");
+        assert_eq!(responses[0].status.as_ref().unwrap().code, Code::Ok as i32);
+        assert_eq!(
+            responses[1].status.as_ref().unwrap().code,
+            Code::NotFound as i32
+        );
+    }
+
+    #[tokio::test]
+    async fn test_byte_stream_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(
+            Storage::new(dir.path().to_owned(), DigestFunction::Sha256)
+                .await
+                .unwrap(),
+        );
+        let byte_stream = ByteStreamService {
+            storage: storage.clone(),
+        };
+
+        let data: Vec<u8> = (0..READ_CHUNK_BYTES * 5 / 2).map(|i| i as u8).collect();
+        let digest = storage.put_blob(&data).await.unwrap();
+        let resource_name = format!("blobs/{}/{}", digest.hash, digest.size_bytes);
+
+        let read = |read_offset: usize, read_limit: usize| {
+            byte_stream.read(Request::new(ReadRequest {
+                resource_name: resource_name.clone(),
+                read_offset: read_offset as i64,
+                read_limit: read_limit as i64,
+            }))
+        };
+        let chunks = |response: Response<<ByteStreamService as ByteStream>::ReadStream>| async move {
+            response
+                .into_inner()
+                .map(|r| r.unwrap().data)
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let all = chunks(read(0, 0).await.unwrap()).await;
+        assert_eq!(
+            all.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            vec![READ_CHUNK_BYTES, READ_CHUNK_BYTES, READ_CHUNK_BYTES / 2]
+        );
+        assert_eq!(all.concat(), data);
+
+        let part = chunks(read(10, READ_CHUNK_BYTES).await.unwrap()).await;
+        assert_eq!(part.concat(), &data[10..10 + READ_CHUNK_BYTES]);
+
+        assert!(chunks(read(data.len(), 0).await.unwrap()).await.is_empty());
+        assert_eq!(
+            read(data.len() + 1, 0).await.err().unwrap().code(),
+            tonic::Code::OutOfRange
+        );
+
+        // The size is part of the digest, so the same hash with another size is another blob.
+        let wrong_size = byte_stream
+            .read(Request::new(ReadRequest {
+                resource_name: format!("blobs/{}/{}", digest.hash, READ_CHUNK_BYTES),
+                read_offset: 0,
+                read_limit: 0,
+            }))
+            .await;
+        assert_eq!(wrong_size.err().unwrap().code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_get_tree_sends_shared_directories_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(
+            Storage::new(dir.path().to_owned(), DigestFunction::Sha256)
+                .await
+                .unwrap(),
+        );
+        let cas = CasService {
+            storage: storage.clone(),
+            max_batch_total_size_bytes: 1 << 20,
+        };
+
+        let leaf = storage
+            .put_blob(&Directory::default().encode_to_vec())
+            .await
+            .unwrap();
+        let node = |name: &str| DirectoryNode {
+            name: name.to_owned(),
+            digest: Some(leaf.clone()),
+        };
+        let root = Directory {
+            directories: vec![node("a"), node("b")],
+            ..Default::default()
+        };
+        let root = storage.put_blob(&root.encode_to_vec()).await.unwrap();
+
+        let responses = cas
+            .get_tree(Request::new(GetTreeRequest {
+                root_digest: Some(root),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].directories.len(), 2);
+    }
+}
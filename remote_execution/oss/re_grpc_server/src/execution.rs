@@ -0,0 +1,667 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The `Execution` service. Actions run through a buck2 forkserver, in a fresh directory holding
+//! their input root. Operations complete before `Execute` returns, so `WaitExecution` has nothing
+//! to wait for.
+
+use std::collections::HashSet;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Component;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use buck2_forkserver::client::ForkserverClient;
+use buck2_forkserver::run::GatherOutputStatus;
+use futures::future::BoxFuture;
+use futures::stream;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use futures::StreamExt;
+use prost::Message;
+use re_grpc_proto::build::bazel::remote::execution::v2::execution_server::Execution;
+use re_grpc_proto::build::bazel::remote::execution::v2::execution_stage;
+use re_grpc_proto::build::bazel::remote::execution::v2::Action;
+use re_grpc_proto::build::bazel::remote::execution::v2::ActionResult;
+use re_grpc_proto::build::bazel::remote::execution::v2::Command;
+use re_grpc_proto::build::bazel::remote::execution::v2::Digest;
+use re_grpc_proto::build::bazel::remote::execution::v2::Directory;
+use re_grpc_proto::build::bazel::remote::execution::v2::DirectoryNode;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteOperationMetadata;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecutedActionMetadata;
+use re_grpc_proto::build::bazel::remote::execution::v2::FileNode;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputDirectory;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputFile;
+use re_grpc_proto::build::bazel::remote::execution::v2::OutputSymlink;
+use re_grpc_proto::build::bazel::remote::execution::v2::SymlinkNode;
+use re_grpc_proto::build::bazel::remote::execution::v2::Tree;
+use re_grpc_proto::build::bazel::remote::execution::v2::WaitExecutionRequest;
+use re_grpc_proto::google::longrunning::operation;
+use re_grpc_proto::google::longrunning::Operation;
+use re_grpc_proto::google::rpc::Code;
+use tokio::sync::Semaphore;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+
+use crate::storage::to_rpc_status;
+use crate::storage::Storage;
+use crate::storage::StorageError;
+
+pub(crate) struct ExecutionService {
+    pub(crate) storage: Arc<Storage>,
+    pub(crate) forkserver: ForkserverClient,
+    /// Limits how many actions run at once.
+    pub(crate) concurrency: Arc<Semaphore>,
+}
+
+fn now() -> Option<prost_types::Timestamp> {
+    Some(SystemTime::now().into())
+}
+
+fn error_response(code: Code, message: String) -> ExecuteResponse {
+    ExecuteResponse {
+        status: Some(re_grpc_proto::google::rpc::Status {
+            code: code as i32,
+            message,
+            details: Vec::new(),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Paths in actions must stay inside of the directory they run in.
+fn relative_path(path: &str) -> anyhow::Result<&Path> {
+    let path = Path::new(path);
+    if !path
+        .components()
+        .all(|c| matches!(c, Component::Normal(..)))
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid relative path `{}`",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
+fn file_name(name: &str) -> anyhow::Result<&Path> {
+    let path = relative_path(name)?;
+    if path.components().count() != 1 {
+        return Err(anyhow::anyhow!("Invalid file name `{}`", name));
+    }
+    Ok(path)
+}
+
+async fn read_message<M: Message + Default>(
+    storage: &Storage,
+    digest: &Digest,
+) -> anyhow::Result<M> {
+    storage
+        .read_message(digest)
+        .await?
+        .ok_or_else(|| StorageError::MissingBlob(digest.clone()).into())
+}
+
+/// Write the directory `digest` refers to, and everything below it, to `dest`. Symlinks are
+/// created last, so that nothing is ever written through one.
+async fn materialize_directory(
+    storage: &Storage,
+    digest: &Digest,
+    dest: &Path,
+) -> anyhow::Result<()> {
+    let mut queue = vec![(digest.clone(), dest.to_owned())];
+    let mut symlinks = Vec::new();
+    while let Some((digest, dir)) = queue.pop() {
+        let directory: Directory = read_message(storage, &digest).await?;
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Error creating `{}`", dir.display()))?;
+
+        let mut names = HashSet::new();
+        for name in directory
+            .files
+            .iter()
+            .map(|f| &f.name)
+            .chain(directory.directories.iter().map(|d| &d.name))
+            .chain(directory.symlinks.iter().map(|s| &s.name))
+        {
+            if !names.insert(file_name(name)?) {
+                return Err(anyhow::anyhow!(
+                    "Duplicate entry `{}` in `{}`",
+                    name,
+                    dir.display()
+                ));
+            }
+        }
+
+        for file in directory.files {
+            let path = dir.join(&file.name);
+            storage
+                .copy_blob(&file.digest.unwrap_or_default(), &path)
+                .await?;
+            let mode = if file.is_executable { 0o755 } else { 0o644 };
+            tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).await?;
+        }
+        for symlink in directory.symlinks {
+            symlinks.push((dir.join(&symlink.name), symlink.target));
+        }
+        for subdir in directory.directories {
+            queue.push((subdir.digest.unwrap_or_default(), dir.join(&subdir.name)));
+        }
+    }
+    for (path, target) in symlinks {
+        tokio::fs::symlink(&target, &path)
+            .await
+            .with_context(|| format!("Error creating symlink `{}`", path.display()))?;
+    }
+    Ok(())
+}
+
+impl ExecutionService {
+    async fn execute_action(&self, request: &ExecuteRequest) -> anyhow::Result<ExecuteResponse> {
+        let queued_timestamp = now();
+        let action_digest = request.action_digest.clone().unwrap_or_default();
+
+        if !request.skip_cache_lookup {
+            if let Some(result) = self.storage.get_action_result(&action_digest).await? {
+                return Ok(ExecuteResponse {
+                    result: Some(result),
+                    cached_result: true,
+                    ..Default::default()
+                });
+            }
+        }
+
+        let action: Action = read_message(&self.storage, &action_digest).await?;
+        let command: Command = read_message(
+            &self.storage,
+            &action.command_digest.clone().unwrap_or_default(),
+        )
+        .await?;
+
+        let _permit = self.concurrency.acquire().await?;
+
+        let exec_root = self.storage.temp_path();
+        let res = self
+            .run_action(&action_digest, &action, &command, &exec_root)
+            .await;
+        if let Err(e) = tokio::fs::remove_dir_all(&exec_root).await {
+            tracing::warn!("Error removing `{}`: {}", exec_root.display(), e);
+        }
+        let (mut result, response) = match res? {
+            Ok(result) => result,
+            Err(response) => return Ok(response),
+        };
+
+        if let Some(metadata) = &mut result.execution_metadata {
+            metadata.queued_timestamp = queued_timestamp;
+        }
+
+        if result.exit_code == 0 && !action.do_not_cache {
+            self.storage
+                .put_action_result(&action_digest, &result)
+                .await?;
+        }
+
+        Ok(ExecuteResponse {
+            result: Some(result),
+            ..response
+        })
+    }
+
+    /// Run the action in `exec_root`. Returns an error response, rather than an error, if the
+    /// action itself is to blame.
+    async fn run_action(
+        &self,
+        action_digest: &Digest,
+        action: &Action,
+        command: &Command,
+        exec_root: &Path,
+    ) -> anyhow::Result<Result<(ActionResult, ExecuteResponse), ExecuteResponse>> {
+        let worker_start_timestamp = now();
+
+        let input_fetch_start_timestamp = now();
+        materialize_directory(
+            &self.storage,
+            &action.input_root_digest.clone().unwrap_or_default(),
+            exec_root,
+        )
+        .await?;
+        let input_fetch_completed_timestamp = now();
+
+        let cwd = exec_root.join(relative_path(&command.working_directory)?);
+        let outputs: Vec<&String> = if command.output_paths.is_empty() {
+            command
+                .output_files
+                .iter()
+                .chain(&command.output_directories)
+                .collect()
+        } else {
+            command.output_paths.iter().collect()
+        };
+        for output in &outputs {
+            if let Some(parent) = cwd.join(relative_path(output)?).parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+
+        let Some((exe, args)) = command.arguments.split_first() else {
+            return Ok(Err(error_response(
+                Code::InvalidArgument,
+                "The command has no arguments".to_owned(),
+            )));
+        };
+        let mut env = vec![buck2_forkserver_proto::EnvDirective {
+            data: Some(buck2_forkserver_proto::EnvClear {}.into()),
+        }];
+        for var in &command.environment_variables {
+            env.push(buck2_forkserver_proto::EnvDirective {
+                data: Some(
+                    buck2_forkserver_proto::EnvSet {
+                        key: var.name.clone().into_bytes(),
+                        value: var.value.clone().into_bytes(),
+                    }
+                    .into(),
+                ),
+            });
+        }
+        let req = buck2_forkserver_proto::CommandRequest {
+            exe: exe.clone().into_bytes(),
+            argv: args.iter().map(|a| a.clone().into_bytes()).collect(),
+            cwd: Some(buck2_forkserver_proto::WorkingDirectory {
+                path: cwd.as_os_str().as_bytes().to_vec(),
+            }),
+            timeout: action.timeout.clone(),
+            env,
+            enable_miniperf: false,
+            std_redirects: None,
+            graceful_shutdown_timeout_s: None,
+            action_digest: Some(action_digest.hash.clone()),
+            sandbox: None,
+        };
+
+        let execution_start_timestamp = now();
+        let (status, stdout, stderr) = self
+            .forkserver
+            .execute(req, futures::future::pending())
+            .await?;
+        let execution_completed_timestamp = now();
+
+        let exit_code = match status {
+            GatherOutputStatus::Finished { exit_code, .. }
+            | GatherOutputStatus::SandboxViolation { exit_code, .. } => exit_code,
+            GatherOutputStatus::TimedOut(timeout) => {
+                return Ok(Err(error_response(
+                    Code::DeadlineExceeded,
+                    format!("The action timed out after {:?}", timeout),
+                )));
+            }
+            GatherOutputStatus::Cancelled => {
+                return Ok(Err(error_response(
+                    Code::Cancelled,
+                    "The action was cancelled".to_owned(),
+                )));
+            }
+            GatherOutputStatus::SpawnFailed(reason) => {
+                return Ok(Err(error_response(
+                    Code::InvalidArgument,
+                    format!("Failed to spawn the action: {}", reason),
+                )));
+            }
+        };
+
+        let output_upload_start_timestamp = now();
+        let mut result = ActionResult {
+            exit_code,
+            stdout_digest: Some(self.storage.put_blob(&stdout).await?),
+            stderr_digest: Some(self.storage.put_blob(&stderr).await?),
+            stdout_raw: stdout,
+            stderr_raw: stderr,
+            ..Default::default()
+        };
+        for output in outputs {
+            self.upload_output(&cwd, output, &mut result).await?;
+        }
+        let output_upload_completed_timestamp = now();
+
+        result.execution_metadata = Some(ExecutedActionMetadata {
+            worker: "re_grpc_server".to_owned(),
+            worker_start_timestamp,
+            worker_completed_timestamp: now(),
+            input_fetch_start_timestamp,
+            input_fetch_completed_timestamp,
+            execution_start_timestamp,
+            execution_completed_timestamp,
+            output_upload_start_timestamp,
+            output_upload_completed_timestamp,
+            ..Default::default()
+        });
+
+        Ok(Ok((
+            result,
+            ExecuteResponse {
+                status: Some(to_rpc_status(Ok(()))),
+                ..Default::default()
+            },
+        )))
+    }
+
+    /// Add the output at `path` to `result`, if the action created it.
+    async fn upload_output(
+        &self,
+        cwd: &Path,
+        path: &str,
+        result: &mut ActionResult,
+    ) -> anyhow::Result<()> {
+        let abspath = cwd.join(relative_path(path)?);
+        let metadata = match tokio::fs::symlink_metadata(&abspath).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Error reading `{}`", abspath.display()));
+            }
+        };
+
+        if metadata.is_symlink() {
+            result.output_symlinks.push(OutputSymlink {
+                path: path.to_owned(),
+                target: read_link(&abspath).await?,
+                node_properties: None,
+            });
+        } else if metadata.is_dir() {
+            let mut children = Vec::new();
+            let root = self.upload_directory(&abspath, &mut children).await?;
+            let tree = Tree {
+                root: Some(root),
+                children,
+            };
+            result.output_directories.push(OutputDirectory {
+                path: path.to_owned(),
+                tree_digest: Some(self.storage.put_blob(&tree.encode_to_vec()).await?),
+                is_topologically_sorted: false,
+            });
+        } else {
+            result.output_files.push(OutputFile {
+                path: path.to_owned(),
+                digest: Some(self.storage.put_file(&abspath).await?),
+                is_executable: metadata.permissions().mode() & 0o111 != 0,
+                contents: Vec::new(),
+                node_properties: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Store the files below `path`, and return the `Directory` for it. The `Directory` of every
+    /// subdirectory is added to `children`.
+    fn upload_directory<'a>(
+        &'a self,
+        path: &'a Path,
+        children: &'a mut Vec<Directory>,
+    ) -> BoxFuture<'a, anyhow::Result<Directory>> {
+        async move {
+            let mut entries = Vec::new();
+            let mut read_dir = tokio::fs::read_dir(path)
+                .await
+                .with_context(|| format!("Error reading `{}`", path.display()))?;
+            while let Some(entry) = read_dir.next_entry().await? {
+                let name = entry.file_name().into_string().map_err(|name| {
+                    anyhow::anyhow!("Output `{}` is not UTF-8", name.to_string_lossy())
+                })?;
+                entries.push((name, entry.path(), entry.file_type().await?));
+            }
+            // Directories must list their entries sorted by name.
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let mut directory = Directory::default();
+            for (name, path, file_type) in entries {
+                if file_type.is_symlink() {
+                    directory.symlinks.push(SymlinkNode {
+                        name,
+                        target: read_link(&path).await?,
+                        node_properties: None,
+                    });
+                } else if file_type.is_dir() {
+                    let child = self.upload_directory(&path, children).await?;
+                    let digest = self
+                        .storage
+                        .digest_function()
+                        .digest(&child.encode_to_vec());
+                    children.push(child);
+                    directory.directories.push(DirectoryNode {
+                        name,
+                        digest: Some(digest),
+                    });
+                } else {
+                    let metadata = tokio::fs::metadata(&path).await?;
+                    directory.files.push(FileNode {
+                        name,
+                        digest: Some(self.storage.put_file(&path).await?),
+                        is_executable: metadata.permissions().mode() & 0o111 != 0,
+                        node_properties: None,
+                    });
+                }
+            }
+            Ok(directory)
+        }
+        .boxed()
+    }
+}
+
+async fn read_link(path: &Path) -> anyhow::Result<String> {
+    let target = tokio::fs::read_link(path)
+        .await
+        .with_context(|| format!("Error reading symlink `{}`", path.display()))?;
+    target
+        .into_os_string()
+        .into_string()
+        .map_err(|_| anyhow::anyhow!("Target of symlink `{}` is not UTF-8", path.display()))
+}
+
+#[tonic::async_trait]
+impl Execution for ExecutionService {
+    type ExecuteStream = BoxStream<'static, Result<Operation, Status>>;
+
+    async fn execute(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<Self::ExecuteStream>, Status> {
+        let request = request.into_inner();
+        let action_digest = request.action_digest.clone().unwrap_or_default();
+
+        let response = match self.execute_action(&request).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!(
+                    "Error executing `{}:{}`: {:#}",
+                    action_digest.hash,
+                    action_digest.size_bytes,
+                    e
+                );
+                ExecuteResponse {
+                    status: Some(to_rpc_status(Err(e))),
+                    ..Default::default()
+                }
+            }
+        };
+
+        let metadata = ExecuteOperationMetadata {
+            stage: execution_stage::Value::Completed as i32,
+            action_digest: Some(action_digest),
+            stdout_stream_name: String::new(),
+            stderr_stream_name: String::new(),
+        };
+        let operation = Operation {
+            name: format!("operations/{}", uuid::Uuid::new_v4()),
+            metadata: Some(prost_types::Any {
+                type_url:
+                    "type.googleapis.com/build.bazel.remote.execution.v2.ExecuteOperationMetadata"
+                        .to_owned(),
+                value: metadata.encode_to_vec(),
+            }),
+            done: true,
+            result: Some(operation::Result::Response(prost_types::Any {
+                type_url: "type.googleapis.com/build.bazel.remote.execution.v2.ExecuteResponse"
+                    .to_owned(),
+                value: response.encode_to_vec(),
+            })),
+        };
+        Ok(Response::new(stream::once(async { Ok(operation) }).boxed()))
+    }
+
+    type WaitExecutionStream = BoxStream<'static, Result<Operation, Status>>;
+
+    async fn wait_execution(
+        &self,
+        request: Request<WaitExecutionRequest>,
+    ) -> Result<Response<Self::WaitExecutionStream>, Status> {
+        Err(Status::not_found(format!(
+            "Operation `{}` is unknown: operations complete before `Execute` returns",
+            request.into_inner().name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::DigestFunction;
+
+    #[test]
+    fn test_relative_path() {
+        assert!(relative_path("a/b").is_ok());
+        assert!(relative_path("").is_ok());
+        assert!(relative_path("/etc").is_err());
+        assert!(relative_path("a/../../b").is_err());
+
+        assert!(file_name("a").is_ok());
+        assert!(file_name("a/b").is_err());
+        assert!(file_name("..").is_err());
+        assert!(file_name("").is_err());
+    }
+
+    async fn put_directory(storage: &Storage, directory: Directory) -> Digest {
+        storage.put_blob(&directory.encode_to_vec()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_materialize_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path().join("store"), DigestFunction::Sha256)
+            .await
+            .unwrap();
+        let contents = storage.put_blob(b"contents").await.unwrap();
+
+        let sub = put_directory(
+            &storage,
+            Directory {
+                files: vec![FileNode {
+                    name: "run.sh".to_owned(),
+                    digest: Some(contents.clone()),
+                    is_executable: true,
+                    node_properties: None,
+                }],
+                ..Default::default()
+            },
+        )
+        .await;
+        let root = put_directory(
+            &storage,
+            Directory {
+                files: vec![FileNode {
+                    name: "a.txt".to_owned(),
+                    digest: Some(contents),
+                    is_executable: false,
+                    node_properties: None,
+                }],
+                directories: vec![DirectoryNode {
+                    name: "sub".to_owned(),
+                    digest: Some(sub),
+                }],
+                symlinks: vec![SymlinkNode {
+                    name: "link".to_owned(),
+                    target: "sub/run.sh".to_owned(),
+                    node_properties: None,
+                }],
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let dest = dir.path().join("exec");
+        materialize_directory(&storage, &root, &dest).await.unwrap();
+
+        assert_eq!(std::fs::read(dest.join("a.txt")).unwrap(), b"contents");
+        let mode = |path: &str| {
+            std::fs::metadata(dest.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode("a.txt"), 0o644);
+        assert_eq!(mode("sub/run.sh"), 0o755);
+        assert_eq!(
+            std::fs::read_link(dest.join("link")).unwrap(),
+            Path::new("sub/run.sh")
+        );
+        assert_eq!(std::fs::read(dest.join("link")).unwrap(), b"contents");
+    }
+
+    #[tokio::test]
+    async fn test_materialize_directory_duplicate_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path().join("store"), DigestFunction::Sha256)
+            .await
+            .unwrap();
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        let contents = storage.put_blob(b"contents").await.unwrap();
+
+        let sub = put_directory(
+            &storage,
+            Directory {
+                files: vec![FileNode {
+                    name: "escaped".to_owned(),
+                    digest: Some(contents),
+                    is_executable: false,
+                    node_properties: None,
+                }],
+                ..Default::default()
+            },
+        )
+        .await;
+        let root = put_directory(
+            &storage,
+            Directory {
+                directories: vec![DirectoryNode {
+                    name: "x".to_owned(),
+                    digest: Some(sub),
+                }],
+                symlinks: vec![SymlinkNode {
+                    name: "x".to_owned(),
+                    target: outside.to_str().unwrap().to_owned(),
+                    node_properties: None,
+                }],
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let dest = dir.path().join("exec");
+        assert!(materialize_directory(&storage, &root, &dest).await.is_err());
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    }
+}
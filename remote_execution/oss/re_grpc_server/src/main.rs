@@ -0,0 +1,209 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A small REAPI server that keeps blobs and action results on local disk. It is meant for
+//! testing buck2's remote execution client, and for sharing a cache between a few machines.
+//! Actions can optionally be run through a buck2 forkserver.
+
+mod action_cache;
+mod capabilities;
+mod cas;
+#[cfg(unix)]
+mod execution;
+mod storage;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::Parser;
+use re_grpc_proto::build::bazel::remote::execution::v2::action_cache_server::ActionCacheServer;
+use re_grpc_proto::build::bazel::remote::execution::v2::capabilities_server::CapabilitiesServer;
+use re_grpc_proto::build::bazel::remote::execution::v2::content_addressable_storage_server::ContentAddressableStorageServer;
+#[cfg(unix)]
+use re_grpc_proto::build::bazel::remote::execution::v2::execution_server::ExecutionServer;
+use re_grpc_proto::google::bytestream::byte_stream_server::ByteStreamServer;
+use tracing_subscriber::EnvFilter;
+
+use crate::action_cache::ActionCacheService;
+use crate::capabilities::CapabilitiesService;
+use crate::cas::ByteStreamService;
+use crate::cas::CasService;
+use crate::storage::DigestFunction;
+use crate::storage::Storage;
+
+/// Room for what requests carry besides blobs, on top of `--max-batch-total-size-bytes`.
+const MESSAGE_HEADROOM_BYTES: usize = 1 << 20;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "re_grpc_server",
+    about = "A local REAPI cache and execution server"
+)]
+struct Opt {
+    /// The address to listen on.
+    #[clap(long, default_value = "127.0.0.1:8980")]
+    listen: SocketAddr,
+
+    /// Allow listening on an address other hosts can reach. The server doesn't authenticate
+    /// clients, so anyone who can connect can write action results and, with
+    /// `--execute-with-forkserver`, run commands as the server's user.
+    #[clap(long)]
+    allow_unauthenticated_remote_clients: bool,
+
+    /// The directory to store blobs and action results in. It is created if missing.
+    #[clap(long)]
+    root: PathBuf,
+
+    /// The digest function clients must use. This has to match `digest_algorithms` in the
+    /// `buck2` section of the clients' buckconfig.
+    #[clap(long, value_enum, default_value = "sha256")]
+    digest_function: DigestFunction,
+
+    /// The largest batch request clients should send. Larger requests are rejected.
+    #[clap(long, default_value_t = 4 << 20)]
+    max_batch_total_size_bytes: i64,
+
+    /// Refuse action results uploaded by clients, so that only actions the server executed
+    /// itself are cached.
+    #[clap(long)]
+    deny_action_cache_updates: bool,
+
+    /// Enable the `Execution` service, running actions through the forkserver of this buck2
+    /// binary.
+    #[cfg(unix)]
+    #[clap(long, value_name = "BUCK2")]
+    execute_with_forkserver: Option<PathBuf>,
+
+    /// How many actions to run at once. Defaults to the number of CPUs.
+    #[cfg(unix)]
+    #[clap(long)]
+    execution_concurrency: Option<usize>,
+}
+
+#[cfg(unix)]
+async fn execution_service(
+    opt: &Opt,
+    storage: &Arc<Storage>,
+    max_message_size: usize,
+) -> anyhow::Result<Option<ExecutionServer<execution::ExecutionService>>> {
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+
+    let Some(buck2) = &opt.execute_with_forkserver else {
+        return Ok(None);
+    };
+
+    let state_dir = AbsNormPathBuf::new(storage.root().join("forkserver"))?;
+    let forkserver = buck2_forkserver::unix::launch_forkserver(
+        buck2,
+        &["forkserver"],
+        &state_dir,
+        buck2_common::init::ResourceControlConfig::default().serialize()?,
+    )
+    .await
+    .with_context(|| format!("Error launching the forkserver of `{}`", buck2.display()))?;
+
+    let concurrency = match opt.execution_concurrency {
+        Some(concurrency) => concurrency,
+        None => std::thread::available_parallelism()?.get(),
+    };
+
+    Ok(Some(
+        ExecutionServer::new(execution::ExecutionService {
+            storage: storage.clone(),
+            forkserver,
+            concurrency: Arc::new(tokio::sync::Semaphore::new(concurrency)),
+        })
+        .max_decoding_message_size(max_message_size)
+        .max_encoding_message_size(usize::MAX),
+    ))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let opt = Opt::parse();
+
+    if !opt.listen.ip().is_loopback() && !opt.allow_unauthenticated_remote_clients {
+        anyhow::bail!(
+            "Refusing to listen on {} without `--allow-unauthenticated-remote-clients`: the server \
+            doesn't authenticate clients",
+            opt.listen
+        );
+    }
+    let max_message_size = usize::try_from(opt.max_batch_total_size_bytes)
+        .context("Invalid `--max-batch-total-size-bytes`")?
+        .saturating_add(MESSAGE_HEADROOM_BYTES);
+
+    tokio::fs::create_dir_all(&opt.root)
+        .await
+        .with_context(|| format!("Error creating `{}`", opt.root.display()))?;
+    let root = tokio::fs::canonicalize(&opt.root).await?;
+    let storage = Arc::new(Storage::new(root, opt.digest_function).await?);
+
+    #[cfg(unix)]
+    let execution = execution_service(&opt, &storage, max_message_size).await?;
+    #[cfg(unix)]
+    let exec_enabled = execution.is_some();
+    #[cfg(not(unix))]
+    let exec_enabled = false;
+
+    let capabilities = CapabilitiesService {
+        digest_function: opt.digest_function,
+        max_batch_total_size_bytes: opt.max_batch_total_size_bytes,
+        exec_enabled,
+        action_cache_update_enabled: !opt.deny_action_cache_updates,
+    };
+
+    let router = tonic::transport::Server::builder()
+        .add_service(CapabilitiesServer::new(capabilities))
+        .add_service(
+            ContentAddressableStorageServer::new(CasService {
+                storage: storage.clone(),
+                max_batch_total_size_bytes: opt.max_batch_total_size_bytes,
+            })
+            .max_decoding_message_size(max_message_size)
+            .max_encoding_message_size(usize::MAX),
+        )
+        .add_service(
+            ByteStreamServer::new(ByteStreamService {
+                storage: storage.clone(),
+            })
+            .max_decoding_message_size(max_message_size)
+            .max_encoding_message_size(usize::MAX),
+        )
+        .add_service(
+            ActionCacheServer::new(ActionCacheService {
+                storage: storage.clone(),
+                update_enabled: !opt.deny_action_cache_updates,
+            })
+            .max_decoding_message_size(max_message_size)
+            .max_encoding_message_size(usize::MAX),
+        );
+    #[cfg(unix)]
+    let router = router.add_optional_service(execution);
+
+    tracing::info!(
+        "Serving `{}` on {} (execution {})",
+        storage.root().display(),
+        opt.listen,
+        if exec_enabled { "enabled" } else { "disabled" }
+    );
+
+    router
+        .serve(opt.listen)
+        .await
+        .with_context(|| format!("Error serving on {}", opt.listen))?;
+
+    Ok(())
+}
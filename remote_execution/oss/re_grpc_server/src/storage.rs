@@ -0,0 +1,574 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Blobs and action results on local disk.
+//!
+//! Blobs live under `cas/`, sharded by the first two characters of their hash, and action results
+//! under `ac/`. Everything is written to `tmp/` first and renamed into place, so readers never see
+//! partial files. Nothing is ever evicted.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use prost::Message;
+use re_grpc_proto::build::bazel::remote::execution::v2::digest_function;
+use re_grpc_proto::build::bazel::remote::execution::v2::ActionResult;
+use re_grpc_proto::build::bazel::remote::execution::v2::Digest;
+use sha1::Digest as _;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tonic::Status;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum StorageError {
+    #[error("Invalid digest `{}:{}`", .0.hash, .0.size_bytes)]
+    InvalidDigest(Digest),
+    #[error(
+        "Blob does not match its digest: expected `{}:{}`, got `{}:{}`",
+        .expected.hash,
+        .expected.size_bytes,
+        .actual.hash,
+        .actual.size_bytes
+    )]
+    DigestMismatch { expected: Digest, actual: Digest },
+    #[error("Blob `{}:{}` is not in the CAS", .0.hash, .0.size_bytes)]
+    MissingBlob(Digest),
+}
+
+/// Report `e` to the client, blaming them for it if it's about what they sent.
+pub(crate) fn to_status(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<StorageError>() {
+        Some(e @ StorageError::MissingBlob(..)) => Status::failed_precondition(e.to_string()),
+        Some(e) => Status::invalid_argument(e.to_string()),
+        None => Status::internal(format!("{:#}", e)),
+    }
+}
+
+/// Like `to_status`, for errors that are reported inside of a response.
+pub(crate) fn to_rpc_status(res: anyhow::Result<()>) -> re_grpc_proto::google::rpc::Status {
+    match res {
+        Ok(()) => re_grpc_proto::google::rpc::Status::default(),
+        Err(e) => {
+            let status = to_status(e);
+            re_grpc_proto::google::rpc::Status {
+                code: status.code() as i32,
+                message: status.message().to_owned(),
+                details: Vec::new(),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub(crate) enum DigestFunction {
+    Sha1,
+    Sha256,
+}
+
+impl DigestFunction {
+    pub(crate) fn to_proto(self) -> digest_function::Value {
+        match self {
+            Self::Sha1 => digest_function::Value::Sha1,
+            Self::Sha256 => digest_function::Value::Sha256,
+        }
+    }
+
+    fn hash_len(self) -> usize {
+        match self {
+            Self::Sha1 => 40,
+            Self::Sha256 => 64,
+        }
+    }
+
+    pub(crate) fn hasher(self) -> Hasher {
+        let inner = match self {
+            Self::Sha1 => HasherInner::Sha1(sha1::Sha1::new()),
+            Self::Sha256 => HasherInner::Sha256(sha2::Sha256::new()),
+        };
+        Hasher { inner, size: 0 }
+    }
+
+    pub(crate) fn digest(self, data: &[u8]) -> Digest {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+}
+
+enum HasherInner {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+}
+
+pub(crate) struct Hasher {
+    inner: HasherInner,
+    size: i64,
+}
+
+impl Hasher {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match &mut self.inner {
+            HasherInner::Sha1(h) => h.update(data),
+            HasherInner::Sha256(h) => h.update(data),
+        }
+        self.size += data.len() as i64;
+    }
+
+    pub(crate) fn finish(self) -> Digest {
+        let hash = match self.inner {
+            HasherInner::Sha1(h) => hex::encode(h.finalize()),
+            HasherInner::Sha256(h) => hex::encode(h.finalize()),
+        };
+        Digest {
+            hash,
+            size_bytes: self.size,
+        }
+    }
+}
+
+pub(crate) struct Storage {
+    root: PathBuf,
+    digest_function: DigestFunction,
+}
+
+impl Storage {
+    pub(crate) async fn new(
+        root: PathBuf,
+        digest_function: DigestFunction,
+    ) -> anyhow::Result<Self> {
+        for dir in ["cas", "ac", "tmp"] {
+            let dir = root.join(dir);
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .with_context(|| format!("Error creating `{}`", dir.display()))?;
+        }
+        Ok(Self {
+            root,
+            digest_function,
+        })
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn digest_function(&self) -> DigestFunction {
+        self.digest_function
+    }
+
+    /// Digests end up in paths, so only accept ones that look like what we would produce.
+    fn check_digest(&self, digest: &Digest) -> anyhow::Result<()> {
+        if digest.size_bytes < 0
+            || digest.hash.len() != self.digest_function.hash_len()
+            || !digest
+                .hash
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        {
+            return Err(StorageError::InvalidDigest(digest.clone()).into());
+        }
+        Ok(())
+    }
+
+    fn blob_path(&self, digest: &Digest) -> anyhow::Result<PathBuf> {
+        self.check_digest(digest)?;
+        Ok(self
+            .root
+            .join("cas")
+            .join(&digest.hash[..2])
+            .join(&digest.hash))
+    }
+
+    fn action_result_path(&self, action_digest: &Digest) -> anyhow::Result<PathBuf> {
+        self.check_digest(action_digest)?;
+        Ok(self.root.join("ac").join(format!(
+            "{}_{}",
+            action_digest.hash, action_digest.size_bytes
+        )))
+    }
+
+    pub(crate) fn temp_path(&self) -> PathBuf {
+        self.root.join("tmp").join(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Clients may assume the empty blob is always there, so it's never looked up on disk. Only
+    /// the actual empty hash counts: anything else claiming to be empty is just missing.
+    pub(crate) fn is_empty_blob(&self, digest: &Digest) -> anyhow::Result<bool> {
+        self.check_digest(digest)?;
+        Ok(digest.size_bytes == 0 && digest.hash == self.digest_function.digest(b"").hash)
+    }
+
+    /// Blobs are stored by hash alone, so a digest with the right hash and the wrong size must
+    /// not find them.
+    pub(crate) async fn has_blob(&self, digest: &Digest) -> anyhow::Result<bool> {
+        if self.is_empty_blob(digest)? {
+            return Ok(true);
+        }
+        let path = self.blob_path(digest)?;
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => Ok(metadata.len() == digest.size_bytes as u64),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("Error reading `{}`", path.display())),
+        }
+    }
+
+    pub(crate) async fn read_blob(&self, digest: &Digest) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.is_empty_blob(digest)? {
+            return Ok(Some(Vec::new()));
+        }
+        let path = self.blob_path(digest)?;
+        match tokio::fs::read(&path).await {
+            Ok(data) if data.len() as u64 == digest.size_bytes as u64 => Ok(Some(data)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Error reading `{}`", path.display())),
+        }
+    }
+
+    /// Open a blob to read it bit by bit, along with its size. The empty blob isn't stored, so it
+    /// has no file.
+    pub(crate) async fn open_blob(
+        &self,
+        digest: &Digest,
+    ) -> anyhow::Result<Option<(tokio::fs::File, u64)>> {
+        let path = self.blob_path(digest)?;
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Error opening `{}`", path.display()));
+            }
+        };
+        let size = file
+            .metadata()
+            .await
+            .with_context(|| format!("Error reading `{}`", path.display()))?
+            .len();
+        if size != digest.size_bytes as u64 {
+            return Ok(None);
+        }
+        Ok(Some((file, size)))
+    }
+
+    /// Like `read_blob`, but decodes the blob as a message.
+    pub(crate) async fn read_message<M: Message + Default>(
+        &self,
+        digest: &Digest,
+    ) -> anyhow::Result<Option<M>> {
+        match self.read_blob(digest).await? {
+            Some(data) => Ok(Some(M::decode(data.as_slice()).with_context(|| {
+                format!(
+                    "Error decoding blob `{}:{}`",
+                    digest.hash, digest.size_bytes
+                )
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write a copy of a blob to `dest`.
+    pub(crate) async fn copy_blob(&self, digest: &Digest, dest: &Path) -> anyhow::Result<()> {
+        if self.is_empty_blob(digest)? {
+            tokio::fs::write(dest, b"")
+                .await
+                .with_context(|| format!("Error writing `{}`", dest.display()))?;
+            return Ok(());
+        }
+        if !self.has_blob(digest).await? {
+            return Err(StorageError::MissingBlob(digest.clone()).into());
+        }
+        let path = self.blob_path(digest)?;
+        match tokio::fs::copy(&path, dest).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::MissingBlob(digest.clone()).into())
+            }
+            Err(e) => Err(e).with_context(|| {
+                format!("Error copying `{}` to `{}`", path.display(), dest.display())
+            }),
+        }
+    }
+
+    pub(crate) async fn write_blob(&self, digest: &Digest, data: &[u8]) -> anyhow::Result<()> {
+        let mut writer = self.blob_writer().await?;
+        writer.write(data).await?;
+        writer.commit(Some(digest)).await?;
+        Ok(())
+    }
+
+    /// Store a blob, and return its digest.
+    pub(crate) async fn put_blob(&self, data: &[u8]) -> anyhow::Result<Digest> {
+        let digest = self.digest_function.digest(data);
+        if !self.has_blob(&digest).await? {
+            self.write_blob(&digest, data).await?;
+        }
+        Ok(digest)
+    }
+
+    /// Store a copy of the file at `path`, and return its digest.
+    pub(crate) async fn put_file(&self, path: &Path) -> anyhow::Result<Digest> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("Error opening `{}`", path.display()))?;
+        let mut writer = self.blob_writer().await?;
+        let mut buf = vec![0; 1 << 20];
+        loop {
+            let n = file
+                .read(&mut buf)
+                .await
+                .with_context(|| format!("Error reading `{}`", path.display()))?;
+            if n == 0 {
+                break;
+            }
+            writer.write(&buf[..n]).await?;
+        }
+        writer.commit(None).await
+    }
+
+    pub(crate) async fn blob_writer(&self) -> anyhow::Result<BlobWriter<'_>> {
+        let temp = self.temp_path();
+        let file = tokio::fs::File::create(&temp)
+            .await
+            .with_context(|| format!("Error creating `{}`", temp.display()))?;
+        Ok(BlobWriter {
+            storage: self,
+            temp,
+            file: Some(file),
+            hasher: Some(self.digest_function.hasher()),
+        })
+    }
+
+    pub(crate) async fn get_action_result(
+        &self,
+        action_digest: &Digest,
+    ) -> anyhow::Result<Option<ActionResult>> {
+        let path = self.action_result_path(action_digest)?;
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Error reading `{}`", path.display()));
+            }
+        };
+        Ok(Some(ActionResult::decode(data.as_slice()).with_context(
+            || format!("Error decoding `{}`", path.display()),
+        )?))
+    }
+
+    pub(crate) async fn put_action_result(
+        &self,
+        action_digest: &Digest,
+        action_result: &ActionResult,
+    ) -> anyhow::Result<()> {
+        let path = self.action_result_path(action_digest)?;
+        let temp = self.temp_path();
+        tokio::fs::write(&temp, action_result.encode_to_vec())
+            .await
+            .with_context(|| format!("Error writing `{}`", temp.display()))?;
+        rename(&temp, &path).await
+    }
+}
+
+/// Writes a blob to a temporary file, and moves it into the CAS once it's complete and matches
+/// its digest.
+pub(crate) struct BlobWriter<'a> {
+    storage: &'a Storage,
+    temp: PathBuf,
+    file: Option<tokio::fs::File>,
+    hasher: Option<Hasher>,
+}
+
+impl BlobWriter<'_> {
+    pub(crate) fn size(&self) -> i64 {
+        self.hasher.as_ref().map_or(0, |h| h.size)
+    }
+
+    pub(crate) async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let (Some(file), Some(hasher)) = (&mut self.file, &mut self.hasher) else {
+            return Err(anyhow::anyhow!("Blob was already committed"));
+        };
+        file.write_all(data)
+            .await
+            .with_context(|| format!("Error writing `{}`", self.temp.display()))?;
+        hasher.update(data);
+        Ok(())
+    }
+
+    /// Move the blob into the CAS. If `expected` is set, the blob must match it.
+    pub(crate) async fn commit(mut self, expected: Option<&Digest>) -> anyhow::Result<Digest> {
+        let (Some(mut file), Some(hasher)) = (self.file.take(), self.hasher.take()) else {
+            return Err(anyhow::anyhow!("Blob was already committed"));
+        };
+        file.flush().await?;
+        drop(file);
+
+        let digest = hasher.finish();
+        if let Some(expected) = expected {
+            if expected != &digest {
+                return Err(StorageError::DigestMismatch {
+                    expected: expected.clone(),
+                    actual: digest,
+                }
+                .into());
+            }
+        }
+
+        rename(&self.temp, &self.storage.blob_path(&digest)?).await?;
+        Ok(digest)
+    }
+}
+
+impl Drop for BlobWriter<'_> {
+    fn drop(&mut self) {
+        // Either the blob was never committed, or the rename failed.
+        let _ignored = std::fs::remove_file(&self.temp);
+    }
+}
+
+async fn rename(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Error creating `{}`", parent.display()))?;
+    }
+    tokio::fs::rename(from, to)
+        .await
+        .with_context(|| format!("Error moving `{}` to `{}`", from.display(), to.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn storage() -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path().to_owned(), DigestFunction::Sha256)
+            .await
+            .unwrap();
+        (dir, storage)
+    }
+
+    #[tokio::test]
+    async fn test_blob_roundtrip() {
+        let (_dir, storage) = storage().await;
+
+        let digest = storage.digest_function().digest(b"hello");
+        assert!(!storage.has_blob(&digest).await.unwrap());
+        assert_eq!(storage.read_blob(&digest).await.unwrap(), None);
+
+        assert_eq!(storage.put_blob(b"hello").await.unwrap(), digest);
+        assert!(storage.has_blob(&digest).await.unwrap());
+        assert_eq!(
+            storage.read_blob(&digest).await.unwrap().as_deref(),
+            Some(b"hello".as_slice())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_blob_is_always_present() {
+        let (_dir, storage) = storage().await;
+
+        let digest = storage.digest_function().digest(b"");
+        assert!(storage.has_blob(&digest).await.unwrap());
+        assert_eq!(storage.read_blob(&digest).await.unwrap(), Some(Vec::new()));
+
+        // A size of zero alone doesn't make a blob empty.
+        let digest = Digest {
+            size_bytes: 0,
+            ..storage.digest_function().digest(b"hello")
+        };
+        assert!(!storage.has_blob(&digest).await.unwrap());
+        assert_eq!(storage.read_blob(&digest).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_blob_size_must_match() {
+        let (_dir, storage) = storage().await;
+
+        let digest = storage.put_blob(b"hello").await.unwrap();
+        let wrong_size = Digest {
+            size_bytes: 3,
+            ..digest.clone()
+        };
+        assert!(!storage.has_blob(&wrong_size).await.unwrap());
+        assert_eq!(storage.read_blob(&wrong_size).await.unwrap(), None);
+        assert!(storage.open_blob(&wrong_size).await.unwrap().is_none());
+        assert_eq!(
+            storage
+                .open_blob(&digest)
+                .await
+                .unwrap()
+                .map(|(_, size)| size),
+            Some(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_mismatched_blob() {
+        let (_dir, storage) = storage().await;
+
+        let digest = storage.digest_function().digest(b"hello");
+        let err = storage.write_blob(&digest, b"goodbye").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StorageError>(),
+            Some(StorageError::DigestMismatch { .. })
+        ));
+        assert!(!storage.has_blob(&digest).await.unwrap());
+        // The temporary file was cleaned up.
+        assert_eq!(
+            std::fs::read_dir(storage.root().join("tmp"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_digest() {
+        let (_dir, storage) = storage().await;
+
+        for hash in ["../../etc/passwd", "abc", &"A".repeat(64)] {
+            let digest = Digest {
+                hash: hash.to_owned(),
+                size_bytes: 1,
+            };
+            let err = storage.has_blob(&digest).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<StorageError>(),
+                Some(StorageError::InvalidDigest(..))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_action_result_roundtrip() {
+        let (_dir, storage) = storage().await;
+
+        let action_digest = storage.digest_function().digest(b"action");
+        assert_eq!(
+            storage.get_action_result(&action_digest).await.unwrap(),
+            None
+        );
+
+        let action_result = ActionResult {
+            exit_code: 1,
+            stdout_raw: b"out".to_vec(),
+            ..Default::default()
+        };
+        storage
+            .put_action_result(&action_digest, &action_result)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_action_result(&action_digest).await.unwrap(),
+            Some(action_result)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+import typing
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.buck_workspace import buck_test
+from buck2.tests.e2e_util.helper.utils import read_what_ran
+
+
+def _executors(
+    what_ran: typing.List[typing.Dict[str, typing.Any]],
+) -> typing.List[str]:
+    return [line["reproducer"]["executor"] for line in what_ran]
+
+
+@buck_test()
+async def test_local_disk_cache_hit_after_clean(buck: Buck) -> None:
+    target = "root//:hello"
+
+    await buck.build(target, "--local-only")
+    assert _executors(await read_what_ran(buck)) == ["Local"]
+
+    await buck.clean()
+
+    result = await buck.build(target, "--local-only")
+    assert _executors(await read_what_ran(buck)) == ["LocalDiskCache"]
+    output = result.get_build_report().output_for_target(target)
+    assert output.read_text() == "hello\n"
+
+
+@buck_test()
+async def test_local_disk_cache_skip_read(buck: Buck) -> None:
+    target = "root//:hello"
+
+    await buck.build(target, "--local-only")
+    await buck.clean()
+
+    await buck.build(target, "--local-only", "--no-remote-cache")
+    assert _executors(await read_what_ran(buck)) == ["Local"]
+
+
+@buck_test()
+async def test_local_disk_cache_requires_allow_cache_upload(buck: Buck) -> None:
+    target = "root//:no_upload"
+
+    await buck.build(target, "--local-only")
+    await buck.clean()
+
+    await buck.build(target, "--local-only")
+    assert _executors(await read_what_ran(buck)) == ["Local"]
@@ -0,0 +1,25 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+def _write_file(ctx):
+    out = ctx.actions.declare_output("out")
+
+    ctx.actions.run(
+        ["sh", "-c", 'echo "$1" > "$2"', "--", ctx.attrs.contents, out.as_output()],
+        category = "write_file",
+        allow_cache_upload = ctx.attrs.allow_cache_upload,
+    )
+
+    return [DefaultInfo(out)]
+
+write_file = rule(
+    impl = _write_file,
+    attrs = {
+        "allow_cache_upload": attrs.bool(default = True),
+        "contents": attrs.string(),
+    },
+)
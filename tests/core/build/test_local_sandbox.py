@@ -0,0 +1,60 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.asserts import expect_failure
+from buck2.tests.e2e_util.buck_workspace import buck_test
+from buck2.tests.e2e_util.helper.utils import read_what_ran
+
+
+@buck_test()
+async def test_local_sandbox_declared_inputs(buck: Buck) -> None:
+    target = "root//:copy"
+    result = await buck.build(target, "--local-only")
+    output = result.get_build_report().output_for_target(target)
+    assert output.read_text() == "declared\n"
+
+
+@buck_test()
+async def test_local_sandbox_undeclared_input(buck: Buck) -> None:
+    await expect_failure(
+        buck.build(":sandboxed", "--local-only"),
+        stderr_regex="did not declare as inputs:\\n  undeclared.txt",
+    )
+
+
+@buck_test()
+async def test_local_sandbox_only_exposes_declared_outputs(buck: Buck) -> None:
+    # Put another output in the directory the sandboxed action writes to.
+    await buck.build(":list_outputs[sibling]", "--local-only")
+    target = "root//:list_outputs"
+    result = await buck.build(target, "--local-only")
+    output = result.get_build_report().output_for_target(target)
+    assert output.read_text() == "listing\n"
+
+
+@buck_test()
+async def test_local_sandbox_disabled(buck: Buck) -> None:
+    await buck.build(":unsandboxed", "--local-only")
+
+
+@buck_test()
+async def test_detect_undeclared_inputs(buck: Buck) -> None:
+    await buck.build(
+        ":unsandboxed",
+        "--local-only",
+        "-c",
+        "buck2.detect_undeclared_inputs=true",
+    )
+    out = await read_what_ran(buck)
+    undeclared = {line["identity"]: line.get("undeclared_inputs") for line in out}
+    assert undeclared == {
+        "root//:unsandboxed (<unspecified>) (read_undeclared)": ["undeclared.txt"],
+    }
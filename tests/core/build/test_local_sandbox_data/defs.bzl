@@ -0,0 +1,46 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+def _copy(ctx):
+    out = ctx.actions.declare_output("out")
+
+    ctx.actions.run(
+        ["sh", "-c", 'cat "$1" > "$2"', "--", ctx.attrs.src, out.as_output()],
+        category = "copy",
+        local_sandbox = True,
+    )
+
+    return [DefaultInfo(out)]
+
+copy = rule(impl = _copy, attrs = {"src": attrs.source()})
+
+def _read_undeclared(ctx):
+    out = ctx.actions.declare_output("out")
+
+    ctx.actions.run(
+        ["sh", "-c", 'cat undeclared.txt > "$1"', "--", out.as_output()],
+        category = "read_undeclared",
+        local_sandbox = ctx.attrs.sandbox,
+    )
+
+    return [DefaultInfo(out)]
+
+read_undeclared = rule(impl = _read_undeclared, attrs = {"sandbox": attrs.bool()})
+
+def _list_outputs(ctx):
+    sibling = ctx.actions.write("dir/sibling", "")
+    out = ctx.actions.declare_output("dir/listing")
+
+    ctx.actions.run(
+        ["sh", "-c", 'ls "$(dirname "$1")" > "$1"', "--", out.as_output()],
+        category = "list_outputs",
+        local_sandbox = True,
+    )
+
+    return [DefaultInfo(out, sub_targets = {"sibling": [DefaultInfo(sibling)]})]
+
+list_outputs = rule(impl = _list_outputs, attrs = {})
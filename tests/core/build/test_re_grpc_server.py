@@ -0,0 +1,159 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+import asyncio
+import contextlib
+import os
+import socket
+import tempfile
+import typing
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.buck_workspace import buck_test
+from buck2.tests.e2e_util.helper.utils import filter_events, read_what_ran
+
+
+def _executors(
+    what_ran: typing.List[typing.Dict[str, typing.Any]],
+) -> typing.List[str]:
+    return [line["reproducer"]["executor"] for line in what_ran]
+
+
+def _free_port() -> int:
+    with socket.socket() as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+async def _wait_for_port(port: int) -> None:
+    for _ in range(100):
+        try:
+            _, writer = await asyncio.open_connection("127.0.0.1", port)
+        except OSError:
+            await asyncio.sleep(0.1)
+            continue
+        writer.close()
+        await writer.wait_closed()
+        return
+    raise AssertionError(f"re_grpc_server did not listen on port {port}")
+
+
+@contextlib.asynccontextmanager
+async def _re_grpc_server(buck: Buck, *args: str) -> typing.AsyncIterator[None]:
+    """Run a server and point `buck` at it for the duration of the block."""
+    port = _free_port()
+
+    with tempfile.TemporaryDirectory() as root:
+        server = await asyncio.create_subprocess_exec(
+            os.environ["RE_GRPC_SERVER"],
+            "--root",
+            root,
+            "--listen",
+            f"127.0.0.1:{port}",
+            "--execute-with-forkserver",
+            str(buck.path_to_executable),
+            *args,
+        )
+        try:
+            await _wait_for_port(port)
+
+            # The RE client is created with the daemon, which ignores `-c` flags.
+            with open(buck.cwd / ".buckconfig.local", "w") as f:
+                f.write(
+                    f"""
+[buck2_re_client]
+engine_address = grpc://127.0.0.1:{port}
+action_cache_address = grpc://127.0.0.1:{port}
+cas_address = grpc://127.0.0.1:{port}
+                    """
+                )
+            await buck.kill()
+
+            yield
+        finally:
+            server.terminate()
+            await server.wait()
+
+
+async def _cache_uploads(buck: Buck) -> typing.List[typing.Dict[str, typing.Any]]:
+    return await filter_events(buck, "Event", "data", "SpanEnd", "data", "CacheUpload")
+
+
+@buck_test()
+async def test_build_with_re_grpc_server(buck: Buck) -> None:
+    target = "root//:hello"
+
+    async with _re_grpc_server(buck):
+        result = await buck.build(target, "--remote-only")
+        assert _executors(await read_what_ran(buck)) == ["Re"]
+        output = result.get_build_report().output_for_target(target)
+        assert output.read_text() == "hello\n"
+
+        await buck.clean()
+
+        result = await buck.build(target, "--remote-only")
+        assert _executors(await read_what_ran(buck)) == ["Cache"]
+        output = result.get_build_report().output_for_target(target)
+        assert output.read_text() == "hello\n"
+
+
+@buck_test()
+async def test_paranoid_download_from_re_grpc_server(buck: Buck) -> None:
+    target = "root//:hello"
+
+    async with _re_grpc_server(buck):
+        await buck.build(target, "--remote-only")
+        await buck.clean()
+
+        # Paranoid mode skips the action cache, and checks what it downloads before
+        # moving it into place.
+        result = await buck.build(
+            target, "--remote-only", env={"BUCK_PARANOID": "true"}
+        )
+        assert _executors(await read_what_ran(buck)) == ["Re"]
+        output = result.get_build_report().output_for_target(target)
+        assert output.read_text() == "hello\n"
+
+
+@buck_test()
+async def test_cache_upload_to_re_grpc_server(buck: Buck) -> None:
+    target = "root//:hello"
+
+    async with _re_grpc_server(buck):
+        await buck.build(target, "-c", "test.local=true")
+        assert _executors(await read_what_ran(buck)) == ["Local"]
+        uploads = await _cache_uploads(buck)
+        assert len(uploads) == 1
+        assert uploads[0]["success"]
+
+        await buck.clean()
+
+        result = await buck.build(target, "--remote-only")
+        assert _executors(await read_what_ran(buck)) == ["Cache"]
+        output = result.get_build_report().output_for_target(target)
+        assert output.read_text() == "hello\n"
+
+
+@buck_test()
+async def test_cache_upload_permission_denied_by_re_grpc_server(buck: Buck) -> None:
+    target = "root//:hello"
+
+    async with _re_grpc_server(buck, "--deny-action-cache-updates"):
+        # The build succeeds, but the permission check stops the upload.
+        await buck.build(target, "-c", "test.local=true")
+        assert _executors(await read_what_ran(buck)) == ["Local"]
+        uploads = await _cache_uploads(buck)
+        assert len(uploads) == 1
+        assert not uploads[0]["success"]
+        assert "PermissionDenied" in uploads[0]["error"]
+
+        await buck.clean()
+
+        await buck.build(target, "--remote-only")
+        assert _executors(await read_what_ran(buck)) == ["Re"]
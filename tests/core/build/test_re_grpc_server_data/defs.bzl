@@ -0,0 +1,41 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# Run actions locally and upload their results, instead of running them on the server.
+_LOCAL = read_config("test", "local", "false") == "true"
+
+def _execution_platforms(ctx):
+    platform = ExecutionPlatformInfo(
+        label = ctx.label.raw_target(),
+        configuration = ConfigurationInfo(constraints = {}, values = {}),
+        executor_config = CommandExecutorConfig(
+            local_enabled = _LOCAL,
+            remote_enabled = not _LOCAL,
+            remote_cache_enabled = True,
+            allow_cache_uploads = _LOCAL,
+            remote_execution_properties = {},
+        ),
+    )
+
+    return [
+        DefaultInfo(),
+        ExecutionPlatformRegistrationInfo(platforms = [platform]),
+    ]
+
+execution_platforms = rule(impl = _execution_platforms, attrs = {})
+
+def _write_file(ctx):
+    out = ctx.actions.declare_output("out")
+
+    ctx.actions.run(
+        ["sh", "-c", 'echo "$1" > "$2"', "--", ctx.attrs.contents, out.as_output()],
+        category = "write_file",
+    )
+
+    return [DefaultInfo(out)]
+
+write_file = rule(impl = _write_file, attrs = {"contents": attrs.string()})
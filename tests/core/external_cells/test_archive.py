@@ -0,0 +1,75 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+import hashlib
+import tarfile
+from pathlib import Path
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.asserts import expect_failure
+from buck2.tests.e2e_util.buck_workspace import buck_test
+
+
+def _archive(cwd: Path) -> Path:
+    return (cwd.parent / "libfoo-1.0.tar.gz").absolute()
+
+
+def _set_archive(cwd: Path, sha256: str) -> None:
+    p = cwd / ".buckconfig"
+    data = p.read_text().splitlines()[:-3]
+    data.append(f"  url = file://{_archive(cwd)}")
+    data.append(f"  sha256 = {sha256}")
+    data.append("  strip_prefix = libfoo-1.0")
+    p.write_text("\n".join(data))
+
+
+def _init_archive(cwd: Path) -> str:
+    with tarfile.open(_archive(cwd), "w:gz") as tar:
+        tar.add(cwd / "template", arcname="libfoo-1.0")
+    sha256 = hashlib.sha256(_archive(cwd).read_bytes()).hexdigest()
+    _set_archive(cwd, sha256)
+    return sha256
+
+
+@buck_test()
+async def test_build(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+
+    res = await buck.build_without_report("libfoo//:t", "--show-full-simple-output")
+    assert Path(res.stdout.strip()).read_text().strip() == "archived"
+
+
+@buck_test()
+async def test_expand_external(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+    await buck.expand_external_cell("libfoo")
+    assert (buck.cwd / "libfoo" / "src.txt").exists()
+    assert "buildfile" in (buck.cwd / "libfoo" / ".buckconfig").read_text()
+
+
+@buck_test()
+async def test_bad_checksum(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+    _set_archive(cwd=buck.cwd, sha256="0" * 64)
+
+    await expect_failure(
+        buck.build("libfoo//:t"),
+        stderr_regex="but external cell config expects",
+    )
+
+
+@buck_test()
+async def test_no_refetch_on_restart(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+
+    await buck.build("libfoo//:t")
+    await buck.kill()
+
+    _archive(cwd=buck.cwd).unlink()
+    await buck.build("libfoo//:t")
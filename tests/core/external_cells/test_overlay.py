@@ -0,0 +1,77 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+import hashlib
+import tarfile
+from pathlib import Path
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.asserts import expect_failure
+from buck2.tests.e2e_util.buck_workspace import buck_test
+
+
+def _archive(cwd: Path) -> Path:
+    return (cwd.parent / "libfoo.tar.gz").absolute()
+
+
+def _init_archive(cwd: Path) -> None:
+    with tarfile.open(_archive(cwd), "w:gz") as tar:
+        tar.add(cwd / "template", arcname=".")
+    sha256 = hashlib.sha256(_archive(cwd).read_bytes()).hexdigest()
+
+    p = cwd / ".buckconfig"
+    data = p.read_text().splitlines()[:-2]
+    data.append(f"  url = file://{_archive(cwd)}")
+    data.append(f"  sha256 = {sha256}")
+    p.write_text("\n".join(data))
+
+
+async def _build_output(buck: Buck) -> str:
+    res = await buck.build_without_report("libfoo//:t", "--show-full-simple-output")
+    return Path(res.stdout.strip()).read_text().strip()
+
+
+@buck_test()
+async def test_patch_applied(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+    assert await _build_output(buck) == "patched"
+
+
+@buck_test()
+async def test_editing_patch(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+    assert await _build_output(buck) == "patched"
+
+    patch = buck.cwd / "patches" / "change_src.patch"
+    patch.write_text(patch.read_text().replace("+patched", "+patched again"))
+    assert await _build_output(buck) == "patched again"
+
+    # Line endings alone don't make it a different patch.
+    patch.write_bytes(patch.read_bytes().replace(b"\n", b"\r\n"))
+    assert await _build_output(buck) == "patched again"
+
+
+@buck_test()
+async def test_expand_external(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+    await buck.expand_external_cell("libfoo")
+    assert (buck.cwd / "libfoo" / "src.txt").read_text().strip() == "patched"
+
+
+@buck_test()
+async def test_bad_patch(buck: Buck) -> None:
+    _init_archive(cwd=buck.cwd)
+    (buck.cwd / "patches" / "change_src.patch").write_text(
+        "--- a/src.txt\n+++ b/src.txt\n@@ -1 +1 @@\n-not the contents\n+patched\n"
+    )
+
+    await expect_failure(
+        buck.build("libfoo//:t"),
+        stderr_regex="Error applying patch `patches/change_src.patch`",
+    )
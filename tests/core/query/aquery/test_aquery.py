@@ -8,6 +8,8 @@
 # pyre-strict
 
 
+import json
+
 from buck2.tests.e2e_util.api.buck import Buck
 from buck2.tests.e2e_util.buck_workspace import buck_test
 from buck2.tests.e2e_util.helper.golden import golden
@@ -81,6 +83,85 @@ async def test_deps(buck: Buck) -> None:
     )
 
 
+@buck_test()
+async def test_category(buck: Buck) -> None:
+    stdout = (
+        await buck.aquery("category(copy, all_actions(//:test))", "-a", "identifier")
+    ).stdout
+
+    golden(
+        output=stdout,
+        rel_path="category.golden.json",
+    )
+
+
+@buck_test()
+async def test_identifier(buck: Buck) -> None:
+    stdout = (
+        await buck.aquery(
+            "identifier('^sub_', all_actions(//:test))", "-a", "identifier"
+        )
+    ).stdout
+
+    golden(
+        output=stdout,
+        rel_path="identifier.golden.json",
+    )
+
+
+def _identifiers(stdout: str) -> list[str]:
+    return sorted(attrs["identifier"] for attrs in json.loads(stdout).values())
+
+
+@buck_test()
+async def test_argv_regex(buck: Buck) -> None:
+    stdout = (
+        await buck.aquery(
+            r"argv_regex('-DNAME=a\.h', all_actions(//:run))", "-a", "identifier"
+        )
+    ).stdout
+    assert _identifiers(stdout) == ["a.h"]
+
+    # The regex can span arguments.
+    stdout = (
+        await buck.aquery(
+            "argv_regex('^cp -DNAME', all_actions(//:run))", "-a", "identifier"
+        )
+    ).stdout
+    assert _identifiers(stdout) == ["a.h", "b.h"]
+
+    stdout = (
+        await buck.aquery(
+            "argv_regex('-DNAME=c', all_actions(//:run))", "-a", "identifier"
+        )
+    ).stdout
+    assert _identifiers(stdout) == []
+
+    # Actions that don't run a command never match.
+    stdout = (
+        await buck.aquery("argv_regex('.*', all_actions(//:test))", "-a", "identifier")
+    ).stdout
+    assert _identifiers(stdout) == []
+
+
+@buck_test()
+async def test_consumers(buck: Buck) -> None:
+    stdout = (
+        await buck.aquery("consumers(all_actions(//:run), a.h)", "-a", "identifier")
+    ).stdout
+    assert _identifiers(stdout) == ["a.h"]
+
+    stdout = (
+        await buck.aquery("consumers(all_actions(//:run), b.h)", "-a", "identifier")
+    ).stdout
+    assert _identifiers(stdout) == ["b.h"]
+
+    stdout = (
+        await buck.aquery("consumers(all_actions(//:test), a.h)", "-a", "identifier")
+    ).stdout
+    assert _identifiers(stdout) == []
+
+
 @buck_test()
 async def test_bxl_aquery_target(buck: Buck) -> None:
     stdout = (await buck.bxl("//:aquery.bxl:target")).stdout
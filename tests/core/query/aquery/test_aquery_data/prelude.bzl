@@ -30,3 +30,17 @@ def _test(ctx: AnalysisContext):
     )]
 
 test = rule(impl = _test, attrs = {})
+
+def _run_test(ctx: AnalysisContext):
+    outputs = []
+    for src in ctx.attrs.srcs:
+        out = ctx.actions.declare_output(src.short_path + ".out")
+        ctx.actions.run(
+            cmd_args("cp", "-DNAME=" + src.short_path, src, out.as_output()),
+            category = "run_copy",
+            identifier = src.short_path,
+        )
+        outputs.append(out)
+    return [DefaultInfo(default_outputs = outputs)]
+
+run_test = rule(impl = _run_test, attrs = {"srcs": attrs.list(attrs.source())})
@@ -141,6 +141,37 @@ async def test_attributes(buck: Buck) -> None:
     } == attrs_json_out
 
 
+@buck_test(data_dir="unsorted")
+async def test_select_resolutions(buck: Buck) -> None:
+    out = await buck.cquery(
+        "--select-resolutions",
+        "--json",
+        "set(root//lib:lib3 root//lib:lib1)",
+    )
+    out = json.loads(_replace_hash(out.stdout))
+    assert {
+        "root//lib:lib3 (root//platforms:platform1#<HASH>)": {
+            "buck.select_resolutions": {
+                "cmd": [
+                    {
+                        "selected": "root//lib:constraint",
+                        "matched": [
+                            {
+                                "key": "root//lib:constraint",
+                                "constraints": {},
+                                "buckconfigs": {},
+                            }
+                        ],
+                    }
+                ],
+            },
+        },
+        "root//lib:lib1 (root//platforms:platform1#<HASH>)": {
+            "buck.select_resolutions": {},
+        },
+    } == out
+
+
 # Tests for "%Ss" uses
 @buck_test(data_dir="unsorted")
 async def test_args_as_set(buck: Buck) -> None:
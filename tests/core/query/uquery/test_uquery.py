@@ -10,6 +10,7 @@
 
 import json
 import re
+import tempfile
 from pathlib import Path
 from typing import List
 
@@ -542,3 +543,49 @@ async def test_uquery_rdeps(buck: Buck) -> None:
 
     result = await buck.query("""rdeps(root//bin:the_binary, //lib:file1, 100)""")
     assert result.stdout == "root//bin:the_binary\nroot//lib:lib1\nroot//lib:file1\n"
+
+
+@buck_test(data_dir="bxl_simple")
+async def test_query_limits(buck: Buck) -> None:
+    query = "deps(root//bin:the_binary) - root//lib:file1"
+    result = await buck.uquery(query, "--max-results", "11", "--timeout", "1m")
+    assert "root//bin:the_binary\n" in result.stdout
+
+    await expect_failure(
+        buck.uquery(query, "--max-results", "10"),
+        stderr_regex="query evaluated to 11 results, more than the limit of 10",
+    )
+    await expect_failure(
+        buck.uquery(query, "--max-traversal-nodes", "10"),
+        stderr_regex="query loaded more than 10 targets and files in total",
+    )
+
+
+@buck_test(data_dir="bxl_simple")
+async def test_uquery_snapshot(buck: Buck) -> None:
+    with tempfile.TemporaryDirectory() as tmp:
+        snapshot = str(Path(tmp) / "snapshot.jsonl")
+        await buck.targets("//...", "--streaming", "--snapshot", "--output", snapshot)
+
+        for query in [
+            "deps(root//lib:lib1)",
+            "rdeps(root//bin:the_binary, //lib:file1)",
+            "kind(constraint_setting, //...)",
+            "owner(bin/TARGETS.fixture)",
+        ]:
+            expected = await buck.uquery(query)
+            result = await buck.uquery(query, "--snapshot", snapshot)
+            # Targets are listed in a different order in the snapshot.
+            assert sorted(result.stdout.splitlines()) == sorted(
+                expected.stdout.splitlines()
+            ), query
+
+        result = await buck.uquery(
+            "root//lib:lib1", "--snapshot", snapshot, "--output-attribute", "^name$"
+        )
+        assert json.loads(result.stdout) == {"root//lib:lib1": {"name": "lib1"}}
+
+        await expect_failure(
+            buck.uquery("root//lib:lib1", "--snapshot", snapshot, "--dot"),
+            stderr_regex="only supports the default and JSON output formats",
+        )
@@ -67,6 +67,38 @@ async def test_active_commands(buck: Buck) -> None:
         assert "subscribe" in commands[0]["argv"]
 
 
+@buck_test()
+async def test_query(buck: Buck) -> None:
+    query = "rdeps(//..., //:stage1)"
+    async with await buck.subscribe("--uquery", query) as subscribe:
+        msg = await subscribe.read_message()
+        result = msg["response"]["QueryResult"]
+        assert result["id"] == query
+        assert result["full"]
+        assert sorted(result["added"]) == ["root//:stage1", "root//:stage2"]
+
+        with open(buck.cwd / "TARGETS.fixture", "a") as f:
+            f.write('cp(name = "stage3", src = ":stage2")\n')
+
+        msg = await subscribe.read_message()
+        result = msg["response"]["QueryResult"]
+        assert not result["full"]
+        assert result["added"] == ["root//:stage3"]
+        assert result["removed"] == []
+
+
+@buck_test()
+async def test_query_limits(buck: Buck) -> None:
+    query = "rdeps(//..., //:stage1)"
+    async with await buck.subscribe(
+        "--uquery", query, "--max-results", "1"
+    ) as subscribe:
+        msg = await subscribe.read_message()
+        result = msg["response"]["QueryResult"]
+        assert result["id"] == query
+        assert "more than the limit of 1" in result["error"]
+
+
 @buck_test()
 async def test_disconnect_eof(buck: Buck) -> None:
     async with await buck.subscribe() as subscribe:
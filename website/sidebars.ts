@@ -125,6 +125,7 @@ export const sidebars: SidebarsConfig = {
             'users/advanced/deferred_materialization',
             'users/advanced/restarter',
             'users/advanced/in_memory_cache',
+            'users/advanced/local_disk_cache',
             'users/advanced/external_cells',
             isInternal() ? 'users/advanced/offline_build_archives' : null,
             isInternal() ? 'users/advanced/vpnless' : null,